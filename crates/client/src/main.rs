@@ -17,10 +17,13 @@ async fn main() {
     data.split(',').map(|v| v.to_string()).collect()
   });
 
-  let mut client = client::Client::new(None, None);
+  let mut client = client::Client::new(None, None, None);
 
   for address in addresses.iter() {
-    client.add_relay(address.to_string()).await;
+    client
+      .add_relay(address.to_string())
+      .await
+      .expect("RELAY_LIST must only have ws:// or wss:// urls");
   } 
 
   // client.connect().await;
@@ -57,12 +60,10 @@ async fn main() {
   // reply to root
   // let event = client.create_event(EventKind::Text, "REPLY TO THIS IF YOU CAN".to_string(), None);
   // let content = String::from("Replying 😎");
-  // let marker = Marker::Root;
-  // client.reply_to_event(event, None, marker, content).await;
+  // client.reply_to_event(event, None, content).await;
 
   // reply to reply
   // let content = String::from("Replying to reply! 😎");
-  // let marker = Marker::Reply;
   // let event = json!({
   //   "content": "Replying 😎",
   //   "created_at": 1686668598,
@@ -84,7 +85,7 @@ async fn main() {
   //   ]
   // });
   // let event = Event::from_value(event).unwrap();
-  // client.reply_to_event(event, None, marker, content).await;
+  // client.reply_to_event(event, None, content).await;
 
   //
   // sleep(Duration::new(19, 0));
@@ -0,0 +1,53 @@
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use guilospanck_nostr_sdk::{
+  client::communication_with_relay::close::ClientToRelayCommClose, relay::pool::RelayPool,
+};
+use tokio::runtime::Runtime;
+use tokio_tungstenite::tungstenite::Message;
+
+const RELAYS: usize = 50;
+
+/// Creates a pool with [`RELAYS`] relays. Nothing listens on their port, so
+/// they stay disconnected and the broadcast messages are only queued.
+///
+async fn relay_pool() -> RelayPool {
+  let pool = RelayPool::new();
+  for i in 0..RELAYS {
+    pool
+      .add_relay(
+        format!("ws://127.0.0.1:1/{i}").parse().unwrap(),
+        Message::Text(String::new()),
+      )
+      .await;
+  }
+  pool
+}
+
+fn broadcast_messages(c: &mut Criterion) {
+  let runtime = Runtime::new().unwrap();
+  let message = Message::from(ClientToRelayCommClose::new_close(String::from("potato")).as_json());
+
+  let mut group = c.benchmark_group("broadcast");
+  group.sample_size(10);
+  group.measurement_time(Duration::from_secs(2));
+  group.bench_function("broadcast_messages to 50 relays", |b| {
+    // a new pool for each sample, so the queued messages do not pile up
+    b.to_async(&runtime).iter_custom(|iters| {
+      let message = message.clone();
+      async move {
+        let pool = relay_pool().await;
+        let start = Instant::now();
+        for _ in 0..iters {
+          pool.broadcast_messages(message.clone()).await;
+        }
+        start.elapsed()
+      }
+    })
+  });
+  group.finish();
+}
+
+criterion_group!(benches, broadcast_messages);
+criterion_main!(benches);
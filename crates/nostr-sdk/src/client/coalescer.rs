@@ -0,0 +1,456 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use uuid::Uuid;
+
+use crate::{
+  client::communication_with_relay::{
+    close::ClientToRelayCommClose, request::ClientToRelayCommRequest,
+  },
+  event::Event,
+  filter::Filter,
+};
+
+/// What must be sent to the relays after the coalesced
+/// subscriptions change.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoalescedUpdate {
+  /// Sends a `REQ` of a combined subscription (see [`SubscriptionCoalescer::add`]).
+  Req(ClientToRelayCommRequest),
+  /// Closes a combined subscription, as no app subscription needs it anymore.
+  Close(ClientToRelayCommClose),
+}
+
+impl CoalescedUpdate {
+  pub fn as_json(&self) -> String {
+    match self {
+      CoalescedUpdate::Req(request) => request.as_json(),
+      CoalescedUpdate::Close(close) => close.as_json(),
+    }
+  }
+}
+
+/// What [`SubscriptionCoalescer::add`] did with an app subscription.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddedSubscription {
+  /// It was coalesced: the updates must be sent to the relays for it.
+  Coalesced(Vec<CoalescedUpdate>),
+  /// It cannot be coalesced, so the caller must send its `REQ`, after the
+  /// updates (the `CLOSE`s of the combined subscriptions it no longer needs).
+  NotCoalesced(Vec<CoalescedUpdate>),
+}
+
+/// App subscriptions whose filters only differ on `#e`,
+/// sent to the relays as combined subscriptions.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Group {
+  /// The filter shared by all members, without `#e`.
+  base_filter: Filter,
+  /// Filter of each app subscription of the group.
+  members: BTreeMap<String, Filter>,
+  /// `#e` of each subscription sent to the relays for the group.
+  relay_subscriptions: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Group {
+  fn request(&self, relay_subscription_id: &str, e: &BTreeSet<String>) -> ClientToRelayCommRequest {
+    let filter = Filter {
+      e: Some(e.iter().cloned().collect()),
+      ..self.base_filter.clone()
+    };
+
+    ClientToRelayCommRequest::new_req(relay_subscription_id.to_string(), vec![filter])
+  }
+}
+
+/// Gets the only filter of an app subscription, its `#e`, and its filter without
+/// `#e` (with its key) shared by its group, or `None` when it cannot be coalesced.
+///
+fn coalescible_parts(filters: &[Filter]) -> Option<(&Filter, &Vec<String>, Filter, String)> {
+  let [filter] = filters else {
+    return None;
+  };
+  let e = filter.e.as_ref()?;
+  if filter.limit.is_some() {
+    return None;
+  }
+
+  let base_filter = Filter {
+    e: None,
+    ..filter.clone()
+  };
+  let group_key = serde_json::to_string(&base_filter).ok()?;
+
+  Some((filter, e, base_filter, group_key))
+}
+
+/// Multiplexes app-level subscriptions onto fewer relay subscriptions.
+///
+/// Subscriptions with a single filter that has `#e` (e.g. the replies to
+/// each visible note) are merged with the ones that have the same filter
+/// apart from `#e`, so the relays get one `REQ` for all the `#e` of
+/// the ones added at once (e.g. on connect, see [`SubscriptionCoalescer::requests`]).
+/// The events received for a combined subscription are then given
+/// back to the app subscriptions they match with [`SubscriptionCoalescer::demux`].
+///
+/// Subscriptions that cannot be coalesced are left to the caller.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SubscriptionCoalescer {
+  /// Groups by their base filter (as JSON).
+  groups: HashMap<String, Group>,
+  /// Group (base filter) of each coalesced app subscription.
+  app_subscriptions: HashMap<String, String>,
+}
+
+impl SubscriptionCoalescer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds an app subscription. Returns what must be sent to the relays for it,
+  /// and whether it was coalesced.
+  ///
+  /// Only its `#e` that are not subscribed yet are sent, in a `REQ` of their own,
+  /// so the relays do not send again the stored events of the other ones.
+  /// Filters with a `limit` are not coalesced, as the relays would apply it
+  /// to the events of all the `#e` together.
+  ///
+  pub fn add(&mut self, app_subscription_id: &str, filters: &[Filter]) -> AddedSubscription {
+    // an app subscription being replaced may move to another group, or not be coalesced anymore
+    let mut updates = self.remove(app_subscription_id).unwrap_or_default();
+    let Some((filter, e, base_filter, group_key)) = coalescible_parts(filters) else {
+      return AddedSubscription::NotCoalesced(updates);
+    };
+
+    let group = self
+      .groups
+      .entry(group_key.clone())
+      .or_insert_with(|| Group {
+        base_filter,
+        members: BTreeMap::new(),
+        relay_subscriptions: BTreeMap::new(),
+      });
+    group
+      .members
+      .insert(app_subscription_id.to_string(), filter.clone());
+
+    let subscribed: BTreeSet<&String> = group.relay_subscriptions.values().flatten().collect();
+    let new_e: BTreeSet<String> = e
+      .iter()
+      .filter(|e| !subscribed.contains(e))
+      .cloned()
+      .collect();
+    if !new_e.is_empty() {
+      let relay_subscription_id = Uuid::new_v4().to_string();
+      updates.push(CoalescedUpdate::Req(
+        group.request(&relay_subscription_id, &new_e),
+      ));
+      group
+        .relay_subscriptions
+        .insert(relay_subscription_id, new_e);
+    }
+
+    self
+      .app_subscriptions
+      .insert(app_subscription_id.to_string(), group_key);
+
+    AddedSubscription::Coalesced(updates)
+  }
+
+  /// Removes an app subscription. Returns the `CLOSE`s of the combined
+  /// subscriptions none of the remaining app subscriptions need,
+  /// or `None` when the app subscription was not coalesced.
+  ///
+  pub fn remove(&mut self, app_subscription_id: &str) -> Option<Vec<CoalescedUpdate>> {
+    let group_key = self.app_subscriptions.remove(app_subscription_id)?;
+    let group = self.groups.get_mut(&group_key)?;
+    group.members.remove(app_subscription_id);
+
+    let needed_e: BTreeSet<&String> = group
+      .members
+      .values()
+      .filter_map(|filter| filter.e.as_ref())
+      .flatten()
+      .collect();
+    let unneeded: Vec<String> = group
+      .relay_subscriptions
+      .iter()
+      .filter(|(_, e)| e.iter().all(|e| !needed_e.contains(e)))
+      .map(|(relay_subscription_id, _)| relay_subscription_id.clone())
+      .collect();
+    for relay_subscription_id in unneeded.iter() {
+      group.relay_subscriptions.remove(relay_subscription_id);
+    }
+
+    if group.members.is_empty() {
+      self.groups.remove(&group_key);
+    }
+
+    Some(
+      unneeded
+        .into_iter()
+        .map(|relay_subscription_id| {
+          CoalescedUpdate::Close(ClientToRelayCommClose::new_close(relay_subscription_id))
+        })
+        .collect(),
+    )
+  }
+
+  /// Checks if the app subscription was coalesced.
+  pub fn contains(&self, app_subscription_id: &str) -> bool {
+    self.app_subscriptions.contains_key(app_subscription_id)
+  }
+
+  /// Gets the `REQ`s of all the combined subscriptions,
+  /// ordered by their subscription id.
+  ///
+  pub fn requests(&self) -> Vec<ClientToRelayCommRequest> {
+    let mut requests: Vec<ClientToRelayCommRequest> = self
+      .groups
+      .values()
+      .flat_map(|group| {
+        group
+          .relay_subscriptions
+          .iter()
+          .map(|(relay_subscription_id, e)| group.request(relay_subscription_id, e))
+      })
+      .collect();
+    requests.sort_by(|a, b| a.subscription_id.cmp(&b.subscription_id));
+    requests
+  }
+
+  /// Gets the app subscriptions an event received for `relay_subscription_id` belongs to.
+  ///
+  /// Subscriptions that were not coalesced are sent to the relays
+  /// with their own id, so that is the one returned for them.
+  ///
+  pub fn demux(&self, relay_subscription_id: &str, event: &Event) -> Vec<String> {
+    match self.groups.values().find(|group| {
+      group
+        .relay_subscriptions
+        .contains_key(relay_subscription_id)
+    }) {
+      Some(group) => group
+        .members
+        .iter()
+        .filter(|(_, filter)| filter.matches(event))
+        .map(|(app_subscription_id, _)| app_subscription_id.clone())
+        .collect(),
+      None => vec![relay_subscription_id.to_string()],
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::event::{id::EventId, kind::EventKind, tag::Tag};
+
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  fn replies_filter(event_id: &str) -> Filter {
+    Filter {
+      kinds: Some(vec![EventKind::Text]),
+      e: Some(vec![event_id.to_string()]),
+      ..Default::default()
+    }
+  }
+
+  fn reply_to(event_id: &str) -> Event {
+    Event {
+      kind: EventKind::Text,
+      tags: vec![Tag::Event(EventId(event_id.to_string()), None, None)],
+      ..Default::default()
+    }
+  }
+
+  fn req(relay_subscription_id: &str, e: &[&str]) -> CoalescedUpdate {
+    CoalescedUpdate::Req(ClientToRelayCommRequest::new_req(
+      relay_subscription_id.to_string(),
+      vec![Filter {
+        kinds: Some(vec![EventKind::Text]),
+        e: Some(e.iter().map(|e| e.to_string()).collect()),
+        ..Default::default()
+      }],
+    ))
+  }
+
+  fn close(relay_subscription_id: &str) -> CoalescedUpdate {
+    CoalescedUpdate::Close(ClientToRelayCommClose::new_close(
+      relay_subscription_id.to_string(),
+    ))
+  }
+
+  fn coalesced(added: AddedSubscription) -> Vec<CoalescedUpdate> {
+    match added {
+      AddedSubscription::Coalesced(updates) => updates,
+      AddedSubscription::NotCoalesced(_) => panic!("the subscription was not coalesced"),
+    }
+  }
+
+  fn relay_subscription_id(updates: &[CoalescedUpdate]) -> String {
+    match &updates[0] {
+      CoalescedUpdate::Req(request) => request.subscription_id.clone(),
+      CoalescedUpdate::Close(close) => close.subscription_id.clone(),
+    }
+  }
+
+  #[test]
+  fn subscriptions_with_e_filters_are_coalesced_and_demultiplexed() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let both_filter = Filter {
+      e: Some(vec![String::from("a"), String::from("b")]),
+      ..replies_filter("a")
+    };
+
+    let first = coalesced(coalescer.add("replies-a", &[replies_filter("a")]));
+    let first_id = relay_subscription_id(&first);
+    let second = coalesced(coalescer.add("replies-ab", &[both_filter]));
+    let second_id = relay_subscription_id(&second);
+
+    // only the `#e` not subscribed yet are sent
+    assert_eq!(first, vec![req(&first_id, &["a"])]);
+    assert_eq!(second, vec![req(&second_id, &["b"])]);
+    assert_eq!(
+      coalescer.add("replies-b", &[replies_filter("b")]),
+      AddedSubscription::Coalesced(vec![])
+    );
+
+    let mut expected_requests = vec![req(&first_id, &["a"]), req(&second_id, &["b"])];
+    expected_requests.sort_by_key(CoalescedUpdate::as_json);
+    let mut requests: Vec<CoalescedUpdate> = coalescer
+      .requests()
+      .into_iter()
+      .map(CoalescedUpdate::Req)
+      .collect();
+    requests.sort_by_key(CoalescedUpdate::as_json);
+    assert_eq!(requests, expected_requests);
+
+    assert_eq!(
+      coalescer.demux(&first_id, &reply_to("a")),
+      vec![String::from("replies-a"), String::from("replies-ab")]
+    );
+    assert_eq!(
+      coalescer.demux(&second_id, &reply_to("b")),
+      vec![String::from("replies-ab"), String::from("replies-b")]
+    );
+    assert!(coalescer.demux(&first_id, &reply_to("c")).is_empty());
+  }
+
+  #[test]
+  fn subscriptions_without_e_filters_are_not_coalesced() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let metadata_filter = Filter {
+      kinds: Some(vec![EventKind::Metadata]),
+      ..Default::default()
+    };
+
+    assert_eq!(
+      coalescer.add("metadata", &[metadata_filter]),
+      AddedSubscription::NotCoalesced(vec![])
+    );
+    assert_eq!(
+      coalescer.add("two-filters", &[replies_filter("a"), replies_filter("b")]),
+      AddedSubscription::NotCoalesced(vec![])
+    );
+    assert!(coalescer.requests().is_empty());
+    assert_eq!(
+      coalescer.demux("metadata", &Event::default()),
+      vec![String::from("metadata")]
+    );
+  }
+
+  #[test]
+  fn subscriptions_with_a_limit_are_not_coalesced() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let limited_filter = Filter {
+      limit: Some(10),
+      ..replies_filter("a")
+    };
+
+    assert_eq!(
+      coalescer.add("limited", &[limited_filter]),
+      AddedSubscription::NotCoalesced(vec![])
+    );
+    assert!(!coalescer.contains("limited"));
+    assert!(coalescer.requests().is_empty());
+  }
+
+  #[test]
+  fn different_base_filters_are_not_merged() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let reactions_filter = Filter {
+      kinds: Some(vec![EventKind::Reaction]),
+      e: Some(vec![String::from("a")]),
+      ..Default::default()
+    };
+
+    coalescer.add("replies-a", &[replies_filter("a")]);
+    coalescer.add("reactions-a", &[reactions_filter]);
+
+    assert_eq!(coalescer.requests().len(), 2);
+  }
+
+  #[test]
+  fn remove_closes_the_combined_subscriptions_no_longer_needed() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let a_id = relay_subscription_id(&coalesced(
+      coalescer.add("replies-a", &[replies_filter("a")]),
+    ));
+    coalescer.add("replies-a-again", &[replies_filter("a")]);
+    let b_id = relay_subscription_id(&coalesced(
+      coalescer.add("replies-b", &[replies_filter("b")]),
+    ));
+
+    // another app subscription still needs `a`
+    assert_eq!(coalescer.remove("replies-a"), Some(vec![]));
+    assert!(!coalescer.contains("replies-a"));
+
+    assert_eq!(coalescer.remove("replies-b"), Some(vec![close(&b_id)]));
+    assert_eq!(
+      coalescer.remove("replies-a-again"),
+      Some(vec![close(&a_id)])
+    );
+    assert!(coalescer.requests().is_empty());
+    assert_eq!(coalescer.remove("replies-b"), None);
+  }
+
+  #[test]
+  fn replacing_a_subscription_closes_the_combined_one_it_leaves() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let reactions_filter = Filter {
+      kinds: Some(vec![EventKind::Reaction]),
+      ..replies_filter("a")
+    };
+    let a_id = relay_subscription_id(&coalesced(coalescer.add("notes-a", &[replies_filter("a")])));
+
+    let updates = coalesced(coalescer.add("notes-a", &[reactions_filter]));
+
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[0], close(&a_id));
+    assert!(matches!(updates[1], CoalescedUpdate::Req(_)));
+    assert_eq!(coalescer.requests().len(), 1);
+  }
+
+  #[test]
+  fn replacing_a_subscription_with_one_not_coalesced_closes_the_combined_one() {
+    let mut coalescer = SubscriptionCoalescer::new();
+    let limited_filter = Filter {
+      limit: Some(10),
+      ..replies_filter("b")
+    };
+    let a_id = relay_subscription_id(&coalesced(coalescer.add("notes", &[replies_filter("a")])));
+
+    assert_eq!(
+      coalescer.add("notes", &[limited_filter]),
+      AddedSubscription::NotCoalesced(vec![close(&a_id)])
+    );
+    assert!(!coalescer.contains("notes"));
+    assert!(coalescer.requests().is_empty());
+  }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::{
+  event::{build_event, kind::EventKind, Event},
+  schnorr::AsymmetricKeys,
+};
+
+use super::Error;
+
+/// Kind of the event authorizing the `CONNECTIONS` command. It is
+/// ephemeral (NIP-16), so relays do not store it if it is ever published.
+///
+pub const CONNECTIONS_ADMIN_KIND: u64 = 29999;
+
+/// Admin command asking the relay for a summary of the clients
+/// connected to it.
+///
+/// `admin_event` is an event of kind [`CONNECTIONS_ADMIN_KIND`] with the content
+/// `"CONNECTIONS"`, signed by the relay admin. The relay only accepts it
+/// for a short while after its `created_at`, so it cannot be replayed later.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientToRelayCommConnections {
+  pub code: String, // "CONNECTIONS"
+  pub admin_event: Event,
+}
+
+impl ClientToRelayCommConnections {
+  pub fn new_connections(admin_event: Event) -> Self {
+    Self {
+      code: "CONNECTIONS".to_string(),
+      admin_event,
+    }
+  }
+
+  /// Creates the command with an `admin_event` created now and signed with `keys`.
+  pub fn signed(keys: &AsymmetricKeys) -> Self {
+    Self::new_connections(build_event(
+      keys,
+      EventKind::Custom(CONNECTIONS_ADMIN_KIND),
+      String::from("CONNECTIONS"),
+      vec![],
+    ))
+  }
+
+  /// Serialize as [`Value`]
+  pub fn as_value(&self) -> Value {
+    json!(["CONNECTIONS", self.admin_event])
+  }
+
+  /// Deserialize from [`Value`]
+  pub fn from_value(msg: Value) -> Result<Self, Error> {
+    let v = msg.as_array().ok_or(Error::InvalidData)?;
+
+    if v.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let v_len: usize = v.len();
+
+    // Connections
+    // ["CONNECTIONS", <admin event JSON>]
+    if v[0] != "CONNECTIONS" || v_len != 2 {
+      return Err(Error::InvalidData);
+    }
+
+    let admin_event = serde_json::from_value(v[1].clone())?;
+    Ok(Self::new_connections(admin_event))
+  }
+
+  /// Get [`ClientToRelayCommConnections`] as JSON string
+  pub fn as_json(&self) -> String {
+    self.as_value().to_string()
+  }
+
+  /// Deserialize [`ClientToRelayCommConnections`] from JSON string
+  pub fn from_json<S>(msg: S) -> Result<Self, Error>
+  where
+    S: Into<String>,
+  {
+    let msg: &str = &msg.into();
+
+    if msg.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let value: Value = serde_json::from_str(msg)?;
+    Self::from_value(value)
+  }
+}
+
+impl Default for ClientToRelayCommConnections {
+  fn default() -> Self {
+    Self {
+      code: String::from("CONNECTIONS"),
+      admin_event: Event::default(),
+    }
+  }
+}
+
+impl Serialize for ClientToRelayCommConnections {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let json_value: Value = self.as_value();
+    json_value.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for ClientToRelayCommConnections {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let json_value: Value = Value::deserialize(deserializer)?;
+
+    ClientToRelayCommConnections::from_value(json_value).map_err(serde::de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn test_client_to_relay_comm_connections_as_json() {
+    let connections = ClientToRelayCommConnections::new_connections(Event::default());
+
+    assert_eq!(
+      connections.as_value(),
+      json!(["CONNECTIONS", Event::default()])
+    );
+  }
+
+  #[test]
+  fn test_client_to_relay_comm_connections_from_json() {
+    let connections = ClientToRelayCommConnections::signed(&crate::schnorr::generate_keys());
+
+    let result = ClientToRelayCommConnections::from_json(connections.as_json());
+
+    assert_eq!(result.unwrap(), connections);
+    assert_eq!(
+      connections.admin_event.kind,
+      EventKind::Custom(CONNECTIONS_ADMIN_KIND)
+    );
+    assert!(connections.admin_event.verify().is_ok());
+    assert!(ClientToRelayCommConnections::from_json(r#"["CONNECTIONS","mock_sig"]"#).is_err());
+    assert!(ClientToRelayCommConnections::from_json(r#"["CONNECTIONS"]"#).is_err());
+  }
+}
@@ -1,3 +1,4 @@
+use log::debug;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{json, Value};
 
@@ -25,6 +26,9 @@ impl ClientToRelayCommEvent {
   }
 
   /// Deserialize from [`Value`]
+  ///
+  /// An `EVENT` message whose event is not valid is an [`Error::MalformedEvent`].
+  ///
   pub fn from_value(msg: Value) -> Result<Self, Error> {
     let v = msg.as_array().ok_or(Error::InvalidData)?;
 
@@ -40,7 +44,11 @@ impl ClientToRelayCommEvent {
       return Err(Error::InvalidData);
     }
 
-    let event: Event = serde_json::from_value(v[1].clone())?;
+    let event: Event = serde_json::from_value(v[1].clone()).map_err(|err| {
+      debug!("Malformed event json: {err}");
+      let id = v[1]["id"].as_str().unwrap_or_default();
+      Error::MalformedEvent(id.to_string())
+    })?;
     Ok(Self::new_event(event))
   }
   
@@ -174,4 +182,21 @@ mod tests {
 
     assert_eq!(result, mock.mock_client_event);
   }
+
+  #[test]
+  fn test_client_to_relay_comm_event_from_json_with_malformed_event() {
+    let mock = EvtSut::new();
+
+    let mut event_json = mock.mock_event.as_value();
+    event_json["created_at"] = json!("yesterday");
+    let result = ClientToRelayCommEvent::from_json(json!(["EVENT", event_json]).to_string());
+    assert!(matches!(result, Err(Error::MalformedEvent(id)) if id == mock.mock_event.id));
+
+    let result = ClientToRelayCommEvent::from_json(r#"["EVENT",{"content":"potato"}]"#);
+    assert!(matches!(result, Err(Error::MalformedEvent(id)) if id.is_empty()));
+
+    // not an `EVENT` message
+    let result = ClientToRelayCommEvent::from_json(r#"["REQ","potato",{}]"#);
+    assert!(matches!(result, Err(Error::InvalidData)));
+  }
 }
@@ -10,15 +10,13 @@
 ///
 /// 
 use crate::{
-  event::{
-    tag::{Tag, TagKind},
-    Event,
-  },
-  filter::Filter,
+  event::Event,
+  filter::{Filter, IdMatching},
 };
 
 // Internal `client_to_relay_communication` modules
 pub mod close;
+pub mod connections;
 pub mod event;
 pub mod request;
 
@@ -29,7 +27,11 @@ pub enum Error {
   #[error(transparent)]
   Json(#[from] serde_json::Error),
   #[error("Invalid data")]
-  InvalidData
+  InvalidData,
+  /// The event of an `["EVENT", <event JSON>]` message is not a valid event.
+  /// Holds its id, if it has one, so the relay can refuse it.
+  #[error("malformed event json")]
+  MalformedEvent(String),
 }
 
 impl serde::de::Error for Error {
@@ -38,103 +40,19 @@ impl serde::de::Error for Error {
   }
 }
 
-pub fn check_event_match_filter(event: Event, filter: Filter) -> bool {
-  // Check IDs
-  if let Some(ids) = filter.ids {
-    let id_in_list = ids
-      .iter()
-      .any(|id| *id.0 == event.id || id.0.starts_with(&event.id));
-    if !id_in_list {
-      return false;
-    }
-  }
-
-  // Check Authors
-  if let Some(authors) = filter.authors {
-    let author_in_list = authors
-      .iter()
-      .any(|author| *author == event.pubkey || author.starts_with(&event.pubkey));
-    if !author_in_list {
-      return false;
-    }
-  }
-
-  // Check Kinds
-  if let Some(kinds) = filter.kinds {
-    let kind_in_list = kinds.iter().any(|kind| *kind == event.kind);
-    if !kind_in_list {
-      return false;
-    }
-  }
-
-  // Check Since
-  if let Some(since) = filter.since {
-    let event_after_since = since <= event.created_at;
-    if !event_after_since {
-      return false;
-    }
-  }
-
-  // Check Until
-  if let Some(until) = filter.until {
-    let event_before_until = until >= event.created_at;
-    if !event_before_until {
-      return false;
-    }
-  }
-
-  // Check #e tag
-  if let Some(event_ids) = filter.e {
-    match event
-      .tags
-      .iter()
-      .position(|event_tag| TagKind::from(event_tag.clone()) == TagKind::Event)
-    {
-      Some(index) => {
-        if let Tag::Event(event_event_tag_id, _, _) = &event.tags[index] {
-          if !event_ids
-            .iter()
-            .any(|event_id| *event_id == event_event_tag_id.0)
-          {
-            return false;
-          }
-        }
-      }
-      None => return false,
-    }
-  }
-
-  // Check #p tag
-  if let Some(pubkeys) = filter.p {
-    match event
-      .tags
-      .iter()
-      .position(|event_tag| TagKind::from(event_tag.clone()) == TagKind::PubKey)
-    {
-      Some(index) => {
-        if let Tag::PubKey(event_pubkey_tag_pubkey, _) = &event.tags[index] {
-          for pubkey in pubkeys {
-            if !event_pubkey_tag_pubkey
-              .iter()
-              .any(|evt_pubkey_tag| *evt_pubkey_tag == pubkey)
-            {
-              return false;
-            }
-          }
-        }
-      }
-      None => return false,
-    }
-  }
-
-  true
+/// Checks if the event matches the filter, comparing
+/// its `ids` and `authors` as set by `id_matching`
+/// (see [`Filter::matches_with`]).
+///
+pub fn check_event_match_filter(event: &Event, filter: &Filter, id_matching: IdMatching) -> bool {
+  filter.matches_with(event, id_matching)
 }
 
 #[cfg(test)]
 mod tests {
   use crate::{
-    event::{id::EventId, kind::EventKind, Timestamp},
-    filter::Filter,
+    event::{id::EventId, kind::EventKind, marker::Marker, tag::Tag, Timestamp},
+    filter::{Filter, IdMatching},
   };
 
   use super::*;
@@ -159,8 +77,70 @@ mod tests {
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
+  }
+
+  #[test]
+  fn test_filter_match_id_and_author_prefixes() {
+    let event = Event {
+      id: String::from("05b25af3-4250-4fbf-8ef5-97220858f9ab"),
+      pubkey: String::from("02c7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76"),
+      ..Default::default()
+    };
+    let by_id_prefix = Filter::new().ids(vec![String::from("05b2")]);
+    let by_author_prefix = Filter::new().authors(vec![String::from("02c7e1")]);
+    // the event id must start with the filter one, not the other way around
+    let by_longer_id = Filter::new().ids(vec![format!("{}0", event.id)]);
+    let by_longer_author = Filter::new().authors(vec![format!("{}0", event.pubkey)]);
+    let short_event = Event {
+      id: String::from("05b2"),
+      pubkey: String::from("02c7e1"),
+      ..Default::default()
+    };
+
+    assert!(check_event_match_filter(
+      &event,
+      &by_id_prefix,
+      IdMatching::Prefix
+    ));
+    assert!(check_event_match_filter(
+      &event,
+      &by_author_prefix,
+      IdMatching::Prefix
+    ));
+    assert_eq!(
+      check_event_match_filter(&event, &by_longer_id, IdMatching::Prefix),
+      false
+    );
+    assert_eq!(
+      check_event_match_filter(&event, &by_longer_author, IdMatching::Prefix),
+      false
+    );
+    assert_eq!(
+      check_event_match_filter(
+        &short_event,
+        &Filter::new().ids(vec![event.id.clone()]),
+        IdMatching::Prefix
+      ),
+      false
+    );
+
+    assert_eq!(by_id_prefix.matches_with(&event, IdMatching::Exact), false);
+    assert_eq!(
+      by_author_prefix.matches_with(&event, IdMatching::Exact),
+      false
+    );
+    assert!(Filter::new()
+      .ids(vec![event.id.clone()])
+      .authors(vec![event.pubkey.clone()])
+      .matches_with(&event, IdMatching::Exact));
   }
 
   #[test]
@@ -182,8 +162,14 @@ mod tests {
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
   }
 
   #[test]
@@ -203,8 +189,14 @@ mod tests {
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
   }
 
   #[test]
@@ -225,8 +217,14 @@ mod tests {
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
   }
 
   #[test]
@@ -247,8 +245,14 @@ mod tests {
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
   }
 
   #[test]
@@ -262,16 +266,33 @@ mod tests {
       ..Default::default()
     };
     let event = Event {
-      tags: vec![Tag::Event(EventId(mock_filter_e_tag), None, None)],
+      tags: vec![Tag::Event(EventId(mock_filter_e_tag.clone()), None, None)],
       ..Default::default()
     };
     let event2 = Event {
-      tags: vec![Tag::Event(EventId(mock_filter_e_tag2), None, None)],
+      tags: vec![Tag::Event(EventId(mock_filter_e_tag2.clone()), None, None)],
+      ..Default::default()
+    };
+    let reply_to_reply = Event {
+      tags: vec![
+        Tag::Event(EventId(mock_filter_e_tag2), None, Some(Marker::Root)),
+        Tag::Event(EventId(mock_filter_e_tag), None, Some(Marker::Reply)),
+      ],
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
+    assert_eq!(
+      check_event_match_filter(&reply_to_reply, &filter, IdMatching::Prefix),
+      true
+    );
   }
 
   #[test]
@@ -293,8 +314,101 @@ mod tests {
       ..Default::default()
     };
 
-    assert_eq!(check_event_match_filter(event, filter.clone()), true);
-    assert_eq!(check_event_match_filter(event2, filter), false);
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
+  }
+
+  #[test]
+  fn test_filter_t_tag() {
+    let filter = Filter {
+      t: Some(vec![String::from("nostr")]),
+      ..Default::default()
+    };
+    let event = Event {
+      tags: vec![Tag::hashtag("bitcoin"), Tag::hashtag("nostr")],
+      ..Default::default()
+    };
+    let event2 = Event {
+      tags: vec![Tag::hashtag("bitcoin")],
+      ..Default::default()
+    };
+    let event_without_tags = Event::default();
+
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
+    assert_eq!(
+      check_event_match_filter(&event_without_tags, &filter, IdMatching::Prefix),
+      false
+    );
+  }
+
+  #[test]
+  fn test_filter_a_tag() {
+    let article = Event {
+      kind: EventKind::Custom(30023),
+      pubkey: String::from("614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6"),
+      tags: vec![Tag::identifier("potato-recipes")],
+      ..Default::default()
+    };
+    let filter = Filter::new().a_tags(vec![article.address()]);
+    let event = Event {
+      tags: vec![Tag::address(&article.address())],
+      ..Default::default()
+    };
+    let event2 = Event {
+      tags: vec![Tag::address(
+        "30023:614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6:tomato-recipes",
+      )],
+      ..Default::default()
+    };
+
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
+    assert_eq!(
+      check_event_match_filter(&Event::default(), &filter, IdMatching::Prefix),
+      false
+    );
+  }
+
+  #[test]
+  fn test_filter_search() {
+    let filter = Filter::from_string(String::from(r#"{"search":"potato"}"#)).unwrap();
+    let event = Event {
+      content: String::from("Potato soup is the best soup."),
+      ..Default::default()
+    };
+    let event2 = Event {
+      content: String::from("Tomato soup is fine too."),
+      ..Default::default()
+    };
+
+    assert_eq!(filter, Filter::new().search("potato"));
+    assert_eq!(
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
+      true
+    );
+    assert_eq!(
+      check_event_match_filter(&event2, &filter, IdMatching::Prefix),
+      false
+    );
   }
 
   #[test]
@@ -334,7 +448,7 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event.clone(), filter.clone()),
+      check_event_match_filter(&event, &filter, IdMatching::Prefix),
       true
     );
 
@@ -346,7 +460,7 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event_different_id, filter.clone()),
+      check_event_match_filter(&event_different_id, &filter, IdMatching::Prefix),
       false
     );
 
@@ -359,7 +473,7 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event_different_author, filter.clone()),
+      check_event_match_filter(&event_different_author, &filter, IdMatching::Prefix),
       false
     );
 
@@ -371,7 +485,7 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event_different_kind, filter.clone()),
+      check_event_match_filter(&event_different_kind, &filter, IdMatching::Prefix),
       false
     );
 
@@ -383,7 +497,7 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event_different_created_at, filter.clone()),
+      check_event_match_filter(&event_different_created_at, &filter, IdMatching::Prefix),
       false
     );
 
@@ -399,7 +513,7 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event_different_p_tag, filter.clone()),
+      check_event_match_filter(&event_different_p_tag, &filter, IdMatching::Prefix),
       false
     );
 
@@ -415,8 +529,44 @@ mod tests {
     };
 
     assert_eq!(
-      check_event_match_filter(event_different_p_tag, filter),
+      check_event_match_filter(&event_different_p_tag, &filter, IdMatching::Prefix),
       false
     );
   }
+
+  #[test]
+  fn test_filter_match_by_reference_leaves_event_and_filter_untouched() {
+    let mock_filter_e_tag = String::from("05b25af3-4250-4fbf-8ef5-97220858f9ab");
+    let filter = Filter {
+      kinds: Some(vec![EventKind::Text]),
+      e: Some(vec![mock_filter_e_tag.clone()]),
+      ..Default::default()
+    };
+    let event = Event {
+      kind: EventKind::Text,
+      tags: vec![Tag::Event(EventId(mock_filter_e_tag), None, None)],
+      ..Default::default()
+    };
+    let event_different_kind = Event {
+      kind: EventKind::Metadata,
+      ..event.clone()
+    };
+    let expected_filter = filter.clone();
+    let expected_event = event.clone();
+
+    // the same event and filter can be matched as many times as needed
+    for _ in 0..3 {
+      assert_eq!(
+        check_event_match_filter(&event, &filter, IdMatching::Prefix),
+        true
+      );
+      assert_eq!(
+        check_event_match_filter(&event_different_kind, &filter, IdMatching::Prefix),
+        false
+      );
+    }
+
+    assert_eq!(filter, expected_filter);
+    assert_eq!(event, expected_event);
+  }
 }
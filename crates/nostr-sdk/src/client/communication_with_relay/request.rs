@@ -149,9 +149,16 @@ mod tests {
         kinds: None,
         e: None,
         p: None,
+        t: None,
+        a: None,
         since: None,
         until: None,
         limit: filter_limit,
+        search: None,
+        #[cfg(feature = "filter-exclusions")]
+        exclude_authors: None,
+        #[cfg(feature = "filter-exclusions")]
+        exclude_kinds: None,
       };
 
       let mock_client_request = ClientToRelayCommRequest {
@@ -247,8 +254,8 @@ mod tests {
         ]),
         kinds: Some(vec![
           EventKind::Text,
-          EventKind::Custom(6),
-          EventKind::Custom(7),
+          EventKind::Repost,
+          EventKind::Reaction,
           EventKind::Custom(9735),
         ]),
         ..Default::default()
@@ -259,4 +266,20 @@ mod tests {
     assert_eq!(result2, client_request_for_expectation_2);
     assert_eq!(result3, expected_client_request_for_from_json_3);
   }
+
+  #[test]
+  fn test_client_to_relay_comm_request_deserialize_broken_filter() {
+    let broken_filters = [
+      json!(["REQ", "sub", "not a filter"]),
+      json!(["REQ", "sub", {"kinds": "not kinds"}]),
+      json!(["REQ", "sub", {"ids": []}, {"limit": -1}]),
+    ];
+
+    for broken_filter in broken_filters {
+      let result: Result<ClientToRelayCommRequest, _> =
+        serde_json::from_value(broken_filter.clone());
+      assert!(result.is_err(), "{broken_filter} should not deserialize");
+      assert!(ClientToRelayCommRequest::from_json(broken_filter.to_string()).is_err());
+    }
+  }
 }
@@ -1,4 +1,4 @@
-use std::{fs, u8, vec};
+use std::{fs, sync::Arc, u8, vec};
 
 use ::hex::decode;
 use bitcoin_hashes::hex::ToHex;
@@ -6,27 +6,42 @@ use redb::{Database, ReadableTable, TableDefinition};
 
 use crate::schnorr;
 
-use super::{ClientDatabase, Result};
+use super::{ClientDatabase, Items, Result};
 
 const TABLE_NAME: &str = "keys";
 const KEYS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new(TABLE_NAME);
+/// Keys of each account, by label: the private key followed by the public key.
+const ACCOUNTS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("accounts");
+const PRIVATE_KEY_LENGTH: usize = 32;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Keys {
   pub private_key: Vec<u8>,
   pub public_key: Vec<u8>,
 }
 
+impl From<&schnorr::AsymmetricKeys> for Keys {
+  fn from(keys: &schnorr::AsymmetricKeys) -> Self {
+    let pubkey = schnorr::normalize_pubkey(&keys.public_key.to_hex()).unwrap();
+
+    Self {
+      private_key: keys.private_key.secret_bytes().to_vec(),
+      public_key: decode(pubkey).unwrap(),
+    }
+  }
+}
+
+#[derive(Debug)]
 pub struct KeysTable {
-  db: Database,
+  db: Arc<Database>,
   keys: Keys,
 }
 
-impl<'a> ClientDatabase<'a> for KeysTable {
-  type K = &'a str;
-  type V = &'a [u8];
+impl ClientDatabase for KeysTable {
+  type K = str;
+  type V = [u8];
 
-  fn write_to_db(&self, k: Self::K, v: Self::V) -> Result<()> {
+  fn write_to_db(&self, k: &Self::K, v: &Self::V) -> Result<()> {
     let write_txn = self.db.begin_write()?;
     {
       let mut table = write_txn.open_table(KEYS_TABLE)?;
@@ -36,7 +51,7 @@ impl<'a> ClientDatabase<'a> for KeysTable {
     Ok(())
   }
 
-  fn remove_from_db(&self, k: Self::K) -> Result<()> {
+  fn remove_from_db(&self, k: &Self::K) -> Result<()> {
     let write_txn = self.db.begin_write()?;
     {
       let mut table = write_txn.open_table(KEYS_TABLE)?;
@@ -45,6 +60,19 @@ impl<'a> ClientDatabase<'a> for KeysTable {
     write_txn.commit()?;
     Ok(())
   }
+
+  fn read_all(&self) -> Result<Items<Self::K, Self::V>> {
+    let read_txn = self.db.begin_read()?;
+    let table = read_txn.open_table(KEYS_TABLE)?;
+
+    let mut items = vec![];
+    for item in table.iter()? {
+      let (k, v) = item?;
+      items.push((k.value().to_string(), v.value().to_vec()));
+    }
+
+    Ok(items)
+  }
 }
 
 impl Default for KeysTable {
@@ -66,10 +94,21 @@ impl KeysTable {
     {
       let write_txn = db.begin_write().unwrap();
       write_txn.open_table(KEYS_TABLE).unwrap(); // this basically just creates the table if doesn't exist
+      write_txn.open_table(ACCOUNTS_TABLE).unwrap();
       write_txn.commit().unwrap();
     }
 
-    Self { db, keys }
+    Self {
+      db: Arc::new(db),
+      keys,
+    }
+  }
+
+  /// Gets the database of the table, so other tables
+  /// stored in the same file can share it.
+  ///
+  pub(crate) fn database(&self) -> Arc<Database> {
+    Arc::clone(&self.db)
   }
 
   pub fn get_client_keys(&self) -> Result<Option<Keys>> {
@@ -109,10 +148,7 @@ impl KeysTable {
         self.keys.public_key = keys.public_key;
       }
       None => {
-        let generated = schnorr::generate_keys();
-        self.keys.private_key = generated.private_key.secret_bytes().to_vec();
-        let pubkey = &generated.public_key.to_hex()[2..];
-        self.keys.public_key = decode(pubkey).unwrap();
+        self.keys = Keys::from(&schnorr::generate_keys());
 
         self.write_to_db("private_key", &self.keys.private_key)?;
         self.write_to_db("public_key", &self.keys.public_key)?;
@@ -121,6 +157,78 @@ impl KeysTable {
 
     Ok(self.keys.clone())
   }
+
+  /// Stores `keys` as the default keys of the client, replacing the ones it had.
+  pub fn set_client_keys(&mut self, keys: &schnorr::AsymmetricKeys) -> Result<()> {
+    let keys = Keys::from(keys);
+    self.write_to_db("private_key", &keys.private_key)?;
+    self.write_to_db("public_key", &keys.public_key)?;
+    self.keys = keys;
+    Ok(())
+  }
+
+  /// Stores the keys of an account under `label`, replacing
+  /// the ones it had, if any.
+  ///
+  pub fn insert_account(&self, label: &str, keys: &schnorr::AsymmetricKeys) -> Result<()> {
+    let keys = Keys::from(keys);
+    let value = [keys.private_key, keys.public_key].concat();
+
+    let write_txn = self.db.begin_write()?;
+    {
+      let mut table = write_txn.open_table(ACCOUNTS_TABLE)?;
+      table.insert(label, value.as_slice())?;
+    }
+    write_txn.commit()?;
+    Ok(())
+  }
+
+  /// Gets the keys of the account with `label`.
+  pub fn get_account(&self, label: &str) -> Result<Option<Keys>> {
+    let read_txn = self.db.begin_read()?;
+    let table = read_txn.open_table(ACCOUNTS_TABLE)?;
+
+    let keys = table.get(label)?.and_then(|value| {
+      let value = value.value();
+      if value.len() <= PRIVATE_KEY_LENGTH {
+        return None;
+      }
+      let (private_key, public_key) = value.split_at(PRIVATE_KEY_LENGTH);
+      Some(Keys {
+        private_key: private_key.to_vec(),
+        public_key: public_key.to_vec(),
+      })
+    });
+
+    Ok(keys)
+  }
+
+  /// Gets the labels of all accounts, in alphabetical order.
+  pub fn list_accounts(&self) -> Result<Vec<String>> {
+    let read_txn = self.db.begin_read()?;
+    let table = read_txn.open_table(ACCOUNTS_TABLE)?;
+
+    let mut labels = vec![];
+    for item in table.iter()? {
+      labels.push(item?.0.value().to_string());
+    }
+
+    Ok(labels)
+  }
+
+  /// Same as `get_or_create_client_keys`, but for the account with `label`.
+  pub fn get_or_create_account(&mut self, label: &str) -> Result<Keys> {
+    self.keys = match self.get_account(label)? {
+      Some(keys) => keys,
+      None => {
+        let generated = schnorr::generate_keys();
+        self.insert_account(label, &generated)?;
+        Keys::from(&generated)
+      }
+    };
+
+    Ok(self.keys.clone())
+  }
 }
 
 #[cfg(test)]
@@ -186,6 +294,59 @@ mod tests {
     assert_eq!(keys.unwrap().public_key, sut.public_key.1);
   }
 
+  #[test]
+  fn read_all() {
+    let sut = Sut::new("read_all");
+
+    assert!(sut.keys_table.read_all().unwrap().is_empty());
+
+    sut
+      .keys_table
+      .write_to_db(&sut.public_key.0, &sut.public_key.1)
+      .unwrap();
+    sut
+      .keys_table
+      .write_to_db(&sut.private_key.0, &sut.private_key.1)
+      .unwrap();
+
+    assert_eq!(
+      sut.keys_table.read_all().unwrap(),
+      vec![sut.private_key.clone(), sut.public_key.clone()]
+    );
+  }
+
+  #[test]
+  fn accounts() {
+    let mut sut = Sut::new("accounts");
+    let alice = schnorr::generate_keys();
+    let bob = schnorr::generate_keys();
+
+    assert!(sut.keys_table.list_accounts().unwrap().is_empty());
+    assert_eq!(sut.keys_table.get_account("alice").unwrap(), None);
+
+    sut.keys_table.insert_account("bob", &bob).unwrap();
+    sut.keys_table.insert_account("alice", &alice).unwrap();
+
+    assert_eq!(
+      sut.keys_table.list_accounts().unwrap(),
+      vec![String::from("alice"), String::from("bob")]
+    );
+    assert_eq!(
+      sut.keys_table.get_account("alice").unwrap(),
+      Some(Keys::from(&alice))
+    );
+    assert_eq!(
+      sut.keys_table.get_account("bob").unwrap(),
+      Some(Keys::from(&bob))
+    );
+    assert_eq!(
+      sut.keys_table.get_or_create_account("bob").unwrap(),
+      Keys::from(&bob)
+    );
+    // accounts are kept apart from the default identity
+    assert!(sut.keys_table.get_client_keys().unwrap().is_none());
+  }
+
   #[test]
   fn remove_from_db() {
     let sut = Sut::new("remove_from_db");
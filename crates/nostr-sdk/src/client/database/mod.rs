@@ -4,10 +4,14 @@ pub mod subscriptions_table;
 
 type Result<T> = result::Result<T, redb::Error>;
 
-trait ClientDatabase<'a> {
-  type K;
-  type V;
-  fn write_to_db(&self, k: Self::K, v: Self::V) -> Result<()>;
-  fn remove_from_db(&self, k: Self::K) -> Result<()>;
-}
+/// Owned `(key, value)` pairs read from a table.
+pub type Items<K, V> = Vec<(<K as ToOwned>::Owned, <V as ToOwned>::Owned)>;
 
+pub trait ClientDatabase {
+  type K: ?Sized + ToOwned;
+  type V: ?Sized + ToOwned;
+  fn write_to_db(&self, k: &Self::K, v: &Self::V) -> Result<()>;
+  fn remove_from_db(&self, k: &Self::K) -> Result<()>;
+  /// Reads all the `(key, value)` pairs of the table, ordered by key.
+  fn read_all(&self) -> Result<Items<Self::K, Self::V>>;
+}
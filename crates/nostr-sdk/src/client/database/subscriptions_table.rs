@@ -1,16 +1,71 @@
+use log::{error, warn};
 use redb::{Database, ReadableTable, TableDefinition};
-use std::{collections::HashMap, fs};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fs, sync::Arc};
 
 use crate::filter::Filter;
 
-use super::{ClientDatabase, Result};
+use super::{ClientDatabase, Items, Result};
 
 const TABLE_NAME: &str = "subscriptions";
 const SUBSCRIPTIONS_TABLE: TableDefinition<&str, &str> = TableDefinition::new(TABLE_NAME);
 
+/// Version of the format the subscriptions are stored in:
+///   - 0: the JSON array of the filters of the subscription;
+///   - 1: `{"version": 1, "filters": [<filters>]}`.
+///
+const SUBSCRIPTIONS_FORMAT_VERSION: u64 = 1;
+
+/// A subscription, as stored in the table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StoredSubscription {
+  version: u64,
+  filters: Vec<Filter>,
+}
+
+impl StoredSubscription {
+  fn new(filters: Vec<Filter>) -> Self {
+    Self {
+      version: SUBSCRIPTIONS_FORMAT_VERSION,
+      filters,
+    }
+  }
+
+  /// Decodes a stored subscription of any known version.
+  /// Returns `None` when it is not in a format this version understands.
+  ///
+  fn decode(value: &str) -> Option<Self> {
+    if let Ok(stored) = serde_json::from_str::<Self>(value) {
+      return (stored.version <= SUBSCRIPTIONS_FORMAT_VERSION).then_some(stored);
+    }
+
+    let filters = Filter::from_string_array(value.to_string()).ok()?;
+    Some(Self {
+      version: 0,
+      filters,
+    })
+  }
+
+  fn encode(&self) -> String {
+    serde_json::to_string(self).unwrap()
+  }
+
+  /// Gets the version of a subscription stored in a format newer than the
+  /// one this version understands (e.g. by a newer client, before a downgrade).
+  ///
+  fn newer_version(value: &str) -> Option<u64> {
+    serde_json::from_str::<Value>(value)
+      .ok()?
+      .get("version")?
+      .as_u64()
+      .filter(|version| *version > SUBSCRIPTIONS_FORMAT_VERSION)
+  }
+}
+
 #[derive(Debug)]
 pub struct SubscriptionsTable {
-  db: Database,
+  db: Arc<Database>,
 }
 
 impl Default for SubscriptionsTable {
@@ -19,11 +74,11 @@ impl Default for SubscriptionsTable {
   }
 }
 
-impl<'a> ClientDatabase<'a> for SubscriptionsTable {
-  type K = &'a str;
-  type V = &'a str;
+impl ClientDatabase for SubscriptionsTable {
+  type K = str;
+  type V = str;
 
-  fn write_to_db(&self, k: Self::K, v: Self::V) -> Result<()> {
+  fn write_to_db(&self, k: &Self::K, v: &Self::V) -> Result<()> {
     let write_txn = self.db.begin_write()?;
     {
       let mut table = write_txn.open_table(SUBSCRIPTIONS_TABLE)?;
@@ -33,7 +88,7 @@ impl<'a> ClientDatabase<'a> for SubscriptionsTable {
     Ok(())
   }
 
-  fn remove_from_db(&self, k: Self::K) -> Result<()> {
+  fn remove_from_db(&self, k: &Self::K) -> Result<()> {
     let write_txn = self.db.begin_write()?;
     {
       let mut table = write_txn.open_table(SUBSCRIPTIONS_TABLE)?;
@@ -42,6 +97,19 @@ impl<'a> ClientDatabase<'a> for SubscriptionsTable {
     write_txn.commit()?;
     Ok(())
   }
+
+  fn read_all(&self) -> Result<Items<Self::K, Self::V>> {
+    let read_txn = self.db.begin_read()?;
+    let table = read_txn.open_table(SUBSCRIPTIONS_TABLE)?;
+
+    let mut items = vec![];
+    for item in table.iter()? {
+      let (k, v) = item?;
+      items.push((k.value().to_string(), v.value().to_string()));
+    }
+
+    Ok(items)
+  }
 }
 
 impl SubscriptionsTable {
@@ -53,6 +121,13 @@ impl SubscriptionsTable {
     };
     let db = Database::create(format!("db/{table_name}.redb")).unwrap();
 
+    Self::with_database(Arc::new(db))
+  }
+
+  /// Creates the table in a database that is already open
+  /// (e.g. the one of the keys, when both are in the same file).
+  ///
+  pub(crate) fn with_database(db: Arc<Database>) -> Self {
     {
       let write_txn = db.begin_write().unwrap();
       write_txn.open_table(SUBSCRIPTIONS_TABLE).unwrap(); // this basically just creates the table if doesn't exist
@@ -62,25 +137,43 @@ impl SubscriptionsTable {
     Self { db }
   }
 
+  /// Gets all stored subscriptions. The ones stored in an older format
+  /// are migrated to the current one, while the ones that cannot be decoded
+  /// are removed, so the client starts fresh instead of failing to load.
+  /// The ones stored in a newer format are skipped, but kept for the
+  /// version of the client that understands them.
+  ///
   pub fn get_all_subscriptions(&self) -> Result<HashMap<String, Vec<Filter>>> {
     let mut subscriptions: HashMap<String, Vec<Filter>> = HashMap::new();
-    let read_txn = self.db.begin_read()?;
-    let table = read_txn.open_table(SUBSCRIPTIONS_TABLE)?;
 
-    table.iter().unwrap().for_each(|subscription| {
-      let subs = subscription.unwrap();
-      let subs_id = subs.0.value();
-      let subs_req_filters = subs.1.value();
-      let filters_deserialized: Vec<Filter> =
-        Filter::from_string_array(subs_req_filters.to_string()).unwrap();
-      subscriptions.insert(subs_id.to_string(), filters_deserialized);
-    });
+    for (subs_id, value) in self.read_all()? {
+      if let Some(version) = StoredSubscription::newer_version(&value) {
+        warn!("Skipping the subscription {subs_id}, as it is stored in a newer format (version {version})");
+        continue;
+      }
+
+      let Some(stored) = StoredSubscription::decode(&value) else {
+        error!("Removing the subscription {subs_id}, as it could not be decoded: {value}");
+        self.remove_from_db(&subs_id)?;
+        continue;
+      };
+
+      if stored.version < SUBSCRIPTIONS_FORMAT_VERSION {
+        let migrated = StoredSubscription::new(stored.filters);
+        self.write_to_db(&subs_id, &migrated.encode())?;
+        subscriptions.insert(subs_id, migrated.filters);
+        continue;
+      }
+
+      subscriptions.insert(subs_id, stored.filters);
+    }
 
     Ok(subscriptions)
   }
 
-  pub fn add_new_subscription(&self, k: &str, v: &str) {
-    self.write_to_db(k, v).unwrap();
+  pub fn add_new_subscription(&self, k: &str, filters: Vec<Filter>) {
+    let stored = StoredSubscription::new(filters);
+    self.write_to_db(k, &stored.encode()).unwrap();
   }
 
   pub fn remove_subscription(&self, k: &str) {
@@ -152,6 +245,126 @@ mod tests {
     );
   }
 
+  #[test]
+  fn read_all() {
+    let sut = Sut::new("read_all_subscription_table");
+
+    sut
+      .subscriptions_table
+      .write_to_db("b-subs-id", &sut.filter_json)
+      .unwrap();
+    sut
+      .subscriptions_table
+      .write_to_db("a-subs-id", &sut.filter_json)
+      .unwrap();
+
+    assert_eq!(
+      sut.subscriptions_table.read_all().unwrap(),
+      vec![
+        (String::from("a-subs-id"), sut.filter_json.clone()),
+        (String::from("b-subs-id"), sut.filter_json.clone()),
+      ]
+    );
+  }
+
+  #[test]
+  fn add_new_subscription_stores_the_version() {
+    let sut = Sut::new("add_new_subscription_subscription_table");
+
+    sut
+      .subscriptions_table
+      .add_new_subscription(&sut.subscription_id, sut.filters.clone());
+
+    let stored = sut.subscriptions_table.read_all().unwrap();
+    assert_eq!(
+      stored,
+      vec![(
+        sut.subscription_id.clone(),
+        format!(r#"{{"version":1,"filters":{}}}"#, sut.filter_json)
+      )]
+    );
+    assert_eq!(
+      sut.subscriptions_table.get_all_subscriptions().unwrap(),
+      HashMap::from([(sut.subscription_id.clone(), sut.filters.clone())])
+    );
+  }
+
+  #[test]
+  fn get_all_subscriptions_migrates_old_format() {
+    let sut = Sut::new("migrates_old_format_subscription_table");
+    let old_format_filters = r#"[{"authors":["pubkey"],"kinds":[1]}]"#;
+    let expected_filters = vec![Filter {
+      authors: Some(vec![String::from("pubkey")]),
+      kinds: Some(vec![crate::event::kind::EventKind::Text]),
+      ..Default::default()
+    }];
+    sut
+      .subscriptions_table
+      .write_to_db(&sut.subscription_id, old_format_filters)
+      .unwrap();
+
+    let subscriptions = sut.subscriptions_table.get_all_subscriptions().unwrap();
+
+    assert_eq!(
+      subscriptions.get(&sut.subscription_id),
+      Some(&expected_filters)
+    );
+    // the row was rewritten in the current format
+    assert_eq!(
+      sut.subscriptions_table.read_all().unwrap(),
+      vec![(
+        sut.subscription_id.clone(),
+        StoredSubscription::new(expected_filters).encode()
+      )]
+    );
+  }
+
+  #[test]
+  fn get_all_subscriptions_drops_undecodable_rows() {
+    let sut = Sut::new("drops_undecodable_subscription_table");
+    sut
+      .subscriptions_table
+      .write_to_db("garbage", "not json")
+      .unwrap();
+    sut
+      .subscriptions_table
+      .add_new_subscription(&sut.subscription_id, sut.filters.clone());
+
+    let subscriptions = sut.subscriptions_table.get_all_subscriptions().unwrap();
+
+    assert_eq!(
+      subscriptions,
+      HashMap::from([(sut.subscription_id.clone(), sut.filters.clone())])
+    );
+    assert_eq!(sut.subscriptions_table.read_all().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn get_all_subscriptions_skips_but_keeps_rows_of_a_newer_format() {
+    let sut = Sut::new("keeps_newer_format_subscription_table");
+    // the filters may not be decodable by this version either
+    let from_the_future = r#"{"version":99,"filters":"potato"}"#;
+    sut
+      .subscriptions_table
+      .write_to_db("from-the-future", from_the_future)
+      .unwrap();
+    sut
+      .subscriptions_table
+      .add_new_subscription(&sut.subscription_id, sut.filters.clone());
+
+    let subscriptions = sut.subscriptions_table.get_all_subscriptions().unwrap();
+
+    assert_eq!(
+      subscriptions,
+      HashMap::from([(sut.subscription_id.clone(), sut.filters.clone())])
+    );
+    let rows = sut.subscriptions_table.read_all().unwrap();
+    assert!(rows.contains(&(
+      String::from("from-the-future"),
+      String::from(from_the_future)
+    )));
+  }
+
   #[test]
   fn remove_from_db() {
     let sut = Sut::new("remove_from_db_subscription_table");
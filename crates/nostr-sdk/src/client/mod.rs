@@ -1,23 +1,29 @@
+pub mod coalescer;
 pub mod communication_with_relay;
 pub mod database;
 
 use bitcoin_hashes::hex::ToHex;
-use log::debug;
+use futures_util::{stream, Stream, StreamExt};
+use log::{debug, error, warn};
 use std::{
-  collections::HashMap,
+  cmp::Reverse,
+  collections::{BTreeMap, HashMap, HashSet},
+  env, fs,
   sync::Arc,
   time::{Duration, SystemTime, UNIX_EPOCH},
   vec,
 };
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio_tungstenite::tungstenite::protocol::Message;
 
 use uuid::Uuid;
 
 use crate::{
   client::{
+    coalescer::{AddedSubscription, CoalescedUpdate, SubscriptionCoalescer},
     communication_with_relay::{
       close::ClientToRelayCommClose, event::ClientToRelayCommEvent,
       request::ClientToRelayCommRequest,
@@ -32,10 +38,20 @@ use crate::{
     kind::EventKind,
     marker::Marker,
     tag::{Tag, UncheckedRecommendRelayURL},
-    Event,
+    thread::parse_thread,
+    Event, PubKey, Timestamp,
   },
   filter::Filter,
-  relay::pool::RelayPool,
+  nip05::Nip05Verifier,
+  nip11::{http_url, HttpNip11Fetcher, Nip11Fetcher, RelayInformationDocument},
+  nip19::{self, NEvent},
+  nip28::ChannelMetadata,
+  nip65::{parse_relay_tag, relay_tag, RelayListMarker, RELAY_LIST_KIND},
+  relay::{
+    pool::{RelayPool, RelayPoolNotification, RelayStatus},
+    relay_url::{RelayUrl, RelayUrlError},
+  },
+  schnorr::AsymmetricKeys,
 };
 
 #[cfg(not(test))]
@@ -50,46 +66,234 @@ fn get_time_now() -> SystemTime {
   UNIX_EPOCH + Duration::new(SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST, 0)
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// [`Client`] error
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+  /// Error serializing or deserializing JSON data
+  #[error(transparent)]
+  Json(#[from] serde_json::Error),
+  #[error("Invalid data")]
+  InvalidData,
+  /// Error reading or writing the database of the client
+  #[error(transparent)]
+  Database(#[from] redb::Error),
+  /// No relay answered with all of its events (EOSE) to get
+  /// the current contact list from, e.g.: none is connected
+  #[error("The contact list could not be loaded from any relay")]
+  ContactListNotLoaded,
+}
+
+/// Gets the default relays of the client from the `NOSTR_RELAYS` env var
+/// (e.g.: `wss://relay1.com,wss://relay2.com`) or, if it is not set, from the
+/// file at the path of the `NOSTR_RELAYS_FILE` env var (one or more per line).
+///
+fn get_default_relays() -> Vec<String> {
+  default_relays_from(
+    env::var("NOSTR_RELAYS").ok(),
+    env::var("NOSTR_RELAYS_FILE").ok(),
+  )
+}
+
+/// Gets the default relays from the value of the `NOSTR_RELAYS` env var
+/// or, without it, from the file at `relays_file` (see [`get_default_relays`]).
+///
+fn default_relays_from(relays: Option<String>, relays_file: Option<String>) -> Vec<String> {
+  let relays = match relays {
+    Some(relays) => relays,
+    None => relays_file
+      .and_then(|path| {
+        fs::read_to_string(&path)
+          .map_err(|err| warn!("Could not read the relays file {path}: {err}"))
+          .ok()
+      })
+      .unwrap_or_default(),
+  };
+
+  relays
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|relay| !relay.is_empty())
+    .map(String::from)
+    .collect()
+}
+
+/// Kind of the event holding the contact list (NIP-02).
+const CONTACT_LIST_KIND: u64 = 3;
+
+/// How long each relay has to answer each page of [`Client::backfill`].
+const BACKFILL_PAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the [`Client::backfill`] of a relay continues from.
+#[derive(Debug, Clone)]
+struct BackfillCursor {
+  url: String,
+  until: Option<Timestamp>,
+  /// Events created at `until` already received from the relay, which it sends again.
+  received_at_until: u64,
+}
+
+/// How long each relay has to answer the query of the current contact list
+/// before it is changed (see [`Client::follow`]).
+///
+const CONTACT_LIST_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks if the tag is the "p" tag of the contact `pubkey` in a contact list.
+/// Each contact has the format `["p", <pubkey>, <relay-url>, <petname>]`.
+///
+fn is_contact_tag(tag: &Tag, pubkey: &str) -> bool {
+  matches!(tag, Tag::PubKey(pubkeys, _) if pubkeys.first().is_some_and(|contact| contact == pubkey))
+}
+
+/// Result of a NIP-05 verification and when it was done.
+#[derive(Debug, Clone)]
+struct Nip05CacheEntry {
+  verified: bool,
+  verified_at: SystemTime,
+}
+
+/// Metadata (kind 0) of an author.
+/// Missing fields are left empty and the other ones (e.g. `nip05`, `lud16`) are kept in `extra`.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Metadata {
   pub name: String,
   pub about: String,
   pub picture: String,
+  /// Fields other than `name`, `about` and `picture`,
+  /// serialized after them, in alphabetical order.
+  #[serde(flatten)]
+  pub extra: BTreeMap<String, Value>,
 }
 
 impl Metadata {
   pub fn as_str(&self) -> String {
     serde_json::to_string(self).unwrap()
   }
+
+  /// Sets the field `key` to `value`. `name`, `about` and `picture`
+  /// are only set when `value` is a string.
+  ///
+  pub fn set_field(&mut self, key: &str, value: Value) {
+    let field = match key {
+      "name" => &mut self.name,
+      "about" => &mut self.about,
+      "picture" => &mut self.picture,
+      _ => {
+        self.extra.insert(key.to_string(), value);
+        return;
+      }
+    };
+    if let Value::String(value) = value {
+      *field = value;
+    }
+  }
+
+  /// Gets the field `key` of `extra`, if it is a string.
+  fn extra_str(&self, key: &str) -> Option<&str> {
+    self.extra.get(key).and_then(Value::as_str)
+  }
+
+  /// NIP-05 identifier (e.g. `bob@example.com`).
+  pub fn nip05(&self) -> Option<&str> {
+    self.extra_str("nip05")
+  }
+
+  /// Lightning address (LUD-16) to receive zaps.
+  pub fn lud16(&self) -> Option<&str> {
+    self.extra_str("lud16")
+  }
+
+  pub fn banner(&self) -> Option<&str> {
+    self.extra_str("banner")
+  }
+
+  pub fn website(&self) -> Option<&str> {
+    self.extra_str("website")
+  }
+
+  /// Parses the metadata from the content of a `Metadata` (kind 0) event.
+  ///
+  pub fn from_event(event: &Event) -> Result<Self, Error> {
+    if event.kind != EventKind::Metadata {
+      return Err(Error::InvalidData);
+    }
+
+    Ok(serde_json::from_str(&event.content)?)
+  }
 }
 
 #[derive(Debug)]
 pub struct Client {
   keys: Keys,
+  keys_table: KeysTable,
+  /// Label of the account whose keys the client uses, if any.
+  account: Option<String>,
   pub metadata: Metadata,
   subscriptions: Arc<Mutex<HashMap<String, Vec<Filter>>>>,
   subscriptions_db: SubscriptionsTable,
+  /// Whether subscriptions that only differ on `#e` are sent to the relays as one `REQ`.
+  coalesce_subscriptions: bool,
+  coalescer: Arc<Mutex<SubscriptionCoalescer>>,
+  /// Latest contact list (kind 3) of the client.
+  contact_list: Arc<Mutex<Option<Event>>>,
+  /// NIP-05 verifications, by identifier and pubkey.
+  nip05_cache: Arc<Mutex<HashMap<(String, PubKey), Nip05CacheEntry>>>,
+  /// NIP-11 supported NIPs, by relay url.
+  relay_capabilities_cache: Arc<Mutex<HashMap<String, Vec<u16>>>>,
+  /// Fetches the NIP-11 documents for [`Client::relay_capabilities`].
+  nip11_fetcher: HttpNip11Fetcher,
   pool: RelayPool,
 }
 
 impl Default for Client {
   fn default() -> Self {
-    Self::new(None, None)
+    Self::new(None, None, None)
   }
 }
 
 impl Client {
-  pub fn new(keys_table_name: Option<String>, subscriptions_table_name: Option<String>) -> Self {
-    let keys = KeysTable::new(keys_table_name).get_or_create_client_keys().unwrap();
-    let subscriptions_db = SubscriptionsTable::new(subscriptions_table_name);
-    let subscriptions = subscriptions_db.get_all_subscriptions().unwrap();
+  /// Creates a client with the keys of the `account` with that label
+  /// or, if there is none, with the default keys of the client.
+  /// The keys are created the first time they are needed.
+  ///
+  pub fn new(
+    keys_table_name: Option<String>,
+    subscriptions_table_name: Option<String>,
+    account: Option<&str>,
+  ) -> Self {
+    let mut keys_table = KeysTable::new(keys_table_name.clone());
+    let keys = match account {
+      Some(label) => keys_table.get_or_create_account(label).unwrap(),
+      None => keys_table.get_or_create_client_keys().unwrap(),
+    };
+    // both tables can be in the same file, which can only be opened once
+    let subscriptions_db = match subscriptions_table_name {
+      Some(name) if keys_table_name.as_ref() == Some(&name) => {
+        SubscriptionsTable::with_database(keys_table.database())
+      }
+      name => SubscriptionsTable::new(name),
+    };
+    let subscriptions = subscriptions_db
+      .get_all_subscriptions()
+      .unwrap_or_else(|err| {
+        error!("Could not load the stored subscriptions: {err}");
+        HashMap::new()
+      });
 
     let pool = RelayPool::new();
 
     Self {
       keys,
+      keys_table,
+      account: account.map(String::from),
       subscriptions: Arc::new(Mutex::new(subscriptions)),
       subscriptions_db,
+      coalesce_subscriptions: false,
+      coalescer: Arc::new(Mutex::new(SubscriptionCoalescer::new())),
+      contact_list: Arc::new(Mutex::new(None)),
+      nip05_cache: Arc::new(Mutex::new(HashMap::new())),
+      relay_capabilities_cache: Arc::new(Mutex::new(HashMap::new())),
+      nip11_fetcher: HttpNip11Fetcher::new(),
       metadata: Metadata::default(),
       pool,
     }
@@ -110,21 +314,62 @@ impl Client {
     self
   }
 
+  /// Sets any field of the metadata (e.g. `nip05`, `lud16`, `banner`, `website`).
+  /// See [`Metadata::set_field`].
+  ///
+  pub fn set_metadata_field(&mut self, key: &str, value: Value) -> &mut Self {
+    self.metadata.set_field(key, value);
+    self
+  }
+
+  /// Enables (or disables) coalescing the subscriptions that only differ
+  /// on `#e` into shared `REQ`s (see [`SubscriptionCoalescer`]).
+  ///
+  pub fn coalesce_subscriptions(&mut self, enabled: bool) -> &mut Self {
+    self.coalesce_subscriptions = enabled;
+    self
+  }
+
   /// Adds relay to the pool
   /// (and automatically connects to it and sends client metadata).
-  pub async fn add_relay(&mut self, relay: String) {
+  ///
+  /// Fails if `relay` is not a websocket (`ws://` or `wss://`) url.
+  ///
+  pub async fn add_relay(&mut self, relay: String) -> Result<(), RelayUrlError> {
+    let relay = RelayUrl::parse(&relay)?;
     self
       .pool
-      .add_relay(
-        relay.clone(),
-        Message::from(self.get_event_metadata().as_json()),
-      )
+      .add_relay(relay, Message::from(self.get_event_metadata().as_json()))
       .await;
+    Ok(())
+  }
+
+  /// Adds each one of the `relays` to the pool (see [`Client::add_relay`]).
+  /// The invalid urls are skipped with a warning.
+  ///
+  pub async fn add_relays(&mut self, relays: Vec<String>) {
+    for relay in relays {
+      if let Err(err) = self.add_relay(relay.clone()).await {
+        warn!("Skipping the relay {relay}: {err}");
+      }
+    }
+  }
+
+  /// Adds the default relays (from the `NOSTR_RELAYS` env var or from
+  /// the file at `NOSTR_RELAYS_FILE`) to the pool.
+  ///
+  pub async fn with_default_relays(&mut self) -> &mut Self {
+    self.add_relays(get_default_relays()).await;
+    self
   }
 
   /// This function has the same semantics as `crate::relay::pool::RelayPool.remove_relay()`.
+  /// An invalid url is never in the pool, so there is nothing to remove.
+  ///
   pub async fn remove_relay(&mut self, relay: String) {
-    self.pool.remove_relay(relay).await;
+    if let Ok(relay) = RelayUrl::parse(&relay) {
+      self.pool.remove_relay(relay).await;
+    }
   }
 
   fn get_timestamp_in_seconds(&self) -> u64 {
@@ -139,6 +384,18 @@ impl Client {
     self.keys.public_key.to_hex()
   }
 
+  /// Switches the identity of the client to `keys`, used to sign the events from now on.
+  /// They are stored as the keys of its account or, if it has none, as its default keys.
+  ///
+  pub fn set_keys(&mut self, keys: AsymmetricKeys) -> Result<(), Error> {
+    match &self.account {
+      Some(label) => self.keys_table.insert_account(label, &keys)?,
+      None => self.keys_table.set_client_keys(&keys)?,
+    }
+    self.keys = Keys::from(&keys);
+    Ok(())
+  }
+
   fn create_event(&self, kind: EventKind, content: String, tags: Option<Vec<Tag>>) -> Event {
     let pubkey = self.keys.public_key.to_hex();
     let created_at = self.get_timestamp_in_seconds();
@@ -149,32 +406,69 @@ impl Client {
     event
   }
 
+  /// Creates a reply to `event_referenced`, with its `"e"` tags marked as in NIP-10:
+  ///   - when replying to a root event, the referenced event is the `root`;
+  ///   - when replying to a reply, the root of the thread is kept as `root`
+  ///     and the referenced event is the `reply`.
+  ///
+  /// The `"p"` tag has the author of the referenced event followed by
+  /// everyone already in its `"p"` tags.
+  ///
   pub fn create_reply_to_event(
     &self,
     event_referenced: Event,
     recommended_relay_url: Option<UncheckedRecommendRelayURL>,
-    marker: Marker,
     content: String,
   ) -> ClientToRelayCommEvent {
-    let event_id_referenced = EventId(event_referenced.id);
     let recommended_relay = recommended_relay_url.unwrap_or(UncheckedRecommendRelayURL::default());
+    let thread = parse_thread(&event_referenced);
+    let event_id_referenced = EventId(event_referenced.id);
 
     // e tags
-    let e_tag = Tag::Event(event_id_referenced, Some(recommended_relay), Some(marker));
+    let mut tags = match thread.root {
+      Some(root) => vec![
+        Tag::Event(root, Some(recommended_relay.clone()), Some(Marker::Root)),
+        Tag::Event(
+          event_id_referenced,
+          Some(recommended_relay),
+          Some(Marker::Reply),
+        ),
+      ],
+      None => vec![Tag::Event(
+        event_id_referenced,
+        Some(recommended_relay),
+        Some(Marker::Root),
+      )],
+    };
 
     // whenever replying to an event, the p tag should have at least the pubkey of the creator of the event
     let mut pubkeys_from_event_referenced: Vec<String> = vec![event_referenced.pubkey];
     for tag in event_referenced.tags {
       if let Tag::PubKey(event_pubkey_tag_pubkey, _) = tag {
-        if !event_pubkey_tag_pubkey.is_empty() {
-          pubkeys_from_event_referenced.extend_from_slice(&event_pubkey_tag_pubkey);
+        for pubkey in event_pubkey_tag_pubkey {
+          if !pubkeys_from_event_referenced.contains(&pubkey) {
+            pubkeys_from_event_referenced.push(pubkey);
+          }
         }
       }
     }
 
-    let p_tag = Tag::PubKey(pubkeys_from_event_referenced, None);
+    tags.push(Tag::PubKey(pubkeys_from_event_referenced, None));
+
+    ClientToRelayCommEvent {
+      event: self.create_event(EventKind::Text, content, Some(tags)),
+      ..Default::default()
+    }
+  }
 
-    let tags = vec![e_tag, p_tag];
+  /// Creates a text note mentioning (quoting) `event`, with an `"e"` tag
+  /// marked as `mention` (NIP-10) and a `"p"` tag with its author.
+  ///
+  pub fn mention_event(&self, event: &Event, content: String) -> ClientToRelayCommEvent {
+    let tags = vec![
+      Tag::Event(EventId(event.id.clone()), None, Some(Marker::Mention)),
+      Tag::PubKey(vec![event.pubkey.clone()], None),
+    ];
 
     ClientToRelayCommEvent {
       event: self.create_event(EventKind::Text, content, Some(tags)),
@@ -182,6 +476,41 @@ impl Client {
     }
   }
 
+  /// Creates an event with an `["expiration", <expires_at>]` tag (NIP-40)
+  /// appended to `tags`, after which the relays stop serving it.
+  ///
+  pub fn create_event_with_expiration(
+    &self,
+    kind: EventKind,
+    content: String,
+    mut tags: Vec<Tag>,
+    expires_at: Timestamp,
+  ) -> ClientToRelayCommEvent {
+    tags.push(Tag::expiration(expires_at));
+
+    ClientToRelayCommEvent {
+      event: self.create_event(kind, content, Some(tags)),
+      ..Default::default()
+    }
+  }
+
+  /// Publishes to the relays an event that expires (NIP-40) at `expires_at`.
+  pub async fn publish_with_expiration(
+    &self,
+    kind: EventKind,
+    content: String,
+    tags: Vec<Tag>,
+    expires_at: Timestamp,
+  ) {
+    self
+      .broadcast_messages(
+        self
+          .create_event_with_expiration(kind, content, tags, expires_at)
+          .as_json(),
+      )
+      .await;
+  }
+
   pub fn create_text_note_event(&self, note: String) -> ClientToRelayCommEvent {
     ClientToRelayCommEvent {
       event: self.create_event(EventKind::Text, note, None),
@@ -189,6 +518,260 @@ impl Client {
     }
   }
 
+  /// Creates a signed event from a JSON object with the shape
+  /// `{"kind": <number>, "content": <string>, "tags": [...], "created_at": <number>}`,
+  /// where `tags` and `created_at` are optional.
+  /// The pubkey is the one of the client and, if `created_at` is not
+  /// informed, the current timestamp is used.
+  ///
+  pub fn sign_json(&self, json: Value) -> Result<Event, Error> {
+    let object = json.as_object().ok_or(Error::InvalidData)?;
+
+    let kind = object
+      .get("kind")
+      .and_then(|kind| kind.as_u64())
+      .ok_or(Error::InvalidData)?;
+    let content = object
+      .get("content")
+      .and_then(|content| content.as_str())
+      .ok_or(Error::InvalidData)?;
+    let tags: Vec<Tag> = match object.get("tags") {
+      Some(tags) => serde_json::from_value(tags.clone())?,
+      None => vec![],
+    };
+    let created_at = match object.get("created_at") {
+      Some(created_at) => created_at.as_u64().ok_or(Error::InvalidData)?,
+      None => self.get_timestamp_in_seconds(),
+    };
+
+    let mut event = Event::new_without_signature(
+      self.get_hex_public_key(),
+      created_at,
+      EventKind::from(kind),
+      tags,
+      content.to_string(),
+    );
+    event.sign_event(self.keys.private_key.clone());
+    Ok(event)
+  }
+
+  /// Creates a reaction (NIP-25) to `event_reacted`. The `reaction` is the content
+  /// of the event: `+` (like), `-` (dislike) or an emoji.
+  ///
+  pub fn create_reaction_event(
+    &self,
+    event_reacted: &Event,
+    reaction: &str,
+  ) -> ClientToRelayCommEvent {
+    let tags = vec![
+      Tag::Event(EventId(event_reacted.id.clone()), None, None),
+      Tag::PubKey(vec![event_reacted.pubkey.clone()], None),
+    ];
+
+    ClientToRelayCommEvent {
+      event: self.create_event(EventKind::Reaction, reaction.to_string(), Some(tags)),
+      ..Default::default()
+    }
+  }
+
+  /// Reacts (NIP-25) to `event` and publishes the reaction to the relays.
+  pub async fn react_to_event(&self, event: &Event, reaction: &str) {
+    self
+      .broadcast_messages(self.create_reaction_event(event, reaction).as_json())
+      .await;
+  }
+
+  /// Creates a deletion request (NIP-09) for the events `ids`, with an `e` tag
+  /// for each one of them. The `reason`, if any, is the content of the event.
+  ///
+  pub fn create_deletion_event(
+    &self,
+    ids: Vec<EventId>,
+    reason: Option<String>,
+  ) -> ClientToRelayCommEvent {
+    let tags = ids
+      .into_iter()
+      .map(|id| Tag::Event(id, None, None))
+      .collect();
+
+    ClientToRelayCommEvent {
+      event: self.create_event(EventKind::Deletion, reason.unwrap_or_default(), Some(tags)),
+      ..Default::default()
+    }
+  }
+
+  /// Requests (NIP-09) the deletion of the events `ids` to the relays.
+  pub async fn delete_events(&self, ids: Vec<EventId>, reason: Option<String>) {
+    self
+      .broadcast_messages(self.create_deletion_event(ids, reason).as_json())
+      .await;
+  }
+
+  /// Requests (NIP-09) the deletion of `event` to the relays.
+  pub async fn request_deletion_of(&self, event: &Event) {
+    self
+      .delete_events(vec![EventId(event.id.clone())], None)
+      .await;
+  }
+
+  /// Gets the id of the event reacted to and the reaction of a reaction event (NIP-25).
+  /// The event reacted to is the one of the last `e` tag and an empty content is a `+`.
+  ///
+  /// Returns `None` if `event` is not a reaction.
+  ///
+  pub fn parse_reaction(event: &Event) -> Option<(EventId, String)> {
+    if event.kind != EventKind::Reaction {
+      return None;
+    }
+
+    let event_id = event.tags.iter().rev().find_map(|tag| match tag {
+      Tag::Event(event_id, _, _) => Some(event_id.clone()),
+      _ => None,
+    })?;
+    let reaction = if event.content.is_empty() {
+      String::from("+")
+    } else {
+      event.content.clone()
+    };
+
+    Some((event_id, reaction))
+  }
+
+  /// Creates a public chat channel (NIP-28, kind 40) with `metadata`.
+  /// The id of the event is the id of the channel.
+  ///
+  pub fn create_channel_event(&self, metadata: &ChannelMetadata) -> ClientToRelayCommEvent {
+    ClientToRelayCommEvent {
+      event: self.create_event(EventKind::ChannelCreation, metadata.as_str(), None),
+      ..Default::default()
+    }
+  }
+
+  /// Creates a public chat channel (NIP-28) and publishes it to the relays.
+  ///
+  /// Returns the id of the channel.
+  ///
+  pub async fn create_channel(&self, metadata: ChannelMetadata) -> EventId {
+    let channel = self.create_channel_event(&metadata);
+    self.broadcast_messages(channel.as_json()).await;
+    EventId(channel.event.id)
+  }
+
+  /// Creates an update (NIP-28, kind 41) of the metadata of the channel `channel_id`.
+  pub fn create_channel_metadata_event(
+    &self,
+    channel_id: &EventId,
+    metadata: &ChannelMetadata,
+  ) -> ClientToRelayCommEvent {
+    let tags = vec![Tag::Event(
+      channel_id.clone(),
+      Some(UncheckedRecommendRelayURL::default()),
+      Some(Marker::Root),
+    )];
+
+    ClientToRelayCommEvent {
+      event: self.create_event(EventKind::ChannelMetadata, metadata.as_str(), Some(tags)),
+      ..Default::default()
+    }
+  }
+
+  /// Updates the metadata of the channel `channel_id` (NIP-28) on the relays.
+  /// Clients only take into account updates made by the creator of the channel.
+  ///
+  pub async fn set_channel_metadata(&self, channel_id: EventId, metadata: ChannelMetadata) {
+    self
+      .broadcast_messages(
+        self
+          .create_channel_metadata_event(&channel_id, &metadata)
+          .as_json(),
+      )
+      .await;
+  }
+
+  /// Creates a message (NIP-28, kind 42) to the channel `channel_id`.
+  /// The channel is the `root` of the message and, when replying
+  /// to another message, it is the `reply`.
+  ///
+  pub fn create_channel_message_event(
+    &self,
+    channel_id: &EventId,
+    content: String,
+    reply_to: Option<EventId>,
+  ) -> ClientToRelayCommEvent {
+    let mut tags = vec![Tag::Event(
+      channel_id.clone(),
+      Some(UncheckedRecommendRelayURL::default()),
+      Some(Marker::Root),
+    )];
+    if let Some(reply_to) = reply_to {
+      tags.push(Tag::Event(
+        reply_to,
+        Some(UncheckedRecommendRelayURL::default()),
+        Some(Marker::Reply),
+      ));
+    }
+
+    ClientToRelayCommEvent {
+      event: self.create_event(EventKind::ChannelMessage, content, Some(tags)),
+      ..Default::default()
+    }
+  }
+
+  /// Sends a message to the channel `channel_id` (NIP-28), optionally
+  /// replying to the message `reply_to`.
+  ///
+  pub async fn send_channel_message(
+    &self,
+    channel_id: EventId,
+    content: String,
+    reply_to: Option<EventId>,
+  ) {
+    self
+      .broadcast_messages(
+        self
+          .create_channel_message_event(&channel_id, content, reply_to)
+          .as_json(),
+      )
+      .await;
+  }
+
+  /// Creates the relay list (NIP-65, kind 10002) of the client:
+  /// the relays it reads from and writes to.
+  ///
+  pub fn create_relay_list_event(
+    &self,
+    relays: &[(UncheckedRecommendRelayURL, RelayListMarker)],
+  ) -> ClientToRelayCommEvent {
+    let tags = relays
+      .iter()
+      .map(|(url, marker)| relay_tag(url, *marker))
+      .collect();
+
+    ClientToRelayCommEvent {
+      event: self.create_event(RELAY_LIST_KIND, String::new(), Some(tags)),
+      ..Default::default()
+    }
+  }
+
+  /// Publishes the relay list (NIP-65) of the client, replacing the previous one.
+  pub async fn set_relay_list(&self, relays: Vec<(UncheckedRecommendRelayURL, RelayListMarker)>) {
+    self
+      .broadcast_messages(self.create_relay_list_event(&relays).as_json())
+      .await;
+  }
+
+  /// Gets the relays of a relay list event (NIP-65), in the order of its `"r"` tags.
+  ///
+  /// Returns an empty list if `event` is not a relay list.
+  ///
+  pub fn parse_relay_list(event: &Event) -> Vec<(UncheckedRecommendRelayURL, RelayListMarker)> {
+    if event.kind != RELAY_LIST_KIND {
+      return vec![];
+    }
+
+    event.tags.iter().filter_map(parse_relay_tag).collect()
+  }
+
   pub fn get_event_metadata(&self) -> ClientToRelayCommEvent {
     ClientToRelayCommEvent {
       event: self.create_event(EventKind::Metadata, self.metadata.as_str(), None),
@@ -206,36 +789,65 @@ impl Client {
     }
   }
 
-  pub async fn subscribe(&self, filters: Vec<Filter>) {
+  /// Subscribes to the events matching `filters` on all relays, keeping
+  /// the subscription in memory and in the database.
+  ///
+  /// Returns the (generated) subscription id, e.g.: to [`Client::unsubscribe`] later.
+  ///
+  pub async fn subscribe(&self, filters: Vec<Filter>) -> String {
     let filter_subscription = self.get_filter_subscription_request(filters.clone());
 
     debug!("SUBSCRIBING to {:?}", filter_subscription);
 
+    let added = match self.coalesce_subscriptions {
+      true => self
+        .coalescer
+        .lock()
+        .await
+        .add(&filter_subscription.subscription_id, &filters),
+      false => AddedSubscription::NotCoalesced(vec![]),
+    };
+
     // Broadcast REQ subscription to all relays in the pool
-    self.broadcast_messages(filter_subscription.as_json()).await;
+    let (updates, coalesced) = match added {
+      AddedSubscription::Coalesced(updates) => (updates, true),
+      AddedSubscription::NotCoalesced(updates) => (updates, false),
+    };
+    for update in updates {
+      self.broadcast_messages(update.as_json()).await;
+    }
+    if !coalesced {
+      self.broadcast_messages(filter_subscription.as_json()).await;
+    }
 
     // save to db
-    let filters_string = serde_json::to_string(&filters).unwrap();
     self
       .subscriptions_db
-      .add_new_subscription(&filter_subscription.subscription_id, &filters_string);
+      .add_new_subscription(&filter_subscription.subscription_id, filters.clone());
 
     // save to memory
     self
       .subscriptions_mut()
       .await
-      .insert(filter_subscription.subscription_id, filters);
+      .insert(filter_subscription.subscription_id.clone(), filters);
+
+    filter_subscription.subscription_id
   }
 
   pub async fn unsubscribe(&self, subscription_id: &str) {
-    let close_subscription = ClientToRelayCommClose {
-      subscription_id: subscription_id.to_string(),
-      ..Default::default()
-    }
-    .as_json();
+    let close_subscriptions = match self.coalescer.lock().await.remove(subscription_id) {
+      Some(updates) => updates.iter().map(CoalescedUpdate::as_json).collect(),
+      None => vec![ClientToRelayCommClose {
+        subscription_id: subscription_id.to_string(),
+        ..Default::default()
+      }
+      .as_json()],
+    };
 
     // Broadcast CLOSE subscription to all relays in the pool
-    self.broadcast_messages(close_subscription).await;
+    for close_subscription in close_subscriptions {
+      self.broadcast_messages(close_subscription).await;
+    }
 
     // remove from db
     self.subscriptions_db.remove_subscription(subscription_id);
@@ -244,17 +856,71 @@ impl Client {
     self.subscriptions_mut().await.remove(subscription_id);
   }
 
-  pub async fn subscribe_to_all_stored_requests(&self) {
+  /// Closes all the active subscriptions, e.g.: before shutting down.
+  /// Removes them from memory and from the database.
+  ///
+  pub async fn unsubscribe_all(&self) {
+    let mut subscription_ids: Vec<String> = self.subscriptions().await.into_keys().collect();
+    subscription_ids.sort();
+
+    for subscription_id in subscription_ids.iter() {
+      self.unsubscribe(subscription_id).await;
+    }
+  }
+
+  /// Gets the REQs of all stored subscriptions, ordered by subscription id.
+  /// When coalescing, the combined REQs come after the ones that could not be coalesced.
+  ///
+  async fn stored_requests(&self) -> Vec<String> {
     let subscriptions = self.subscriptions().await;
+    let mut subscriptions: Vec<(&String, &Vec<Filter>)> = subscriptions.iter().collect();
+    subscriptions.sort_by_key(|(subs_id, _)| *subs_id);
+
+    let mut coalescer = self.coalescer.lock().await;
+    let mut requests: Vec<String> = subscriptions
+      .into_iter()
+      .filter(|(subs_id, filters)| {
+        // subscriptions loaded from the database are coalesced on the first (re)connection
+        !self.coalesce_subscriptions
+          || (!coalescer.contains(subs_id)
+            && !matches!(
+              coalescer.add(subs_id, filters),
+              AddedSubscription::Coalesced(_)
+            ))
+      })
+      .map(|(subs_id, filters)| {
+        ClientToRelayCommRequest {
+          filters: filters.clone(),
+          subscription_id: subs_id.clone(),
+          ..Default::default()
+        }
+        .as_json()
+      })
+      .collect();
+
+    requests.extend(
+      coalescer
+        .requests()
+        .into_iter()
+        .map(|request| request.as_json()),
+    );
 
-    for (subs_id, filters) in subscriptions.iter() {
-      let filter_subscription = ClientToRelayCommRequest {
-        filters: filters.clone(),
-        subscription_id: subs_id.clone(),
-        ..Default::default()
-      }
-      .as_json();
+    requests
+  }
+
+  /// Gets the app subscriptions an event received for `relay_subscription_id`
+  /// belongs to (see [`SubscriptionCoalescer::demux`]).
+  ///
+  pub async fn demux_event(&self, relay_subscription_id: &str, event: &Event) -> Vec<String> {
+    self
+      .coalescer
+      .lock()
+      .await
+      .demux(relay_subscription_id, event)
+  }
 
+  pub async fn subscribe_to_all_stored_requests(&self) {
+    for filter_subscription in self.stored_requests().await {
       // Broadcast subscription to all relays in the pool
       self.broadcast_messages(filter_subscription).await;
     }
@@ -269,73 +935,859 @@ impl Client {
     self.subscribe(vec![filter]).await;
   }
 
-  pub async fn follow_myself(&self) {
-    let pubkey = self.keys.public_key.to_hex();
-    let filter = Filter {
-      authors: Some(vec![pubkey]),
-      ..Default::default()
-    };
+  /// Subscribes to the events with the `hashtag` (`["t", <hashtag>]` tag, NIP-12).
+  ///
+  pub async fn subscribe_hashtag(&self, hashtag: &str) {
+    let filter = Filter::new().t_tags(vec![hashtag.to_string()]);
 
     self.subscribe(vec![filter]).await;
   }
 
-  pub async fn subscriptions(&self) -> HashMap<String, Vec<Filter>> {
-    let subscriptions = self.subscriptions.lock().await;
-    subscriptions.clone()
-  }
+  /// Updates the contact list of the client with one fetched from a relay.
+  /// It is only updated if `event` is a contact list of this client
+  /// newer than the current one.
+  ///
+  pub async fn update_contact_list(&self, event: Event) {
+    if event.kind != EventKind::Custom(CONTACT_LIST_KIND)
+      || event.pubkey != self.get_hex_public_key()
+    {
+      return;
+    }
 
-  pub async fn subscriptions_mut(&self) -> MutexGuard<HashMap<String, Vec<Filter>>> {
-    self.subscriptions.lock().await
+    let mut contact_list = self.contact_list.lock().await;
+    let is_newer = match contact_list.as_ref() {
+      Some(current) => event.created_at > current.created_at,
+      None => true,
+    };
+    if is_newer {
+      *contact_list = Some(event);
+    }
   }
 
-  pub async fn send_updated_metadata(&self) {
-    self.broadcast_messages(self.get_event_metadata().as_json()).await
+  /// Gets the pubkeys of the contacts in the current contact list.
+  pub async fn contacts(&self) -> Vec<PubKey> {
+    let contact_list = self.contact_list.lock().await;
+    let tags = contact_list
+      .as_ref()
+      .map(|event| event.tags.clone())
+      .unwrap_or_default();
+
+    tags
+      .into_iter()
+      .filter_map(|tag| match tag {
+        Tag::PubKey(pubkeys, _) => pubkeys.first().cloned(),
+        _ => None,
+      })
+      .collect()
   }
 
-  pub async fn broadcast_messages(&self, to_publish: String) {
-    self
+  /// Gets the current contact list from the connected relays, unless it is already
+  /// known, so changing it does not replace the one published by another client.
+  /// Unless a relay answered with all of its events (EOSE), it cannot be known,
+  /// so it fails instead.
+  ///
+  async fn load_contact_list(&self, contact_list: &mut Option<Event>) -> Result<(), Error> {
+    if contact_list.is_some() {
+      return Ok(());
+    }
+
+    let is_any_relay_connected = self
+      .relay_status()
+      .await
+      .values()
+      .any(|status| status.connected);
+    if !is_any_relay_connected {
+      return Err(Error::ContactListNotLoaded);
+    }
+
+    let filter = Filter::new()
+      .kinds(vec![EventKind::Custom(CONTACT_LIST_KIND)])
+      .authors(vec![self.get_hex_public_key()])
+      .limit(1);
+    let results = self
       .pool
-      .broadcast_messages(Message::from(to_publish))
+      .query(vec![filter], CONTACT_LIST_QUERY_TIMEOUT)
       .await;
-  }
+    if !results.iter().any(|result| result.reached_eose) {
+      return Err(Error::ContactListNotLoaded);
+    }
 
-  pub async fn close_connection(&self, relay_url: String) {
-    self.pool.disconnect_relay(relay_url).await;
+    *contact_list = results
+      .into_iter()
+      .flat_map(|result| result.events)
+      .filter(|event| event.pubkey == self.get_hex_public_key())
+      .max_by_key(|event| event.created_at);
+    Ok(())
   }
 
-  pub async fn connect(&self) {
+  /// Publishes a new contact list with `tags` and keeps it as the current one.
+  async fn publish_contact_list(&self, contact_list: &mut Option<Event>, tags: Vec<Tag>) {
+    let event = self.create_event(
+      EventKind::Custom(CONTACT_LIST_KIND),
+      String::new(),
+      Some(tags),
+    );
+
     self
-      .pool
-      .connect(Message::from(self.get_event_metadata().as_json()))
+      .broadcast_messages(
+        ClientToRelayCommEvent {
+          event: event.clone(),
+          ..Default::default()
+        }
+        .as_json(),
+      )
       .await;
-  }
 
-  pub async fn get_notifications(&self) {
-    self.pool.notifications().await;
+    *contact_list = Some(event);
   }
-}
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+  /// Follows an author by adding it to the contact list (kind 3)
+  /// and re-publishing it. The other contacts are kept untouched.
+  ///
+  /// If the contact list is not known yet, it is first fetched from the relays
+  /// (see [`Client::update_contact_list`]). Fails if there is none connected.
+  ///
+  pub async fn follow(&self, pubkey: &str) -> Result<(), Error> {
+    let mut contact_list = self.contact_list.lock().await;
+    self.load_contact_list(&mut contact_list).await?;
+    let mut tags = contact_list
+      .as_ref()
+      .map(|event| event.tags.clone())
+      .unwrap_or_default();
+
+    if tags.iter().any(|tag| is_contact_tag(tag, pubkey)) {
+      return Ok(());
+    }
+    tags.push(Tag::PubKey(vec![pubkey.to_string()], None));
 
-  #[cfg(test)]
-  use pretty_assertions::assert_eq;
-  use serde_json::json;
-  use std::fs;
+    self.publish_contact_list(&mut contact_list, tags).await;
+    Ok(())
+  }
 
-  fn remove_temp_db(table_name: &str) {
-    fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  /// Unfollows an author by removing it from the contact list (kind 3)
+  /// and re-publishing it. The other contacts are kept untouched.
+  ///
+  /// The contact list is fetched first, if needed (see [`Client::follow`]).
+  ///
+  pub async fn unfollow(&self, pubkey: &str) -> Result<(), Error> {
+    let mut contact_list = self.contact_list.lock().await;
+    self.load_contact_list(&mut contact_list).await?;
+    let mut tags = contact_list
+      .as_ref()
+      .map(|event| event.tags.clone())
+      .unwrap_or_default();
+
+    let tags_length = tags.len();
+    tags.retain(|tag| !is_contact_tag(tag, pubkey));
+    if tags.len() == tags_length {
+      return Ok(());
+    }
+
+    self.publish_contact_list(&mut contact_list, tags).await;
+    Ok(())
   }
 
-  #[test]
-  fn metadata() {
-    // arrange
-    let name = "Client test";
+  /// Verifies a NIP-05 `identifier` against `pubkey`, caching the result
+  /// for `ttl`. While the cached result is fresh, `verifier` is not called again.
+  /// Failed verifications (e.g. network errors) are not cached.
+  ///
+  pub async fn verify_nip05_cached(
+    &self,
+    verifier: &impl Nip05Verifier,
+    identifier: &str,
+    pubkey: &PubKey,
+    ttl: Duration,
+  ) -> bool {
+    let key = (identifier.to_string(), pubkey.clone());
+    let now = get_time_now();
+
+    if let Some(entry) = self.nip05_cache.lock().await.get(&key) {
+      let is_fresh = now
+        .duration_since(entry.verified_at)
+        .is_ok_and(|elapsed| elapsed < ttl);
+      if is_fresh {
+        return entry.verified;
+      }
+    }
+
+    // the cache is not locked meanwhile, so other verifications do not wait for this one
+    match verifier.verify(identifier, pubkey).await {
+      Ok(verified) => {
+        self.nip05_cache.lock().await.insert(
+          key,
+          Nip05CacheEntry {
+            verified,
+            verified_at: now,
+          },
+        );
+        verified
+      }
+      Err(err) => {
+        debug!("Error verifying NIP-05 identifier {identifier}: {err}");
+        self.nip05_cache.lock().await.remove(&key);
+        false
+      }
+    }
+  }
+
+  /// Gets the NIPs supported by the relay at `url` (e.g. `wss://relay.damus.io`),
+  /// from its NIP-11 information document, fetched from the HTTP(S) version
+  /// of the url. The result is cached, so features like AUTH or COUNT can
+  /// be checked per relay before each use.
+  /// Returns `None` if the document could not be fetched or parsed.
+  ///
+  pub async fn relay_capabilities(&self, url: String) -> Option<Vec<u16>> {
+    self.relay_capabilities_with(url, &self.nip11_fetcher).await
+  }
+
+  /// Same as [`Client::relay_capabilities`], but the document is fetched by `fetcher`.
+  ///
+  pub async fn relay_capabilities_with(
+    &self,
+    url: String,
+    fetcher: &impl Nip11Fetcher,
+  ) -> Option<Vec<u16>> {
+    if let Some(supported_nips) = self.relay_capabilities_cache.lock().await.get(&url) {
+      return Some(supported_nips.clone());
+    }
+
+    // the cache is not locked while fetching, so other relays do not wait for this one
+    let document = match http_url(&url) {
+      Ok(http_url) => fetcher.fetch(&http_url).await,
+      Err(err) => Err(err),
+    }
+    .and_then(|document| RelayInformationDocument::from_json(&document));
+    match document {
+      Ok(document) => {
+        self
+          .relay_capabilities_cache
+          .lock()
+          .await
+          .insert(url, document.supported_nips.clone());
+        Some(document.supported_nips)
+      }
+      Err(err) => {
+        debug!("Error getting the NIP-11 document of {url}: {err}");
+        None
+      }
+    }
+  }
+
+  pub async fn follow_myself(&self) {
+    let pubkey = self.keys.public_key.to_hex();
+    let filter = Filter {
+      authors: Some(vec![pubkey]),
+      ..Default::default()
+    };
+
+    self.subscribe(vec![filter]).await;
+  }
+
+  pub async fn subscriptions(&self) -> HashMap<String, Vec<Filter>> {
+    let subscriptions = self.subscriptions.lock().await;
+    subscriptions.clone()
+  }
+
+  pub async fn subscriptions_mut(&self) -> MutexGuard<HashMap<String, Vec<Filter>>> {
+    self.subscriptions.lock().await
+  }
+
+  /// Sends the current metadata to the connected relays. The others
+  /// get it once they (re)connect, as it is sent on every connection.
+  ///
+  pub async fn send_updated_metadata(&self) {
+    self
+      .pool
+      .update_metadata(Message::from(self.get_event_metadata().as_json()))
+      .await;
+  }
+
+  /// Replaces the metadata of the client and sends it to the relays
+  /// (see [`Client::send_updated_metadata`]).
+  ///
+  pub async fn set_metadata_and_broadcast(&mut self, metadata: Metadata) {
+    self.metadata = metadata;
+    self.send_updated_metadata().await;
+  }
+
+  pub async fn broadcast_messages(&self, to_publish: String) {
+    self
+      .pool
+      .broadcast_messages(Message::from(to_publish))
+      .await;
+  }
+
+  pub async fn close_connection(&self, relay_url: String) {
+    if let Ok(relay_url) = RelayUrl::parse(&relay_url) {
+      self.pool.disconnect_relay(relay_url).await;
+    }
+  }
+
+  /// Connects again to a relay disconnected with [`Client::close_connection`],
+  /// keeping the subscriptions: it receives the metadata and then the
+  /// REQs of all stored subscriptions.
+  ///
+  pub async fn reconnect_relay(&self, relay_url: String) {
+    let Ok(relay_url) = RelayUrl::parse(&relay_url) else {
+      return;
+    };
+    let requests = self
+      .stored_requests()
+      .await
+      .into_iter()
+      .map(Message::from)
+      .collect();
+
+    self.pool.reconnect_relay(relay_url, requests).await;
+  }
+
+  /// Gets, by url, whether each relay is connected and how many
+  /// messages are waiting to be sent to it.
+  ///
+  pub async fn relay_status(&self) -> HashMap<String, RelayStatus> {
+    self.pool.status().await
+  }
+
+  /// Disconnects from every relay, removing them from the pool,
+  /// and stops the notifications.
+  ///
+  pub async fn disconnect_all(&self) {
+    self.pool.shutdown().await;
+  }
+
+  /// Connects to the relays of the pool that are not connected yet.
+  /// Each one receives the metadata, then the REQs of all stored subscriptions
+  /// and only then the messages queued while it was not connected.
+  ///
+  pub async fn connect(&self) {
+    let requests = self
+      .stored_requests()
+      .await
+      .into_iter()
+      .map(Message::from)
+      .collect();
+
+    self.pool.connect(requests).await;
+  }
+
+  /// Gets the `nostr:nevent1...` URI (NIP-19/NIP-21) to share `event`,
+  /// with its id, author, kind and the relays where it can be found.
+  ///
+  /// Fails if the id or the author of `event` are not hex or if
+  /// a relay hint is too long to be encoded.
+  ///
+  pub fn share_link(
+    &self,
+    event: &Event,
+    relay_hints: Vec<String>,
+  ) -> Result<String, nip19::Error> {
+    NEvent {
+      event_id: event.id.clone(),
+      relays: relay_hints,
+      author: Some(event.pubkey.clone()),
+      kind: Some(event.kind),
+    }
+    .to_uri()
+  }
+
+  /// Checks which of the connected relays have the event with `id`,
+  /// e.g. to show that an event was "published to N/M relays".
+  ///
+  /// Each relay gets a one-shot REQ for the event and, if it does not
+  /// answer it within `timeout`, is considered as not having it.
+  ///
+  pub async fn event_presence(&self, id: String, timeout: Duration) -> HashMap<String, bool> {
+    self.pool.event_presence(&id, timeout).await
+  }
+
+  /// Gets the metadata (kind 0) of the `pubkeys`, e.g. of the contacts,
+  /// by pubkey. Each connected relay gets a one-shot REQ for it and has
+  /// up to `timeout` to answer it.
+  ///
+  /// Only the newest metadata event of each author is used. The pubkeys
+  /// without one (or with one that cannot be parsed) are left out.
+  ///
+  pub async fn get_contacts_metadata(
+    &self,
+    pubkeys: Vec<String>,
+    timeout: Duration,
+  ) -> HashMap<String, Metadata> {
+    let filter = Filter::new()
+      .kinds(vec![EventKind::Metadata])
+      .authors(pubkeys);
+
+    let mut newest: HashMap<String, Event> = HashMap::new();
+    let results = self.pool.query(vec![filter], timeout).await;
+    for event in results.into_iter().flat_map(|result| result.events) {
+      match newest.get(&event.pubkey) {
+        Some(stored) if stored.created_at >= event.created_at => {}
+        _ => {
+          newest.insert(event.pubkey.clone(), event);
+        }
+      }
+    }
+
+    newest
+      .into_iter()
+      .filter_map(|(pubkey, event)| Some((pubkey, Metadata::from_event(&event).ok()?)))
+      .collect()
+  }
+
+  /// Pages through the events that match `base_filter` stored by the connected
+  /// relays, newest first within each page. Each page is a one-shot REQ to each
+  /// relay (see [`RelayPool::query_relay`]) for up to `page_size` events older than
+  /// the oldest one that relay sent before, until it sends fewer than asked for.
+  ///
+  /// The events created at the same second as the oldest one may not have been
+  /// all sent yet, so they are asked again (with a `limit` increased by the ones
+  /// already received). The events sent by more than one relay are only returned once.
+  ///
+  /// The `limit` of `base_filter` is replaced by `page_size`.
+  ///
+  pub fn backfill(&self, base_filter: Filter, page_size: u64) -> impl Stream<Item = Event> + '_ {
+    // the cursors of the relays not fully paged yet, or `None` before the first page
+    let first_cursors: Option<Vec<BackfillCursor>> = None;
+
+    stream::unfold(
+      (first_cursors, HashSet::<String>::new()),
+      move |(cursors, mut ids)| {
+        let base_filter = base_filter.clone();
+        async move {
+          let cursors = match cursors {
+            Some(cursors) => cursors,
+            None => self
+              .relay_status()
+              .await
+              .into_iter()
+              .filter(|(_, status)| status.connected)
+              .map(|(url, _)| BackfillCursor {
+                url,
+                until: base_filter.until,
+                received_at_until: 0,
+              })
+              .collect(),
+          };
+          if cursors.is_empty() {
+            return None;
+          }
+
+          let queries = cursors.into_iter().map(|cursor| {
+            let limit = page_size + cursor.received_at_until;
+            let filter = Filter {
+              until: cursor.until,
+              limit: Some(limit),
+              ..base_filter.clone()
+            };
+            async move {
+              let result = self
+                .pool
+                .query_relay(&cursor.url, vec![filter], BACKFILL_PAGE_TIMEOUT)
+                .await;
+              (cursor.url, limit, result.events)
+            }
+          });
+
+          let mut page = vec![];
+          let mut next_cursors = vec![];
+          for (url, limit, events) in futures_util::future::join_all(queries).await {
+            // the last page of the relay is the one with fewer events than asked for,
+            // including the ones already received from it or from another relay
+            let oldest = events.iter().map(|event| event.created_at).min();
+            if let Some(oldest) = oldest.filter(|_| events.len() as u64 >= limit) {
+              next_cursors.push(BackfillCursor {
+                url,
+                until: Some(oldest),
+                received_at_until: events
+                  .iter()
+                  .filter(|event| event.created_at == oldest)
+                  .count() as u64,
+              });
+            }
+            page.extend(
+              events
+                .into_iter()
+                .filter(|event| ids.insert(event.id.clone())),
+            );
+          }
+          page.sort_by_key(|event| Reverse(event.created_at));
+
+          Some((stream::iter(page), (Some(next_cursors), ids)))
+        }
+      },
+    )
+    .flatten()
+  }
+
+  pub async fn get_notifications(&self) {
+    self.pool.notifications().await;
+  }
+
+  /// Gets a receiver of the notifications (events, EOSEs and notices from the relays)
+  /// sent from now on. They are only sent after `get_notifications` is called.
+  ///
+  pub fn notifications_receiver(&self) -> broadcast::Receiver<RelayPoolNotification> {
+    self.pool.notifications_receiver()
+  }
+
+  /// Stops processing the messages of the relays (e.g. when the app goes to background)
+  /// without disconnecting from them. Meanwhile, the most recent ones are buffered.
+  ///
+  pub async fn pause_notifications(&self) {
+    self.pool.pause_notifications().await;
+  }
+
+  /// Notifies the messages buffered while paused and resumes the notifications.
+  pub async fn resume_notifications(&self) {
+    self.pool.resume_notifications().await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use futures_util::{SinkExt, StreamExt};
+  use tokio::{net::TcpListener, time};
+
+  use crate::{
+    event::{build_event, thread::Thread},
+    relay::{
+      communication_with_client::{eose::RelayToClientCommEose, event::RelayToClientCommEvent},
+      test_relay::TestRelay,
+    },
+    schnorr::generate_keys,
+  };
+
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+  use serde_json::json;
+  use std::{
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+  };
+
+  fn remove_temp_db(table_name: &str) {
+    fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  /// Starts a relay that answers every REQ with the `stored_events`
+  /// that match it (up to its `limit`, newest first), followed by an EOSE.
+  /// Returns its url.
+  async fn spawn_mock_relay(stored_events: Vec<Event>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let stored_events = stored_events.clone();
+        tokio::spawn(async move {
+          let mut ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+          while let Some(Ok(msg)) = ws_stream.next().await {
+            let Ok(request) = ClientToRelayCommRequest::from_json(msg.to_text().unwrap_or_default())
+            else {
+              continue;
+            };
+            let mut matching: Vec<&Event> = stored_events
+              .iter()
+              .filter(|event| request.filters.iter().any(|filter| filter.matches(event)))
+              .collect();
+            matching.sort_by_key(|event| Reverse(event.created_at));
+            let limit = request.filters.iter().map(|filter| filter.limit).max();
+            if let Some(limit) = limit.flatten() {
+              matching.truncate(limit as usize);
+            }
+            for event in matching {
+              let event_msg =
+                RelayToClientCommEvent::new_event(request.subscription_id.clone(), event.clone());
+              let _ = ws_stream.send(Message::from(event_msg.as_json())).await;
+            }
+            let eose = RelayToClientCommEose::new_eose(request.subscription_id);
+            let _ = ws_stream.send(Message::from(eose.as_json())).await;
+          }
+        });
+      }
+    });
+
+    format!("ws://{addr}/")
+  }
+
+  #[tokio::test]
+  async fn backfill() {
+    let test_relay = TestRelay::start().await.unwrap();
+    let mut client = Client::new(
+      Some("backfill".to_string()),
+      Some("backfill".to_string()),
+      None,
+    );
+    client.add_relay(test_relay.url()).await.unwrap();
+
+    let keys = generate_keys();
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let events: Vec<Event> = (0..5)
+      .map(|i| {
+        let mut event = Event::new_without_signature(
+          keys.public_key.to_string()[2..].to_string(),
+          now - i,
+          EventKind::Text,
+          vec![],
+          format!("event {i}"),
+        );
+        event.sign_event(keys.private_key.secret_bytes().to_vec());
+        event
+      })
+      .collect();
+    for event in events.iter() {
+      let event_message = ClientToRelayCommEvent {
+        event: event.clone(),
+        ..Default::default()
+      };
+      client.broadcast_messages(event_message.as_json()).await;
+    }
+    time::timeout(Duration::from_secs(5), async {
+      while test_relay.events().len() < events.len() {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    let backfilled: Vec<Event> = client
+      .backfill(Filter::new().kinds(vec![EventKind::Text]), 2)
+      .collect()
+      .await;
+
+    // 3 pages (2 + 2 + 1 events), newest first
+    assert_eq!(backfilled, events);
+
+    client.disconnect_all().await;
+    remove_temp_db("backfill");
+  }
+
+  #[tokio::test]
+  async fn backfill_from_several_relays() {
+    let mut client = Client::new(
+      Some("backfill_from_several_relays".to_string()),
+      Some("backfill_from_several_relays".to_string()),
+      None,
+    );
+    let keys = generate_keys();
+    let signed_note = |created_at: Timestamp, content: &str| {
+      let mut event = Event::new_without_signature(
+        keys.public_key.to_string()[2..].to_string(),
+        created_at,
+        EventKind::Text,
+        vec![],
+        String::from(content),
+      );
+      event.sign_event(keys.private_key.secret_bytes().to_vec());
+      event
+    };
+    let shared_note = signed_note(10, "shared");
+    // more events than fit in a page are created at the same second
+    let first_relay_events = vec![
+      shared_note.clone(),
+      signed_note(9, "a"),
+      signed_note(9, "b"),
+      signed_note(8, "c"),
+      signed_note(7, "d"),
+    ];
+    let second_relay_events = vec![shared_note.clone(), signed_note(3, "e")];
+    client
+      .add_relay(spawn_mock_relay(first_relay_events.clone()).await)
+      .await
+      .unwrap();
+    client
+      .add_relay(spawn_mock_relay(second_relay_events.clone()).await)
+      .await
+      .unwrap();
+    time::timeout(Duration::from_secs(5), async {
+      while client
+        .relay_status()
+        .await
+        .values()
+        .any(|status| !status.connected)
+      {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    let mut backfilled: Vec<Event> = client
+      .backfill(Filter::new().kinds(vec![EventKind::Text]), 2)
+      .collect()
+      .await;
+
+    // the oldest event of the second relay does not make the first one skip any,
+    // and the shared one is only returned once
+    let mut expected: Vec<Event> = first_relay_events
+      .into_iter()
+      .chain(second_relay_events.into_iter().skip(1))
+      .collect();
+    backfilled.sort_by_key(|event| event.id.clone());
+    expected.sort_by_key(|event| event.id.clone());
+    assert_eq!(backfilled, expected);
+
+    client.disconnect_all().await;
+    remove_temp_db("backfill_from_several_relays");
+  }
+
+  #[tokio::test]
+  async fn event_presence() {
+    let mut client = Client::new(
+      Some("event_presence".to_string()),
+      Some("event_presence".to_string()),
+      None,
+    );
+    let event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    )
+    .unwrap();
+    let relay_with_event = spawn_mock_relay(vec![event.clone()]).await;
+    let relay_without_event = spawn_mock_relay(vec![]).await;
+
+    client.add_relay(relay_with_event.clone()).await.unwrap();
+    client.add_relay(relay_without_event.clone()).await.unwrap();
+
+    let presence = client
+      .event_presence(event.id.clone(), Duration::from_secs(5))
+      .await;
+
+    assert_eq!(
+      presence,
+      HashMap::from([(relay_with_event, true), (relay_without_event, false)])
+    );
+
+    remove_temp_db("event_presence");
+  }
+
+  #[tokio::test]
+  async fn get_contacts_metadata() {
+    let mut client = Client::new(
+      Some("get_contacts_metadata".to_string()),
+      Some("get_contacts_metadata".to_string()),
+      None,
+    );
+    let alice = generate_keys();
+    let bob = generate_keys();
+    let alice_profile = build_event(
+      &alice,
+      EventKind::Metadata,
+      json!({"name": "alice"}).to_string(),
+      vec![],
+    );
+    let mut old_alice_profile = Event::new_without_signature(
+      alice_profile.pubkey.clone(),
+      alice_profile.created_at - 60,
+      EventKind::Metadata,
+      vec![],
+      json!({"name": "old alice"}).to_string(),
+    );
+    old_alice_profile.sign_event(alice.private_key.secret_bytes().to_vec());
+    let bob_profile = build_event(
+      &bob,
+      EventKind::Metadata,
+      json!({"name": "bob", "about": "Just a potato."}).to_string(),
+      vec![],
+    );
+    let relay = spawn_mock_relay(vec![
+      old_alice_profile,
+      alice_profile.clone(),
+      bob_profile.clone(),
+    ])
+    .await;
+    client.add_relay(relay).await.unwrap();
+
+    let metadata = client
+      .get_contacts_metadata(
+        vec![alice_profile.pubkey.clone(), bob_profile.pubkey.clone()],
+        Duration::from_secs(5),
+      )
+      .await;
+
+    assert_eq!(
+      metadata,
+      HashMap::from([
+        (
+          alice_profile.pubkey.clone(),
+          Metadata::from_event(&alice_profile).unwrap()
+        ),
+        (
+          bob_profile.pubkey.clone(),
+          Metadata::from_event(&bob_profile).unwrap()
+        ),
+      ])
+    );
+    assert_eq!(metadata[&alice_profile.pubkey].name, "alice");
+    assert_eq!(metadata[&bob_profile.pubkey].about, "Just a potato.");
+
+    remove_temp_db("get_contacts_metadata");
+  }
+
+  #[test]
+  fn metadata_from_event() {
+    let content = r#"{"name":"bob","about":"Just a potato.","picture":"https://example.com/bob.png","nip05":"bob@example.com","lud16":"bob@wallet.example.com","display_name":"Bob"}"#;
+    let event = Event {
+      kind: EventKind::Metadata,
+      content: content.to_string(),
+      ..Default::default()
+    };
+
+    assert_eq!(
+      Metadata::from_event(&event).unwrap(),
+      Metadata {
+        name: String::from("bob"),
+        about: String::from("Just a potato."),
+        picture: String::from("https://example.com/bob.png"),
+        extra: BTreeMap::from([
+          (String::from("display_name"), json!("Bob")),
+          (String::from("lud16"), json!("bob@wallet.example.com")),
+          (String::from("nip05"), json!("bob@example.com")),
+        ]),
+      }
+    );
+    assert_eq!(
+      Metadata::from_event(&event).unwrap().nip05(),
+      Some("bob@example.com")
+    );
+
+    let event_without_picture = Event {
+      content: String::from(r#"{"name":"bob"}"#),
+      ..event.clone()
+    };
+    assert_eq!(
+      Metadata::from_event(&event_without_picture).unwrap().picture,
+      String::new()
+    );
+
+    let not_metadata_event = Event {
+      kind: EventKind::Text,
+      ..event.clone()
+    };
+    assert!(Metadata::from_event(&not_metadata_event).is_err());
+
+    let invalid_content_event = Event {
+      content: String::from("not json"),
+      ..event
+    };
+    assert!(Metadata::from_event(&invalid_content_event).is_err());
+  }
+
+  #[test]
+  fn metadata() {
+    // arrange
+    let name = "Client test";
     let about = "Client about";
     let picture = "client.picture.com";
 
-    let mut client = Client::new(Some("metadata".to_string()), Some("metadata".to_string()));
+    let mut client = Client::new(
+      Some("metadata".to_string()),
+      Some("metadata".to_string()),
+      None,
+    );
 
     // act
     client.name(name).about(about).picture(picture);
@@ -353,122 +1805,602 @@ mod tests {
     assert_eq!(client.metadata.about, "anotherpotato".to_string());
     assert_eq!(client.metadata.picture, "picturepotato".to_string());
 
-    remove_temp_db("metadata");
+    remove_temp_db("metadata");
+  }
+
+  #[test]
+  fn set_metadata_field() {
+    let mut client = Client::new(
+      Some("set_metadata_field".to_string()),
+      Some("set_metadata_field".to_string()),
+      None,
+    );
+
+    client
+      .name("bob")
+      .set_metadata_field("lud16", json!("bob@wallet.example.com"))
+      .set_metadata_field("about", json!("Just a potato."));
+
+    assert_eq!(client.metadata.lud16(), Some("bob@wallet.example.com"));
+    assert_eq!(client.metadata.about, String::from("Just a potato."));
+    assert_eq!(
+      client.get_event_metadata().event.content,
+      r#"{"name":"bob","about":"Just a potato.","picture":"","lud16":"bob@wallet.example.com"}"#
+    );
+
+    remove_temp_db("set_metadata_field");
+  }
+
+  #[tokio::test]
+  async fn add_and_remove_relay() {
+    // arrange
+    let relay = "wss://relay1.com".to_string();
+    let mut client = Client::new(
+      Some("add_remove_relay".to_string()),
+      Some("add_remove_relay".to_string()),
+      None,
+    );
+
+    client.add_relay(relay.clone()).await.unwrap();
+    assert_eq!(client.pool.relays().await.len(), 1);
+    assert_eq!(
+      client.add_relay(String::from("http://relay2.com")).await,
+      Err(RelayUrlError::UnsupportedScheme(String::from("http")))
+    );
+    assert_eq!(client.pool.relays().await.len(), 1);
+
+    client.remove_relay(relay).await;
+    assert!(client.pool.relays().await.is_empty());
+
+    remove_temp_db("add_remove_relay");
+  }
+
+  #[tokio::test]
+  async fn relay_status() {
+    let mut client = Client::new(
+      Some("relay_status".to_string()),
+      Some("relay_status".to_string()),
+      None,
+    );
+    assert!(client.relay_status().await.is_empty());
+
+    // nothing listens on it, so it is never connected
+    let relay = String::from("ws://127.0.0.1:1");
+    client.add_relay(relay.clone()).await.unwrap();
+
+    assert_eq!(
+      client.relay_status().await,
+      HashMap::from([(
+        RelayUrl::parse(&relay).unwrap().to_string(),
+        RelayStatus {
+          connected: false,
+          pending_messages: 0,
+        }
+      )])
+    );
+
+    remove_temp_db("relay_status");
+  }
+
+  #[test]
+  fn default_relays_from() {
+    assert_eq!(
+      super::default_relays_from(
+        Some(String::from(
+          "wss://relay1.com, wss://relay2.com,,wss://relay3.com"
+        )),
+        None
+      ),
+      vec!["wss://relay1.com", "wss://relay2.com", "wss://relay3.com"]
+    );
+
+    // without `NOSTR_RELAYS`, they are read from `NOSTR_RELAYS_FILE`
+    let relays_file = env::temp_dir().join("default_relays_from.txt");
+    fs::write(&relays_file, "wss://relay4.com\nwss://relay1.com\n").unwrap();
+    let relays_file_path = Some(relays_file.to_string_lossy().to_string());
+    assert_eq!(
+      super::default_relays_from(None, relays_file_path.clone()),
+      vec!["wss://relay4.com", "wss://relay1.com"]
+    );
+    assert_eq!(
+      super::default_relays_from(Some(String::from("wss://relay1.com")), relays_file_path),
+      vec!["wss://relay1.com"]
+    );
+    fs::remove_file(&relays_file).unwrap();
+
+    // a relays file that cannot be read has no relays
+    assert_eq!(
+      super::default_relays_from(None, Some(relays_file.to_string_lossy().to_string())),
+      Vec::<String>::new()
+    );
+    assert_eq!(super::default_relays_from(None, None), Vec::<String>::new());
+  }
+
+  #[tokio::test]
+  async fn add_relays_skips_the_invalid_ones() {
+    let mut client = Client::new(
+      Some("add_relays_skips_the_invalid_ones".to_string()),
+      Some("add_relays_skips_the_invalid_ones".to_string()),
+      None,
+    );
+
+    client
+      .add_relays(super::default_relays_from(
+        Some(String::from(
+          "wss://relay1.com, not a relay,http://relay2.com,wss://relay3.com",
+        )),
+        None,
+      ))
+      .await;
+
+    let mut relays: Vec<String> = client.pool.relays().await.keys().cloned().collect();
+    relays.sort();
+    assert_eq!(
+      relays,
+      vec![
+        RelayUrl::parse("wss://relay1.com").unwrap().to_string(),
+        RelayUrl::parse("wss://relay3.com").unwrap().to_string(),
+      ]
+    );
+
+    remove_temp_db("add_relays_skips_the_invalid_ones");
+  }
+
+  #[test]
+  fn get_timestamp_in_seconds() {
+    let client = Client::new(
+      Some("timestamp".to_string()),
+      Some("timestamp".to_string()),
+      None,
+    );
+    let timestamp = client.get_timestamp_in_seconds();
+    assert_eq!(timestamp, SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST);
+
+    remove_temp_db("timestamp");
+  }
+
+  #[test]
+  fn create_event() {
+    let client = Client::new(
+      Some("create_event".to_string()),
+      Some("create_event".to_string()),
+      None,
+    );
+    let kind = EventKind::Text;
+    let content = String::from("Content test");
+    let tags = None;
+
+    let event = client.create_event(kind, content.clone(), tags);
+
+    assert_eq!(event.content, content);
+    assert_eq!(event.kind, kind);
+    assert_eq!(event.tags, []);
+    assert_eq!(event.pubkey, client.get_hex_public_key());
+    assert_eq!(
+      event.created_at,
+      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+    );
+
+    remove_temp_db("create_event");
+  }
+
+  #[test]
+  fn create_reply_to_event() {
+    let client = Client::new(
+      Some("create_reply_to_event".to_string()),
+      Some("create_reply_to_event".to_string()),
+      None,
+    );
+    let kind = EventKind::Text;
+    let content = String::from("Content test");
+    let tags = None;
+    let event = client.create_event(kind, content, tags);
+
+    let recommended_relay_url = None;
+    let content_for_reply = String::from("Replying to event");
+    let replyed_event = client.create_reply_to_event(
+      event.clone(),
+      recommended_relay_url,
+      content_for_reply.clone(),
+    );
+
+    let tags_json_string = serde_json::to_string(&replyed_event.event.tags).unwrap();
+
+    let expected_tags = json!([
+      [
+        "e".to_string(),
+        event.id,
+        "".to_string(),
+        "root".to_string()
+      ],
+      ["p".to_string(), client.get_hex_public_key()]
+    ])
+    .to_string();
+
+    assert_eq!(replyed_event.event.content, content_for_reply);
+    assert_eq!(tags_json_string, expected_tags);
+    assert_eq!(
+      event.created_at,
+      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+    );
+
+    remove_temp_db("create_reply_to_event");
+  }
+
+  #[test]
+  fn create_reply_to_reply_keeps_the_root() {
+    let client = Client::new(
+      Some("create_reply_to_reply".to_string()),
+      Some("create_reply_to_reply".to_string()),
+      None,
+    );
+    let other_pubkey =
+      String::from("82341f882b6eabcd2ba7f1ef90aad961cf074af15b9ef44a09f9d2a8fbfbe6a2");
+    let root = client.create_event(EventKind::Text, String::from("Root"), None);
+    let reply = Event {
+      pubkey: other_pubkey.clone(),
+      ..client
+        .create_reply_to_event(root.clone(), None, String::from("Reply"))
+        .event
+    };
+
+    let reply_to_reply = client.create_reply_to_event(
+      reply.clone(),
+      Some(UncheckedRecommendRelayURL(String::from("wss://relay.com"))),
+      String::from("Reply to reply"),
+    );
+
+    let expected_tags = json!([
+      ["e", root.id, "wss://relay.com", "root"],
+      ["e", reply.id, "wss://relay.com", "reply"],
+      ["p", other_pubkey, client.get_hex_public_key()]
+    ])
+    .to_string();
+
+    assert_eq!(
+      serde_json::to_string(&reply_to_reply.event.tags).unwrap(),
+      expected_tags
+    );
+    assert_eq!(
+      parse_thread(&reply_to_reply.event),
+      Thread {
+        root: Some(EventId(root.id)),
+        reply: Some(EventId(reply.id)),
+        mentions: vec![],
+      }
+    );
+
+    remove_temp_db("create_reply_to_reply");
+  }
+
+  #[test]
+  fn create_text_note_event() {
+    let client = Client::new(
+      Some("create_text_note_event".to_string()),
+      Some("create_text_note_event".to_string()),
+      None,
+    );
+    let note = String::from("Test Note");
+
+    let text_note_event = client.create_text_note_event(note.clone());
+
+    assert_eq!(text_note_event.event.content, note);
+    assert_eq!(text_note_event.event.kind, EventKind::Text);
+    assert_eq!(
+      text_note_event.event.created_at,
+      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+    );
+
+    remove_temp_db("create_text_note_event");
+  }
+
+  #[test]
+  fn sign_json() {
+    let client = Client::new(
+      Some("sign_json".to_string()),
+      Some("sign_json".to_string()),
+      None,
+    );
+
+    let event = client
+      .sign_json(json!({"kind": 1, "content": "hi"}))
+      .unwrap();
+
+    assert_eq!(event.kind, EventKind::Text);
+    assert_eq!(event.content, String::from("hi"));
+    assert_eq!(event.tags, vec![]);
+    assert_eq!(event.pubkey, client.get_hex_public_key());
+    assert_eq!(
+      event.created_at,
+      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+    );
+    assert!(event.check_event_id());
+    assert!(event.check_event_signature());
+
+    let event_with_tags = client
+      .sign_json(json!({"kind": 7, "content": "+", "tags": [["e", "potato"]], "created_at": 10}))
+      .unwrap();
+    assert_eq!(
+      event_with_tags.tags,
+      vec![Tag::Event(EventId(String::from("potato")), None, None)]
+    );
+    assert_eq!(event_with_tags.created_at, 10);
+    assert!(event_with_tags.check_event_signature());
+
+    assert!(client.sign_json(json!({"content": "hi"})).is_err());
+    assert!(client.sign_json(json!({"kind": 1})).is_err());
+    assert!(client.sign_json(json!({"kind": 1, "content": 2})).is_err());
+    assert!(client.sign_json(json!(["kind", 1])).is_err());
+
+    remove_temp_db("sign_json");
+  }
+
+  #[test]
+  fn create_reaction_event() {
+    let client = Client::new(
+      Some("create_reaction_event".to_string()),
+      Some("create_reaction_event".to_string()),
+      None,
+    );
+    let note = client.create_text_note_event(String::from("Like me")).event;
+
+    let reaction = client.create_reaction_event(&note, "+").event;
+
+    assert_eq!(reaction.kind, EventKind::Reaction);
+    assert_eq!(reaction.content, String::from("+"));
+    assert_eq!(
+      reaction.tags,
+      vec![
+        Tag::Event(EventId(note.id.clone()), None, None),
+        Tag::PubKey(vec![note.pubkey.clone()], None),
+      ]
+    );
+    assert!(reaction.check_event_signature());
+    assert_eq!(
+      Client::parse_reaction(&reaction),
+      Some((EventId(note.id.clone()), String::from("+")))
+    );
+    assert_eq!(Client::parse_reaction(&note), None);
+
+    remove_temp_db("create_reaction_event");
   }
 
-  #[tokio::test]
-  async fn add_and_remove_relay() {
-    // arrange
-    let relay = "relay1".to_string();
-    let mut client = Client::new(
-      Some("add_remove_relay".to_string()),
-      Some("add_remove_relay".to_string()),
+  #[test]
+  fn create_event_with_expiration() {
+    let client = Client::new(
+      Some("create_event_with_expiration".to_string()),
+      Some("create_event_with_expiration".to_string()),
+      None,
     );
+    let expires_at = SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST + 3600;
+
+    let event = client
+      .create_event_with_expiration(
+        EventKind::Text,
+        String::from("Going live in an hour"),
+        vec![Tag::hashtag("nostr")],
+        expires_at,
+      )
+      .event;
 
-    client.add_relay(relay.clone()).await;
-    assert_eq!(client.pool.relays().await.len(), 1);
-
-    client.remove_relay(relay).await;
-    assert!(client.pool.relays().await.is_empty());
+    assert_eq!(
+      event.tags,
+      vec![Tag::hashtag("nostr"), Tag::expiration(expires_at)]
+    );
+    assert_eq!(
+      event.tags[1].as_vec(),
+      vec!["expiration".to_string(), expires_at.to_string()]
+    );
+    assert_eq!(event.expiration(), Some(expires_at));
+    assert!(event.check_event_signature());
 
-    remove_temp_db("add_remove_relay");
+    remove_temp_db("create_event_with_expiration");
   }
 
   #[test]
-  fn get_timestamp_in_seconds() {
-    let client = Client::new(Some("timestamp".to_string()), Some("timestamp".to_string()));
-    let timestamp = client.get_timestamp_in_seconds();
-    assert_eq!(timestamp, SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST);
+  fn mention_event() {
+    let client = Client::new(
+      Some("mention_event".to_string()),
+      Some("mention_event".to_string()),
+      None,
+    );
+    let note = client
+      .create_text_note_event(String::from("Quote me"))
+      .event;
+
+    let mention = client
+      .mention_event(&note, String::from("Look at this"))
+      .event;
+
+    assert_eq!(mention.kind, EventKind::Text);
+    assert_eq!(mention.content, String::from("Look at this"));
+    let expected_tags = vec![
+      Tag::Event(EventId(note.id.clone()), None, Some(Marker::Mention)),
+      Tag::PubKey(vec![note.pubkey.clone()], None),
+    ];
+    assert_eq!(mention.tags, expected_tags);
+    assert!(mention.check_event_signature());
+    // the marker survives the serialization
+    assert_eq!(
+      mention.tags[0].as_vec(),
+      vec!["e", note.id.as_str(), "", "mention"]
+    );
+    assert_eq!(
+      Event::from_json(mention.as_json()).unwrap().tags,
+      expected_tags
+    );
 
-    remove_temp_db("timestamp");
+    remove_temp_db("mention_event");
   }
 
   #[test]
-  fn create_event() {
+  fn create_deletion_event() {
     let client = Client::new(
-      Some("create_event".to_string()),
-      Some("create_event".to_string()),
+      Some("create_deletion_event".to_string()),
+      Some("create_deletion_event".to_string()),
+      None,
     );
-    let kind = EventKind::Text;
-    let content = String::from("Content test");
-    let tags = None;
+    let first = client.create_text_note_event(String::from("first")).event;
+    let second = client.create_text_note_event(String::from("second")).event;
 
-    let event = client.create_event(kind, content.clone(), tags);
+    let deletion = client
+      .create_deletion_event(
+        vec![EventId(first.id.clone()), EventId(second.id.clone())],
+        Some(String::from("posted by mistake")),
+      )
+      .event;
 
-    assert_eq!(event.content, content);
-    assert_eq!(event.kind, kind);
-    assert_eq!(event.tags, []);
-    assert_eq!(event.pubkey, client.get_hex_public_key());
+    assert_eq!(deletion.kind, EventKind::Deletion);
+    assert_eq!(deletion.content, String::from("posted by mistake"));
     assert_eq!(
-      event.created_at,
-      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+      deletion.tags,
+      vec![
+        Tag::Event(EventId(first.id.clone()), None, None),
+        Tag::Event(EventId(second.id.clone()), None, None),
+      ]
     );
+    assert!(deletion.check_event_signature());
 
-    remove_temp_db("create_event");
+    let without_reason = client
+      .create_deletion_event(vec![EventId(first.id.clone())], None)
+      .event;
+    assert_eq!(without_reason.content, String::new());
+
+    remove_temp_db("create_deletion_event");
   }
 
   #[test]
-  fn create_reply_to_event() {
+  fn create_channel_events() {
     let client = Client::new(
-      Some("create_reply_to_event".to_string()),
-      Some("create_reply_to_event".to_string()),
+      Some("create_channel_events".to_string()),
+      Some("create_channel_events".to_string()),
+      None,
     );
-    let kind = EventKind::Text;
-    let content = String::from("Content test");
-    let tags = None;
-    let event = client.create_event(kind, content, tags);
+    let metadata = ChannelMetadata {
+      name: String::from("potatoes"),
+      about: String::from("all about potatoes"),
+      picture: String::from("https://potato.com/potato.png"),
+    };
 
-    let recommended_relay_url = None;
-    let content_for_reply = String::from("Replying to event");
-    let marker = Marker::Root;
-    let replyed_event = client.create_reply_to_event(
-      event.clone(),
-      recommended_relay_url,
-      marker.clone(),
-      content_for_reply.clone(),
+    let channel = client.create_channel_event(&metadata).event;
+
+    assert_eq!(channel.kind, EventKind::ChannelCreation);
+    assert_eq!(
+      channel.content,
+      r#"{"name":"potatoes","about":"all about potatoes","picture":"https://potato.com/potato.png"}"#
+    );
+    assert_eq!(channel.tags, vec![]);
+    assert!(channel.check_event_signature());
+    let channel_id = EventId(channel.id);
+    let root = Tag::Event(
+      channel_id.clone(),
+      Some(UncheckedRecommendRelayURL::default()),
+      Some(Marker::Root),
     );
 
-    let tags_json_string = serde_json::to_string(&replyed_event.event.tags).unwrap();
+    let new_metadata = ChannelMetadata {
+      name: String::from("tomatoes"),
+      ..metadata
+    };
+    let update = client
+      .create_channel_metadata_event(&channel_id, &new_metadata)
+      .event;
 
-    let expected_tags = json!([
-      [
-        "e".to_string(),
-        event.id,
-        "".to_string(),
-        marker.to_string()
-      ],
-      ["p".to_string(), client.get_hex_public_key()]
-    ])
-    .to_string();
+    assert_eq!(update.kind, EventKind::ChannelMetadata);
+    assert_eq!(update.content, new_metadata.as_str());
+    assert_eq!(update.tags, vec![root.clone()]);
+    assert_eq!(
+      update.tags[0].as_vec(),
+      vec!["e", channel_id.0.as_str(), "", "root"]
+    );
 
-    assert_eq!(replyed_event.event.content, content_for_reply);
-    assert_eq!(tags_json_string, expected_tags);
+    let message = client
+      .create_channel_message_event(&channel_id, String::from("hi"), None)
+      .event;
+
+    assert_eq!(message.kind, EventKind::ChannelMessage);
+    assert_eq!(message.content, String::from("hi"));
+    assert_eq!(message.tags, vec![root.clone()]);
+
+    let reply = client
+      .create_channel_message_event(
+        &channel_id,
+        String::from("hello"),
+        Some(EventId(message.id.clone())),
+      )
+      .event;
+
+    assert_eq!(reply.kind, EventKind::ChannelMessage);
     assert_eq!(
-      event.created_at,
-      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+      reply.tags,
+      vec![
+        root,
+        Tag::Event(
+          EventId(message.id),
+          Some(UncheckedRecommendRelayURL::default()),
+          Some(Marker::Reply),
+        ),
+      ]
     );
+    assert!(reply.check_event_signature());
 
-    remove_temp_db("create_reply_to_event");
+    remove_temp_db("create_channel_events");
   }
 
   #[test]
-  fn create_text_note_event() {
+  fn relay_list_round_trip() {
     let client = Client::new(
-      Some("create_text_note_event".to_string()),
-      Some("create_text_note_event".to_string()),
+      Some("relay_list_round_trip".to_string()),
+      Some("relay_list_round_trip".to_string()),
+      None,
     );
-    let note = String::from("Test Note");
-
-    let text_note_event = client.create_text_note_event(note.clone());
-
-    assert_eq!(text_note_event.event.content, note);
-    assert_eq!(text_note_event.event.kind, EventKind::Text);
+    let relays = vec![
+      (
+        UncheckedRecommendRelayURL(String::from("wss://read.com")),
+        RelayListMarker::Read,
+      ),
+      (
+        UncheckedRecommendRelayURL(String::from("wss://write.com")),
+        RelayListMarker::Write,
+      ),
+      (
+        UncheckedRecommendRelayURL(String::from("wss://both.com")),
+        RelayListMarker::Both,
+      ),
+    ];
+
+    let relay_list = client.create_relay_list_event(&relays).event;
+
+    assert_eq!(relay_list.kind, EventKind::Custom(10002));
+    assert_eq!(relay_list.content, String::new());
     assert_eq!(
-      text_note_event.event.created_at,
-      SECONDS_AFTER_UNIX_EPOCH_FOR_TIME_NOW_CONFIG_TEST
+      relay_list
+        .tags
+        .iter()
+        .map(|tag| tag.as_vec())
+        .collect::<Vec<Vec<String>>>(),
+      vec![
+        vec!["r", "wss://read.com", "read"],
+        vec!["r", "wss://write.com", "write"],
+        vec!["r", "wss://both.com"],
+      ]
     );
-
-    remove_temp_db("create_text_note_event");
+    assert!(relay_list.check_event_signature());
+    assert_eq!(Client::parse_relay_list(&relay_list), relays);
+
+    // unknown markers are ignored
+    let mut with_unknown_marker = relay_list.clone();
+    with_unknown_marker.tags = vec![Tag::from_string(String::from(
+      r#"["r","wss://potato.com","potato"]"#,
+    ))];
+    assert_eq!(Client::parse_relay_list(&with_unknown_marker), vec![]);
+
+    let note = client
+      .create_text_note_event(String::from("not a relay list"))
+      .event;
+    assert_eq!(Client::parse_relay_list(&note), vec![]);
+
+    remove_temp_db("relay_list_round_trip");
   }
 
   #[test]
@@ -476,6 +2408,7 @@ mod tests {
     let client = Client::new(
       Some("get_event_metadata".to_string()),
       Some("get_event_metadata".to_string()),
+      None,
     );
 
     let metadata_event = client.get_event_metadata();
@@ -495,6 +2428,7 @@ mod tests {
     let client = Client::new(
       Some("get_filter_subscription_request".to_string()),
       Some("get_filter_subscription_request".to_string()),
+      None,
     );
     let filter = Filter::default();
     let metadata_event = client.get_filter_subscription_request(vec![filter.clone()]);
@@ -510,6 +2444,7 @@ mod tests {
     let client = Client::new(
       Some("subscribe_and_unsubcribe".to_string()),
       Some("subscribe_and_unsubcribe".to_string()),
+      None,
     );
     // Initial
     let subscriptions = client.subscriptions().await;
@@ -519,17 +2454,17 @@ mod tests {
 
     // subscribe
     let filter = Filter::default();
-    client.subscribe(vec![filter]).await;
+    let subscription_id = client.subscribe(vec![filter]).await;
 
     // after subscription
     let subscriptions = client.subscriptions().await;
     let subscriptions_from_db = client.subscriptions_db.get_all_subscriptions().unwrap();
     assert_eq!(subscriptions.len(), 1);
     assert_eq!(subscriptions_from_db.len(), 1);
+    assert!(subscriptions.contains_key(&subscription_id));
 
     // unsubscribe
-    let subscription_id = subscriptions.keys().next().unwrap();
-    client.unsubscribe(subscription_id).await;
+    client.unsubscribe(&subscription_id).await;
 
     // after unsubscribtion
     let subscriptions = client.subscriptions().await;
@@ -540,11 +2475,230 @@ mod tests {
     remove_temp_db("subscribe_and_unsubcribe");
   }
 
+  #[tokio::test]
+  async fn subscribe_hashtag() {
+    let client = Client::new(
+      Some("subscribe_hashtag".to_string()),
+      Some("subscribe_hashtag".to_string()),
+      None,
+    );
+
+    client.subscribe_hashtag("nostr").await;
+
+    let subscriptions = client.subscriptions().await;
+    let filters: Vec<&Vec<Filter>> = subscriptions.values().collect();
+    assert_eq!(
+      filters,
+      vec![&vec![Filter {
+        t: Some(vec![String::from("nostr")]),
+        ..Default::default()
+      }]]
+    );
+    assert!(filters[0][0].as_str().contains(r##""#t":["nostr"]"##));
+
+    remove_temp_db("subscribe_hashtag");
+  }
+
+  #[tokio::test]
+  async fn unsubscribe_all() {
+    let client = Client::new(
+      Some("unsubscribe_all".to_string()),
+      Some("unsubscribe_all".to_string()),
+      None,
+    );
+    // safe without subscriptions
+    client.unsubscribe_all().await;
+
+    client
+      .subscribe(vec![Filter::new().kinds(vec![EventKind::Text])])
+      .await;
+    client
+      .subscribe(vec![Filter::new().kinds(vec![EventKind::Metadata])])
+      .await;
+    assert_eq!(client.subscriptions().await.len(), 2);
+    assert_eq!(
+      client
+        .subscriptions_db
+        .get_all_subscriptions()
+        .unwrap()
+        .len(),
+      2
+    );
+
+    client.unsubscribe_all().await;
+
+    assert!(client.subscriptions().await.is_empty());
+    assert!(client
+      .subscriptions_db
+      .get_all_subscriptions()
+      .unwrap()
+      .is_empty());
+
+    remove_temp_db("unsubscribe_all");
+  }
+
+  #[test]
+  fn new_with_account() {
+    let table_name = "new_with_account";
+    // only one client can have the database open at a time
+    let public_key_of = |account: Option<&str>| {
+      Client::new(
+        Some(table_name.to_string()),
+        Some(table_name.to_string()),
+        account,
+      )
+      .get_hex_public_key()
+    };
+
+    let alice = public_key_of(Some("alice"));
+    let bob = public_key_of(Some("bob"));
+    let default = public_key_of(None);
+
+    assert_ne!(alice, bob);
+    assert_ne!(alice, default);
+    assert_ne!(bob, default);
+
+    // each account is loaded back by its label
+    assert_eq!(public_key_of(Some("alice")), alice);
+    assert_eq!(public_key_of(Some("bob")), bob);
+    assert_eq!(public_key_of(None), default);
+
+    remove_temp_db(table_name);
+  }
+
+  #[test]
+  fn set_keys() {
+    let table_name = "set_keys";
+    let new_client = || {
+      Client::new(
+        Some(table_name.to_string()),
+        Some(table_name.to_string()),
+        None,
+      )
+    };
+    let mut client = new_client();
+    let previous_public_key = client.get_hex_public_key();
+    let keys = crate::schnorr::generate_keys();
+    // In order to use Schnorr signatures, we have to drop the first byte of pubkey
+    let public_key = keys.public_key.to_string()[2..].to_string();
+
+    client.set_keys(keys).unwrap();
+
+    assert_ne!(client.get_hex_public_key(), previous_public_key);
+    assert_eq!(client.get_hex_public_key(), public_key);
+    let event = client.create_text_note_event(String::from("potato")).event;
+    assert_eq!(event.pubkey, public_key);
+    assert!(event.check_event_signature());
+
+    // only one client can have the database open at a time
+    drop(client);
+    assert_eq!(new_client().get_hex_public_key(), public_key);
+
+    remove_temp_db(table_name);
+  }
+
+  #[test]
+  fn share_link() {
+    let client = Client::new(
+      Some("share_link".to_string()),
+      Some("share_link".to_string()),
+      None,
+    );
+    let event = client.create_text_note_event(String::from("potato")).event;
+    let relay_hints = vec![String::from("wss://relay.damus.io")];
+
+    let link = client.share_link(&event, relay_hints.clone()).unwrap();
+
+    assert!(link.starts_with("nostr:nevent1"));
+    let nevent = NEvent::decode(&link).unwrap();
+    assert_eq!(nevent.event_id, event.id);
+    assert_eq!(nevent.author, Some(client.get_hex_public_key()));
+    assert_eq!(nevent.relays, relay_hints);
+    assert_eq!(nevent.kind, Some(EventKind::Text));
+
+    // an event that cannot be encoded
+    let event = Event {
+      id: String::from("potato"),
+      ..event
+    };
+    assert!(matches!(
+      client.share_link(&event, relay_hints),
+      Err(nip19::Error::Hex(_))
+    ));
+
+    remove_temp_db("share_link");
+  }
+
+  #[tokio::test]
+  async fn coalesced_subscriptions() {
+    let mut client = Client::new(
+      Some("coalesced_subscriptions".to_string()),
+      Some("coalesced_subscriptions".to_string()),
+      None,
+    );
+    client.coalesce_subscriptions(true);
+    let replies_to = |event_id: &str| Filter {
+      e: Some(vec![event_id.to_string()]),
+      ..Default::default()
+    };
+    let reply_to = |event_id: &str| Event {
+      tags: vec![Tag::Event(EventId(event_id.to_string()), None, None)],
+      ..Default::default()
+    };
+
+    client.subscribe(vec![replies_to("a")]).await;
+    client.subscribe(vec![replies_to("b")]).await;
+    client.subscribe(vec![replies_to("a")]).await;
+
+    // the app subscriptions are stored, but the relays only get a REQ per `#e`
+    let subscriptions = client.subscriptions().await;
+    assert_eq!(subscriptions.len(), 3);
+    let requests: Vec<ClientToRelayCommRequest> = client
+      .stored_requests()
+      .await
+      .into_iter()
+      .map(|request| ClientToRelayCommRequest::from_json(request).unwrap())
+      .collect();
+    assert_eq!(requests.len(), 2);
+    let request_of = |event_id: &str| {
+      requests
+        .iter()
+        .find(|request| request.filters == vec![replies_to(event_id)])
+        .unwrap()
+    };
+
+    // and each app subscription gets its own events back
+    let subscription_ids_of = |event_id: &str| {
+      let mut subscription_ids: Vec<String> = subscriptions
+        .iter()
+        .filter(|(_, filters)| filters[0] == replies_to(event_id))
+        .map(|(subs_id, _)| subs_id.clone())
+        .collect();
+      subscription_ids.sort();
+      subscription_ids
+    };
+    assert_eq!(
+      client
+        .demux_event(&request_of("a").subscription_id, &reply_to("a"))
+        .await,
+      subscription_ids_of("a")
+    );
+    assert_eq!(
+      client
+        .demux_event(&request_of("b").subscription_id, &reply_to("b"))
+        .await,
+      subscription_ids_of("b")
+    );
+
+    remove_temp_db("coalesced_subscriptions");
+  }
+
   #[tokio::test]
   async fn follow_author() {
     let client = Client::new(
       Some("follow_author".to_string()),
       Some("follow_author".to_string()),
+      None,
     );
     let author = String::from("potato_author");
     let subscriptions = client.subscriptions().await;
@@ -580,6 +2734,7 @@ mod tests {
     let client = Client::new(
       Some("follow_myself".to_string()),
       Some("follow_myself".to_string()),
+      None,
     );
     let subscriptions = client.subscriptions().await;
     let subscriptions_from_db = client.subscriptions_db.get_all_subscriptions().unwrap();
@@ -608,4 +2763,297 @@ mod tests {
 
     remove_temp_db("follow_myself");
   }
+
+  #[tokio::test]
+  async fn follow_and_unfollow() {
+    let client = Client::new(
+      Some("follow_and_unfollow".to_string()),
+      Some("follow_and_unfollow".to_string()),
+      None,
+    );
+    let friend = String::from("friend_pubkey");
+    let other_friend_tag = Tag::try_from(vec![
+      "p",
+      "other_friend_pubkey",
+      "wss://relay.com",
+      "other_friend",
+    ])
+    .unwrap();
+    let mut contact_list = Event::new_without_signature(
+      client.get_hex_public_key(),
+      10,
+      EventKind::Custom(CONTACT_LIST_KIND),
+      vec![other_friend_tag.clone()],
+      String::new(),
+    );
+    contact_list.sign_event(client.keys.private_key.clone());
+    client.update_contact_list(contact_list).await;
+
+    // follow
+    client.follow(&friend).await.unwrap();
+    client.follow(&friend).await.unwrap();
+
+    let tags = client.contact_list.lock().await.clone().unwrap().tags;
+    assert_eq!(
+      tags,
+      vec![
+        other_friend_tag.clone(),
+        Tag::PubKey(vec![friend.clone()], None)
+      ]
+    );
+    assert_eq!(
+      client.contacts().await,
+      vec![String::from("other_friend_pubkey"), friend.clone()]
+    );
+
+    // unfollow
+    client.unfollow(&friend).await.unwrap();
+
+    let contact_list = client.contact_list.lock().await.clone().unwrap();
+    assert_eq!(contact_list.tags, vec![other_friend_tag]);
+    assert_eq!(contact_list.kind, EventKind::Custom(CONTACT_LIST_KIND));
+    assert!(contact_list.check_event_signature());
+
+    remove_temp_db("follow_and_unfollow");
+  }
+
+  #[tokio::test]
+  async fn follow_loads_the_contact_list_first() {
+    let mut client = Client::new(
+      Some("follow_loads_the_contact_list_first".to_string()),
+      Some("follow_loads_the_contact_list_first".to_string()),
+      None,
+    );
+    let friend = String::from("friend_pubkey");
+
+    // without relays, the contact list published by another client cannot be known
+    assert!(matches!(
+      client.follow(&friend).await,
+      Err(Error::ContactListNotLoaded)
+    ));
+    assert!(matches!(
+      client.unfollow(&friend).await,
+      Err(Error::ContactListNotLoaded)
+    ));
+    assert_eq!(client.contact_list.lock().await.clone(), None);
+
+    let other_friend_tag = Tag::PubKey(vec![String::from("other_friend_pubkey")], None);
+    let mut published_contact_list = Event::new_without_signature(
+      client.get_hex_public_key(),
+      10,
+      EventKind::Custom(CONTACT_LIST_KIND),
+      vec![other_friend_tag.clone()],
+      String::new(),
+    );
+    published_contact_list.sign_event(client.keys.private_key.clone());
+    let relay = spawn_mock_relay(vec![published_contact_list]).await;
+    client.add_relay(relay).await.unwrap();
+    time::timeout(Duration::from_secs(5), async {
+      while !client
+        .relay_status()
+        .await
+        .values()
+        .any(|status| status.connected)
+      {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    client.follow(&friend).await.unwrap();
+
+    // the published contacts are kept
+    assert_eq!(
+      client.contacts().await,
+      vec![String::from("other_friend_pubkey"), friend]
+    );
+
+    client.disconnect_all().await;
+    remove_temp_db("follow_loads_the_contact_list_first");
+  }
+
+  #[tokio::test]
+  async fn follow_fails_when_no_relay_answers_with_the_contact_list() {
+    let mut client = Client::new(
+      Some("follow_fails_when_no_relay_answers_with_the_contact_list".to_string()),
+      Some("follow_fails_when_no_relay_answers_with_the_contact_list".to_string()),
+      None,
+    );
+    // a relay that drops the connection instead of answering the REQs
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(async move {
+          let mut ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+          while let Some(Ok(msg)) = ws_stream.next().await {
+            if ClientToRelayCommRequest::from_json(msg.to_text().unwrap_or_default()).is_ok() {
+              break;
+            }
+          }
+        });
+      }
+    });
+    client.add_relay(url).await.unwrap();
+    time::timeout(Duration::from_secs(5), async {
+      while !client
+        .relay_status()
+        .await
+        .values()
+        .any(|status| status.connected)
+      {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    // the published contact list would be replaced by one with only the friend
+    assert!(matches!(
+      client.follow(&String::from("friend_pubkey")).await,
+      Err(Error::ContactListNotLoaded)
+    ));
+    assert_eq!(client.contact_list.lock().await.clone(), None);
+
+    client.disconnect_all().await;
+    remove_temp_db("follow_fails_when_no_relay_answers_with_the_contact_list");
+  }
+
+  struct MockNip05Verifier {
+    calls: AtomicUsize,
+    /// cache of the client, which must not be locked while verifying
+    nip05_cache: Arc<Mutex<HashMap<(String, PubKey), Nip05CacheEntry>>>,
+  }
+
+  #[async_trait::async_trait]
+  impl Nip05Verifier for MockNip05Verifier {
+    async fn verify(
+      &self,
+      _identifier: &str,
+      _pubkey: &PubKey,
+    ) -> Result<bool, crate::nip05::Error> {
+      assert!(self.nip05_cache.try_lock().is_ok());
+      self.calls.fetch_add(1, Ordering::Relaxed);
+      Ok(true)
+    }
+  }
+
+  struct MockNip11Fetcher {
+    requested_urls: std::sync::Mutex<Vec<String>>,
+  }
+
+  #[async_trait::async_trait]
+  impl Nip11Fetcher for MockNip11Fetcher {
+    async fn fetch(&self, http_url: &str) -> Result<String, crate::nip11::Error> {
+      self
+        .requested_urls
+        .lock()
+        .unwrap()
+        .push(http_url.to_string());
+      Ok(String::from(
+        r#"{"name":"potato relay","supported_nips":[1,11,42,45]}"#,
+      ))
+    }
+  }
+
+  #[tokio::test]
+  async fn relay_capabilities() {
+    let client = Client::new(
+      Some("relay_capabilities".to_string()),
+      Some("relay_capabilities".to_string()),
+      None,
+    );
+    let fetcher = MockNip11Fetcher {
+      requested_urls: std::sync::Mutex::new(vec![]),
+    };
+    let url = String::from("wss://relay.damus.io");
+
+    assert_eq!(
+      client.relay_capabilities_with(url.clone(), &fetcher).await,
+      Some(vec![1, 11, 42, 45])
+    );
+    // cached
+    assert_eq!(
+      client.relay_capabilities_with(url, &fetcher).await,
+      Some(vec![1, 11, 42, 45])
+    );
+    assert_eq!(
+      *fetcher.requested_urls.lock().unwrap(),
+      vec![String::from("https://relay.damus.io/")]
+    );
+
+    assert_eq!(
+      client
+        .relay_capabilities_with(String::from("not a url"), &fetcher)
+        .await,
+      None
+    );
+
+    remove_temp_db("relay_capabilities");
+  }
+
+  #[tokio::test]
+  async fn relay_capabilities_are_fetched_over_http_by_default() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let client = Client::new(
+      Some("relay_capabilities_are_fetched_over_http_by_default".to_string()),
+      Some("relay_capabilities_are_fetched_over_http_by_default".to_string()),
+      None,
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut request = [0; 1024];
+      let _ = stream.read(&mut request).await;
+      let document = r#"{"supported_nips":[1,11]}"#;
+      let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{document}",
+        document.len()
+      );
+      stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    assert_eq!(client.relay_capabilities(url).await, Some(vec![1, 11]));
+
+    remove_temp_db("relay_capabilities_are_fetched_over_http_by_default");
+  }
+
+  #[tokio::test]
+  async fn verify_nip05_cached() {
+    let client = Client::new(
+      Some("verify_nip05_cached".to_string()),
+      Some("verify_nip05_cached".to_string()),
+      None,
+    );
+    let verifier = MockNip05Verifier {
+      calls: AtomicUsize::new(0),
+      nip05_cache: client.nip05_cache.clone(),
+    };
+    let identifier = "bob@example.com";
+    let pubkey = client.get_hex_public_key();
+    let ttl = Duration::from_secs(60);
+
+    assert!(
+      client
+        .verify_nip05_cached(&verifier, identifier, &pubkey, ttl)
+        .await
+    );
+    assert!(
+      client
+        .verify_nip05_cached(&verifier, identifier, &pubkey, ttl)
+        .await
+    );
+    assert_eq!(verifier.calls.load(Ordering::Relaxed), 1);
+
+    // an expired entry is verified again
+    client
+      .verify_nip05_cached(&verifier, identifier, &pubkey, Duration::ZERO)
+      .await;
+    assert_eq!(verifier.calls.load(Ordering::Relaxed), 2);
+
+    remove_temp_db("verify_nip05_cached");
+  }
 }
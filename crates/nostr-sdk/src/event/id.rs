@@ -21,12 +21,30 @@ impl EventId {
     tags: Vec<Tag>,
     content: String,
   ) -> Self {
-    let data = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    let data = serialized_for_id(pubkey, created_at, kind, tags, content);
     let hash = sha256::Hash::hash(data.as_bytes());
     Self(hash.to_hex())
   }
 }
 
+/// Serializes the event data as `[0, <pubkey>, <created_at>, <kind>, <tags>, <content>]`,
+/// without whitespaces, which is what gets hashed to create the [`EventId`].
+///
+/// As required by NIP-01, strings are UTF-8 and only the quote, the backslash and
+/// the control characters are escaped (`\"`, `\\`, `\n`, `\r`, `\t`, `\b`, `\f`
+/// and `\u00XX` for the other control characters). Any other character
+/// (including the ones outside the BMP) is written as is.
+///
+pub(crate) fn serialized_for_id(
+  pubkey: PubKey,
+  created_at: Timestamp,
+  kind: EventKind,
+  tags: Vec<Tag>,
+  content: String,
+) -> String {
+  json!([0, pubkey, created_at, kind, tags, content]).to_string()
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -88,4 +106,59 @@ mod tests {
     assert_eq!(expected, event_id);
     assert_ne!(not_expected, event_id);
   }
+
+  #[test]
+  fn recomputes_the_id_of_a_known_event() {
+    let event_id = EventId::new(
+      String::from("614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6"),
+      1684589418,
+      EventKind::Text,
+      vec![],
+      String::from("potato"),
+    );
+
+    assert_eq!(
+      event_id,
+      EventId(String::from(
+        "00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae"
+      ))
+    );
+  }
+
+  #[test]
+  fn serialized_for_id_is_the_canonical_array() {
+    let tags = vec![
+      Tag::Event(
+        EventId(String::from("event_id")),
+        Some(UncheckedRecommendRelayURL(String::from("wss://relay.com"))),
+        Some(Marker::Root),
+      ),
+      Tag::PubKey(vec![String::from("pubkey")], None),
+    ];
+
+    let serialized = serialized_for_id(
+      String::from("pubkey"),
+      1684589418,
+      EventKind::Text,
+      tags,
+      String::from("potato"),
+    );
+
+    assert_eq!(
+      serialized,
+      r#"[0,"pubkey",1684589418,1,[["e","event_id","wss://relay.com","root"],["p","pubkey"]],"potato"]"#
+    );
+  }
+
+  #[test]
+  fn serialized_for_id_escapes_content_as_nip01() {
+    let content = String::from("\"quote\" \\ slash/ \n\r\t\u{8}\u{c} \u{0}\u{1f} é 😀 \\ud83d");
+
+    let serialized = serialized_for_id(String::from("pubkey"), 1, EventKind::Text, vec![], content);
+
+    assert_eq!(
+      serialized,
+      r#"[0,"pubkey",1,1,[],"\"quote\" \\ slash/ \n\r\t\b\f \u0000\u001f é 😀 \\ud83d"]"#
+    );
+  }
 }
@@ -4,11 +4,20 @@ use std::fmt;
 use std::num::ParseIntError;
 use std::str::FromStr;
 
+use super::tag::{Tag, TagKind};
+
+/// Error of [`EventKind::validate_tags`]
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TagValidationError {
+  #[error("kind {kind} events must have a \"{tag}\" tag")]
+  MissingTag { kind: EventKind, tag: TagKind },
+}
+
 /// Defines the type of the event.
 /// Different types will change the meaning of different keys
 /// of event object.
 /// `Text` is the default.
-#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum EventKind {
   /// The content is set to a stringfied JSON object
   /// `{name: <username>, about: <string>, picture: <url, string>}`
@@ -24,21 +33,87 @@ pub enum EventKind {
   /// The content is set to the URL (e.g.: `wss://somerelay.com`) of a relay
   /// the event creator wants to recommend to its followers.
   RecommendRelay,
+  /// Asks for the deletion of the events referenced in its `"e"` tags (NIP-09).
+  /// The content may be the reason for the deletion.
+  Deletion,
+  /// A repost of a text note (NIP-18).
+  /// The content may be the stringified JSON of the reposted note.
+  Repost,
+  /// A reaction (like, dislike, emoji) to another event (NIP-25).
+  Reaction,
+  /// Creates a public chat channel (NIP-28).
+  ChannelCreation,
+  /// Updates the metadata of a public chat channel (NIP-28).
+  ChannelMetadata,
+  /// A message sent to a public chat channel (NIP-28).
+  ChannelMessage,
+  /// Hides a message of a public chat channel for the user (NIP-28).
+  ChannelHideMessage,
+  /// Mutes a user of a public chat channel for the user (NIP-28).
+  ChannelMuteUser,
   /// A custom kind that we haven't implemented yet.
   Custom(u64),
 }
 
-// impl EventKind {
-//   /// Get [`EventKind`] as `u32`
-//   pub fn as_u32(&self) -> u32 {
-//     self.as_u64() as u32
-//   }
+impl EventKind {
+  /// Checks if the kind is replaceable (NIP-16), i.e.: a relay
+  /// only needs to store the latest event of this kind for a given pubkey.
+  /// `Metadata` (0), contacts (3) and kinds from 10000 to 19999 are replaceable.
+  ///
+  pub fn is_replaceable(&self) -> bool {
+    match self {
+      Self::Metadata => true,
+      Self::Custom(kind) => *kind == 3 || (10000..20000).contains(kind),
+      _ => false,
+    }
+  }
 
-//   /// Get [`EventKind`] as `u64`
-//   pub fn as_u64(&self) -> u64 {
-//     (*self).into()
-//   }
-// }
+  /// Checks if the kind is parameterized replaceable (NIP-33), i.e.: a relay
+  /// only needs to store the latest event of this kind for a given pubkey
+  /// and `"d"` tag. Kinds from 30000 to 39999 are parameterized replaceable.
+  ///
+  pub fn is_parameterized_replaceable(&self) -> bool {
+    matches!(self, Self::Custom(kind) if (30000..40000).contains(kind))
+  }
+
+  /// Kinds of the tags events of this kind must have:
+  ///   - `Deletion` (NIP-09): `"e"`, the events to be deleted;
+  ///   - `Repost` (NIP-18): `"e"`, the reposted note;
+  ///   - `Reaction` (NIP-25): `"e"` and `"p"`, the event reacted to and its author.
+  ///
+  pub fn required_tags(&self) -> Vec<TagKind> {
+    match self {
+      Self::Deletion | Self::Repost => vec![TagKind::Event],
+      Self::Reaction => vec![TagKind::Event, TagKind::PubKey],
+      _ => vec![],
+    }
+  }
+
+  /// Checks if `tags` has all the tags required for this kind (see [`EventKind::required_tags`]).
+  ///
+  pub fn validate_tags(&self, tags: &[Tag]) -> Result<(), TagValidationError> {
+    match self
+      .required_tags()
+      .into_iter()
+      .find(|required| !tags.iter().any(|tag| tag.kind() == *required))
+    {
+      Some(tag) => Err(TagValidationError::MissingTag { kind: *self, tag }),
+      None => Ok(()),
+    }
+  }
+}
+
+impl EventKind {
+  /// Get [`EventKind`] as `u32`
+  pub fn as_u32(&self) -> u32 {
+    self.as_u64() as u32
+  }
+
+  /// Get [`EventKind`] as `u64`
+  pub fn as_u64(&self) -> u64 {
+    (*self).into()
+  }
+}
 
 impl FromStr for EventKind {
   type Err = ParseIntError;
@@ -54,6 +129,14 @@ impl From<u64> for EventKind {
       0 => Self::Metadata,
       1 => Self::Text,
       2 => Self::RecommendRelay,
+      5 => Self::Deletion,
+      6 => Self::Repost,
+      7 => Self::Reaction,
+      40 => Self::ChannelCreation,
+      41 => Self::ChannelMetadata,
+      42 => Self::ChannelMessage,
+      43 => Self::ChannelHideMessage,
+      44 => Self::ChannelMuteUser,
       x => Self::Custom(x),
     }
   }
@@ -65,6 +148,14 @@ impl From<EventKind> for u64 {
       EventKind::Metadata => 0,
       EventKind::Text => 1,
       EventKind::RecommendRelay => 2,
+      EventKind::Deletion => 5,
+      EventKind::Repost => 6,
+      EventKind::Reaction => 7,
+      EventKind::ChannelCreation => 40,
+      EventKind::ChannelMetadata => 41,
+      EventKind::ChannelMessage => 42,
+      EventKind::ChannelHideMessage => 43,
+      EventKind::ChannelMuteUser => 44,
       EventKind::Custom(u) => u,
     }
   }
@@ -107,11 +198,90 @@ impl<'de> Deserialize<'de> for EventKind {
 
 impl fmt::Display for EventKind {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    match self {
-      Self::Metadata => write!(f, "0"),
-      Self::Text => write!(f, "1"),
-      Self::RecommendRelay => write!(f, "2"),
-      Self::Custom(kind) => write!(f, "{kind}"),
+    write!(f, "{}", self.as_u64())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::event::id::EventId;
+
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn named_kinds_round_trip() {
+    let named_kinds = [
+      (0, EventKind::Metadata),
+      (1, EventKind::Text),
+      (2, EventKind::RecommendRelay),
+      (5, EventKind::Deletion),
+      (6, EventKind::Repost),
+      (7, EventKind::Reaction),
+      (40, EventKind::ChannelCreation),
+      (41, EventKind::ChannelMetadata),
+      (42, EventKind::ChannelMessage),
+      (43, EventKind::ChannelHideMessage),
+      (44, EventKind::ChannelMuteUser),
+    ];
+
+    for (number, kind) in named_kinds {
+      assert_eq!(EventKind::from(number as u64), kind);
+      assert_eq!(kind.as_u32(), number);
+      assert_eq!(EventKind::from(kind.as_u64()), kind);
+      assert_eq!(kind.to_string(), number.to_string());
     }
   }
+
+  #[test]
+  fn custom_kinds_round_trip() {
+    for number in [3u64, 4, 9735, 10002, 30023] {
+      let kind = EventKind::from(number);
+
+      assert_eq!(kind, EventKind::Custom(number));
+      assert_eq!(kind.as_u64(), number);
+      assert_eq!(kind.as_u32(), number as u32);
+    }
+  }
+
+  #[test]
+  fn validate_tags_requires_the_tags_of_the_kind() {
+    let e_tag = Tag::Event(EventId(String::from("id")), None, None);
+    let p_tag = Tag::PubKey(vec![String::from("pubkey")], None);
+
+    assert_eq!(
+      EventKind::Reaction.validate_tags(std::slice::from_ref(&e_tag)),
+      Err(TagValidationError::MissingTag {
+        kind: EventKind::Reaction,
+        tag: TagKind::PubKey
+      })
+    );
+    assert_eq!(
+      EventKind::Reaction.validate_tags(&[p_tag.clone(), e_tag.clone()]),
+      Ok(())
+    );
+    assert_eq!(
+      EventKind::Deletion.validate_tags(std::slice::from_ref(&p_tag)),
+      Err(TagValidationError::MissingTag {
+        kind: EventKind::Deletion,
+        tag: TagKind::Event
+      })
+    );
+    assert_eq!(EventKind::Repost.validate_tags(&[e_tag]), Ok(()));
+    // kinds without required tags
+    assert_eq!(EventKind::Text.validate_tags(&[]), Ok(()));
+  }
+
+  #[test]
+  fn parameterized_replaceable_kinds() {
+    assert!(EventKind::Custom(30000).is_parameterized_replaceable());
+    assert!(EventKind::Custom(30023).is_parameterized_replaceable());
+    assert!(EventKind::Custom(39999).is_parameterized_replaceable());
+    assert!(!EventKind::Custom(40000).is_parameterized_replaceable());
+    assert!(!EventKind::Custom(10002).is_parameterized_replaceable());
+    assert!(!EventKind::Metadata.is_parameterized_replaceable());
+    assert!(!EventKind::Custom(30023).is_replaceable());
+  }
 }
@@ -38,3 +38,25 @@ where
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn marker_round_trips() {
+    for marker in [
+      Marker::Root,
+      Marker::Reply,
+      Marker::Mention,
+      Marker::Default,
+    ] {
+      assert_eq!(Marker::from(marker.to_string()), marker);
+    }
+    assert_eq!(Marker::from("mention"), Marker::Mention);
+    assert_eq!(Marker::from("potato"), Marker::Default);
+  }
+}
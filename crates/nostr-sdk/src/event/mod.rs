@@ -1,7 +1,10 @@
-use std::str::FromStr;
+use std::{
+  str::FromStr,
+  time::{SystemTime, UNIX_EPOCH},
+};
 
 use secp256k1::{schnorr, Secp256k1};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
 
 // Event Modules
@@ -9,15 +12,28 @@ pub mod id;
 pub mod kind;
 pub mod marker;
 pub mod tag;
+pub mod thread;
+
+use crate::{
+  client::database::keys_table::Keys,
+  schnorr::{normalize_pubkey, AsymmetricKeys},
+};
 
 use self::id::EventId;
 use self::kind::EventKind;
 use self::marker::Marker;
-use self::tag::Tag;
+use self::tag::{Tag, TagKind, ALT_TAG, EXPIRATION_TAG, IDENTIFIER_TAG};
 
 pub type PubKey = String;
 pub type Timestamp = u64;
 
+/// Length, in hex characters, of the id of an event (32 bytes).
+const ID_HEX_LEN: usize = 64;
+/// Length, in hex characters, of the x-only pubkey of an event (32 bytes).
+const PUBKEY_HEX_LEN: usize = 64;
+/// Length, in hex characters, of the schnorr signature of an event (64 bytes).
+const SIG_HEX_LEN: usize = 128;
+
 /// [`Event`] error
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -28,6 +44,17 @@ pub enum Error {
   InvalidData,
 }
 
+/// [`Event::verify`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum EventVerifyError {
+  #[error("event id does not match")]
+  IdMismatch,
+  #[error("bad signature format")]
+  InvalidSignatureFormat,
+  #[error("bad signature")]
+  SignatureVerificationFailed,
+}
+
 ///
 /// Event is the only object that exists in the Nostr protocol.
 ///
@@ -100,6 +127,24 @@ impl Event {
     self.sig = signed.to_string();
   }
 
+  /// Recomputes the id from the current fields and signs it again with `keys`,
+  /// which also become the author of the event. Used to finalize an event
+  /// (e.g. a draft) after changing its tags or content.
+  ///
+  pub fn resign(&mut self, keys: &Keys) {
+    self.sig = String::new();
+    self.pubkey = hex::encode(&keys.public_key);
+    self.id = EventId::new(
+      self.pubkey.clone(),
+      self.created_at,
+      self.kind,
+      self.tags.clone(),
+      self.content.clone(),
+    )
+    .0;
+    self.sign_event(keys.private_key.clone());
+  }
+
   pub fn check_event_id(&self) -> bool {
     EventId::new(
       self.pubkey.clone(),
@@ -112,15 +157,139 @@ impl Event {
   }
 
   pub fn check_event_signature(&self) -> bool {
+    self.verify_signature().is_ok()
+  }
+
+  fn verify_signature(&self) -> Result<(), EventVerifyError> {
     let secp = Secp256k1::new();
-    let sig = match schnorr::Signature::from_str(&self.sig) {
-      Ok(signature) => signature,
-      Err(_) => return false,
-    };
+    let sig = schnorr::Signature::from_str(&self.sig)
+      .map_err(|_| EventVerifyError::InvalidSignatureFormat)?;
     let msg = self.id.clone();
 
-    crate::schnorr::verify_schnorr(&secp, msg, sig, self.pubkey.clone())
-      .unwrap_or(false)
+    match crate::schnorr::verify_schnorr(&secp, msg, sig, self.pubkey.clone()) {
+      Ok(true) => Ok(()),
+      _ => Err(EventVerifyError::SignatureVerificationFailed),
+    }
+  }
+
+  /// Checks both that the id matches the serialized event data
+  /// and that the signature is valid over it, telling which one failed.
+  ///
+  /// A valid signature alone is not enough: the author may
+  /// have signed an id that does not belong to the event.
+  ///
+  pub fn verify(&self) -> Result<(), EventVerifyError> {
+    if !self.check_event_id() {
+      return Err(EventVerifyError::IdMismatch);
+    }
+    self.verify_signature()
+  }
+
+  /// Gets the name of the first of `id`, `pubkey` and `sig` that is not
+  /// hex of the expected length, if any.
+  ///
+  pub fn malformed_hex_field(&self) -> Option<&'static str> {
+    let is_hex = |value: &str, len: usize| {
+      value.len() == len && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+    };
+
+    if !is_hex(&self.id, ID_HEX_LEN) {
+      Some("id")
+    } else if !is_hex(&self.pubkey, PUBKEY_HEX_LEN) {
+      Some("pubkey")
+    } else if !is_hex(&self.sig, SIG_HEX_LEN) {
+      Some("sig")
+    } else {
+      None
+    }
+  }
+
+  /// Checks, cheaply (before any hashing or cryptography), that the `id`, `pubkey`
+  /// and `sig` are hex of the expected length (see [`Event::malformed_hex_field`]).
+  ///
+  pub fn is_valid_hex_fields(&self) -> bool {
+    self.malformed_hex_field().is_none()
+  }
+
+  /// Same as [`Event::verify`], without the reason.
+  ///
+  pub fn is_fully_valid(&self) -> bool {
+    self.verify().is_ok()
+  }
+
+  /// Gets the tags of the event of the given `kind`, in the order they appear.
+  pub fn tags_by_kind(&self, kind: TagKind) -> Vec<&Tag> {
+    self.tags.iter().filter(|tag| tag.kind() == kind).collect()
+  }
+
+  /// Gets the first tag of the event of the given `kind`, if any.
+  pub fn first_tag(&self, kind: TagKind) -> Option<&Tag> {
+    self.tags.iter().find(|tag| tag.kind() == kind)
+  }
+
+  /// Gets the timestamp of the `["expiration", <unix timestamp>]` tag (NIP-40).
+  ///
+  /// Returns `None` if the event does not have it or if it is not a valid timestamp.
+  ///
+  pub fn expiration(&self) -> Option<Timestamp> {
+    self.tags.iter().find_map(|tag| match tag {
+      Tag::Generic(TagKind::Custom(kind), values) if kind == EXPIRATION_TAG => {
+        values.first().and_then(|expiration| expiration.parse().ok())
+      }
+      _ => None,
+    })
+  }
+
+  /// Checks if the event is already expired (NIP-40) at the `now` timestamp.
+  pub fn is_expired(&self, now: Timestamp) -> bool {
+    matches!(self.expiration(), Some(expiration) if expiration <= now)
+  }
+
+  /// Gets the human-readable description of the `["alt", <description>]` tag (NIP-31),
+  /// if the event has one.
+  ///
+  pub fn alt(&self) -> Option<String> {
+    self.tags.iter().find_map(|tag| match tag {
+      Tag::Generic(TagKind::Custom(kind), values) if kind == ALT_TAG => values.first().cloned(),
+      _ => None,
+    })
+  }
+
+  /// Gets the value of the `["d", <identifier>]` tag (NIP-33).
+  /// Without one, the identifier is empty.
+  ///
+  pub fn identifier(&self) -> String {
+    self
+      .tags
+      .iter()
+      .find_map(|tag| match tag {
+        Tag::Generic(TagKind::Custom(kind), values) if kind == IDENTIFIER_TAG => {
+          values.first().cloned()
+        }
+        _ => None,
+      })
+      .unwrap_or_default()
+  }
+
+  /// Gets the address (`<kind>:<pubkey>:<d tag>`) of the event,
+  /// used to reference parameterized replaceable events in `"a"` tags (NIP-33).
+  ///
+  pub fn address(&self) -> String {
+    format!("{}:{}:{}", self.kind, self.pubkey, self.identifier())
+  }
+
+  /// Parses the content of the event as JSON, the way some kinds carry it
+  /// (e.g.: `Metadata` (kind 0) or relay lists (kind 10002)).
+  ///
+  pub fn content_as_json(&self) -> Result<Value, Error> {
+    self.content_as()
+  }
+
+  /// Deserializes the JSON content of the event into `T`
+  /// (e.g.: the [`Metadata`](crate::client::Metadata) of a kind 0 event).
+  ///
+  pub fn content_as<T: DeserializeOwned>(&self) -> Result<T, Error> {
+    serde_json::from_str(&self.content).map_err(Error::Json)
   }
 
   /// Deserializes from [`Value`]
@@ -148,12 +317,86 @@ impl Event {
     Self::from_value(value)
   }
 
+  /// Deserialize [`Event`] from JSON string, rejecting (with [`Error::InvalidData`])
+  /// the events with top-level fields other than the ones of an [`Event`],
+  /// which [`Event::from_json`] silently ignores.
+  ///
+  pub fn from_json_strict<S>(msg: S) -> Result<Self, Error>
+  where
+    S: Into<String>,
+  {
+    let msg: &str = &msg.into();
+
+    if msg.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let value: Value = serde_json::from_str(msg)?;
+    match serde_json::from_value::<StrictEvent>(value.clone()) {
+      Ok(event) => Ok(event.into()),
+      // a well-formed event, apart from its unknown fields
+      Err(_) if Self::from_value(value.clone()).is_ok() => Err(Error::InvalidData),
+      Err(err) => Err(Error::Json(err)),
+    }
+  }
+
   /// Get [`Event`] in JSON string
   pub fn as_json(&self) -> String {
     serde_json::to_string(self).unwrap()
   }
 }
 
+/// Same fields as an [`Event`], but denying unknown ones
+/// (see [`Event::from_json_strict`]).
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StrictEvent {
+  id: String,
+  pubkey: PubKey,
+  created_at: Timestamp,
+  kind: EventKind,
+  tags: Vec<Tag>,
+  content: String,
+  sig: String,
+}
+
+impl From<StrictEvent> for Event {
+  fn from(event: StrictEvent) -> Self {
+    Self {
+      id: event.id,
+      pubkey: event.pubkey,
+      created_at: event.created_at,
+      kind: event.kind,
+      tags: event.tags,
+      content: event.content,
+      sig: event.sig,
+    }
+  }
+}
+
+/// Builds an event created now, with its id computed and signed with `keys`.
+///
+/// Unlike the events created by the `Client`, it does not need
+/// a loaded identity (database), so it can be used by tools and tests.
+///
+pub fn build_event(
+  keys: &AsymmetricKeys,
+  kind: EventKind,
+  content: String,
+  tags: Vec<Tag>,
+) -> Event {
+  let pubkey = normalize_pubkey(&keys.public_key.to_string()).unwrap();
+  let created_at = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_secs();
+
+  let mut event = Event::new_without_signature(pubkey, created_at, kind, tags, content);
+  event.sign_event(keys.private_key.secret_bytes().to_vec());
+  event
+}
+
 #[cfg(test)]
 mod tests {
   use super::{tag::UncheckedRecommendRelayURL, *};
@@ -253,6 +496,29 @@ mod tests {
     assert_eq!(expected_serialized, expected_event.as_json());
   }
 
+  #[test]
+  fn from_json_strict_rejects_unknown_fields() {
+    let (expected_event, expected_serialized) = make_sut(false, false);
+    let with_unknown_field = expected_serialized.replacen('{', r#"{"foo":"bar","#, 1);
+
+    assert_eq!(
+      Event::from_json_strict(&expected_serialized).unwrap(),
+      expected_event
+    );
+    assert!(matches!(
+      Event::from_json_strict(&with_unknown_field),
+      Err(Error::InvalidData)
+    ));
+    assert!(matches!(
+      Event::from_json_strict(r#"{"id":"missing the other fields"}"#),
+      Err(Error::Json(_))
+    ));
+    assert_eq!(
+      Event::from_json(&with_unknown_field).unwrap(),
+      expected_event
+    );
+  }
+
   #[test]
   fn check_event_id() {
     let (expected_event, _) = make_sut(false, true);
@@ -264,6 +530,60 @@ mod tests {
     assert_eq!(event_with_correct_signature.check_event_id(), true);
   }
 
+  #[test]
+  fn check_event_id_holds_for_content_with_tricky_characters() {
+    const CHARS: [char; 16] = [
+      '\0', '\\', '"', '\n', '\r', '\t', '\u{8}', '\u{c}', '\u{1f}', '\u{7f}', '/', 'u', 'é',
+      '\u{ffff}', '😀', '\u{10ffff}',
+    ];
+    // simple deterministic generator, so failures can be reproduced
+    let mut seed: u64 = 42;
+    let mut next = || {
+      seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+      (seed >> 33) as usize
+    };
+
+    for _ in 0..500 {
+      let len = next() % 32;
+      let content: String = (0..len).map(|_| CHARS[next() % CHARS.len()]).collect();
+      let event = Event::new_without_signature(
+        String::from("02c7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76"),
+        1673002822,
+        EventKind::Text,
+        vec![],
+        content.clone(),
+      );
+
+      assert!(event.check_event_id(), "content: {content:?}");
+
+      // the id must still match after going over the wire
+      let received = Event::from_json(event.as_json()).unwrap();
+      assert_eq!(received.content, content);
+      assert!(received.check_event_id(), "content: {content:?}");
+    }
+  }
+
+  #[test]
+  fn check_event_id_holds_for_escaped_surrogate_pairs() {
+    let event = Event::new_without_signature(
+      String::from("02c7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76"),
+      1673002822,
+      EventKind::Text,
+      vec![],
+      String::from("😀"),
+    );
+    // same event, but with the emoji escaped as a UTF-16 surrogate pair
+    let escaped_json = event.as_json().replace('😀', "\\ud83d\\ude00");
+    assert!(escaped_json.contains(r"\ud83d\ude00"));
+
+    let received = Event::from_json(escaped_json).unwrap();
+
+    assert_eq!(received.content, String::from("😀"));
+    assert!(received.check_event_id());
+    // a lone surrogate is not valid UTF-8, so it can't be the content of an event
+    assert!(Event::from_json(event.as_json().replace('😀', "\\ud83d")).is_err());
+  }
+
   #[test]
   fn check_event_signature() {
     let (expected_event, _) = make_sut(false, true);
@@ -275,6 +595,119 @@ mod tests {
     assert_eq!(event_with_correct_signature.check_event_signature(), true);
   }
 
+  #[test]
+  fn expiration() {
+    let expiration_tag = Tag::Generic(
+      TagKind::Custom(String::from("expiration")),
+      vec![String::from("1684589418")],
+    );
+    let event = Event {
+      tags: vec![expiration_tag],
+      ..Default::default()
+    };
+    let event_without_expiration = Event::default();
+    let event_with_invalid_expiration = Event {
+      tags: vec![Tag::Generic(
+        TagKind::Custom(String::from("expiration")),
+        vec![String::from("potato")],
+      )],
+      ..Default::default()
+    };
+
+    assert_eq!(event.expiration(), Some(1684589418));
+    assert_eq!(event_without_expiration.expiration(), None);
+    assert_eq!(event_with_invalid_expiration.expiration(), None);
+
+    assert_eq!(event.is_expired(1684589417), false);
+    assert!(event.is_expired(1684589418));
+    assert!(event.is_expired(1684589419));
+    assert_eq!(event_without_expiration.is_expired(u64::MAX), false);
+  }
+
+  #[test]
+  fn tags_by_kind_and_first_tag() {
+    let root = Tag::Event(EventId(String::from("root")), None, Some(Marker::Root));
+    let reply = Tag::Event(EventId(String::from("reply")), None, Some(Marker::Reply));
+    let pubkey = Tag::PubKey(vec![String::from("pubkey")], None);
+    let hashtag = Tag::hashtag("nostr");
+    let event = Event {
+      tags: vec![root.clone(), hashtag.clone(), pubkey.clone(), reply.clone()],
+      ..Default::default()
+    };
+
+    assert_eq!(event.tags_by_kind(TagKind::Event), vec![&root, &reply]);
+    assert_eq!(event.tags_by_kind(TagKind::PubKey), vec![&pubkey]);
+    assert_eq!(event.tags_by_kind(TagKind::from("t")), vec![&hashtag]);
+    assert!(event.tags_by_kind(TagKind::from("d")).is_empty());
+
+    assert_eq!(event.first_tag(TagKind::Event), Some(&root));
+    assert_eq!(event.first_tag(TagKind::from("t")), Some(&hashtag));
+    assert_eq!(event.first_tag(TagKind::from("d")), None);
+  }
+
+  #[test]
+  fn alt() {
+    let description = "A calendar event about nostr";
+    let event = Event {
+      kind: EventKind::Custom(31923),
+      tags: vec![Tag::alt(description)],
+      ..Default::default()
+    };
+
+    assert_eq!(event.alt(), Some(description.to_string()));
+    assert_eq!(Event::default().alt(), None);
+
+    let event_from_json = Event::from_json(event.as_json()).unwrap();
+    assert_eq!(event_from_json.alt(), Some(description.to_string()));
+    assert!(event
+      .as_json()
+      .contains(r#"["alt","A calendar event about nostr"]"#));
+  }
+
+  #[test]
+  fn identifier_and_address() {
+    let event = Event {
+      kind: EventKind::Custom(30023),
+      pubkey: String::from("614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6"),
+      tags: vec![Tag::identifier("potato-recipes")],
+      ..Default::default()
+    };
+
+    assert_eq!(event.identifier(), String::from("potato-recipes"));
+    assert_eq!(
+      event.address(),
+      String::from(
+        "30023:614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6:potato-recipes"
+      )
+    );
+    assert_eq!(Event::default().identifier(), String::new());
+  }
+
+  #[test]
+  fn content_as_json_and_content_as() {
+    let event = Event {
+      kind: EventKind::Metadata,
+      content: String::from(r#"{"name":"bob","about":"potato","nip05":"bob@example.com"}"#),
+      ..Default::default()
+    };
+
+    assert_eq!(
+      event.content_as_json().unwrap(),
+      json!({"name": "bob", "about": "potato", "nip05": "bob@example.com"})
+    );
+
+    let metadata: crate::client::Metadata = event.content_as().unwrap();
+    assert_eq!(metadata.name, String::from("bob"));
+    assert_eq!(metadata.about, String::from("potato"));
+    assert_eq!(metadata.nip05(), Some("bob@example.com"));
+
+    let not_json = Event {
+      content: String::from("potato"),
+      ..Default::default()
+    };
+    assert!(matches!(not_json.content_as_json(), Err(Error::Json(_))));
+  }
+
   #[test]
   fn sign_event() {
     let event_sut = make_sut(false, false);
@@ -293,4 +726,181 @@ mod tests {
 
     assert_eq!(event.check_event_signature(), true);
   }
+
+  #[test]
+  fn build_event_without_a_client() {
+    let keys = crate::schnorr::generate_keys();
+
+    let event = build_event(
+      &keys,
+      EventKind::Text,
+      String::from("potato"),
+      vec![Tag::hashtag("nostr")],
+    );
+
+    assert_eq!(event.verify(), Ok(()));
+    assert_eq!(event.pubkey, keys.public_key.to_string()[2..].to_string());
+    assert_eq!(event.kind, EventKind::Text);
+    assert_eq!(event.content, String::from("potato"));
+    assert_eq!(event.tags, vec![Tag::hashtag("nostr")]);
+    assert!(event.created_at > 0);
+  }
+
+  #[test]
+  fn verify() {
+    let keys = Keys::from(&crate::schnorr::generate_keys());
+    let mut event = Event::new_without_signature(
+      hex::encode(&keys.public_key),
+      1683183423,
+      EventKind::Text,
+      vec![],
+      String::from("potato"),
+    );
+    event.sign_event(keys.private_key.clone());
+
+    assert_eq!(event.verify(), Ok(()));
+
+    let tampered_content = Event {
+      content: String::from("tomato"),
+      ..event.clone()
+    };
+    assert_eq!(tampered_content.verify(), Err(EventVerifyError::IdMismatch));
+
+    let malformed_signature = Event {
+      sig: String::from("not a signature"),
+      ..event.clone()
+    };
+    assert_eq!(
+      malformed_signature.verify(),
+      Err(EventVerifyError::InvalidSignatureFormat)
+    );
+
+    let tampered_signature = Event {
+      sig: event.sig.replace(&event.sig[..4], "0000"),
+      ..event.clone()
+    };
+    assert_eq!(
+      tampered_signature.verify(),
+      Err(EventVerifyError::SignatureVerificationFailed)
+    );
+
+    // signed by someone else
+    let mut impostor = event.clone();
+    let someone_else = Keys::from(&crate::schnorr::generate_keys());
+    impostor.sign_event(someone_else.private_key);
+    assert_eq!(
+      impostor.verify(),
+      Err(EventVerifyError::SignatureVerificationFailed)
+    );
+  }
+
+  #[test]
+  fn is_valid_hex_fields() {
+    let event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    ).unwrap();
+    assert!(event.is_valid_hex_fields());
+    assert_eq!(event.malformed_hex_field(), None);
+    // compressed pubkeys are not, so each key has a single form
+    let compressed_pubkey = Event {
+      pubkey: format!("02{}", event.pubkey),
+      ..event.clone()
+    };
+    assert_eq!(compressed_pubkey.malformed_hex_field(), Some("pubkey"));
+
+    let wrong_length = [
+      Event {
+        id: event.id[..63].to_string(),
+        ..event.clone()
+      },
+      Event {
+        pubkey: format!("{}0", event.pubkey),
+        ..event.clone()
+      },
+      Event {
+        sig: format!("{}00", event.sig),
+        ..event.clone()
+      },
+    ];
+    let non_hex = [
+      Event {
+        id: event.id.replacen('0', "g", 1),
+        ..event.clone()
+      },
+      Event {
+        pubkey: event.pubkey.replacen('6', "z", 1),
+        ..event.clone()
+      },
+      Event {
+        sig: event.sig.replacen('b', " ", 1),
+        ..event.clone()
+      },
+    ];
+    for malformed in [wrong_length, non_hex] {
+      for (malformed, field) in malformed.iter().zip(["id", "pubkey", "sig"]) {
+        assert_eq!(malformed.is_valid_hex_fields(), false);
+        assert_eq!(malformed.malformed_hex_field(), Some(field));
+      }
+    }
+  }
+
+  #[test]
+  fn resign() {
+    let keys = Keys::from(&crate::schnorr::generate_keys());
+    let mut draft = Event::new_without_signature(
+      hex::encode(&keys.public_key),
+      1683183423,
+      EventKind::Text,
+      vec![],
+      String::from("first draft"),
+    );
+    draft.sign_event(keys.private_key.clone());
+    let first_draft = draft.clone();
+
+    draft.content = String::from("final version");
+    draft.tags.push(Tag::hashtag("nostr"));
+    assert_eq!(draft.is_fully_valid(), false);
+
+    draft.resign(&keys);
+
+    assert!(draft.is_fully_valid());
+    assert_ne!(draft.id, first_draft.id);
+    assert_ne!(draft.sig, first_draft.sig);
+    assert_eq!(draft.content, String::from("final version"));
+  }
+
+  #[test]
+  fn is_fully_valid() {
+    let event_sut = make_sut(false, false);
+    let keys = crate::schnorr::generate_keys();
+    let pubkey = &keys.public_key.to_string()[2..];
+    let mut event = Event::new_without_signature(
+      pubkey.to_string(),
+      event_sut.0.created_at,
+      event_sut.0.kind,
+      event_sut.0.tags,
+      event_sut.0.content,
+    );
+    event.sign_event(keys.private_key.secret_bytes().to_vec());
+
+    assert_eq!(event.is_fully_valid(), true);
+
+    // valid signature over an id that does not match the event data
+    let mut event_with_miscomputed_id = Event {
+      id: Event::new_without_signature(
+        pubkey.to_string(),
+        event.created_at,
+        event.kind,
+        vec![],
+        String::from("another content"),
+      )
+      .id,
+      ..event
+    };
+    event_with_miscomputed_id.sign_event(keys.private_key.secret_bytes().to_vec());
+
+    assert_eq!(event_with_miscomputed_id.check_event_signature(), true);
+    assert_eq!(event_with_miscomputed_id.check_event_id(), false);
+    assert_eq!(event_with_miscomputed_id.is_fully_valid(), false);
+  }
 }
@@ -3,7 +3,26 @@ use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer}
 use std::{fmt, vec};
 use url::Url;
 
-use super::{EventId, Marker, PubKey};
+use super::{EventId, Marker, PubKey, Timestamp};
+
+/// Name of the tag holding a human-readable description of the event (NIP-31).
+pub(crate) const ALT_TAG: &str = "alt";
+
+/// Name of the tag that holds the timestamp at which the event expires (NIP-40).
+pub(crate) const EXPIRATION_TAG: &str = "expiration";
+
+/// Name of the tag holding a hashtag of the event (NIP-12).
+pub(crate) const HASHTAG_TAG: &str = "t";
+
+/// Name of the tag holding a reference (URL) of the event (NIP-12).
+pub(crate) const REFERENCE_TAG: &str = "r";
+
+/// Name of the tag holding the identifier of a parameterized replaceable event (NIP-33).
+pub(crate) const IDENTIFIER_TAG: &str = "d";
+
+/// Name of the tag referencing a parameterized replaceable event (NIP-33)
+/// by its address, `<kind>:<pubkey>:<d tag>`.
+pub(crate) const ADDRESS_TAG: &str = "a";
 
 /// [`Tag`] error
 #[derive(Debug, thiserror::Error)]
@@ -81,11 +100,7 @@ where
 
 impl From<Tag> for TagKind {
   fn from(data: Tag) -> Self {
-    match data {
-      Tag::Generic(kind, _) => kind,
-      Tag::Event(_, _, _) => TagKind::Event,
-      Tag::PubKey(_, _) => TagKind::PubKey,
-    }
+    data.kind()
   }
 }
 
@@ -118,6 +133,15 @@ pub enum Tag {
 }
 
 impl Tag {
+  /// Gets the kind (first element) of the tag.
+  pub fn kind(&self) -> TagKind {
+    match self {
+      Tag::Generic(kind, _) => kind.clone(),
+      Tag::Event(_, _, _) => TagKind::Event,
+      Tag::PubKey(_, _) => TagKind::PubKey,
+    }
+  }
+
   pub fn as_str(&self) -> String {
     serde_json::to_string(self).unwrap()
   }
@@ -133,6 +157,67 @@ impl Tag {
   pub fn from_vec(data: Vec<String>) -> Self {
     Self::try_from(data).unwrap()
   }
+
+  /// Creates an `["alt", <description>]` tag (NIP-31), used by clients
+  /// as a fallback to show events of kinds they don't understand.
+  ///
+  pub fn alt(description: &str) -> Self {
+    Self::Generic(
+      TagKind::Custom(ALT_TAG.to_string()),
+      vec![description.to_string()],
+    )
+  }
+
+  /// Creates an `["expiration", <unix timestamp>]` tag (NIP-40): relays
+  /// stop serving the event once `expires_at` is reached.
+  ///
+  pub fn expiration(expires_at: Timestamp) -> Self {
+    Self::Generic(
+      TagKind::Custom(EXPIRATION_TAG.to_string()),
+      vec![expires_at.to_string()],
+    )
+  }
+
+  /// Creates a `["t", <hashtag>]` tag, which can be queried
+  /// with the `#t` of a filter (NIP-12).
+  ///
+  pub fn hashtag(hashtag: &str) -> Self {
+    Self::Generic(
+      TagKind::Custom(HASHTAG_TAG.to_string()),
+      vec![hashtag.to_string()],
+    )
+  }
+
+  /// Creates an `["r", <url>]` tag, referencing e.g. a web page the event
+  /// is about, which can be queried with the `#r` of a filter (NIP-12).
+  ///
+  pub fn reference(url: &str) -> Self {
+    Self::Generic(
+      TagKind::Custom(REFERENCE_TAG.to_string()),
+      vec![url.to_string()],
+    )
+  }
+
+  /// Creates a `["d", <identifier>]` tag, which identifies a parameterized
+  /// replaceable event among the ones of the same kind and author (NIP-33).
+  ///
+  pub fn identifier(identifier: &str) -> Self {
+    Self::Generic(
+      TagKind::Custom(IDENTIFIER_TAG.to_string()),
+      vec![identifier.to_string()],
+    )
+  }
+
+  /// Creates an `["a", <kind>:<pubkey>:<d tag>]` tag referencing a
+  /// parameterized replaceable event (see [`crate::event::Event::address`]),
+  /// which can be queried with the `#a` of a filter (NIP-33).
+  ///
+  pub fn address(address: &str) -> Self {
+    Self::Generic(
+      TagKind::Custom(ADDRESS_TAG.to_string()),
+      vec![address.to_string()],
+    )
+  }
 }
 
 /// Helper function to check pubkey ("p") tag.
@@ -493,6 +578,21 @@ mod tests {
     assert_eq!(Tag::from_vec(third_p_tag_vector), third_expected_p_tag);
   }
 
+  #[test]
+  fn test_generic_tag_constructors() {
+    let hashtag = Tag::hashtag("potato");
+    let reference = Tag::reference("https://potato.com");
+    let identifier = Tag::identifier("cake");
+
+    assert_eq!(hashtag.as_str(), r#"["t","potato"]"#);
+    assert_eq!(reference.as_str(), r#"["r","https://potato.com"]"#);
+    assert_eq!(identifier.as_str(), r#"["d","cake"]"#);
+
+    assert_eq!(Tag::from_vec(hashtag.as_vec()), hashtag);
+    assert_eq!(Tag::from_vec(reference.as_vec()), reference);
+    assert_eq!(Tag::from_vec(identifier.as_vec()), identifier);
+  }
+
   #[test]
   fn test_tag_serializes_and_deserializes_correctly() {
     // Generic - serialization
@@ -0,0 +1,141 @@
+use super::{id::EventId, marker::Marker, tag::Tag, Event};
+
+/// Position of an event in a reply thread, as described by
+/// its `"e"` tags (NIP-10).
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Thread {
+  /// Id of the top-level event of the thread.
+  pub root: Option<EventId>,
+  /// Id of the event being replied to, when it is not the root.
+  pub reply: Option<EventId>,
+  /// Ids of the events that are only quoted or reposted.
+  pub mentions: Vec<EventId>,
+}
+
+/// Parses the `"e"` tags of an event into a [`Thread`].
+///
+/// When any of the tags has a `root`, `reply` or `mention` marker, the markers
+/// are honored and unmarked tags are taken as mentions. Otherwise the deprecated
+/// positional scheme is used:
+///   - one tag: the id of the root being replied to;
+///   - two or more tags: the first one is the root, the last one is the reply
+///     and the ones in between are mentions.
+///
+pub fn parse_thread(event: &Event) -> Thread {
+  let e_tags: Vec<(&EventId, &Option<Marker>)> = event
+    .tags
+    .iter()
+    .filter_map(|tag| match tag {
+      Tag::Event(event_id, _, marker) => Some((event_id, marker)),
+      _ => None,
+    })
+    .collect();
+
+  let is_marked = e_tags.iter().any(|(_, marker)| {
+    matches!(
+      marker,
+      Some(Marker::Root) | Some(Marker::Reply) | Some(Marker::Mention)
+    )
+  });
+
+  let mut thread = Thread::default();
+
+  if is_marked {
+    for (event_id, marker) in e_tags {
+      match marker {
+        Some(Marker::Root) if thread.root.is_none() => thread.root = Some(event_id.clone()),
+        Some(Marker::Reply) if thread.reply.is_none() => thread.reply = Some(event_id.clone()),
+        _ => thread.mentions.push(event_id.clone()),
+      }
+    }
+    return thread;
+  }
+
+  if let Some(((root, _), rest)) = e_tags.split_first() {
+    thread.root = Some((*root).clone());
+    if let Some(((reply, _), mentions)) = rest.split_last() {
+      thread.reply = Some((*reply).clone());
+      thread.mentions = mentions
+        .iter()
+        .map(|(event_id, _)| (*event_id).clone())
+        .collect();
+    }
+  }
+
+  thread
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  fn event_with_tags(tags: Vec<Tag>) -> Event {
+    Event {
+      tags,
+      ..Default::default()
+    }
+  }
+
+  fn e_tag(id: &str, marker: Option<Marker>) -> Tag {
+    Tag::Event(EventId(id.to_string()), None, marker)
+  }
+
+  #[test]
+  fn parse_thread_without_e_tags() {
+    let event = event_with_tags(vec![Tag::PubKey(vec![String::from("pubkey")], None)]);
+
+    assert_eq!(parse_thread(&event), Thread::default());
+  }
+
+  #[test]
+  fn parse_thread_with_markers() {
+    let event = event_with_tags(vec![
+      e_tag("mention", Some(Marker::Mention)),
+      e_tag("reply", Some(Marker::Reply)),
+      e_tag("unmarked", None),
+      e_tag("root", Some(Marker::Root)),
+    ]);
+
+    assert_eq!(
+      parse_thread(&event),
+      Thread {
+        root: Some(EventId(String::from("root"))),
+        reply: Some(EventId(String::from("reply"))),
+        mentions: vec![
+          EventId(String::from("mention")),
+          EventId(String::from("unmarked"))
+        ],
+      }
+    );
+  }
+
+  #[test]
+  fn parse_thread_with_positional_e_tags() {
+    let reply_to_root = event_with_tags(vec![e_tag("root", None)]);
+    assert_eq!(
+      parse_thread(&reply_to_root),
+      Thread {
+        root: Some(EventId(String::from("root"))),
+        ..Default::default()
+      }
+    );
+
+    let reply_to_reply = event_with_tags(vec![
+      e_tag("root", None),
+      e_tag("mention", Some(Marker::Default)),
+      e_tag("reply", None),
+    ]);
+    assert_eq!(
+      parse_thread(&reply_to_reply),
+      Thread {
+        root: Some(EventId(String::from("root"))),
+        reply: Some(EventId(String::from("reply"))),
+        mentions: vec![EventId(String::from("mention"))],
+      }
+    );
+  }
+}
@@ -1,8 +1,36 @@
-use std::vec;
-
-use serde::{Deserialize, Serialize};
+use std::{
+  fmt::Write,
+  time::{SystemTime, UNIX_EPOCH},
+  vec,
+};
+
+use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize};
+
+use crate::event::{
+  id::EventId,
+  kind::EventKind,
+  tag::{Tag, TagKind, ADDRESS_TAG, HASHTAG_TAG},
+  Event, PubKey, Timestamp,
+};
+
+/// How the `ids` and `authors` of a filter are compared with the ones of the events.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IdMatching {
+  /// The id (or pubkey) of the event must start with the one of the filter (NIP-01).
+  #[default]
+  Prefix,
+  /// The id (or pubkey) of the event must be the one of the filter.
+  Exact,
+}
 
-use crate::event::{id::EventId, kind::EventKind, PubKey, Timestamp};
+impl IdMatching {
+  fn matches(&self, event_value: &str, filter_value: &str) -> bool {
+    match self {
+      Self::Prefix => event_value.starts_with(filter_value),
+      Self::Exact => event_value == filter_value,
+    }
+  }
+}
 
 ///
 /// Filters are data structures that clients send to relays (being the first on the first connection)
@@ -12,33 +40,80 @@ use crate::event::{id::EventId, kind::EventKind, PubKey, Timestamp};
 /// P.S.: a "REQ" communication from the client can have multiple filters. In this case, all filters will be
 /// used as `||` operator: anything that matches any of the filters will be sent.
 ///
-/// - ids: a list of events of prefixes
-/// - authors: a list of publickeys or prefixes, the pubkey of an event must be one of these
 /// - kinds: a list of kind numbers
-/// - e: a list of event ids that are referenced in an "e" tag,
-/// - p: a list of pubkeys that are referenced in an "p" tag,
+/// - authors: a list of publickeys or prefixes, the pubkey of an event must be one of these
+/// - ids: a list of events of prefixes
 /// - since: a timestamp. Events must be newer than this to pass
 /// - until: a timestamp. Events must be older than this to pass
 /// - limit: maximum number of events to be returned in the initial query (it can be ignored afterwards)
+/// - e: a list of event ids that are referenced in an "e" tag,
+/// - p: a list of pubkeys that are referenced in an "p" tag,
+/// - t: a list of hashtags that are referenced in a "t" tag (NIP-12),
+/// - a: a list of addresses (`<kind>:<pubkey>:<d tag>`) that are referenced in an "a" tag (NIP-33),
+/// - search: a text the content of the events must contain, ignoring case (NIP-50)
+///
+/// With the `filter-exclusions` feature, it also has (non-standard, so only relays
+/// built with it understand them; the others ignore them):
+/// - exclude_authors: a list of publickeys or prefixes, the pubkey of an event must be none of these
+/// - exclude_kinds: a list of kind numbers, the kind of an event must be none of these
+///
+/// It is serialized in this order, which is the one most relays and clients use,
+/// without the fields that are not set. An empty list is kept, as it matches no event.
 ///
 #[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
 pub struct Filter {
   #[serde(skip_serializing_if="Option::is_none")]
-  pub ids: Option<Vec<EventId>>,
+  pub kinds: Option<Vec<EventKind>>,
   #[serde(skip_serializing_if="Option::is_none")]
   pub authors: Option<Vec<PubKey>>,
   #[serde(skip_serializing_if="Option::is_none")]
-  pub kinds: Option<Vec<EventKind>>,
+  pub ids: Option<Vec<EventId>>,
+  #[serde(default, deserialize_with="deserialize_timestamp", skip_serializing_if="Option::is_none")]
+  pub since: Option<Timestamp>,
+  #[serde(default, deserialize_with="deserialize_timestamp", skip_serializing_if="Option::is_none")]
+  pub until: Option<Timestamp>,
+  #[serde(skip_serializing_if="Option::is_none")]
+  pub limit: Option<Timestamp>,
   #[serde(alias = "#e", rename(serialize = "#e"), skip_serializing_if="Option::is_none")]
   pub e: Option<Vec<String>>,
   #[serde(alias = "#p", rename(serialize = "#p"), skip_serializing_if="Option::is_none")]
   pub p: Option<Vec<String>>,
+  #[serde(alias = "#t", rename(serialize = "#t"), skip_serializing_if="Option::is_none")]
+  pub t: Option<Vec<String>>,
+  #[serde(alias = "#a", rename(serialize = "#a"), skip_serializing_if="Option::is_none")]
+  pub a: Option<Vec<String>>,
   #[serde(skip_serializing_if="Option::is_none")]
-  pub since: Option<Timestamp>,
+  pub search: Option<String>,
+  #[cfg(feature = "filter-exclusions")]
   #[serde(skip_serializing_if="Option::is_none")]
-  pub until: Option<Timestamp>,
+  pub exclude_authors: Option<Vec<PubKey>>,
+  #[cfg(feature = "filter-exclusions")]
   #[serde(skip_serializing_if="Option::is_none")]
-  pub limit: Option<Timestamp>,
+  pub exclude_kinds: Option<Vec<EventKind>>,
+}
+
+/// A timestamp sent as a number or as a string of one (e.g.: `"1673002822"`).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LenientTimestamp {
+  Number(Timestamp),
+  Text(String),
+}
+
+/// Deserializes the `since` and `until` of a filter, coercing the ones
+/// sent as strings by some clients to numbers.
+///
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<Timestamp>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  match Option::<LenientTimestamp>::deserialize(deserializer)? {
+    Some(LenientTimestamp::Number(timestamp)) => Ok(Some(timestamp)),
+    Some(LenientTimestamp::Text(timestamp)) => {
+      timestamp.parse().map(Some).map_err(D::Error::custom)
+    }
+    None => Ok(None),
+  }
 }
 
 impl Filter {
@@ -101,6 +176,24 @@ impl Filter {
     self
   }
 
+  pub fn add_t_tags(&mut self, t_tags: Vec<String>) -> &mut Self {
+    if t_tags.is_empty() {
+      return self
+    }
+
+    self.t = Some(t_tags);
+    self
+  }
+
+  pub fn add_a_tags(&mut self, a_tags: Vec<String>) -> &mut Self {
+    if a_tags.is_empty() {
+      return self
+    }
+
+    self.a = Some(a_tags);
+    self
+  }
+
   pub fn add_since(&mut self, since: u64) -> &mut Self {
     self.since = Some(since);
     self
@@ -116,6 +209,323 @@ impl Filter {
     self
   }
 
+  pub fn add_search(&mut self, search: &str) -> &mut Self {
+    self.search = Some(search.to_string());
+    self
+  }
+
+  #[cfg(feature = "filter-exclusions")]
+  pub fn add_exclude_authors(&mut self, authors: Vec<PubKey>) -> &mut Self {
+    if authors.is_empty() {
+      return self
+    }
+
+    self.exclude_authors = Some(authors);
+    self
+  }
+
+  #[cfg(feature = "filter-exclusions")]
+  pub fn add_exclude_kinds(&mut self, kinds: Vec<EventKind>) -> &mut Self {
+    if kinds.is_empty() {
+      return self
+    }
+
+    self.exclude_kinds = Some(kinds);
+    self
+  }
+
+  /// Sets the `ids` of the filter, consuming it
+  /// (`Filter::new().ids(..).kinds(..)`).
+  pub fn ids(mut self, ids: Vec<String>) -> Self {
+    self.add_ids(ids);
+    self
+  }
+
+  /// Sets the `authors` of the filter, consuming it.
+  pub fn authors(mut self, authors: Vec<PubKey>) -> Self {
+    self.add_authors(authors);
+    self
+  }
+
+  /// Sets the `kinds` of the filter, consuming it.
+  pub fn kinds(mut self, kinds: Vec<EventKind>) -> Self {
+    self.add_kinds(kinds.into_iter().map(u64::from).collect());
+    self
+  }
+
+  /// Sets the `#e` tags of the filter, consuming it.
+  pub fn e_tags(mut self, e_tags: Vec<String>) -> Self {
+    self.add_e_tags(e_tags);
+    self
+  }
+
+  /// Sets the `#p` tags of the filter, consuming it.
+  pub fn p_tags(mut self, p_tags: Vec<String>) -> Self {
+    self.add_p_tags(p_tags);
+    self
+  }
+
+  /// Sets the `#t` tags (hashtags) of the filter, consuming it.
+  pub fn t_tags(mut self, t_tags: Vec<String>) -> Self {
+    self.add_t_tags(t_tags);
+    self
+  }
+
+  /// Sets the `#a` tags (addresses of parameterized replaceable events) of the filter, consuming it.
+  pub fn a_tags(mut self, a_tags: Vec<String>) -> Self {
+    self.add_a_tags(a_tags);
+    self
+  }
+
+  /// Sets the `since` of the filter, consuming it.
+  pub fn since(mut self, since: Timestamp) -> Self {
+    self.add_since(since);
+    self
+  }
+
+  /// Sets the `until` of the filter, consuming it.
+  pub fn until(mut self, until: Timestamp) -> Self {
+    self.add_until(until);
+    self
+  }
+
+  /// Sets the `limit` of the filter, consuming it.
+  pub fn limit(mut self, limit: u64) -> Self {
+    self.add_limit(limit);
+    self
+  }
+
+  /// Sets the `search` (NIP-50) of the filter, consuming it.
+  pub fn search(mut self, search: &str) -> Self {
+    self.add_search(search);
+    self
+  }
+
+  /// Sets the `exclude_authors` of the filter, consuming it.
+  #[cfg(feature = "filter-exclusions")]
+  pub fn exclude_authors(mut self, authors: Vec<PubKey>) -> Self {
+    self.add_exclude_authors(authors);
+    self
+  }
+
+  /// Sets the `exclude_kinds` of the filter, consuming it.
+  #[cfg(feature = "filter-exclusions")]
+  pub fn exclude_kinds(mut self, kinds: Vec<EventKind>) -> Self {
+    self.add_exclude_kinds(kinds);
+    self
+  }
+
+  /// Checks if the filter can match any event at all,
+  /// i.e.: its `since` is not after its `until`.
+  ///
+  pub fn is_valid(&self) -> bool {
+    match (self.since, self.until) {
+      (Some(since), Some(until)) => since <= until,
+      _ => true,
+    }
+  }
+
+  /// Checks if the event matches all the conditions set in the filter.
+  /// Its `ids` and `authors` can be prefixes (see [`IdMatching::Prefix`]).
+  ///
+  pub fn matches(&self, event: &Event) -> bool {
+    self.matches_with(event, IdMatching::Prefix)
+  }
+
+  /// Same as [`Filter::matches`], comparing the `ids` and `authors` as set by `id_matching`.
+  ///
+  pub fn matches_with(&self, event: &Event, id_matching: IdMatching) -> bool {
+    // Check exclusions: an excluded event never matches, whatever the rest of the filter
+    #[cfg(feature = "filter-exclusions")]
+    {
+      let author_excluded = self.exclude_authors.as_ref().is_some_and(|authors| {
+        authors
+          .iter()
+          .any(|author| id_matching.matches(&event.pubkey, author))
+      });
+      let kind_excluded = self
+        .exclude_kinds
+        .as_ref()
+        .is_some_and(|kinds| kinds.contains(&event.kind));
+      if author_excluded || kind_excluded {
+        return false;
+      }
+    }
+
+    // Check IDs
+    if let Some(ids) = &self.ids {
+      let id_in_list = ids.iter().any(|id| id_matching.matches(&event.id, &id.0));
+      if !id_in_list {
+        return false;
+      }
+    }
+
+    // Check Authors
+    if let Some(authors) = &self.authors {
+      let author_in_list = authors
+        .iter()
+        .any(|author| id_matching.matches(&event.pubkey, author));
+      if !author_in_list {
+        return false;
+      }
+    }
+
+    // Check Kinds
+    if let Some(kinds) = &self.kinds {
+      let kind_in_list = kinds.iter().any(|kind| *kind == event.kind);
+      if !kind_in_list {
+        return false;
+      }
+    }
+
+    // Check Since
+    if let Some(since) = self.since {
+      let event_after_since = since <= event.created_at;
+      if !event_after_since {
+        return false;
+      }
+    }
+
+    // Check Until
+    if let Some(until) = self.until {
+      let event_before_until = until >= event.created_at;
+      if !event_before_until {
+        return false;
+      }
+    }
+
+    // Check #e tag
+    if let Some(event_ids) = &self.e {
+      // replies have both the `root` and the `reply` "e" tags (NIP-10),
+      // so any of them can be the one being filtered
+      let has_event_tag_in_list = event.tags.iter().any(|event_tag| match event_tag {
+        Tag::Event(event_event_tag_id, _, _) => event_ids
+          .iter()
+          .any(|event_id| *event_id == event_event_tag_id.0),
+        _ => false,
+      });
+      if !has_event_tag_in_list {
+        return false;
+      }
+    }
+
+    // Check #p tag
+    if let Some(pubkeys) = &self.p {
+      let event_pubkey_tag_pubkey = event.tags.iter().find_map(|event_tag| match event_tag {
+        Tag::PubKey(event_pubkey_tag_pubkey, _) => Some(event_pubkey_tag_pubkey),
+        _ => None,
+      });
+      match event_pubkey_tag_pubkey {
+        Some(event_pubkey_tag_pubkey) => {
+          for pubkey in pubkeys {
+            if !event_pubkey_tag_pubkey
+              .iter()
+              .any(|evt_pubkey_tag| evt_pubkey_tag == pubkey)
+            {
+              return false;
+            }
+          }
+        }
+        None => return false,
+      }
+    }
+
+    // Check #t tag (NIP-12)
+    if let Some(hashtags) = &self.t {
+      let has_hashtag_in_list = event.tags.iter().any(|event_tag| match event_tag {
+        Tag::Generic(TagKind::Custom(kind), values) if kind == HASHTAG_TAG => values
+          .first()
+          .is_some_and(|value| hashtags.contains(value)),
+        _ => false,
+      });
+      if !has_hashtag_in_list {
+        return false;
+      }
+    }
+
+    // Check #a tag (NIP-33)
+    if let Some(addresses) = &self.a {
+      let has_address_in_list = event.tags.iter().any(|event_tag| match event_tag {
+        Tag::Generic(TagKind::Custom(kind), values) if kind == ADDRESS_TAG => values
+          .first()
+          .is_some_and(|value| addresses.contains(value)),
+        _ => false,
+      });
+      if !has_address_in_list {
+        return false;
+      }
+    }
+
+    // Check search (NIP-50)
+    if let Some(search) = &self.search {
+      let content_has_search = event
+        .content
+        .to_lowercase()
+        .contains(&search.to_lowercase());
+      if !content_has_search {
+        return false;
+      }
+    }
+
+    true
+  }
+
+  /// Gets a compact human-readable description of the filter, to be used
+  /// in logs and UIs. E.g.: `authors(2) kinds(1,7) since(2d ago) limit(20)`.
+  ///
+  pub fn summary(&self) -> String {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .expect("Time went backwards")
+      .as_secs();
+    self.summary_at(now)
+  }
+
+  /// Same as [`Filter::summary`], with `since` and `until`
+  /// relative to `now` (a unix timestamp in seconds).
+  ///
+  pub fn summary_at(&self, now: Timestamp) -> String {
+    let mut summary = String::new();
+
+    let counts = [
+      ("ids", self.ids.as_ref().map(|ids| ids.len())),
+      ("authors", self.authors.as_ref().map(|authors| authors.len())),
+      ("#e", self.e.as_ref().map(|e| e.len())),
+      ("#p", self.p.as_ref().map(|p| p.len())),
+      ("#t", self.t.as_ref().map(|t| t.len())),
+      ("#a", self.a.as_ref().map(|a| a.len())),
+    ];
+    for (name, count) in counts {
+      if let Some(count) = count {
+        let _ = write!(summary, "{name}({count}) ");
+      }
+    }
+
+    if let Some(kinds) = &self.kinds {
+      summary.push_str("kinds(");
+      for (i, kind) in kinds.iter().enumerate() {
+        if i > 0 {
+          summary.push(',');
+        }
+        let _ = write!(summary, "{kind}");
+      }
+      summary.push_str(") ");
+    }
+
+    if let Some(since) = self.since {
+      let _ = write!(summary, "since({}) ", relative_time(since, now));
+    }
+    if let Some(until) = self.until {
+      let _ = write!(summary, "until({}) ", relative_time(until, now));
+    }
+    if let Some(limit) = self.limit {
+      let _ = write!(summary, "limit({limit}) ");
+    }
+
+    summary.truncate(summary.trim_end().len());
+    summary
+  }
+
   pub fn as_str(&self) -> String {
     serde_json::to_string(self).unwrap()
   }
@@ -129,6 +539,34 @@ impl Filter {
   }
 }
 
+/// Describes `timestamp` relative to `now` in the biggest unit that fits,
+/// like `2d ago`, `5m ago` or `in 3h`.
+///
+fn relative_time(timestamp: Timestamp, now: Timestamp) -> String {
+  const UNITS: [(u64, &str); 4] = [(86400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+
+  let (difference, is_past) = if timestamp <= now {
+    (now - timestamp, true)
+  } else {
+    (timestamp - now, false)
+  };
+  if difference == 0 {
+    return String::from("now");
+  }
+
+  let (seconds, unit) = UNITS
+    .iter()
+    .find(|(seconds, _)| difference >= *seconds)
+    .unwrap_or(&(1, "s"));
+  let amount = difference / seconds;
+
+  if is_past {
+    format!("{amount}{unit} ago")
+  } else {
+    format!("in {amount}{unit}")
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -137,6 +575,45 @@ mod tests {
   use pretty_assertions::assert_eq;
   use serde_json::{json, Value};
 
+  #[test]
+  fn test_filter_is_valid() {
+    let event = Event {
+      created_at: 150,
+      ..Default::default()
+    };
+
+    assert!(Filter::new().is_valid());
+    assert!(Filter::new().since(100).is_valid());
+    assert!(Filter::new().since(100).until(100).is_valid());
+    assert!(Filter::new().since(100).until(200).matches(&event));
+
+    let reversed = Filter::new().since(200).until(100);
+    assert!(!reversed.is_valid());
+    assert!(!reversed.matches(&event));
+  }
+
+  #[test]
+  fn test_filter_accepts_both_tag_spellings_and_string_timestamps() {
+    let expected = Filter::new()
+      .e_tags(vec![String::from("event_id")])
+      .p_tags(vec![String::from("pubkey")])
+      .since(1673002822)
+      .until(1684589418);
+
+    let spellings = [
+      json!({"#e": ["event_id"], "#p": ["pubkey"], "since": 1673002822, "until": 1684589418}),
+      json!({"e": ["event_id"], "p": ["pubkey"], "since": 1673002822, "until": 1684589418}),
+      json!({"#e": ["event_id"], "p": ["pubkey"], "since": "1673002822", "until": "1684589418"}),
+    ];
+    for spelling in spellings {
+      assert_eq!(Filter::from_string(spelling.to_string()).unwrap(), expected);
+    }
+
+    // a filter without them is still valid
+    assert_eq!(Filter::from_string(String::from("{}")).unwrap(), Filter::new());
+    assert!(Filter::from_string(json!({"since": "yesterday"}).to_string()).is_err());
+  }
+
   #[test]
   fn test_filter_chaining_methods() {
     let ids = vec![String::from("id1"), String::from("id2")];
@@ -161,6 +638,144 @@ mod tests {
     assert_eq!(filter_chained.limit, Some(limit));
   }
 
+  #[test]
+  fn test_filter_builder_methods() {
+    let filter_built = Filter::new()
+      .ids(vec![String::from("id1")])
+      .authors(vec![String::from("author1"), String::from("author2")])
+      .kinds(vec![EventKind::Text, EventKind::Reaction])
+      .e_tags(vec![String::from("e_tag1")])
+      .p_tags(vec![String::from("p_tag1")])
+      .t_tags(vec![String::from("t_tag1")])
+      .a_tags(vec![String::from("a_tag1")])
+      .since(10)
+      .until(11)
+      .limit(12)
+      .search("potato");
+
+    let expected = Filter {
+      ids: Some(vec![EventId(String::from("id1"))]),
+      authors: Some(vec![String::from("author1"), String::from("author2")]),
+      kinds: Some(vec![EventKind::Text, EventKind::Reaction]),
+      e: Some(vec![String::from("e_tag1")]),
+      p: Some(vec![String::from("p_tag1")]),
+      t: Some(vec![String::from("t_tag1")]),
+      a: Some(vec![String::from("a_tag1")]),
+      since: Some(10),
+      until: Some(11),
+      limit: Some(12),
+      search: Some(String::from("potato")),
+      #[cfg(feature = "filter-exclusions")]
+      exclude_authors: None,
+      #[cfg(feature = "filter-exclusions")]
+      exclude_kinds: None,
+    };
+
+    assert_eq!(filter_built, expected);
+    assert_eq!(filter_built.as_str(), expected.as_str());
+    assert_eq!(
+      Filter::new().authors(vec![]).since(10),
+      Filter {
+        since: Some(10),
+        ..Default::default()
+      }
+    );
+  }
+
+  #[test]
+  fn test_filter_matches_should_match_all_requirements_to_be_true() {
+    let mock_filter_id = String::from("05b25af3-4250-4fbf-8ef5-97220858f9ab");
+    let mock_filter_author =
+      String::from("02c7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76");
+    let mock_filter_e_tag =
+      String::from("ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb");
+    let mock_filter_p_tag =
+      String::from("02cd91b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76");
+
+    let filter = Filter::new()
+      .ids(vec![mock_filter_id.clone()])
+      .authors(vec![mock_filter_author.clone()])
+      .kinds(vec![EventKind::Text])
+      .e_tags(vec![mock_filter_e_tag.clone()])
+      .p_tags(vec![mock_filter_p_tag.clone()])
+      .since(1663183423)
+      .until(1683183423);
+    let event = Event {
+      id: mock_filter_id,
+      pubkey: mock_filter_author,
+      kind: EventKind::Text,
+      created_at: 1673183423,
+      tags: vec![
+        Tag::PubKey(vec![mock_filter_p_tag.clone()], None),
+        Tag::Event(EventId(mock_filter_e_tag.clone()), None, None),
+      ],
+      ..Default::default()
+    };
+
+    assert!(filter.matches(&event));
+
+    let events_not_matching = [
+      Event {
+        id: String::from("f6a54af2-1150-4fbf-8ef5-97220858f9ab"),
+        ..event.clone()
+      },
+      Event {
+        pubkey: String::from("02e7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76"),
+        ..event.clone()
+      },
+      Event {
+        kind: EventKind::RecommendRelay,
+        ..event.clone()
+      },
+      Event {
+        created_at: 1773183423,
+        ..event.clone()
+      },
+      Event {
+        tags: vec![
+          Tag::PubKey(vec![String::from("01cd91b1")], None),
+          Tag::Event(EventId(mock_filter_e_tag), None, None),
+        ],
+        ..event.clone()
+      },
+      Event {
+        tags: vec![
+          Tag::PubKey(vec![mock_filter_p_tag], None),
+          Tag::Event(EventId(String::from("21cd91b1")), None, None),
+        ],
+        ..event
+      },
+    ];
+
+    for event_not_matching in events_not_matching.iter() {
+      assert_eq!(filter.matches(event_not_matching), false);
+    }
+  }
+
+  #[test]
+  fn summary() {
+    let now = 1_700_000_000;
+    let filter = Filter::new()
+      .authors(vec![String::from("author1"), String::from("author2")])
+      .kinds(vec![EventKind::Text, EventKind::Reaction])
+      .since(now - 2 * 86400 - 60)
+      .until(now + 3 * 3600)
+      .limit(20);
+
+    assert_eq!(
+      filter.summary_at(now),
+      "authors(2) kinds(1,7) since(2d ago) until(in 3h) limit(20)"
+    );
+    assert_eq!(Filter::new().summary(), "");
+    assert_eq!(
+      Filter::new()
+        .ids(vec![String::from("id")])
+        .since(now)
+        .summary_at(now),
+      "ids(1) since(now)"
+    );
+  }
+
   #[test]
   fn from_string() {
     let filter = json!(
@@ -214,8 +829,8 @@ mod tests {
       p: Some(vec!["potato".to_string()]),
       kinds: Some(vec![
         EventKind::Text,
-        EventKind::Custom(6),
-        EventKind::Custom(7),
+        EventKind::Repost,
+        EventKind::Reaction,
         EventKind::Custom(9735),
       ]),
       ..Default::default()
@@ -238,8 +853,8 @@ mod tests {
       p: Some(vec!["potato".to_string()]),
       kinds: Some(vec![
         EventKind::Text,
-        EventKind::Custom(6),
-        EventKind::Custom(7),
+        EventKind::Repost,
+        EventKind::Reaction,
         EventKind::Custom(9735),
       ]),
       ..Default::default()
@@ -269,4 +884,70 @@ mod tests {
     assert_eq!(result["#p"], expected["#p"]);
     assert_eq!(result["authors"], expected["authors"]);
   }
+
+  #[test]
+  fn as_str_omits_the_fields_not_set_in_the_usual_order() {
+    let filter = Filter {
+      kinds: Some(vec![EventKind::Text]),
+      ..Default::default()
+    };
+    assert_eq!(filter.as_str(), r#"{"kinds":[1]}"#);
+
+    let filter = Filter {
+      ids: Some(vec![EventId(String::from("abc"))]),
+      authors: Some(vec![String::from("def")]),
+      kinds: Some(vec![EventKind::Text]),
+      e: Some(vec![String::from("ghi")]),
+      p: Some(vec![]),
+      since: Some(10),
+      until: Some(20),
+      limit: Some(5),
+      ..Default::default()
+    };
+    assert_eq!(
+      filter.as_str(),
+      r##"{"kinds":[1],"authors":["def"],"ids":["abc"],"since":10,"until":20,"limit":5,"#e":["ghi"],"#p":[]}"##
+    );
+  }
+
+  #[cfg(feature = "filter-exclusions")]
+  #[test]
+  fn exclusions_refuse_otherwise_matching_events() {
+    let excluded_author =
+      String::from("02c7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76");
+    let another_author =
+      String::from("02e7e1b1e9c175ab2d100baf1d5a66e73ecc044e9f8093d0c965741f26aa3abf76");
+    let event = Event {
+      pubkey: another_author,
+      kind: EventKind::Text,
+      ..Default::default()
+    };
+    let excluded_author_event = Event {
+      pubkey: excluded_author.clone(),
+      ..event.clone()
+    };
+    let excluded_kind_event = Event {
+      kind: EventKind::Reaction,
+      ..event.clone()
+    };
+    let filter = Filter::new()
+      .kinds(vec![EventKind::Text, EventKind::Reaction])
+      // prefixes, as with `authors`
+      .exclude_authors(vec![excluded_author[..8].to_string()])
+      .exclude_kinds(vec![EventKind::Reaction]);
+
+    assert!(filter.matches(&event));
+    assert_eq!(filter.matches(&excluded_author_event), false);
+    assert_eq!(filter.matches(&excluded_kind_event), false);
+    // without the exclusions, they would match
+    let without_exclusions = Filter::new().kinds(vec![EventKind::Text, EventKind::Reaction]);
+    assert!(without_exclusions.matches(&excluded_author_event));
+    assert!(without_exclusions.matches(&excluded_kind_event));
+
+    assert_eq!(
+      filter.as_str(),
+      r#"{"kinds":[1,7],"exclude_authors":["02c7e1b1"],"exclude_kinds":[7]}"#
+    );
+    assert_eq!(Filter::from_string(filter.as_str()).unwrap(), filter);
+  }
 }
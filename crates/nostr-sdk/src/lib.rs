@@ -6,4 +6,9 @@ pub mod relay;
 
 pub mod event;
 pub mod filter;
+pub mod nip05;
+pub mod nip11;
+pub mod nip19;
+pub mod nip28;
+pub mod nip65;
 pub mod schnorr;
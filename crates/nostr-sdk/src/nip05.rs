@@ -0,0 +1,252 @@
+//! NIP-05: mapping nostr keys to DNS-based internet identifiers.
+//!
+//! An identifier has the format `<local-part>@<domain>` and is verified by
+//! fetching `https://<domain>/.well-known/nostr.json?name=<local-part>`,
+//! which must contain the pubkey under `names.<local-part>`.
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::event::PubKey;
+
+/// [`Nip05`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+  #[error("Invalid NIP-05 identifier")]
+  InvalidIdentifier,
+  #[error("Could not fetch the NIP-05 well-known document: {0}")]
+  Fetch(String),
+}
+
+/// A NIP-05 identifier, i.e.: `<local-part>@<domain>`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nip05Identifier {
+  pub name: String,
+  pub domain: String,
+}
+
+impl Nip05Identifier {
+  /// Parses an identifier. A bare domain (e.g. `domain.com`) is
+  /// the same as `_@domain.com`.
+  ///
+  pub fn parse(identifier: &str) -> Result<Self, Error> {
+    let (name, domain) = match identifier.split_once('@') {
+      Some((name, domain)) => (name, domain),
+      None => ("_", identifier),
+    };
+
+    let is_valid_name = !name.is_empty()
+      && name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !is_valid_name || domain.is_empty() || domain.contains('@') {
+      return Err(Error::InvalidIdentifier);
+    }
+
+    Ok(Self {
+      name: name.to_lowercase(),
+      domain: domain.to_lowercase(),
+    })
+  }
+
+  /// Gets the URL of the well-known document that verifies this identifier.
+  pub fn well_known_url(&self) -> String {
+    format!(
+      "https://{}/.well-known/nostr.json?name={}",
+      self.domain, self.name
+    )
+  }
+}
+
+/// Checks if the well-known `nostr.json` document maps `name` to `pubkey`.
+///
+pub fn verify_from_json(document: &str, name: &str, pubkey: &str) -> bool {
+  let document: Value = match serde_json::from_str(document) {
+    Ok(document) => document,
+    Err(_) => return false,
+  };
+
+  document
+    .get("names")
+    .and_then(|names| names.get(name))
+    .and_then(|value| value.as_str())
+    == Some(pubkey)
+}
+
+/// Something able to verify a NIP-05 identifier against a pubkey,
+/// usually by fetching the well-known document of its domain (see [`HttpNip05Verifier`]).
+///
+#[async_trait]
+pub trait Nip05Verifier {
+  async fn verify(&self, identifier: &str, pubkey: &PubKey) -> Result<bool, Error>;
+}
+
+/// Verifies NIP-05 identifiers by fetching the well-known
+/// document of their domain over HTTPS.
+///
+#[derive(Debug, Clone)]
+pub struct HttpNip05Verifier {
+  client: reqwest::Client,
+}
+
+impl HttpNip05Verifier {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl Default for HttpNip05Verifier {
+  fn default() -> Self {
+    // the redirects of the well-known document must be ignored
+    let client = reqwest::Client::builder()
+      .redirect(reqwest::redirect::Policy::none())
+      .build()
+      .unwrap_or_default();
+
+    Self { client }
+  }
+}
+
+#[async_trait]
+impl Nip05Verifier for HttpNip05Verifier {
+  async fn verify(&self, identifier: &str, pubkey: &PubKey) -> Result<bool, Error> {
+    let identifier = Nip05Identifier::parse(identifier)?;
+    verify_from_url(
+      &self.client,
+      &identifier.well_known_url(),
+      &identifier.name,
+      pubkey,
+    )
+    .await
+  }
+}
+
+/// Fetches the well-known document at `url` and checks if it maps `name` to `pubkey`.
+///
+async fn verify_from_url(
+  client: &reqwest::Client,
+  url: &str,
+  name: &str,
+  pubkey: &str,
+) -> Result<bool, Error> {
+  let fetch_error = |err: reqwest::Error| Error::Fetch(err.to_string());
+  let response = client.get(url).send().await.map_err(fetch_error)?;
+  if !response.status().is_success() {
+    return Err(Error::Fetch(response.status().to_string()));
+  }
+  let document = response.text().await.map_err(fetch_error)?;
+
+  Ok(verify_from_json(&document, name, pubkey))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn parse_identifier() {
+    assert_eq!(
+      Nip05Identifier::parse("Bob@Example.com"),
+      Ok(Nip05Identifier {
+        name: String::from("bob"),
+        domain: String::from("example.com"),
+      })
+    );
+    assert_eq!(
+      Nip05Identifier::parse("example.com").unwrap().name,
+      String::from("_")
+    );
+    assert_eq!(
+      Nip05Identifier::parse("bob@"),
+      Err(Error::InvalidIdentifier)
+    );
+    assert_eq!(
+      Nip05Identifier::parse("b*b@example.com"),
+      Err(Error::InvalidIdentifier)
+    );
+  }
+
+  #[test]
+  fn well_known_url() {
+    let identifier = Nip05Identifier::parse("bob@example.com").unwrap();
+
+    assert_eq!(
+      identifier.well_known_url(),
+      "https://example.com/.well-known/nostr.json?name=bob"
+    );
+  }
+
+  #[test]
+  fn verify_document() {
+    let pubkey = "b0635d6a9851d3aed0cd6c495b282167acf761729078d975fc341b22650b07b9";
+    let document = format!(r#"{{"names":{{"bob":"{pubkey}"}}}}"#);
+
+    assert!(verify_from_json(&document, "bob", pubkey));
+    assert_eq!(verify_from_json(&document, "alice", pubkey), false);
+    assert_eq!(verify_from_json(&document, "bob", "other"), false);
+    assert_eq!(verify_from_json("not json", "bob", pubkey), false);
+  }
+
+  /// Spawns an HTTP server that answers every request with `response`.
+  ///
+  async fn spawn_http_server(response: String) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).await;
+        let _ = stream.write_all(response.as_bytes()).await;
+      }
+    });
+
+    format!("http://{addr}/.well-known/nostr.json?name=bob")
+  }
+
+  #[tokio::test]
+  async fn verify_from_the_fetched_document() {
+    let pubkey = "b0635d6a9851d3aed0cd6c495b282167acf761729078d975fc341b22650b07b9";
+    let document = format!(r#"{{"names":{{"bob":"{pubkey}"}}}}"#);
+    let url = spawn_http_server(format!(
+      "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{document}",
+      document.len()
+    ))
+    .await;
+    let client = HttpNip05Verifier::new().client;
+
+    assert_eq!(
+      verify_from_url(&client, &url, "bob", pubkey).await,
+      Ok(true)
+    );
+    assert_eq!(
+      verify_from_url(&client, &url, "alice", pubkey).await,
+      Ok(false)
+    );
+  }
+
+  #[tokio::test]
+  async fn verify_fails_when_the_document_is_not_served() {
+    let url = spawn_http_server(String::from(
+      "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    ))
+    .await;
+    let client = HttpNip05Verifier::new().client;
+
+    assert_eq!(
+      verify_from_url(&client, &url, "bob", "pubkey").await,
+      Err(Error::Fetch(String::from("404 Not Found")))
+    );
+    assert_eq!(
+      HttpNip05Verifier::new()
+        .verify("b*b@example.com", &String::from("pubkey"))
+        .await,
+      Err(Error::InvalidIdentifier)
+    );
+  }
+}
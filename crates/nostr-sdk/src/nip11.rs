@@ -0,0 +1,183 @@
+//! NIP-11: relay information document.
+//!
+//! Relays describe themselves (name, supported NIPs, ...) in a JSON document
+//! served over HTTP(S) at the same URL as their websocket, when requested
+//! with the `Accept: application/nostr+json` header.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Value of the `Accept` header of the request for the document.
+pub const ACCEPT_HEADER: &str = "application/nostr+json";
+
+/// [`RelayInformationDocument`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+  #[error("Invalid relay URL")]
+  InvalidUrl,
+  #[error("Invalid relay information document: {0}")]
+  InvalidDocument(String),
+  #[error("Could not fetch the relay information document: {0}")]
+  Fetch(String),
+}
+
+/// The relay information document. Only the fields the client
+/// uses are parsed; the others are ignored.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayInformationDocument {
+  #[serde(default)]
+  pub name: Option<String>,
+  #[serde(default)]
+  pub description: Option<String>,
+  #[serde(default)]
+  pub supported_nips: Vec<u16>,
+}
+
+impl RelayInformationDocument {
+  /// Parses the JSON document served by the relay.
+  pub fn from_json(document: &str) -> Result<Self, Error> {
+    serde_json::from_str(document).map_err(|err| Error::InvalidDocument(err.to_string()))
+  }
+
+  /// Checks if the relay supports the NIP `nip`.
+  pub fn supports(&self, nip: u16) -> bool {
+    self.supported_nips.contains(&nip)
+  }
+}
+
+/// Gets the HTTP(S) URL where the document of the relay at `relay_url` is served:
+/// `wss://` becomes `https://` and `ws://` becomes `http://`.
+///
+pub fn http_url(relay_url: &str) -> Result<String, Error> {
+  let mut url = Url::parse(relay_url).map_err(|_| Error::InvalidUrl)?;
+  let scheme = match url.scheme() {
+    "wss" => "https",
+    "ws" => "http",
+    _ => return Err(Error::InvalidUrl),
+  };
+  url.set_scheme(scheme).map_err(|_| Error::InvalidUrl)?;
+
+  Ok(url.to_string())
+}
+
+/// Something able to fetch the relay information document, usually
+/// by a GET to the `http_url` with the [`ACCEPT_HEADER`] (see [`HttpNip11Fetcher`]).
+///
+#[async_trait]
+pub trait Nip11Fetcher {
+  async fn fetch(&self, http_url: &str) -> Result<String, Error>;
+}
+
+/// Fetches the relay information document over HTTP(S).
+///
+#[derive(Debug, Default, Clone)]
+pub struct HttpNip11Fetcher {
+  client: reqwest::Client,
+}
+
+impl HttpNip11Fetcher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+#[async_trait]
+impl Nip11Fetcher for HttpNip11Fetcher {
+  async fn fetch(&self, http_url: &str) -> Result<String, Error> {
+    let fetch_error = |err: reqwest::Error| Error::Fetch(err.to_string());
+    let response = self
+      .client
+      .get(http_url)
+      .header(reqwest::header::ACCEPT, ACCEPT_HEADER)
+      .send()
+      .await
+      .map_err(fetch_error)?;
+    if !response.status().is_success() {
+      return Err(Error::Fetch(response.status().to_string()));
+    }
+
+    response.text().await.map_err(fetch_error)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn parse_document() {
+    let document = r#"{
+      "name": "potato relay",
+      "description": "only potatoes",
+      "pubkey": "b0635d6a9851d3aed0cd6c495b282167acf761729078d975fc341b22650b07b9",
+      "supported_nips": [1, 11, 42, 45],
+      "software": "git+https://github.com/Guilospanck/nostr.git",
+      "version": "0.1.0"
+    }"#;
+
+    let document = RelayInformationDocument::from_json(document).unwrap();
+
+    assert_eq!(
+      document,
+      RelayInformationDocument {
+        name: Some(String::from("potato relay")),
+        description: Some(String::from("only potatoes")),
+        supported_nips: vec![1, 11, 42, 45],
+      }
+    );
+    assert!(document.supports(42));
+    assert_eq!(document.supports(50), false);
+    assert_eq!(
+      RelayInformationDocument::from_json("{}").unwrap(),
+      RelayInformationDocument::default()
+    );
+    assert!(RelayInformationDocument::from_json("not json").is_err());
+  }
+
+  #[test]
+  fn http_url_from_relay_url() {
+    assert_eq!(
+      http_url("wss://relay.damus.io"),
+      Ok(String::from("https://relay.damus.io/"))
+    );
+    assert_eq!(
+      http_url("ws://127.0.0.1:8080/nostr"),
+      Ok(String::from("http://127.0.0.1:8080/nostr"))
+    );
+    assert_eq!(http_url("https://relay.damus.io"), Err(Error::InvalidUrl));
+    assert_eq!(http_url("not a url"), Err(Error::InvalidUrl));
+  }
+
+  #[tokio::test]
+  async fn http_fetcher_asks_for_the_nostr_json() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let document = r#"{"name":"potato relay","supported_nips":[1,11]}"#;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+      let (mut stream, _) = listener.accept().await.unwrap();
+      let mut request = [0; 1024];
+      let read = stream.read(&mut request).await.unwrap();
+      let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{document}",
+        document.len()
+      );
+      stream.write_all(response.as_bytes()).await.unwrap();
+      String::from_utf8_lossy(&request[..read]).to_lowercase()
+    });
+
+    let fetched = HttpNip11Fetcher::new()
+      .fetch(&format!("http://{addr}/"))
+      .await;
+
+    assert_eq!(fetched, Ok(String::from(document)));
+    let request = server.await.unwrap();
+    assert!(request.contains(&format!("accept: {ACCEPT_HEADER}")));
+  }
+}
@@ -0,0 +1,207 @@
+//! NIP-19: bech32-encoded entities.
+//!
+//! An `nevent` shares an event with the hints needed to find it: the
+//! TLV (type-length-value) encoding of its id (type `0`), the relays
+//! where it can be found (type `1`), its author (type `2`) and its kind
+//! (type `3`), encoded as bech32 with the `nevent` prefix.
+
+use bech32::{FromBase32, ToBase32, Variant};
+
+use crate::event::{kind::EventKind, PubKey};
+
+/// Prefix of the URIs of nostr entities (NIP-21).
+pub const URI_SCHEME: &str = "nostr:";
+
+const NEVENT_HRP: &str = "nevent";
+
+const TLV_SPECIAL: u8 = 0;
+const TLV_RELAY: u8 = 1;
+const TLV_AUTHOR: u8 = 2;
+const TLV_KIND: u8 = 3;
+
+/// [`NEvent`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+  #[error("Invalid bech32: {0}")]
+  Bech32(String),
+  #[error("Invalid hex: {0}")]
+  Hex(String),
+  #[error("Not an nevent")]
+  WrongPrefix,
+  #[error("Invalid TLV data")]
+  InvalidTlv,
+}
+
+impl From<bech32::Error> for Error {
+  fn from(err: bech32::Error) -> Self {
+    Self::Bech32(err.to_string())
+  }
+}
+
+impl From<hex::FromHexError> for Error {
+  fn from(err: hex::FromHexError) -> Self {
+    Self::Hex(err.to_string())
+  }
+}
+
+/// A shareable reference to an event.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NEvent {
+  /// Hex-encoded id of the event.
+  pub event_id: String,
+  /// Relays where the event is likely to be found.
+  pub relays: Vec<String>,
+  /// Hex-encoded (x-only) pubkey of the author of the event.
+  pub author: Option<PubKey>,
+  pub kind: Option<EventKind>,
+}
+
+impl NEvent {
+  /// Encodes as `nevent1...`.
+  pub fn encode(&self) -> Result<String, Error> {
+    let mut tlv = vec![];
+    push_tlv(&mut tlv, TLV_SPECIAL, &hex::decode(&self.event_id)?)?;
+    for relay in self.relays.iter() {
+      push_tlv(&mut tlv, TLV_RELAY, relay.as_bytes())?;
+    }
+    if let Some(author) = &self.author {
+      push_tlv(&mut tlv, TLV_AUTHOR, &hex::decode(author)?)?;
+    }
+    if let Some(kind) = self.kind {
+      push_tlv(&mut tlv, TLV_KIND, &kind.as_u32().to_be_bytes())?;
+    }
+
+    Ok(bech32::encode(
+      NEVENT_HRP,
+      tlv.to_base32(),
+      Variant::Bech32,
+    )?)
+  }
+
+  /// Decodes an `nevent1...`, with or without the `nostr:` scheme.
+  /// Unknown TLV types are ignored.
+  ///
+  pub fn decode(nevent: &str) -> Result<Self, Error> {
+    let nevent = nevent.strip_prefix(URI_SCHEME).unwrap_or(nevent);
+    let (hrp, data, _) = bech32::decode(nevent)?;
+    if hrp != NEVENT_HRP {
+      return Err(Error::WrongPrefix);
+    }
+    let tlv = Vec::<u8>::from_base32(&data)?;
+
+    let mut decoded = Self::default();
+    let mut event_id = None;
+    let mut rest = tlv.as_slice();
+    while let [t, l, tail @ ..] = rest {
+      let length = *l as usize;
+      if tail.len() < length {
+        return Err(Error::InvalidTlv);
+      }
+      let (value, tail) = tail.split_at(length);
+      rest = tail;
+
+      match *t {
+        TLV_SPECIAL if length == 32 => event_id = Some(hex::encode(value)),
+        TLV_RELAY => decoded
+          .relays
+          .push(String::from_utf8(value.to_vec()).map_err(|_| Error::InvalidTlv)?),
+        TLV_AUTHOR if length == 32 => decoded.author = Some(hex::encode(value)),
+        TLV_KIND => {
+          let kind: [u8; 4] = value.try_into().map_err(|_| Error::InvalidTlv)?;
+          decoded.kind = Some(EventKind::from(u32::from_be_bytes(kind) as u64));
+        }
+        TLV_SPECIAL | TLV_AUTHOR => return Err(Error::InvalidTlv),
+        _ => {}
+      }
+    }
+    if !rest.is_empty() {
+      return Err(Error::InvalidTlv);
+    }
+
+    decoded.event_id = event_id.ok_or(Error::InvalidTlv)?;
+    Ok(decoded)
+  }
+
+  /// Gets the `nostr:nevent1...` URI (NIP-21).
+  pub fn to_uri(&self) -> Result<String, Error> {
+    Ok(format!("{URI_SCHEME}{}", self.encode()?))
+  }
+}
+
+fn push_tlv(tlv: &mut Vec<u8>, t: u8, value: &[u8]) -> Result<(), Error> {
+  let length = u8::try_from(value.len()).map_err(|_| Error::InvalidTlv)?;
+  tlv.push(t);
+  tlv.push(length);
+  tlv.extend_from_slice(value);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn encodes_as_the_nip19_example() {
+    // nprofile example of NIP-19, but with the `nevent` prefix
+    let nevent = NEvent {
+      event_id: String::from("3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d"),
+      relays: vec![
+        String::from("wss://r.x.com"),
+        String::from("wss://djbas.sadkb.com"),
+      ],
+      ..Default::default()
+    };
+
+    let encoded = nevent.encode().unwrap();
+
+    // same TLV data, only the prefix and the checksum differ
+    let data = &encoded["nevent1".len()..encoded.len() - 6];
+    assert_eq!(
+      data,
+      "qqsrhuxx8l9ex335q7he0f09aej04zpazpl0ne2cgukyawd24mayt8gpp4mhxue69uhhytnc9e3k7mgpz4mhxue69uhkg6nzv9ejuumpv34kytnrdaks"
+    );
+    assert_eq!(NEvent::decode(&encoded).unwrap(), nevent);
+  }
+
+  #[test]
+  fn round_trips() {
+    let nevent = NEvent {
+      event_id: String::from("00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae"),
+      relays: vec![String::from("wss://relay.damus.io")],
+      author: Some(String::from(
+        "614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6",
+      )),
+      kind: Some(EventKind::Text),
+    };
+
+    let uri = nevent.to_uri().unwrap();
+
+    assert!(uri.starts_with("nostr:nevent1"));
+    assert_eq!(NEvent::decode(&uri).unwrap(), nevent);
+    assert_eq!(
+      NEvent::decode(uri.strip_prefix(URI_SCHEME).unwrap()).unwrap(),
+      nevent
+    );
+  }
+
+  #[test]
+  fn rejects_invalid_nevents() {
+    let npub = bech32::encode("npub", [0u8; 32].to_base32(), Variant::Bech32).unwrap();
+    assert_eq!(NEvent::decode(&npub), Err(Error::WrongPrefix));
+
+    let without_id = bech32::encode(NEVENT_HRP, [1u8, 0].to_base32(), Variant::Bech32).unwrap();
+    assert_eq!(NEvent::decode(&without_id), Err(Error::InvalidTlv));
+
+    let truncated = bech32::encode(NEVENT_HRP, [0u8, 32, 1].to_base32(), Variant::Bech32).unwrap();
+    assert_eq!(NEvent::decode(&truncated), Err(Error::InvalidTlv));
+
+    assert!(matches!(
+      NEvent::decode("nevent1invalid"),
+      Err(Error::Bech32(_))
+    ));
+  }
+}
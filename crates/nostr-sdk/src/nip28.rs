@@ -0,0 +1,24 @@
+//! NIP-28: public chat channels.
+//!
+//! A channel is created by a kind 40 event whose content is its metadata.
+//! Its metadata is updated by kind 41 events and its messages are kind 42
+//! events, both referencing the channel creation event with a `root` `"e"` tag.
+
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a channel, serialized as the content of the
+/// `ChannelCreation` (kind 40) and `ChannelMetadata` (kind 41) events.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelMetadata {
+  pub name: String,
+  pub about: String,
+  pub picture: String,
+}
+
+impl ChannelMetadata {
+  pub fn as_str(&self) -> String {
+    serde_json::to_string(self).unwrap()
+  }
+}
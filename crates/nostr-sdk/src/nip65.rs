@@ -0,0 +1,63 @@
+//! NIP-65: relay list metadata.
+//!
+//! A replaceable event (kind 10002) whose `"r"` tags are the relays the author
+//! reads from and writes to: `["r", <url>]` for both, or `["r", <url>, "read"]`
+//! and `["r", <url>, "write"]` for only one of them.
+
+use crate::event::{
+  kind::EventKind,
+  tag::{Tag, TagKind, UncheckedRecommendRelayURL},
+};
+
+/// Kind of the relay list event.
+pub const RELAY_LIST_KIND: EventKind = EventKind::Custom(10002);
+
+const RELAY_TAG: &str = "r";
+
+/// How the author uses a relay of its list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayListMarker {
+  Read,
+  Write,
+  Both,
+}
+
+impl RelayListMarker {
+  fn from_tag_value(marker: Option<&str>) -> Option<Self> {
+    match marker {
+      None => Some(Self::Both),
+      Some("read") => Some(Self::Read),
+      Some("write") => Some(Self::Write),
+      Some(_) => None,
+    }
+  }
+
+  fn as_tag_value(&self) -> Option<&'static str> {
+    match self {
+      Self::Read => Some("read"),
+      Self::Write => Some("write"),
+      Self::Both => None,
+    }
+  }
+}
+
+/// Gets the `"r"` tag of a relay of the list.
+pub fn relay_tag(url: &UncheckedRecommendRelayURL, marker: RelayListMarker) -> Tag {
+  let mut values = vec![url.0.clone()];
+  values.extend(marker.as_tag_value().map(String::from));
+  Tag::Generic(TagKind::Custom(String::from(RELAY_TAG)), values)
+}
+
+/// Gets the relay of an `"r"` tag. Tags with an unknown marker are ignored.
+pub fn parse_relay_tag(tag: &Tag) -> Option<(UncheckedRecommendRelayURL, RelayListMarker)> {
+  let Tag::Generic(TagKind::Custom(kind), values) = tag else {
+    return None;
+  };
+  if kind != RELAY_TAG {
+    return None;
+  }
+
+  let url = values.first()?;
+  let marker = RelayListMarker::from_tag_value(values.get(1).map(String::as_str))?;
+  Some((UncheckedRecommendRelayURL(url.clone()), marker))
+}
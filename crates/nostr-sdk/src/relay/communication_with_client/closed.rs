@@ -0,0 +1,140 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use super::Error;
+
+/// Used to tell the client that a subscription was ended
+/// (or refused) by the relay, and why.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayToClientCommClosed {
+  pub code: String, // "CLOSED"
+  pub subscription_id: String,
+  pub message: String, // e.g.: "invalid: <reason>"
+}
+
+impl RelayToClientCommClosed {
+  /// Create new `CLOSED` message
+  pub fn new_closed(subscription_id: String, message: String) -> Self {
+    Self {
+      code: "CLOSED".to_string(),
+      subscription_id,
+      message,
+    }
+  }
+
+  /// Serialize as [`Value`]
+  pub fn as_value(&self) -> Value {
+    json!(["CLOSED", self.subscription_id, self.message])
+  }
+
+  /// Deserialize from [`Value`]
+  pub fn from_value(msg: Value) -> Result<Self, Error> {
+    let v = msg.as_array().ok_or(Error::InvalidData)?;
+
+    if v.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let v_len = v.len();
+
+    // CLOSED
+    // ["CLOSED", <subscription_id>, <message>]
+    if v[0] != "CLOSED" || v_len != 3 {
+      return Err(Error::InvalidData);
+    }
+
+    let subscription_id = serde_json::from_value(v[1].clone())?;
+    let message = serde_json::from_value(v[2].clone())?;
+    Ok(Self::new_closed(subscription_id, message))
+  }
+
+  /// Get [`RelayToClientCommClosed`] as JSON string
+  pub fn as_json(&self) -> String {
+    self.as_value().to_string()
+  }
+
+  /// Get [`RelayToClientCommClosed`] from JSON string
+  pub fn from_json<S>(msg: S) -> Result<Self, Error>
+  where
+    S: Into<String>,
+  {
+    let msg: &str = &msg.into();
+
+    if msg.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let value: Value = serde_json::from_str(msg)?;
+    Self::from_value(value)
+  }
+}
+
+impl Default for RelayToClientCommClosed {
+  fn default() -> Self {
+    Self {
+      code: String::from("CLOSED"),
+      subscription_id: String::from(""),
+      message: String::from(""),
+    }
+  }
+}
+
+impl Serialize for RelayToClientCommClosed {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let json_value: Value = self.as_value();
+    json_value.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for RelayToClientCommClosed {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    // Tries to deserialize incoming thing into a json value
+    let json_value: Value = Value::deserialize(deserializer)?;
+
+    // If it succeeds, tries to deserialize it into a [`RelayToClientCommClosed`] struct
+    RelayToClientCommClosed::from_value(json_value).map_err(serde::de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn test_closed_serializes_without_the_struct_key_names() {
+    let closed = RelayToClientCommClosed::new_closed(
+      String::from("potato"),
+      String::from("invalid: mock_message"),
+    );
+
+    let expected_serialized = json!(["CLOSED", "potato", "invalid: mock_message"]).to_string();
+
+    assert_eq!(expected_serialized, closed.as_json());
+  }
+
+  #[test]
+  fn test_closed_deserializes_correctly() {
+    let expected_closed = RelayToClientCommClosed::new_closed(
+      String::from("potato"),
+      String::from("invalid: mock_message"),
+    );
+
+    let serialized = json!(["CLOSED", "potato", "invalid: mock_message"]).to_string();
+
+    assert_eq!(
+      RelayToClientCommClosed::from_json(serialized).unwrap(),
+      expected_closed
+    );
+    assert!(RelayToClientCommClosed::from_json(r#"["CLOSED","potato"]"#).is_err());
+  }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::event::PubKey;
+
+use super::Error;
+
+/// Privacy-safe information about a connected client.
+/// The IP address of the client is never exposed.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionSummary {
+  pub subscriptions: usize,
+  pub pubkey: Option<PubKey>,
+  pub user_agent: Option<String>,
+}
+
+/// Used to answer the admin `CONNECTIONS` command.
+///
+/// `["CONNECTIONS", {"count": <number>, "connections": [<connection summary>, ...]}]`
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayToClientCommConnections {
+  pub code: String, // "CONNECTIONS"
+  pub count: usize,
+  pub connections: Vec<ConnectionSummary>,
+}
+
+impl RelayToClientCommConnections {
+  /// Create new `CONNECTIONS` message
+  pub fn new_connections(connections: Vec<ConnectionSummary>) -> Self {
+    Self {
+      code: "CONNECTIONS".to_string(),
+      count: connections.len(),
+      connections,
+    }
+  }
+
+  /// Serialize as [`Value`]
+  pub fn as_value(&self) -> Value {
+    json!([
+      "CONNECTIONS",
+      {
+        "count": self.count,
+        "connections": self.connections,
+      }
+    ])
+  }
+
+  /// Deserialize from [`Value`]
+  pub fn from_value(msg: Value) -> Result<Self, Error> {
+    let v = msg.as_array().ok_or(Error::InvalidData)?;
+
+    if v.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let v_len = v.len();
+
+    // CONNECTIONS
+    // ["CONNECTIONS", {"count": <number>, "connections": [...]}]
+    if v[0] != "CONNECTIONS" || v_len != 2 {
+      return Err(Error::InvalidData);
+    }
+
+    let connections: Vec<ConnectionSummary> =
+      serde_json::from_value(v[1].get("connections").cloned().ok_or(Error::InvalidData)?)?;
+    Ok(Self::new_connections(connections))
+  }
+
+  /// Get [`RelayToClientCommConnections`] as JSON string
+  pub fn as_json(&self) -> String {
+    self.as_value().to_string()
+  }
+
+  /// Get [`RelayToClientCommConnections`] from JSON string
+  pub fn from_json<S>(msg: S) -> Result<Self, Error>
+  where
+    S: Into<String>,
+  {
+    let msg: &str = &msg.into();
+
+    if msg.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let value: Value = serde_json::from_str(msg)?;
+    Self::from_value(value)
+  }
+}
+
+impl Default for RelayToClientCommConnections {
+  fn default() -> Self {
+    Self::new_connections(vec![])
+  }
+}
+
+impl Serialize for RelayToClientCommConnections {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let json_value: Value = self.as_value();
+    json_value.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for RelayToClientCommConnections {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let json_value: Value = Value::deserialize(deserializer)?;
+
+    RelayToClientCommConnections::from_value(json_value).map_err(serde::de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn test_connections_serializes_and_deserializes_correctly() {
+    let connections = RelayToClientCommConnections::new_connections(vec![ConnectionSummary {
+      subscriptions: 2,
+      pubkey: None,
+      user_agent: Some(String::from("potato/1.0")),
+    }]);
+
+    let expected_serialized = json!([
+      "CONNECTIONS",
+      {
+        "count": 1,
+        "connections": [{"subscriptions": 2, "pubkey": null, "user_agent": "potato/1.0"}],
+      }
+    ])
+    .to_string();
+
+    assert_eq!(connections.as_json(), expected_serialized);
+    assert_eq!(
+      RelayToClientCommConnections::from_json(expected_serialized).unwrap(),
+      connections
+    );
+    assert!(RelayToClientCommConnections::from_json(r#"["CONNECTIONS"]"#).is_err());
+  }
+}
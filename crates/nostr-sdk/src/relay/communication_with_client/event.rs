@@ -29,6 +29,13 @@ impl RelayToClientCommEvent {
     json!(["EVENT", self.subscription_id, self.event])
   }
 
+  /// Gets the `EVENT` message of an already serialized event (see [`Event::as_value`]),
+  /// so an event sent to many subscriptions is only serialized once.
+  ///
+  pub fn json_with_event_json(subscription_id: &str, event_json: &str) -> String {
+    format!(r#"["EVENT",{},{event_json}]"#, Value::from(subscription_id))
+  }
+
   /// Deserialize from [`Value`]
   pub fn from_value(msg: Value) -> Result<Self, Error> {
     let v = msg.as_array().ok_or(Error::InvalidData)?;
@@ -1,7 +1,10 @@
 // internal modules
+pub mod closed;
+pub mod connections;
 pub mod eose;
 pub mod event;
 pub mod notice;
+pub mod ok;
 
 /// [`CommunicationWithClient`] error
 #[derive(thiserror::Error, Debug)]
@@ -3,6 +3,51 @@ use serde_json::{json, Value};
 
 use super::Error;
 
+/// What a `NOTICE` is about, from the machine-readable prefix
+/// of its message (`"<prefix>: <human-readable message>"`), following
+/// the prefixes NIP-01 defines for `OK` and `CLOSED` messages.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NoticeKind {
+  /// `duplicate:`
+  Duplicate,
+  /// `pow:`
+  Pow,
+  /// `blocked:`
+  Blocked,
+  /// `rate-limited:`
+  RateLimited,
+  /// `invalid:`
+  Invalid,
+  /// `restricted:`
+  Restricted,
+  /// `error:`
+  Error,
+  /// Without a known prefix, the message is just informative.
+  #[default]
+  Info,
+}
+
+impl NoticeKind {
+  /// Gets the kind of a `NOTICE` from its `message`.
+  pub fn from_message(message: &str) -> Self {
+    let Some((prefix, _)) = message.split_once(':') else {
+      return Self::Info;
+    };
+
+    match prefix {
+      "duplicate" => Self::Duplicate,
+      "pow" => Self::Pow,
+      "blocked" => Self::Blocked,
+      "rate-limited" => Self::RateLimited,
+      "invalid" => Self::Invalid,
+      "restricted" => Self::Restricted,
+      "error" => Self::Error,
+      _ => Self::Info,
+    }
+  }
+}
+
 /// Used to send human-readable error messages
 /// or other things to clients.
 ///
@@ -21,6 +66,11 @@ impl RelayToClientCommNotice {
     }
   }
 
+  /// Gets what the notice is about (see [`NoticeKind`]).
+  pub fn classify(&self) -> NoticeKind {
+    NoticeKind::from_message(&self.message)
+  }
+
   /// Serialize as [`Value`]
   pub fn as_value(&self) -> Value {
     json!(["NOTICE", self.message])
@@ -148,4 +198,25 @@ mod tests {
       expected_event
     );
   }
+
+  #[test]
+  fn test_notice_classify() {
+    let notices = [
+      ("duplicate: already have this event", NoticeKind::Duplicate),
+      ("pow: difficulty 25 is less than 30", NoticeKind::Pow),
+      ("blocked: you are banned", NoticeKind::Blocked),
+      ("rate-limited: slow down", NoticeKind::RateLimited),
+      ("invalid: filter has since after until", NoticeKind::Invalid),
+      ("restricted: not authorized", NoticeKind::Restricted),
+      ("error: overloaded", NoticeKind::Error),
+      ("Subscription ended.", NoticeKind::Info),
+      ("unknown: prefix", NoticeKind::Info),
+      ("", NoticeKind::Info),
+    ];
+
+    for (message, kind) in notices {
+      let notice = RelayToClientCommNotice::new_notice(String::from(message));
+      assert_eq!(notice.classify(), kind, "{message}");
+    }
+  }
 }
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use super::Error;
+
+/// Used to inform clients whether an `EVENT` they sent
+/// was accepted or rejected (NIP-20).
+///
+/// The message should be prefixed by the reason of a rejection,
+/// like `"invalid: "`, `"blocked: "`, `"rate-limited: "`, etc.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayToClientCommOk {
+  pub code: String, // "OK"
+  pub event_id: String,
+  pub status: bool,
+  pub message: String,
+}
+
+impl RelayToClientCommOk {
+  /// Create new `OK` message
+  pub fn new_ok(event_id: String, status: bool, message: String) -> Self {
+    Self {
+      code: "OK".to_string(),
+      event_id,
+      status,
+      message,
+    }
+  }
+
+  /// Serialize as [`Value`]
+  pub fn as_value(&self) -> Value {
+    json!(["OK", self.event_id, self.status, self.message])
+  }
+
+  /// Deserialize from [`Value`]
+  pub fn from_value(msg: Value) -> Result<Self, Error> {
+    let v = msg.as_array().ok_or(Error::InvalidData)?;
+
+    if v.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let v_len = v.len();
+
+    // OK
+    // ["OK", <event_id>, <true|false>, <message>]
+    if v[0] != "OK" || v_len != 4 {
+      return Err(Error::InvalidData);
+    }
+
+    let event_id = serde_json::from_value(v[1].clone())?;
+    let status = serde_json::from_value(v[2].clone())?;
+    let message = serde_json::from_value(v[3].clone())?;
+    Ok(Self::new_ok(event_id, status, message))
+  }
+
+  /// Get [`RelayToClientCommOk`] as JSON string
+  pub fn as_json(&self) -> String {
+    self.as_value().to_string()
+  }
+
+  /// Get [`RelayToClientCommOk`] from JSON string
+  pub fn from_json<S>(msg: S) -> Result<Self, Error>
+  where
+    S: Into<String>,
+  {
+    let msg: &str = &msg.into();
+
+    if msg.is_empty() {
+      return Err(Error::InvalidData);
+    }
+
+    let value: Value = serde_json::from_str(msg)?;
+    Self::from_value(value)
+  }
+}
+
+impl Default for RelayToClientCommOk {
+  fn default() -> Self {
+    Self {
+      code: String::from("OK"),
+      event_id: String::from(""),
+      status: true,
+      message: String::from(""),
+    }
+  }
+}
+
+impl Serialize for RelayToClientCommOk {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let json_value: Value = self.as_value();
+    json_value.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for RelayToClientCommOk {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    // Tries to deserialize incoming thing into a json value
+    let json_value: Value = Value::deserialize(deserializer)?;
+
+    // If it succeeds, tries to deserialize it into a [`RelayToClientCommOk`] struct
+    RelayToClientCommOk::from_value(json_value).map_err(serde::de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn test_ok_serializes_without_the_struct_key_names() {
+    let ok = RelayToClientCommOk::new_ok(
+      String::from("mock_event_id"),
+      false,
+      String::from("invalid: too old"),
+    );
+
+    let expected_serialized = json!(["OK", "mock_event_id", false, "invalid: too old"]).to_string();
+
+    assert_eq!(expected_serialized, ok.as_json());
+  }
+
+  #[test]
+  fn test_ok_deserializes_correctly() {
+    let expected_ok =
+      RelayToClientCommOk::new_ok(String::from("mock_event_id"), true, String::from(""));
+
+    let serialized = json!(["OK", "mock_event_id", true, ""]).to_string();
+
+    assert_eq!(
+      RelayToClientCommOk::from_json(serialized).unwrap(),
+      expected_ok
+    );
+    assert!(RelayToClientCommOk::from_json(r#"["OK","mock_event_id",true]"#).is_err());
+  }
+}
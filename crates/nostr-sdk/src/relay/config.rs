@@ -0,0 +1,161 @@
+//! Where the relay listens and whether it serves `wss://` directly.
+
+use std::{env, fs, io, path::PathBuf};
+
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsAcceptor};
+use tokio_tungstenite::MaybeTlsStream;
+
+use crate::relay::event_store::EventStoreKind;
+
+pub const DEFAULT_RELAY_HOST: &str = "0.0.0.0:8080";
+
+/// [`TlsConfig`] error
+#[derive(thiserror::Error, Debug)]
+pub enum TlsConfigError {
+  #[error("Could not read the TLS certificate or key: {0}")]
+  Io(#[from] io::Error),
+  #[error("Invalid TLS certificate or key: {0}")]
+  Tls(#[from] native_tls::Error),
+  #[error("RELAY_TLS_CERT_PATH and RELAY_TLS_KEY_PATH must be set together")]
+  Incomplete,
+}
+
+/// Certificate (chain) and private key, both PEM encoded, used by the relay
+/// to terminate TLS itself.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+  pub cert_path: PathBuf,
+  /// PKCS #8 private key
+  pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+  /// Reads the certificate and the key and builds the acceptor of the TLS connections.
+  pub fn acceptor(&self) -> Result<TlsAcceptor, TlsConfigError> {
+    let cert = fs::read(&self.cert_path)?;
+    let key = fs::read(&self.key_path)?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)?;
+
+    Ok(TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?))
+  }
+}
+
+/// How the relay accepts connections: the address it binds to and,
+/// if set, the TLS it terminates (otherwise it is plain `ws://`,
+/// e.g.: behind a proxy that terminates TLS).
+/// And where it persists the events.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayConfig {
+  pub bind: String,
+  pub tls: Option<TlsConfig>,
+  pub event_store: EventStoreKind,
+}
+
+impl Default for RelayConfig {
+  fn default() -> Self {
+    Self {
+      bind: DEFAULT_RELAY_HOST.to_string(),
+      tls: None,
+      event_store: EventStoreKind::default(),
+    }
+  }
+}
+
+/// Gets the config of the relay from the env vars:
+/// - `RELAY_HOST`: address to bind to. Defaults to [`DEFAULT_RELAY_HOST`];
+/// - `RELAY_TLS_CERT_PATH` and `RELAY_TLS_KEY_PATH`: paths of the PEM certificate
+///   and key. TLS is only terminated by the relay when both are set,
+///   setting only one of them is an error;
+/// - `RELAY_EVENT_STORE`: `memory` (the events are lost when the relay stops)
+///   or `redb`. Defaults to `redb`.
+///
+pub fn get_relay_config() -> Result<RelayConfig, TlsConfigError> {
+  let bind = env::var("RELAY_HOST").unwrap_or_else(|_| DEFAULT_RELAY_HOST.to_string());
+  let tls = tls_config_from(
+    env::var("RELAY_TLS_CERT_PATH").ok(),
+    env::var("RELAY_TLS_KEY_PATH").ok(),
+  )?;
+
+  let event_store = match env::var("RELAY_EVENT_STORE").as_deref() {
+    Ok("memory") => EventStoreKind::Memory,
+    _ => EventStoreKind::Redb,
+  };
+
+  Ok(RelayConfig {
+    bind,
+    tls,
+    event_store,
+  })
+}
+
+/// Builds the [`TlsConfig`] from the paths of the certificate and the key.
+/// Having only one of them is an error, so that the relay does not
+/// serve plain `ws://` where `wss://` was meant.
+///
+fn tls_config_from(
+  cert_path: Option<String>,
+  key_path: Option<String>,
+) -> Result<Option<TlsConfig>, TlsConfigError> {
+  match (cert_path, key_path) {
+    (Some(cert_path), Some(key_path)) => Ok(Some(TlsConfig {
+      cert_path: PathBuf::from(cert_path),
+      key_path: PathBuf::from(key_path),
+    })),
+    (None, None) => Ok(None),
+    _ => Err(TlsConfigError::Incomplete),
+  }
+}
+
+/// Does the TLS handshake with the client of `stream`, if the relay terminates TLS.
+pub async fn accept_stream(
+  stream: TcpStream,
+  tls_acceptor: Option<&TlsAcceptor>,
+) -> Result<MaybeTlsStream<TcpStream>, native_tls::Error> {
+  match tls_acceptor {
+    Some(tls_acceptor) => Ok(MaybeTlsStream::NativeTls(
+      tls_acceptor.accept(stream).await?,
+    )),
+    None => Ok(MaybeTlsStream::Plain(stream)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn tls_config_from_both_paths() {
+    let tls = tls_config_from(Some("cert.pem".to_string()), Some("key.pem".to_string())).unwrap();
+
+    assert_eq!(
+      tls,
+      Some(TlsConfig {
+        cert_path: PathBuf::from("cert.pem"),
+        key_path: PathBuf::from("key.pem"),
+      })
+    );
+  }
+
+  #[test]
+  fn tls_config_from_no_paths() {
+    assert_eq!(tls_config_from(None, None).unwrap(), None);
+  }
+
+  #[test]
+  fn tls_config_from_only_one_path_is_an_error() {
+    assert!(matches!(
+      tls_config_from(Some("cert.pem".to_string()), None),
+      Err(TlsConfigError::Incomplete)
+    ));
+    assert!(matches!(
+      tls_config_from(None, Some("key.pem".to_string())),
+      Err(TlsConfigError::Incomplete)
+    ));
+  }
+}
@@ -1,11 +1,33 @@
 use redb::{Database, ReadableTable, TableDefinition, WriteTransaction};
-use std::fs;
+use std::{
+  fs,
+  sync::{
+    mpsc::{self, Sender},
+    Arc,
+  },
+};
 
-use crate::event::Event;
+use crate::{
+  client::database::{ClientDatabase, Items},
+  event::{Event, Timestamp},
+  relay::event_store::spawn_writer,
+};
 
 const TABLE_NAME: &str = "events";
 const EVENTS_TABLE: TableDefinition<u64, &str> = TableDefinition::new("events");
 
+/// A write to the events store, done by the writer thread (see [`spawn_writer`]).
+pub enum WriteCmd {
+  /// Writes the event after the last one in the table.
+  Insert(Arc<Event>),
+  /// Removes the events for which the function is `true`.
+  Remove(Box<dyn Fn(&Event) -> bool + Send>),
+  /// Removes the events expired (NIP-40) at the timestamp.
+  RemoveExpired(Timestamp),
+  /// Answers once every write sent before it is done.
+  Flush(Sender<()>),
+}
+
 pub struct EventsDB {
   db: Database,
 }
@@ -34,7 +56,109 @@ impl EventsDB {
     write_txn.commit()
   }
 
-  pub fn write_to_db(&mut self, k: u64, v: &str) -> Result<(), redb::Error> {
+  /// Writes the event after the last one in the table.
+  pub fn insert(&self, event: &Event) -> Result<(), redb::Error> {
+    self.write_to_db(&self.next_key()?, &event.as_json())
+  }
+
+  /// Deletes the item with the key `k`, if it exists.
+  pub fn delete(&mut self, k: u64) -> Result<(), redb::Error> {
+    self.remove_from_db(&k)
+  }
+
+  /// Removes the event with the id `id` from the table.
+  ///
+  /// Returns whether it was found (and removed).
+  ///
+  pub fn remove_by_event_id(&self, id: &str) -> Result<bool, redb::Error> {
+    let removed = self.remove_items(|event| event.id == id)?;
+    Ok(removed > 0)
+  }
+
+  /// Gets the key to be used when writing a new item,
+  /// which is the one after the last key in the table.
+  ///
+  pub fn next_key(&self) -> Result<u64, redb::Error> {
+    let read_txn = self.db.begin_read()?;
+    let table = read_txn.open_table(EVENTS_TABLE)?;
+    let next_key = match table.iter()?.next_back() {
+      Some(last_item) => last_item?.0.value() + 1,
+      None => 0,
+    };
+
+    Ok(next_key)
+  }
+
+  /// Removes from the table all the events that are expired (NIP-40)
+  /// at the `now` timestamp.
+  ///
+  /// Returns the number of events removed.
+  ///
+  pub fn remove_expired_items(&self, now: Timestamp) -> Result<usize, redb::Error> {
+    self.remove_items(|event| event.is_expired(now))
+  }
+
+  /// Removes from the table all the events for which `should_remove` is `true`.
+  ///
+  /// Returns the number of events removed.
+  ///
+  pub fn remove_items<F>(&self, should_remove: F) -> Result<usize, redb::Error>
+  where
+    F: Fn(&Event) -> bool,
+  {
+    let write_txn = self.begin_write()?;
+    let removed = {
+      let mut table = write_txn.open_table(EVENTS_TABLE)?;
+      let keys_to_remove: Vec<u64> = table
+        .iter()?
+        .filter_map(|item| {
+          let (key, value) = item.ok()?;
+          let event = Event::from_json(value.value()).ok()?;
+          should_remove(&event).then_some(key.value())
+        })
+        .collect();
+
+      for key in keys_to_remove.iter() {
+        table.remove(key)?;
+      }
+
+      keys_to_remove.len()
+    };
+    self.commit_txn(write_txn)?;
+    Ok(removed)
+  }
+
+  pub fn get_all_items(&self) -> Result<Vec<Event>, redb::Error> {
+    let events = self
+      .read_all()?
+      .into_iter()
+      .map(|(_, event_value)| Event::from_json(event_value).unwrap())
+      .collect();
+
+    Ok(events)
+  }
+
+  /// Spawns a thread doing, in order, the writes sent to the returned channel,
+  /// so whoever sends them does not wait for the disk (see [`spawn_writer`]).
+  ///
+  pub fn spawn_writer(self: &Arc<Self>) -> Sender<WriteCmd> {
+    spawn_writer(Box::new(Arc::clone(self)))
+  }
+}
+
+/// Waits until the writes sent to the `writer` before this call are done.
+pub fn wait_for_writes(writer: &Sender<WriteCmd>) {
+  let (done_tx, done_rx) = mpsc::channel();
+  if writer.send(WriteCmd::Flush(done_tx)).is_ok() {
+    let _ = done_rx.recv();
+  }
+}
+
+impl ClientDatabase for EventsDB {
+  type K = u64;
+  type V = str;
+
+  fn write_to_db(&self, k: &Self::K, v: &Self::V) -> Result<(), redb::Error> {
     let write_txn = self.begin_write()?;
     {
       let mut table = write_txn.open_table(EVENTS_TABLE)?;
@@ -44,19 +168,27 @@ impl EventsDB {
     Ok(())
   }
 
-  pub fn get_all_items(&self) -> Result<Vec<Event>, redb::Error> {
-    let mut events: Vec<Event> = vec![];
+  fn remove_from_db(&self, k: &Self::K) -> Result<(), redb::Error> {
+    let write_txn = self.begin_write()?;
+    {
+      let mut table = write_txn.open_table(EVENTS_TABLE)?;
+      table.remove(k)?;
+    }
+    self.commit_txn(write_txn)?;
+    Ok(())
+  }
+
+  fn read_all(&self) -> Result<Items<Self::K, Self::V>, redb::Error> {
     let read_txn = self.db.begin_read()?;
-    let table = read_txn.open_table(EVENTS_TABLE).unwrap();
+    let table = read_txn.open_table(EVENTS_TABLE)?;
 
-    table.iter().unwrap().for_each(|event| {
-      let evt = event.unwrap();
-      let event_value = evt.1.value();
-      let event_deserialized: Event = Event::from_json(event_value).unwrap();
-      events.push(event_deserialized);
-    });
+    let mut items = vec![];
+    for item in table.iter()? {
+      let (k, v) = item?;
+      items.push((k.value(), v.value().to_string()));
+    }
 
-    Ok(events)
+    Ok(items)
   }
 }
 
@@ -65,6 +197,7 @@ mod tests {
   use std::vec;
 
   use super::*;
+  use crate::event::tag::{Tag, TagKind};
 
   #[cfg(test)]
   use pretty_assertions::assert_eq;
@@ -105,20 +238,185 @@ mod tests {
 
   #[test]
   fn write_to_db() {
-    let mut sut = Sut::new("write_to_db");
+    let sut = Sut::new("write_to_db");
     let mock_event = sut.gen_event();
 
     let result = sut.events_db.get_all_items().unwrap();
     assert_eq!(result.len(), 0);
 
-    sut.events_db.write_to_db(0, &mock_event).unwrap();
-    sut.events_db.write_to_db(1, &mock_event).unwrap();
-    sut.events_db.write_to_db(2, &mock_event).unwrap();
+    sut.events_db.write_to_db(&0, &mock_event).unwrap();
+    sut.events_db.write_to_db(&1, &mock_event).unwrap();
+    sut.events_db.write_to_db(&2, &mock_event).unwrap();
 
     let result = sut.events_db.get_all_items().unwrap();
     assert_eq!(result.len(), 3);
   }
 
+  #[test]
+  fn next_key() {
+    let sut = Sut::new("next_key");
+    let mock_event = sut.gen_event();
+
+    assert_eq!(sut.events_db.next_key().unwrap(), 0);
+
+    sut.events_db.write_to_db(&0, &mock_event).unwrap();
+    sut.events_db.write_to_db(&5, &mock_event).unwrap();
+
+    assert_eq!(sut.events_db.next_key().unwrap(), 6);
+  }
+
+  #[test]
+  fn remove_expired_items() {
+    let sut = Sut::new("remove_expired_items");
+    let mock_event = sut.gen_event();
+    let mut expiring_event = Event::from_json(&mock_event).unwrap();
+    expiring_event.tags = vec![Tag::Generic(
+      TagKind::Custom(String::from("expiration")),
+      vec![String::from("1000")],
+    )];
+
+    sut.events_db.write_to_db(&0, &mock_event).unwrap();
+    sut.events_db.write_to_db(&1, &expiring_event.as_json()).unwrap();
+
+    // not expired yet
+    let removed = sut.events_db.remove_expired_items(999).unwrap();
+    assert_eq!(removed, 0);
+    assert_eq!(sut.events_db.get_all_items().unwrap().len(), 2);
+
+    let removed = sut.events_db.remove_expired_items(1000).unwrap();
+    assert_eq!(removed, 1);
+    let result = sut.events_db.get_all_items().unwrap();
+    assert_eq!(result, vec![Event::from_json(&mock_event).unwrap()]);
+  }
+
+  #[test]
+  fn delete() {
+    let mut sut = Sut::new("delete");
+    let events: Vec<Event> = ["first", "second", "third"]
+      .iter()
+      .map(|id| Event {
+        id: id.to_string(),
+        ..Default::default()
+      })
+      .collect();
+    for (key, event) in events.iter().enumerate() {
+      sut
+        .events_db
+        .write_to_db(&(key as u64), &event.as_json())
+        .unwrap();
+    }
+
+    sut.events_db.delete(1).unwrap();
+
+    assert_eq!(
+      sut.events_db.get_all_items().unwrap(),
+      vec![events[0].clone(), events[2].clone()]
+    );
+    // deleting a key that does not exist is a no-op
+    sut.events_db.delete(1).unwrap();
+    assert_eq!(sut.events_db.get_all_items().unwrap().len(), 2);
+    // the deleted key is not reused
+    assert_eq!(sut.events_db.next_key().unwrap(), 3);
+  }
+
+  #[test]
+  fn remove_by_event_id() {
+    let sut = Sut::new("remove_by_event_id");
+    let events: Vec<Event> = ["first", "second", "third"]
+      .iter()
+      .map(|id| Event {
+        id: id.to_string(),
+        ..Default::default()
+      })
+      .collect();
+    for (key, event) in events.iter().enumerate() {
+      sut
+        .events_db
+        .write_to_db(&(key as u64), &event.as_json())
+        .unwrap();
+    }
+
+    assert!(sut.events_db.remove_by_event_id("second").unwrap());
+    assert_eq!(sut.events_db.remove_by_event_id("second").unwrap(), false);
+
+    assert_eq!(
+      sut.events_db.get_all_items().unwrap(),
+      vec![events[0].clone(), events[2].clone()]
+    );
+  }
+
+  #[test]
+  fn remove_items() {
+    let sut = Sut::new("remove_items");
+    let mock_event = sut.gen_event();
+    let other_event = Event {
+      id: String::from("other_id"),
+      ..Event::from_json(&mock_event).unwrap()
+    };
+
+    sut.events_db.write_to_db(&0, &mock_event).unwrap();
+    sut
+      .events_db
+      .write_to_db(&1, &other_event.as_json())
+      .unwrap();
+
+    let removed = sut
+      .events_db
+      .remove_items(|event| event.id == "other_id")
+      .unwrap();
+
+    assert_eq!(removed, 1);
+    assert_eq!(
+      sut.events_db.get_all_items().unwrap(),
+      vec![Event::from_json(&mock_event).unwrap()]
+    );
+  }
+
+  #[test]
+  fn spawn_writer() {
+    let events_db = Arc::new(EventsDB::new(Some("spawn_writer".to_string())).unwrap());
+    let writer = events_db.spawn_writer();
+    let events: Vec<Event> = ["first", "second", "third"]
+      .iter()
+      .map(|id| Event {
+        id: id.to_string(),
+        ..Default::default()
+      })
+      .collect();
+
+    for event in events.iter() {
+      writer
+        .send(WriteCmd::Insert(Arc::new(event.clone())))
+        .unwrap();
+    }
+    writer
+      .send(WriteCmd::Remove(Box::new(|event| event.id == "second")))
+      .unwrap();
+    wait_for_writes(&writer);
+
+    assert_eq!(
+      events_db.get_all_items().unwrap(),
+      vec![events[0].clone(), events[2].clone()]
+    );
+    assert_eq!(events_db.next_key().unwrap(), 3);
+
+    fs::remove_file("db/spawn_writer.redb").unwrap();
+  }
+
+  #[test]
+  fn read_all() {
+    let sut = Sut::new("read_all");
+    let mock_event = sut.gen_event();
+
+    sut.events_db.write_to_db(&3, &mock_event).unwrap();
+    sut.events_db.write_to_db(&1, &mock_event).unwrap();
+
+    assert_eq!(
+      sut.events_db.read_all().unwrap(),
+      vec![(1, mock_event.clone()), (3, mock_event)]
+    );
+  }
+
   #[test]
   fn get_all_items() {
     let sut = Sut::new("get_all_items");
@@ -0,0 +1,213 @@
+//! Where the relay persists the events it stores (see [`EventStore`]).
+
+use std::{
+  sync::{
+    mpsc::{self, Sender},
+    Arc,
+  },
+  thread,
+};
+
+use log::error;
+
+use crate::{
+  event::Event,
+  filter::Filter,
+  relay::database::{EventsDB, WriteCmd},
+};
+
+/// Persistence of the events stored by the relay.
+///
+/// The relay answers the requests from the events it keeps in memory,
+/// so a store is written to by its writer thread (see [`spawn_writer`])
+/// and only read when the relay starts.
+///
+pub trait EventStore: Send {
+  /// Saves the event after the ones already saved.
+  fn save(&mut self, event: &Event);
+
+  /// Deletes the events for which `should_delete` is `true`.
+  ///
+  /// Returns the number of events deleted.
+  ///
+  fn delete_where(&mut self, should_delete: &dyn Fn(&Event) -> bool) -> usize;
+
+  /// Gets all the events saved, in the order they were saved.
+  fn all(&self) -> Vec<Event>;
+
+  /// Deletes the event with the id `id`, if it is saved.
+  fn delete(&mut self, id: &str) {
+    self.delete_where(&|event| event.id == id);
+  }
+
+  /// Gets the events saved that match `filter`, in the order they were saved.
+  fn query(&self, filter: &Filter) -> Vec<Event> {
+    self
+      .all()
+      .into_iter()
+      .filter(|event| filter.matches(event))
+      .collect()
+  }
+}
+
+/// Which [`EventStore`] the relay uses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EventStoreKind {
+  /// Events are lost when the relay stops (see [`MemoryEventStore`]).
+  Memory,
+  /// Events are kept in a redb database (see [`EventsDB`]).
+  #[default]
+  Redb,
+}
+
+/// Opens the store of the kind `kind`.
+pub fn open_event_store(kind: EventStoreKind) -> Result<Box<dyn EventStore>, redb::Error> {
+  match kind {
+    EventStoreKind::Memory => Ok(Box::<MemoryEventStore>::default()),
+    EventStoreKind::Redb => Ok(Box::new(Arc::new(EventsDB::new(None)?))),
+  }
+}
+
+/// Spawns a thread doing, in order, the writes sent to the returned channel
+/// on `store`, so whoever sends them does not wait for it (e.g.: for the disk).
+/// The thread ends once every sender is dropped.
+///
+pub fn spawn_writer(mut store: Box<dyn EventStore>) -> Sender<WriteCmd> {
+  let (writer, writes) = mpsc::channel::<WriteCmd>();
+  thread::spawn(move || {
+    for write in writes {
+      match write {
+        WriteCmd::Insert(event) => store.save(&event),
+        WriteCmd::Remove(should_remove) => {
+          store.delete_where(&should_remove);
+        }
+        WriteCmd::RemoveExpired(now) => {
+          store.delete_where(&|event| event.is_expired(now));
+        }
+        WriteCmd::Flush(done) => {
+          let _ = done.send(());
+        }
+      }
+    }
+  });
+  writer
+}
+
+/// Keeps the events in memory only.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryEventStore {
+  events: Vec<Event>,
+}
+
+impl EventStore for MemoryEventStore {
+  fn save(&mut self, event: &Event) {
+    self.events.push(event.clone());
+  }
+
+  fn delete_where(&mut self, should_delete: &dyn Fn(&Event) -> bool) -> usize {
+    let before = self.events.len();
+    self.events.retain(|event| !should_delete(event));
+    before - self.events.len()
+  }
+
+  fn all(&self) -> Vec<Event> {
+    self.events.clone()
+  }
+}
+
+/// Shared, so the events can also be read while the writer thread writes them
+/// (see [`EventsDB::spawn_writer`]).
+///
+impl EventStore for Arc<EventsDB> {
+  fn save(&mut self, event: &Event) {
+    if let Err(err) = self.insert(event) {
+      error!("Error saving the event {} to the database: {err}", event.id);
+    }
+  }
+
+  fn delete_where(&mut self, should_delete: &dyn Fn(&Event) -> bool) -> usize {
+    self.remove_items(should_delete).unwrap_or_else(|err| {
+      error!("Error deleting events from the database: {err}");
+      0
+    })
+  }
+
+  fn all(&self) -> Vec<Event> {
+    self.get_all_items().unwrap_or_else(|err| {
+      error!("Error reading the events from the database: {err}");
+      vec![]
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::*;
+  use crate::event::{kind::EventKind, tag::Tag};
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  fn make_event_sut(id: &str, kind: EventKind, tags: Vec<Tag>) -> Event {
+    Event {
+      id: id.to_string(),
+      kind,
+      tags,
+      ..Default::default()
+    }
+  }
+
+  /// The same checks, whatever the store.
+  fn check_event_store(store: &mut dyn EventStore) {
+    let note = make_event_sut("note", EventKind::Text, vec![]);
+    let reaction = make_event_sut("reaction", EventKind::Reaction, vec![]);
+    let expiring = make_event_sut("expiring", EventKind::Text, vec![Tag::expiration(1000)]);
+    assert_eq!(store.all(), vec![]);
+
+    for event in [&note, &reaction, &expiring] {
+      store.save(event);
+    }
+
+    assert_eq!(
+      store.all(),
+      vec![note.clone(), reaction.clone(), expiring.clone()]
+    );
+    assert_eq!(
+      store.query(&Filter::new().kinds(vec![EventKind::Text])),
+      vec![note.clone(), expiring.clone()]
+    );
+    assert_eq!(
+      store.query(&Filter::new().ids(vec![String::from("reaction")])),
+      vec![reaction.clone()]
+    );
+    assert_eq!(
+      store.query(&Filter::new().kinds(vec![EventKind::Deletion])),
+      vec![]
+    );
+
+    assert_eq!(store.delete_where(&|event| event.is_expired(1000)), 1);
+    store.delete("reaction");
+    // deleting an event not saved is a no-op
+    store.delete("reaction");
+
+    assert_eq!(store.all(), vec![note.clone()]);
+    assert_eq!(store.query(&Filter::new()), vec![note]);
+  }
+
+  #[test]
+  fn memory_event_store() {
+    check_event_store(&mut MemoryEventStore::default());
+  }
+
+  #[test]
+  fn redb_event_store() {
+    let table_name = "redb_event_store";
+    check_event_store(&mut Arc::new(
+      EventsDB::new(Some(table_name.to_string())).unwrap(),
+    ));
+
+    fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+}
@@ -0,0 +1,263 @@
+use std::{
+  collections::{BTreeSet, HashMap, HashSet},
+  sync::Arc,
+};
+
+use crate::{
+  event::{kind::EventKind, Event, PubKey},
+  filter::{Filter, IdMatching},
+};
+
+/// Length of a complete hex-encoded (x-only) public key.
+/// Authors shorter than that are prefixes and cannot be looked up in the index.
+const PUBKEY_HEX_LENGTH: usize = 64;
+
+/// Indexes the positions of the events stored by the relay
+/// (in the `events` vector) by their author and kind, so
+/// a `REQ` does not need to go through every stored event
+/// when its filters have `authors` or `kinds`.
+///
+/// It also keeps the ids of the stored events, so checking
+/// if an incoming event is a duplicate doesn't need a scan.
+///
+/// The positions are only valid as long as the `events` vector
+/// is only appended to. If events are removed from it, the
+/// index must be rebuilt (see [`EventsIndex::rebuild`]).
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventsIndex {
+  pub by_author: HashMap<PubKey, Vec<usize>>,
+  pub by_kind: HashMap<EventKind, Vec<usize>>,
+  pub ids: HashSet<String>,
+}
+
+impl EventsIndex {
+  /// Creates the index for an already existing list of events.
+  pub fn new(events: &[Arc<Event>]) -> Self {
+    let mut index = Self::default();
+    index.rebuild(events);
+    index
+  }
+
+  /// Indexes a new event that was pushed to the `events` vector at `position`.
+  pub fn insert(&mut self, event: &Event, position: usize) {
+    self
+      .by_author
+      .entry(event.pubkey.clone())
+      .or_default()
+      .push(position);
+    self.by_kind.entry(event.kind).or_default().push(position);
+    self.ids.insert(event.id.clone());
+  }
+
+  /// Checks if an event with this id is stored.
+  pub fn contains(&self, event_id: &str) -> bool {
+    self.ids.contains(event_id)
+  }
+
+  /// Recreates the whole index from scratch.
+  pub fn rebuild(&mut self, events: &[Arc<Event>]) {
+    self.by_author.clear();
+    self.by_kind.clear();
+    self.ids.clear();
+    for (position, event) in events.iter().enumerate() {
+      self.insert(event, position);
+    }
+  }
+
+  /// Returns the positions of the events that can possibly match the filter,
+  /// in ascending order.
+  ///
+  /// Returns `None` when the filter cannot be narrowed by the index
+  /// (it has no `authors` nor `kinds`, or some author is just a prefix),
+  /// meaning all events must be checked.
+  ///
+  pub fn candidates(&self, filter: &Filter) -> Option<Vec<usize>> {
+    let by_authors = filter
+      .authors
+      .as_ref()
+      .filter(|authors| {
+        authors
+          .iter()
+          .all(|author| author.len() == PUBKEY_HEX_LENGTH)
+      })
+      .map(|authors| {
+        authors
+          .iter()
+          .filter_map(|author| self.by_author.get(author))
+          .flatten()
+          .copied()
+          .collect::<BTreeSet<usize>>()
+      });
+
+    let by_kinds = filter.kinds.as_ref().map(|kinds| {
+      kinds
+        .iter()
+        .filter_map(|kind| self.by_kind.get(kind))
+        .flatten()
+        .copied()
+        .collect::<BTreeSet<usize>>()
+    });
+
+    let candidates = match (by_authors, by_kinds) {
+      (Some(by_authors), Some(by_kinds)) => by_authors.intersection(&by_kinds).copied().collect(),
+      (Some(positions), None) | (None, Some(positions)) => positions.into_iter().collect(),
+      (None, None) => return None,
+    };
+
+    Some(candidates)
+  }
+
+  /// Gets all events that match the filter, using the index
+  /// to narrow down the events to be checked whenever possible.
+  ///
+  /// The events are returned in the same order they are in `events`.
+  ///
+  pub fn matching_events<'a>(
+    &self,
+    events: &'a [Arc<Event>],
+    filter: &Filter,
+    id_matching: IdMatching,
+  ) -> Vec<&'a Arc<Event>> {
+    let candidates: Vec<&Arc<Event>> = match self.candidates(filter) {
+      Some(positions) => positions
+        .into_iter()
+        .filter_map(|position| events.get(position))
+        .collect(),
+      None => events.iter().collect(),
+    };
+
+    candidates
+      .into_iter()
+      .filter(|event| filter.matches_with(event, id_matching))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::event::Timestamp;
+
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  const NUMBER_OF_AUTHORS: usize = 50;
+  const NUMBER_OF_KINDS: u64 = 10;
+
+  fn make_author(i: usize) -> PubKey {
+    format!("{:064x}", i)
+  }
+
+  fn make_events(quantity: usize) -> Vec<Arc<Event>> {
+    (0..quantity)
+      .map(|i| {
+        Arc::new(Event {
+          id: format!("{:064x}", i),
+          pubkey: make_author(i % NUMBER_OF_AUTHORS),
+          kind: EventKind::from(i as u64 % NUMBER_OF_KINDS),
+          created_at: i as Timestamp,
+          ..Default::default()
+        })
+      })
+      .collect()
+  }
+
+  fn linear_scan<'a>(events: &'a [Arc<Event>], filter: &Filter) -> Vec<&'a Arc<Event>> {
+    events
+      .iter()
+      .filter(|event| filter.matches(event))
+      .collect()
+  }
+
+  #[test]
+  fn insert_indexes_by_author_and_kind() {
+    let events = make_events(3);
+    let mut index = EventsIndex::default();
+
+    for (position, event) in events.iter().enumerate() {
+      index.insert(event, position);
+    }
+
+    assert_eq!(index, EventsIndex::new(&events));
+    assert_eq!(index.by_author.get(&make_author(1)), Some(&vec![1]));
+    assert_eq!(index.by_kind.get(&EventKind::Text), Some(&vec![1]));
+    assert!(index.contains(&events[2].id));
+  }
+
+  #[test]
+  fn ids_mirror_the_events() {
+    let mut events = make_events(5);
+    let mut index = EventsIndex::new(&events);
+
+    let removed = events.remove(3);
+    index.rebuild(&events);
+
+    assert_eq!(
+      index.ids,
+      events.iter().map(|event| event.id.clone()).collect()
+    );
+    assert_eq!(index.contains(&removed.id), false);
+  }
+
+  #[test]
+  fn candidates_is_none_when_filter_cannot_be_narrowed() {
+    let events = make_events(10);
+    let index = EventsIndex::new(&events);
+
+    let filter = Filter {
+      since: Some(2),
+      ..Default::default()
+    };
+    assert_eq!(index.candidates(&filter), None);
+
+    let filter_with_author_prefix = Filter {
+      authors: Some(vec![String::from("0000")]),
+      ..Default::default()
+    };
+    assert_eq!(index.candidates(&filter_with_author_prefix), None);
+  }
+
+  #[test]
+  fn indexed_path_returns_the_same_events_as_the_linear_scan() {
+    let events = make_events(10_000);
+    let index = EventsIndex::new(&events);
+
+    let filters = [
+      Filter {
+        authors: Some(vec![make_author(3), make_author(7)]),
+        ..Default::default()
+      },
+      Filter {
+        kinds: Some(vec![EventKind::Metadata, EventKind::Reaction]),
+        ..Default::default()
+      },
+      Filter {
+        authors: Some(vec![make_author(3), make_author(10)]),
+        kinds: Some(vec![EventKind::Custom(3)]),
+        since: Some(5_000),
+        ..Default::default()
+      },
+      Filter {
+        authors: Some(vec![make_author(NUMBER_OF_AUTHORS + 1)]),
+        ..Default::default()
+      },
+      Filter {
+        until: Some(100),
+        ..Default::default()
+      },
+    ];
+
+    for filter in filters.iter() {
+      let indexed = index.matching_events(&events, filter, IdMatching::Prefix);
+      let linear = linear_scan(&events, filter);
+
+      assert_eq!(indexed, linear);
+    }
+
+    // the index must actually narrow the events to be checked
+    let candidates = index.candidates(&filters[2]).unwrap();
+    assert_eq!(candidates.len(), 10_000 / NUMBER_OF_AUTHORS);
+  }
+}
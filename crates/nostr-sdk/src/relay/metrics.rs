@@ -0,0 +1,80 @@
+//! Counters of the relay, for monitoring.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Counters of the relay, updated by the connections with the clients
+/// (see `handle_connection`) and read with [`RelayMetrics::snapshot`].
+///
+#[derive(Debug, Default)]
+pub struct RelayMetrics {
+  connected_clients: AtomicUsize,
+  stored_events: AtomicUsize,
+  events_served: AtomicU64,
+}
+
+/// The values of the [`RelayMetrics`] at a given moment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RelayMetricsSnapshot {
+  pub connected_clients: usize,
+  pub stored_events: usize,
+  /// `EVENT` messages sent to the clients, stored or broadcast.
+  pub events_served: u64,
+}
+
+impl RelayMetrics {
+  pub fn client_connected(&self) {
+    self.connected_clients.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn client_disconnected(&self) {
+    self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+  }
+
+  pub fn set_stored_events(&self, stored_events: usize) {
+    self.stored_events.store(stored_events, Ordering::Relaxed);
+  }
+
+  pub fn add_events_served(&self, events_served: usize) {
+    self
+      .events_served
+      .fetch_add(events_served as u64, Ordering::Relaxed);
+  }
+
+  pub fn snapshot(&self) -> RelayMetricsSnapshot {
+    RelayMetricsSnapshot {
+      connected_clients: self.connected_clients.load(Ordering::Relaxed),
+      stored_events: self.stored_events.load(Ordering::Relaxed),
+      events_served: self.events_served.load(Ordering::Relaxed),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn snapshot_reads_the_counters() {
+    let metrics = RelayMetrics::default();
+
+    metrics.client_connected();
+    metrics.client_connected();
+    metrics.client_disconnected();
+    metrics.set_stored_events(10);
+    metrics.set_stored_events(7);
+    metrics.add_events_served(3);
+    metrics.add_events_served(2);
+
+    assert_eq!(
+      metrics.snapshot(),
+      RelayMetricsSnapshot {
+        connected_clients: 1,
+        stored_events: 7,
+        events_served: 5,
+      }
+    );
+  }
+}
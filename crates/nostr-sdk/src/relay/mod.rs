@@ -1,43 +1,152 @@
 pub mod communication_with_client;
+pub mod config;
 pub mod database;
+pub mod event_store;
+pub mod events_index;
+pub mod metrics;
 pub mod pool;
+pub mod pubkey_policy;
+pub mod rate_limiter;
 pub mod receive_from_client;
+pub mod relay_url;
 pub mod send_to_client;
+pub mod test_relay;
 
 use std::{
+  collections::{HashMap, HashSet},
   env,
   io::Error as IoError,
   net::SocketAddr,
-  sync::{Arc, Mutex},
+  sync::{mpsc::Sender, Arc, Mutex, MutexGuard},
+  time::{SystemTime, UNIX_EPOCH},
 };
 
 use futures_util::{future, pin_mut, stream::TryStreamExt, FutureExt, SinkExt, StreamExt};
 
-use log::{debug, error, info};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::time::{self, Duration};
-use tokio_tungstenite::tungstenite::Message;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc::error::TrySendError, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{self, Duration, Instant};
+use tokio_tungstenite::tungstenite::{
+  handshake::server::{Request, Response},
+  http::header::USER_AGENT,
+  Message,
+};
+use tracing::{debug, error, info};
+use tracing_subscriber::EnvFilter;
 
 use crate::{
   client::communication_with_relay::{
-    close::ClientToRelayCommClose, event::ClientToRelayCommEvent, request::ClientToRelayCommRequest,
+    close::ClientToRelayCommClose, connections::ClientToRelayCommConnections,
+    event::ClientToRelayCommEvent, request::ClientToRelayCommRequest, Error as CommError,
   },
-  event::Event,
-  filter::Filter,
+  event::{kind::EventKind, Event, EventVerifyError, PubKey, Timestamp},
+  filter::{Filter, IdMatching},
   relay::{
-    communication_with_client::{eose::RelayToClientCommEose, notice::RelayToClientCommNotice},
-    database::EventsDB,
+    communication_with_client::{
+      eose::RelayToClientCommEose, notice::RelayToClientCommNotice, ok::RelayToClientCommOk,
+    },
+    config::{accept_stream, get_relay_config, TlsConfigError},
+    database::WriteCmd,
+    event_store::{open_event_store, spawn_writer},
+    events_index::EventsIndex,
+    metrics::RelayMetrics,
+    pubkey_policy::{get_pubkey_policy, PubkeyPolicy},
+    rate_limiter::RateLimiter,
   },
 };
 
 use crate::relay::{
   receive_from_client::{
-    close::on_close_message, event::on_event_message, request::on_request_message,
+    close::on_close_message,
+    connections::{get_admin_pubkey, on_connections_message},
+    event::{
+      is_deleted_by_prior_request, is_event_too_far_in_the_future, is_event_too_large,
+      is_event_too_old, is_same_replaceable_event, on_deletion_event, on_event_message,
+      on_replaceable_event,
+    },
+    request::{is_subscription_open, limit_reached_closed, on_request_message},
   },
-  send_to_client::{broadcast_message_to_clients, send_message_to_client},
+  send_to_client::{broadcast_message_to_clients, send_message_to_client, OutboundInfo},
 };
 
-pub type Tx = tokio::sync::mpsc::UnboundedSender<Message>;
+pub type Tx = tokio::sync::mpsc::Sender<Message>;
+
+/// Sends a write to the events store writer thread (see [`spawn_writer`]).
+fn send_to_events_db(events_writer: &Sender<WriteCmd>, write: WriteCmd) {
+  if events_writer.send(write).is_err() {
+    error!("Error writing to the events database: the writer thread is gone");
+  }
+}
+
+/// Sets up `tracing` to write the logs of the relay to stdout, filtered by
+/// `RUST_LOG` (`info` by default). The logs made with `log` are written as well.
+///
+/// Does nothing if the logs are already set up (e.g.: by the binary using the relay).
+///
+pub fn init_tracing() {
+  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+  let _ = tracing_subscriber::fmt()
+    .with_env_filter(filter)
+    .with_ansi(env::var("RUST_LOG_STYLE").ok().as_deref() != Some("never"))
+    .try_init();
+}
+
+/// Default interval, in seconds, between the pings sent to the clients.
+const DEFAULT_PING_INTERVAL_SECS: u64 = 20;
+
+/// Default number of ping intervals without a pong after which
+/// the connection with a client is considered dead.
+const DEFAULT_MISSED_PONGS: u32 = 2;
+
+/// Default interval, in seconds, between the sweeps of expired events (NIP-40).
+const DEFAULT_EXPIRATION_SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// Default number of extra events the `events` vector has room for
+/// before it needs to reallocate.
+const DEFAULT_EVENTS_CAPACITY_GRACE: usize = 1024;
+
+/// Once the relay stores more than `MAX_STORED_EVENTS` events, the oldest ones are
+/// evicted until there are `1 / EVICTION_BATCH_DIVISOR` less than that, so the
+/// eviction (which goes through all the stored events) is not done on every insert.
+const EVICTION_BATCH_DIVISOR: usize = 10;
+
+/// Default number of seconds the `created_at` of the events accepted
+/// by the relay can be ahead of its clock.
+const DEFAULT_MAX_FUTURE_DRIFT_SECS: u64 = 15 * 60;
+
+/// Default maximum size, in bytes, of the (serialized) events accepted by the relay.
+const DEFAULT_MAX_EVENT_SIZE: usize = 128 * 1024;
+
+/// Default maximum number of events being verified (or waiting to be)
+/// at the same time, across all clients.
+const DEFAULT_MAX_PENDING_VERIFICATIONS: usize = 64;
+
+/// Default number of messages waiting to be sent to a client. Once it is
+/// reached, the client is not keeping up with them and is disconnected.
+const DEFAULT_CLIENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Default number of events per second each client can send to the relay.
+const DEFAULT_EVENTS_PER_SEC: f64 = 10.0;
+
+/// Default number of events each client can send to the relay at once,
+/// before being limited to [`DEFAULT_EVENTS_PER_SEC`].
+const DEFAULT_EVENTS_BURST: u32 = 50;
+
+/// Message of the `NOTICE` sent when an event is shed because
+/// there are too many events pending verification.
+const OVERLOADED_NOTICE: &str = "error: overloaded";
+
+/// Message of the `OK` sent when an event is refused because
+/// its author is not allowed to publish to the relay (see [`PubkeyPolicy`]).
+const BLOCKED_PUBKEY_MESSAGE: &str = "blocked: pubkey not allowed";
+
+/// Message of the `OK` sent when the event of an `EVENT` message is not a valid event.
+const MALFORMED_EVENT_MESSAGE: &str = "invalid: malformed event json";
+
+/// Message of the `OK` sent when an event is refused because
+/// the client is sending them too fast.
+const RATE_LIMITED_MESSAGE: &str = "rate-limited: slow down";
 
 /// Holds information about the requests made by a client.
 ///
@@ -45,6 +154,32 @@ pub type Tx = tokio::sync::mpsc::UnboundedSender<Message>;
 pub struct ClientRequests {
   pub subscription_id: String,
   pub filters: Vec<Filter>,
+  /// How many events can still be sent to this subscription (stored + live),
+  /// when all of its filters have a `limit`. `None` means unlimited.
+  pub remaining_limit: Option<u64>,
+  /// How the `ids` of the filters are matched against the events.
+  pub id_matching: IdMatching,
+}
+
+impl ClientRequests {
+  /// Creates a request whose `remaining_limit` is the sum of the limits
+  /// of its filters, or unlimited if any of them has no `limit`, has
+  /// `limit: 0` (only the live events are wanted) or if the sum overflows.
+  ///
+  pub fn new(subscription_id: String, filters: Vec<Filter>, id_matching: IdMatching) -> Self {
+    let remaining_limit = filters
+      .iter()
+      .try_fold(0u64, |total, filter| match filter.limit {
+        Some(limit) if limit > 0 => total.checked_add(limit),
+        _ => None,
+      });
+    Self {
+      subscription_id,
+      filters,
+      remaining_limit,
+      id_matching,
+    }
+  }
 }
 
 /// Holds information about the clients connection.
@@ -54,30 +189,96 @@ pub struct ClientRequests {
 pub struct ClientConnectionInfo {
   pub tx: Tx,
   pub socket_addr: SocketAddr,
+  /// `User-Agent` header sent by the client on the WebSocket handshake.
+  pub user_agent: Option<String>,
+  /// Pubkey the client authenticated with, if any.
+  pub authenticated_pubkey: Option<PubKey>,
   pub requests: Vec<ClientRequests>,
+  /// Notified to close the connection, e.g.: when the client
+  /// is not keeping up with the messages sent to it.
+  pub disconnect: Arc<Notify>,
+}
+
+/// Settings of the relay shared by all the connections.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RelaySettings {
+  /// How the `ids` of the filters of the subscriptions are matched
+  /// against the events: exactly (NIP-01) when the `RELAY_EXACT_ID_MATCHING`
+  /// env var is set to `true`, or as prefixes otherwise.
+  pub id_matching: IdMatching,
+  /// Refuses to store events whose deletion (NIP-09) was requested before
+  /// they arrived, when the `RELAY_HONOR_PRIOR_DELETIONS` env var is set to `true`.
+  pub honor_prior_deletions: bool,
+  /// Refuses events without the tags their kind requires (see [`EventKind::validate_tags`]),
+  /// when the `RELAY_ENFORCE_KIND_TAGS` env var is set to `true`.
+  pub enforce_kind_tags: bool,
+}
+
+impl RelaySettings {
+  /// Reads the settings from the env vars.
+  ///
+  pub fn from_env() -> Self {
+    let is_enabled = |name: &str| env::var(name).is_ok_and(|enabled| enabled == "true");
+    let id_matching = if is_enabled("RELAY_EXACT_ID_MATCHING") {
+      IdMatching::Exact
+    } else {
+      IdMatching::Prefix
+    };
+
+    Self {
+      id_matching,
+      honor_prior_deletions: is_enabled("RELAY_HONOR_PRIOR_DELETIONS"),
+      enforce_kind_tags: is_enabled("RELAY_ENFORCE_KIND_TAGS"),
+    }
+  }
 }
 
 #[derive(Default, Clone, Debug)]
 struct AnyCommunicationFromClient {
   close: ClientToRelayCommClose,
+  connections: ClientToRelayCommConnections,
   event: ClientToRelayCommEvent,
   request: ClientToRelayCommRequest,
+  /// Id (if any) of the event of an `EVENT` message that is not a valid event.
+  malformed_event_id: String,
 }
 
 #[derive(Default, Debug, Clone)]
 struct MsgResult {
   no_op: bool,
   is_close: bool,
+  is_connections: bool,
   is_event: bool,
+  is_malformed_event: bool,
   is_request: bool,
   data: AnyCommunicationFromClient,
 }
 
-/// Helper to parse the function into CLOSE, REQ or EVENT.
+/// Helper to parse the function into CLOSE, REQ, EVENT or CONNECTIONS.
+///
+/// Both text and binary (UTF-8 encoded) frames are accepted.
+/// Any other frame (Ping, Pong, Close) is a no-op.
 ///
-fn parse_message_received_from_client(msg: &str) -> MsgResult {
+fn parse_message_received_from_client(msg: &Message) -> MsgResult {
   let mut result = MsgResult::default();
 
+  let msg = match msg {
+    Message::Text(text) => text.as_str(),
+    Message::Binary(data) => match std::str::from_utf8(data) {
+      Ok(text) => text,
+      Err(err) => {
+        error!("Binary message is not valid UTF-8: {err}");
+        result.no_op = true;
+        return result;
+      }
+    },
+    _ => {
+      result.no_op = true;
+      return result;
+    }
+  };
+
   if let Ok(close_msg) = ClientToRelayCommClose::from_json(msg.to_string()) {
     debug!("Close:\n {:?}\n\n", close_msg);
 
@@ -86,12 +287,20 @@ fn parse_message_received_from_client(msg: &str) -> MsgResult {
     return result;
   }
 
-  if let Ok(event_msg) = ClientToRelayCommEvent::from_json(msg.to_string()) {
-    debug!("Event:\n {:?}\n\n", event_msg);
+  match ClientToRelayCommEvent::from_json(msg.to_string()) {
+    Ok(event_msg) => {
+      debug!("Event:\n {:?}\n\n", event_msg);
 
-    result.is_event = true;
-    result.data.event = event_msg;
-    return result;
+      result.is_event = true;
+      result.data.event = event_msg;
+      return result;
+    }
+    Err(CommError::MalformedEvent(event_id)) => {
+      result.is_malformed_event = true;
+      result.data.malformed_event_id = event_id;
+      return result;
+    }
+    Err(_) => {}
   }
 
   if let Ok(request_msg) = ClientToRelayCommRequest::from_json(msg.to_string()) {
@@ -102,10 +311,417 @@ fn parse_message_received_from_client(msg: &str) -> MsgResult {
     return result;
   }
 
+  if let Ok(connections_msg) = ClientToRelayCommConnections::from_json(msg.to_string()) {
+    // the admin event is not logged, it authorizes the command for a while
+    debug!("Connections (admin command)");
+
+    result.is_connections = true;
+    result.data.connections = connections_msg;
+    return result;
+  }
+
   result.no_op = true;
   result
 }
 
+/// Gets the maximum age, in seconds, of the events accepted by the relay
+/// from the `RELAY_MAX_EVENT_AGE_SECS` env var. If it is not set,
+/// events of any age are accepted.
+///
+fn get_max_event_age_secs() -> Option<u64> {
+  env::var("RELAY_MAX_EVENT_AGE_SECS")
+    .ok()
+    .and_then(|max_age| max_age.parse::<u64>().ok())
+}
+
+/// Gets how many seconds the `created_at` of the events accepted by the relay
+/// can be ahead of its clock, from the `RELAY_MAX_FUTURE_DRIFT_SECS` env var.
+///
+fn get_max_future_drift_secs() -> u64 {
+  env::var("RELAY_MAX_FUTURE_DRIFT_SECS")
+    .ok()
+    .and_then(|max_drift| max_drift.parse::<u64>().ok())
+    .unwrap_or(DEFAULT_MAX_FUTURE_DRIFT_SECS)
+}
+
+/// Gets the maximum size, in bytes, of the (serialized) events accepted
+/// by the relay from the `MAX_EVENT_SIZE` env var.
+///
+fn get_max_event_size() -> usize {
+  env::var("MAX_EVENT_SIZE")
+    .ok()
+    .and_then(|max_size| max_size.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_MAX_EVENT_SIZE)
+}
+
+/// Gets the maximum number of events pending verification
+/// from the `MAX_PENDING_VERIFICATIONS` env var.
+///
+fn get_max_pending_verifications() -> usize {
+  env::var("MAX_PENDING_VERIFICATIONS")
+    .ok()
+    .and_then(|max| max.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_MAX_PENDING_VERIFICATIONS)
+}
+
+/// Gets how many messages can be waiting to be sent to each client
+/// from the `RELAY_CLIENT_CHANNEL_CAPACITY` env var.
+///
+fn get_client_channel_capacity() -> usize {
+  env::var("RELAY_CLIENT_CHANNEL_CAPACITY")
+    .ok()
+    .and_then(|capacity| capacity.parse::<usize>().ok())
+    .filter(|capacity| *capacity > 0)
+    .unwrap_or(DEFAULT_CLIENT_CHANNEL_CAPACITY)
+}
+
+/// Gets how many events per second each client can send to the relay
+/// from the `RELAY_EVENTS_PER_SEC` env var.
+///
+fn get_events_per_sec() -> f64 {
+  env::var("RELAY_EVENTS_PER_SEC")
+    .ok()
+    .and_then(|rate| rate.parse::<f64>().ok())
+    .filter(|rate| *rate >= 0.0)
+    .unwrap_or(DEFAULT_EVENTS_PER_SEC)
+}
+
+/// Gets how many events each client can send to the relay at once
+/// from the `RELAY_EVENTS_BURST` env var.
+///
+fn get_events_burst() -> u32 {
+  env::var("RELAY_EVENTS_BURST")
+    .ok()
+    .and_then(|burst| burst.parse::<u32>().ok())
+    .unwrap_or(DEFAULT_EVENTS_BURST)
+}
+
+/// Disconnects the `clients` whose channel is one of the `lagging` ones
+/// (see [`broadcast_message_to_clients`]), instead of letting the messages
+/// for them pile up in the relay.
+///
+fn disconnect_lagging_clients(clients: &[ClientConnectionInfo], lagging: &[Tx]) {
+  for client in clients
+    .iter()
+    .filter(|client| lagging.iter().any(|tx| tx.same_channel(&client.tx)))
+  {
+    info!(
+      "{} is not keeping up with its messages, disconnecting it.",
+      client.socket_addr
+    );
+    client.disconnect.notify_one();
+  }
+}
+
+/// Gets the maximum number of events stored by the relay from the
+/// `MAX_STORED_EVENTS` env var. If it is not set, there is no limit.
+///
+fn get_max_stored_events() -> Option<usize> {
+  env::var("MAX_STORED_EVENTS")
+    .ok()
+    .and_then(|max| max.parse::<usize>().ok())
+}
+
+/// Gets how many extra events the `events` vector reserves room for at once,
+/// from the `RELAY_EVENTS_CAPACITY_GRACE` env var.
+///
+fn get_events_capacity_grace() -> usize {
+  env::var("RELAY_EVENTS_CAPACITY_GRACE")
+    .ok()
+    .and_then(|grace| grace.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_EVENTS_CAPACITY_GRACE)
+}
+
+/// Moves the events loaded from the DB into a vector with room
+/// for `grace` more events, so the first ones received by the
+/// relay do not make it reallocate.
+///
+fn with_capacity_grace(loaded_events: Vec<Event>, grace: usize) -> Vec<Arc<Event>> {
+  let mut events = Vec::with_capacity(loaded_events.len() + grace);
+  events.extend(loaded_events.into_iter().map(Arc::new));
+  events
+}
+
+/// Checks if the periodic ping to the clients is disabled
+/// by setting the `RELAY_DISABLE_PING` env var to `true`
+/// (e.g.: when a proxy already manages the keepalives).
+///
+fn is_ping_disabled() -> bool {
+  env::var("RELAY_DISABLE_PING").is_ok_and(|disabled| disabled == "true")
+}
+
+/// Gets the interval between the pings sent to the clients
+/// from the `RELAY_PING_INTERVAL_SECS` env var.
+///
+fn get_ping_interval() -> Duration {
+  ping_interval_from(env::var("RELAY_PING_INTERVAL_SECS").ok())
+}
+
+/// Parses the interval, in seconds, between the pings sent to the clients.
+/// If it is not a positive number (a zero interval cannot be ticked),
+/// it is [`DEFAULT_PING_INTERVAL_SECS`].
+///
+fn ping_interval_from(secs: Option<String>) -> Duration {
+  let secs = secs
+    .and_then(|interval| interval.parse::<u64>().ok())
+    .filter(|interval| *interval > 0)
+    .unwrap_or(DEFAULT_PING_INTERVAL_SECS);
+  Duration::from_secs(secs)
+}
+
+/// Gets how long the relay waits for a pong before considering the connection
+/// with a client dead, from the `RELAY_PONG_TIMEOUT_SECS` env var.
+/// If it is not set, it is [`DEFAULT_MISSED_PONGS`] ping intervals.
+///
+fn get_pong_timeout(ping_interval: Duration) -> Duration {
+  env::var("RELAY_PONG_TIMEOUT_SECS")
+    .ok()
+    .and_then(|timeout| timeout.parse::<u64>().ok())
+    .map(Duration::from_secs)
+    .unwrap_or(ping_interval * DEFAULT_MISSED_PONGS)
+}
+
+/// Sends a ping message to the client every `ping_interval`.
+/// When `disabled`, nothing is sent and the future never completes,
+/// so the liveness of the connection is left to the proxy/client.
+///
+/// Completes with an error when no pong was received (see `last_pong`)
+/// for longer than `pong_timeout`, so the dead connection is closed.
+///
+async fn ping_client(
+  tx: Tx,
+  addr: SocketAddr,
+  ping_interval: Duration,
+  pong_timeout: Duration,
+  last_pong: Arc<Mutex<Instant>>,
+  disabled: bool,
+) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+  if disabled {
+    return future::pending().await;
+  }
+
+  let mut interval = time::interval(ping_interval);
+  loop {
+    interval.tick().await;
+
+    if last_pong.lock().unwrap().elapsed() > pong_timeout {
+      info!("No pong from {addr} in {pong_timeout:?}, closing the connection.");
+      break Err(tokio_tungstenite::tungstenite::Error::ConnectionClosed);
+    }
+
+    // Send a ping message. When the channel is full (e.g.: while the stored
+    // events of a REQ are sent), it is skipped: the pong timeout still applies.
+    let ping_message = Message::Ping(vec![]);
+    match tx.try_send(ping_message) {
+      Ok(()) => debug!("Sent ping to {addr}."),
+      Err(TrySendError::Full(_)) => debug!("Skipped the ping to {addr}, its channel is full."),
+      Err(err) => {
+        error!("Error sending ping message: {err}");
+        break Err(tokio_tungstenite::tungstenite::Error::Protocol(
+          tokio_tungstenite::tungstenite::error::ProtocolError::SendAfterClosing,
+        ));
+      }
+    }
+  }
+}
+
+/// Gets the current timestamp in seconds.
+///
+pub(crate) fn get_timestamp_in_seconds() -> Timestamp {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .expect("Time went backwards")
+    .as_secs()
+}
+
+/// Removes the expired events (NIP-40) from memory and from the database.
+///
+/// Returns the number of events removed from memory.
+///
+fn remove_expired_events(
+  events: &mut Vec<Arc<Event>>,
+  events_index: &mut EventsIndex,
+  events_writer: &Sender<WriteCmd>,
+  now: Timestamp,
+) -> usize {
+  let events_before = events.len();
+  events.retain(|event| !event.is_expired(now));
+  let removed = events_before - events.len();
+
+  if removed > 0 {
+    events_index.rebuild(events);
+  }
+
+  send_to_events_db(events_writer, WriteCmd::RemoveExpired(now));
+
+  removed
+}
+
+/// Evicts the oldest events (by `created_at`) from memory and from the database
+/// when there are more than `max_stored_events` of them. They are evicted in a batch,
+/// down to `1 / EVICTION_BATCH_DIVISOR` below `max_stored_events`.
+///
+/// When a replaceable event is evicted, the older versions of it that
+/// are still in the database are removed too, so they don't come back
+/// when the relay is restarted.
+///
+/// Returns the number of events evicted from memory.
+///
+fn evict_oldest_events(
+  events: &mut Vec<Arc<Event>>,
+  events_index: &mut EventsIndex,
+  events_writer: &Sender<WriteCmd>,
+  max_stored_events: usize,
+) -> usize {
+  if events.len() <= max_stored_events {
+    return 0;
+  }
+  let low_watermark = max_stored_events - max_stored_events / EVICTION_BATCH_DIVISOR;
+  let excess = events.len() - low_watermark;
+
+  // only the `excess` oldest ones need to be found, not all of them sorted
+  let mut positions_by_age: Vec<(Timestamp, usize)> = events
+    .iter()
+    .enumerate()
+    .map(|(position, event)| (event.created_at, position))
+    .collect();
+  positions_by_age.select_nth_unstable(excess - 1);
+  let evicted_positions: HashSet<usize> = positions_by_age[..excess]
+    .iter()
+    .map(|(_, position)| *position)
+    .collect();
+
+  let mut evicted_ids: HashSet<String> = HashSet::new();
+  let mut evicted_replaceable: HashMap<(PubKey, EventKind), Timestamp> = HashMap::new();
+  let mut position = 0;
+  events.retain(|event| {
+    let keep = !evicted_positions.contains(&position);
+    position += 1;
+    if !keep {
+      evicted_ids.insert(event.id.clone());
+      if event.kind.is_replaceable() {
+        evicted_replaceable.insert((event.pubkey.clone(), event.kind), event.created_at);
+      }
+    }
+    keep
+  });
+  events_index.rebuild(events);
+
+  send_to_events_db(
+    events_writer,
+    WriteCmd::Remove(Box::new(move |event| {
+      evicted_ids.contains(&event.id)
+        || evicted_replaceable
+          .get(&(event.pubkey.clone(), event.kind))
+          .is_some_and(|evicted_created_at| event.created_at <= *evicted_created_at)
+    })),
+  );
+
+  excess
+}
+
+/// Stores an event received from a client (already verified, see `verify_event`)
+/// and gets the messages to be sent to the clients whose filters match it.
+///
+/// The events already stored, already expired or that are a stale version of a replaceable
+/// event are neither stored nor transmitted.
+/// Deletion events remove the events they reference (NIP-09), and the ones
+/// whose deletion arrived before them are refused if `honor_prior_deletions`.
+///
+fn store_event_and_get_outbound(
+  event: Event,
+  clients: &mut MutexGuard<Vec<ClientConnectionInfo>>,
+  events: &mut Vec<Arc<Event>>,
+  events_index: &mut EventsIndex,
+  events_writer: &Sender<WriteCmd>,
+  honor_prior_deletions: bool,
+) -> Vec<OutboundInfo> {
+  // duplicates are neither stored nor transmitted again
+  if events_index.contains(&event.id) {
+    return vec![];
+  }
+
+  // NIP-40: events that are already expired are neither stored nor transmitted
+  if event.is_expired(get_timestamp_in_seconds()) {
+    return vec![];
+  }
+
+  // NIP-09: the deletion of this event may have arrived before it
+  if honor_prior_deletions && is_deleted_by_prior_request(&event, events, events_index) {
+    return vec![];
+  }
+
+  // NIP-16 and NIP-33: a stale version of a replaceable event is neither stored nor transmitted
+  let replaced_event_id = events
+    .iter()
+    .find(|stored| is_same_replaceable_event(stored, &event))
+    .map(|stored| stored.id.clone());
+  if !on_replaceable_event(&event, events, events_index) {
+    return vec![];
+  }
+  // the replaced version must not come back when the relay is restarted
+  if let Some(replaced_event_id) = replaced_event_id {
+    send_to_events_db(
+      events_writer,
+      WriteCmd::Remove(Box::new(move |stored| stored.id == replaced_event_id)),
+    );
+  }
+
+  // NIP-09: removes the events of the same author this one asks to delete
+  let deleted_event_ids = on_deletion_event(&event, events, events_index);
+  if !deleted_event_ids.is_empty() {
+    send_to_events_db(
+      events_writer,
+      WriteCmd::Remove(Box::new(move |stored| {
+        deleted_event_ids.contains(&stored.id)
+      })),
+    );
+  }
+
+  // reserve in bulk instead of letting it reallocate on every push
+  if events.len() == events.capacity() {
+    events.reserve(get_events_capacity_grace());
+  }
+  // shared (not copied) between the memory and the database writer
+  let event = Arc::new(event);
+  events.push(Arc::clone(&event));
+  events_index.insert(&event, events.len() - 1);
+  send_to_events_db(events_writer, WriteCmd::Insert(Arc::clone(&event)));
+
+  if let Some(max_stored_events) = get_max_stored_events() {
+    evict_oldest_events(events, events_index, events_writer, max_stored_events);
+  }
+
+  on_event_message(&event, clients)
+}
+
+/// Verifies the id and signature of an event received from a client.
+///
+/// It is done in the blocking pool, so neither the runtime nor the locks shared
+/// by the connections are held meanwhile. The `permit` (see `get_max_pending_verifications`)
+/// is held until the verification ends, even if the client disconnects before that.
+///
+async fn verify_event(
+  event: Event,
+  permit: OwnedSemaphorePermit,
+) -> (Event, Result<(), EventVerifyError>) {
+  tokio::task::spawn_blocking(move || {
+    let verified = event.verify();
+    drop(permit);
+    (event, verified)
+  })
+  .await
+  .expect("verifying an event does not panic")
+}
+
+/// What is left to do with a message of a client once it is handled (see `handle_connection`).
+enum Handled {
+  /// The replies to be sent to the client.
+  Replies(Vec<String>),
+  /// An event to be verified (see `verify_event`) before it is stored.
+  Verify(Event, OwnedSemaphorePermit),
+}
+
 /// This function is called when the connection relay-client is closed.
 fn connection_cleanup(
   client_connection_info: Arc<Mutex<Vec<ClientConnectionInfo>>>,
@@ -118,14 +734,46 @@ fn connection_cleanup(
     .retain(|client| client.socket_addr != addr);
 }
 
-async fn handle_connection(
-  raw_stream: TcpStream,
+/// Handles the connection with a client until it is closed.
+///
+/// Everything logged while doing it is tagged with the address of the client
+/// (and its pubkey, once it authenticates) by the `connection` span.
+///
+#[tracing::instrument(
+  name = "connection",
+  skip_all,
+  fields(addr = %addr, pubkey = tracing::field::Empty)
+)]
+// the states shared by all the connections
+#[allow(clippy::too_many_arguments)]
+async fn handle_connection<S>(
+  raw_stream: S,
   addr: SocketAddr,
   client_connection_info: Arc<Mutex<Vec<ClientConnectionInfo>>>,
-  events: Arc<Mutex<Vec<Event>>>,
-  events_db: Arc<Mutex<EventsDB>>,
-) {
-  let ws_stream = tokio_tungstenite::accept_async(raw_stream).await;
+  rate_limiter: Arc<Mutex<RateLimiter>>,
+  pubkey_policy: Arc<PubkeyPolicy>,
+  events: Arc<Mutex<Vec<Arc<Event>>>>,
+  events_index: Arc<Mutex<EventsIndex>>,
+  events_writer: Sender<WriteCmd>,
+  pending_verifications: Arc<Semaphore>,
+  metrics: Arc<RelayMetrics>,
+  settings: RelaySettings,
+) where
+  S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  // the `User-Agent` of the handshake is kept to identify the client (see `CONNECTIONS`)
+  let mut user_agent: Option<String> = None;
+  // the error type is defined by `tungstenite`
+  #[allow(clippy::result_large_err)]
+  let keep_user_agent = |request: &Request, response: Response| {
+    user_agent = request
+      .headers()
+      .get(USER_AGENT)
+      .and_then(|user_agent| user_agent.to_str().ok())
+      .map(String::from);
+    Ok(response)
+  };
+  let ws_stream = tokio_tungstenite::accept_hdr_async(raw_stream, keep_user_agent).await;
   if ws_stream.is_err() {
     error!("{:?}", ws_stream.err().unwrap());
     return;
@@ -133,44 +781,151 @@ async fn handle_connection(
   let ws_stream = ws_stream.ok().unwrap();
   info!("WebSocket connection established: {addr}");
 
-  // Start a periodic timer to send ping messages
-  let ping_interval = Duration::from_secs(20);
-  let mut interval = time::interval(ping_interval);
+  let (tx, mut rx) = tokio::sync::mpsc::channel(get_client_channel_capacity());
+  let disconnect = Arc::new(Notify::new());
 
-  let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+  client_connection_info
+    .lock()
+    .unwrap()
+    .push(ClientConnectionInfo {
+      tx: tx.clone(),
+      socket_addr: addr,
+      user_agent,
+      authenticated_pubkey: None,
+      requests: vec![],
+      disconnect: disconnect.clone(),
+    });
+  metrics.client_connected();
 
   let (mut outgoing, incoming) = ws_stream.split();
 
-  // Spawn the handler to run async
-  let tx_clone = tx.clone();
-  let ping = async {
-    loop {
-      interval.tick().await;
+  // Start a periodic timer to send ping messages
+  // and close the connection when the client stops answering them
+  let last_pong = Arc::new(Mutex::new(Instant::now()));
+  let ping_interval = get_ping_interval();
+  let ping = ping_client(
+    tx.clone(),
+    addr,
+    ping_interval,
+    get_pong_timeout(ping_interval),
+    last_pong.clone(),
+    is_ping_disabled(),
+  );
+
+  // Handles a message of this client: gets the replies to it (see `send_replies`)
+  // or the event to be verified before it is stored (see `store_verified_event`)
+  let handle_message = |msg: Message| -> Handled {
+    let mut replies = vec![];
+
+    if msg.is_pong() {
+      *last_pong.lock().unwrap() = Instant::now();
+    }
 
-      // Send a ping message
-      let ping_message = Message::Ping(vec![]);
-      if let Err(err) = tx_clone.send(ping_message) {
-        error!("Error sending ping message: {err}");
-        break Err(err).map_err(|_err| {
-          tokio_tungstenite::tungstenite::Error::Protocol(
-            tokio_tungstenite::tungstenite::error::ProtocolError::SendAfterClosing,
-          )
-        });
+    let msg_parsed = parse_message_received_from_client(&msg);
+
+    if msg_parsed.no_op {
+      return Handled::Replies(replies);
+    }
+
+    if msg_parsed.is_malformed_event {
+      let ok = RelayToClientCommOk::new_ok(
+        msg_parsed.data.malformed_event_id,
+        false,
+        String::from(MALFORMED_EVENT_MESSAGE),
+      );
+      replies.push(ok.as_json());
+      return Handled::Replies(replies);
+    }
+
+    // Each client can only send so many events (see `RateLimiter`)
+    if msg_parsed.is_event {
+      let acquired = rate_limiter
+        .lock()
+        .unwrap()
+        .try_acquire(addr, Instant::now());
+      if !acquired {
+        let ok = RelayToClientCommOk::new_ok(
+          msg_parsed.data.event.event.id,
+          false,
+          String::from(RATE_LIMITED_MESSAGE),
+        );
+        replies.push(ok.as_json());
+        return Handled::Replies(replies);
       }
-      debug!("Sent ping to {addr}.");
     }
-  };
 
-  let broadcast_incoming = incoming.try_for_each(|msg| {
-    let mut clients = client_connection_info.lock().unwrap();
-    let mut events = events.lock().unwrap();
+    // Obviously malformed events are refused before spending anything on verifying them
+    if msg_parsed.is_event {
+      let event = &msg_parsed.data.event.event;
+      if let Some(field) = event.malformed_hex_field() {
+        let ok = RelayToClientCommOk::new_ok(
+          event.id.clone(),
+          false,
+          format!("invalid: malformed {field}"),
+        );
+        replies.push(ok.as_json());
+        return Handled::Replies(replies);
+      }
+    }
 
-    let msg_parsed = parse_message_received_from_client(msg.to_text().unwrap());
+    if msg_parsed.is_event {
+      let event = msg_parsed.data.event.event;
 
-    if msg_parsed.no_op {
-      return future::ok(());
+      if is_event_too_large(&event, get_max_event_size()) {
+        let ok = RelayToClientCommOk::new_ok(event.id, false, String::from("invalid: too large"));
+        replies.push(ok.as_json());
+        return Handled::Replies(replies);
+      }
+
+      if is_event_too_old(&event, get_timestamp_in_seconds(), get_max_event_age_secs()) {
+        let ok = RelayToClientCommOk::new_ok(event.id, false, String::from("invalid: too old"));
+        replies.push(ok.as_json());
+        return Handled::Replies(replies);
+      }
+
+      if is_event_too_far_in_the_future(
+        &event,
+        get_timestamp_in_seconds(),
+        get_max_future_drift_secs(),
+      ) {
+        let ok = RelayToClientCommOk::new_ok(
+          event.id,
+          false,
+          String::from("invalid: created_at too far in the future"),
+        );
+        replies.push(ok.as_json());
+        return Handled::Replies(replies);
+      }
+
+      if !pubkey_policy.allows(&event.pubkey) {
+        let ok = RelayToClientCommOk::new_ok(event.id, false, String::from(BLOCKED_PUBKEY_MESSAGE));
+        replies.push(ok.as_json());
+        return Handled::Replies(replies);
+      }
+
+      if settings.enforce_kind_tags {
+        if let Err(err) = event.kind.validate_tags(&event.tags) {
+          let ok = RelayToClientCommOk::new_ok(event.id, false, format!("invalid: {err}"));
+          replies.push(ok.as_json());
+          return Handled::Replies(replies);
+        }
+      }
+
+      // Verifying the signature of an event is expensive, so the events being verified
+      // are bounded. Beyond that, they are shed instead of piling up (e.g. on a signature flood).
+      let Ok(permit) = pending_verifications.clone().try_acquire_owned() else {
+        let notice = RelayToClientCommNotice::new_notice(String::from(OVERLOADED_NOTICE));
+        replies.push(notice.as_json());
+        return Handled::Replies(replies);
+      };
+
+      return Handled::Verify(event, permit);
     }
 
+    let mut clients = client_connection_info.lock().unwrap();
+    let events = events.lock().unwrap();
+    let events_index = events_index.lock().unwrap();
+
     if msg_parsed.is_close {
       let closed = on_close_message(
         msg_parsed.clone().data.close.subscription_id,
@@ -181,67 +936,132 @@ async fn handle_connection(
       let message = if closed {
         "Subscription ended.".to_owned()
       } else {
-        "Subscription not found.".to_owned()
+        "error: subscription not found.".to_owned()
       };
       let notice_event = RelayToClientCommNotice {
         message,
         ..Default::default()
       }
       .as_json();
-      send_message_to_client(tx.clone(), notice_event);
+      replies.push(notice_event);
     }
 
     if msg_parsed.is_request {
-      let events_to_send_to_client = on_request_message(
+      // the client is notified by `on_request_message` when the request is refused
+      let Ok(events_to_send_to_client) = on_request_message(
         msg_parsed.clone().data.request.subscription_id,
         msg_parsed.clone().data.request.filters,
         &mut clients,
         addr,
         tx.clone(),
         &events,
-      );
+        &events_index,
+        settings.id_matching,
+      ) else {
+        return Handled::Replies(replies);
+      };
 
-      // Send one event at a time
-      for event_message in events_to_send_to_client {
-        send_message_to_client(tx.clone(), event_message.as_json());
+      // Send one event at a time (but not the ones of blocked authors)
+      for event_message in events_to_send_to_client
+        .into_iter()
+        .filter(|event_message| pubkey_policy.allows(&event_message.event.pubkey))
+      {
+        replies.push(event_message.as_json());
+        metrics.add_events_served(1);
       }
 
       // Send EOSE event to indicate end of stored events
+      let subscription_id = msg_parsed.clone().data.request.subscription_id;
       let eose = RelayToClientCommEose {
-        subscription_id: msg_parsed.clone().data.request.subscription_id,
+        subscription_id: subscription_id.clone(),
         ..Default::default()
       };
-      send_message_to_client(tx.clone(), eose.as_json());
-    }
+      replies.push(eose.as_json());
 
-    if msg_parsed.is_event {
-      let event = msg_parsed.data.event.event;
-
-      // verify event signature and event id. If it is not valid,
-      // doesn't transmit it
-      if !event.check_event_signature() || !event.check_event_id() {
-        return future::ok(());
+      // the stored events already reached the limit of the subscription
+      if !is_subscription_open(&clients, addr, &subscription_id) {
+        replies.push(limit_reached_closed(&subscription_id));
       }
+    }
 
-      let event_stringfied = event.as_json();
-
-      let mut mutable_events_db = events_db.lock().unwrap();
-
-      // update the events array if this event doesn't already exist
-      if !events.iter().any(|evt| evt.id == event.id) {
-        events.push(event.clone());
-        mutable_events_db
-          .write_to_db((events.len() as u64) - 1, &event_stringfied)
-          .unwrap();
-      }
+    if msg_parsed.is_connections {
+      let message = match on_connections_message(
+        &msg_parsed.data.connections.admin_event,
+        get_admin_pubkey(),
+        &clients,
+        get_timestamp_in_seconds(),
+      ) {
+        Ok(connections) => connections.as_json(),
+        Err(err) => RelayToClientCommNotice::new_notice(err.to_string()).as_json(),
+      };
+      replies.push(message);
+    }
 
-      let outbound_client_and_message = on_event_message(event, &mut clients);
+    Handled::Replies(replies)
+  };
+  // Stores an event of this client once verified and returns the replies to it
+  let store_verified_event = |event: Event, verified: Result<(), EventVerifyError>| {
+    if let Err(err) = verified {
+      let ok = RelayToClientCommOk::new_ok(event.id, false, format!("invalid: {err}"));
+      return vec![ok.as_json()];
+    }
 
-      // We want to broadcast the message to everyone that matches the filter.
-      broadcast_message_to_clients(outbound_client_and_message);
+    let mut clients = client_connection_info.lock().unwrap();
+    let mut events = events.lock().unwrap();
+    let mut events_index = events_index.lock().unwrap();
+
+    let outbound_client_and_message = store_event_and_get_outbound(
+      event,
+      &mut clients,
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      settings.honor_prior_deletions,
+    );
+    metrics.set_stored_events(events.len());
+    // We want to broadcast the message to everyone that matches the filter.
+    // The ones not keeping up with the messages are disconnected.
+    for outbound in outbound_client_and_message {
+      metrics.add_events_served(outbound.0.len());
+      let lagging = broadcast_message_to_clients(outbound);
+      disconnect_lagging_clients(&clients, &lagging);
     }
 
-    future::ok(())
+    vec![]
+  };
+  // The replies are sent waiting for room in the channel of the client (i.e.: as it
+  // reads them), so e.g. a REQ matching more stored events than fit in it does not
+  // disconnect the client. Only the live broadcasts are not waited for: the clients
+  // not keeping up with them are disconnected (see `disconnect_lagging_clients`).
+  let send_replies = |replies: Vec<String>| {
+    let tx = tx.clone();
+    async move {
+      for reply in replies {
+        debug!("{reply}");
+        tx.send(Message::Text(reply)).await.map_err(|err| {
+          error!("Error sending message to {addr}: {err}");
+          tokio_tungstenite::tungstenite::Error::Protocol(
+            tokio_tungstenite::tungstenite::error::ProtocolError::SendAfterClosing,
+          )
+        })?;
+      }
+      Ok(())
+    }
+  };
+  let broadcast_incoming = incoming.try_for_each(|msg| {
+    let handled = handle_message(msg);
+    let store_verified_event = &store_verified_event;
+    let send_replies = &send_replies;
+    async move {
+      let replies = match handled {
+        Handled::Replies(replies) => replies,
+        Handled::Verify(event, permit) => {
+          let (event, verified) = verify_event(event, permit).await;
+          store_verified_event(event, verified)
+        }
+      };
+      send_replies(replies).await
+    }
   });
 
   let rx_to_client = async {
@@ -262,44 +1082,121 @@ async fn handle_connection(
     result
   };
 
+  // Completes when the connection has to be closed by the relay (see `disconnect`)
+  let disconnected = async {
+    disconnect.notified().await;
+    Ok::<(), tokio_tungstenite::tungstenite::Error>(())
+  };
+
   // This has to be done in order to:
   // - pin the future in the heap (Box::pin)
   // - be able to compose the vec in `select_all` (all will have the same "Box" type)
   let boxed_broadcast_incoming = broadcast_incoming.boxed();
   let ping = ping.boxed();
   let rx_to_client = rx_to_client.boxed();
+  let disconnected = disconnected.boxed();
 
-  let (_, _, _) = future::select_all(vec![boxed_broadcast_incoming, ping, rx_to_client]).await;
+  let (_, _, _) = future::select_all(vec![
+    boxed_broadcast_incoming,
+    ping,
+    rx_to_client,
+    disconnected,
+  ])
+  .await;
 
   // If the code reaches this part it is because some of the futures above
-  // (namely `broadcast_incoming` or `ping` or `rx_to_client`) is done (connection is closed for some reason).
+  // (namely `broadcast_incoming` or `ping` or `rx_to_client` or `disconnected`) is done (connection is closed for some reason).
   // Therefore we need to do this cleanup.
   connection_cleanup(client_connection_info, addr);
+  rate_limiter.lock().unwrap().remove(&addr);
+  metrics.client_disconnected();
 }
 
 #[derive(Debug)]
 pub enum MainError {
   IoError(IoError),
   RedbError(redb::Error),
+  TlsConfigError(TlsConfigError),
 }
 
 #[tokio::main]
 pub async fn initiate_relay() -> Result<(), MainError> {
-  let addr = env::var("RELAY_HOST").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
-
-  // Read events from DB
-  let events_db = EventsDB::new(None).unwrap();
-  let events = events_db.get_all_items().unwrap();
+  init_tracing();
+
+  let config = get_relay_config().map_err(MainError::TlsConfigError)?;
+  let addr = config.bind;
+  let event_store = config.event_store;
+  let tls_acceptor = match config.tls {
+    Some(tls) => Some(Arc::new(tls.acceptor().map_err(MainError::TlsConfigError)?)),
+    None => None,
+  };
+  let expiration_sweep_interval = env::var("RELAY_EXPIRATION_SWEEP_INTERVAL_SECS")
+    .ok()
+    .and_then(|interval| interval.parse::<u64>().ok())
+    .unwrap_or(DEFAULT_EXPIRATION_SWEEP_INTERVAL_SECS);
+
+  // Read events from the store
+  let event_store = open_event_store(event_store).map_err(MainError::RedbError)?;
+  let mut events = with_capacity_grace(event_store.all(), get_events_capacity_grace());
+  // the writes to the store are done by its own thread, off the hot path
+  let events_writer = spawn_writer(event_store);
+  let mut events_index = EventsIndex::new(&events);
+  if let Some(max_stored_events) = get_max_stored_events() {
+    evict_oldest_events(
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      max_stored_events,
+    );
+  }
 
   // thread-safe and lockable
   let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+  let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(
+    get_events_per_sec(),
+    get_events_burst(),
+  )));
+  let pubkey_policy = Arc::new(get_pubkey_policy());
   let events = Arc::new(Mutex::new(events));
-  let events_db = Arc::new(Mutex::new(events_db));
+  let events_index = Arc::new(Mutex::new(events_index));
+  let pending_verifications = Arc::new(Semaphore::new(get_max_pending_verifications()));
+  let settings = RelaySettings::from_env();
+  let metrics = Arc::new(RelayMetrics::default());
+  metrics.set_stored_events(events.lock().unwrap().len());
+
+  // Periodically remove the expired events (NIP-40)
+  let sweeper_events = Arc::clone(&events);
+  let sweeper_events_index = Arc::clone(&events_index);
+  let sweeper_events_writer = events_writer.clone();
+  let sweeper_metrics = Arc::clone(&metrics);
+  tokio::spawn(async move {
+    let mut interval = time::interval(Duration::from_secs(expiration_sweep_interval));
+    loop {
+      interval.tick().await;
+
+      let mut events = sweeper_events.lock().unwrap();
+      let mut events_index = sweeper_events_index.lock().unwrap();
+      let removed = remove_expired_events(
+        &mut events,
+        &mut events_index,
+        &sweeper_events_writer,
+        get_timestamp_in_seconds(),
+      );
+      if removed > 0 {
+        debug!("Removed {removed} expired events.");
+      }
+      sweeper_metrics.set_stored_events(events.len());
+      debug!("Relay metrics: {:?}", sweeper_metrics.snapshot());
+    }
+  });
 
   // Create the event loop and TCP listener we'll accept connections on.
   let try_socket = TcpListener::bind(&addr).await;
   let listener = try_socket.expect("Failed to bind");
-  info!("Listening on: {addr}");
+  info!(
+    "Listening on: {addr} ({})",
+    if tls_acceptor.is_some() { "wss" } else { "ws" }
+  );
 
   // Handle CTRL+C signal
   let ctrl_c_listener = async {
@@ -313,8 +1210,10 @@ pub async fn initiate_relay() -> Result<(), MainError> {
           ..Default::default()
         }
         .as_json();
-        send_message_to_client(client.tx.clone(), notice_event);
-        client.tx.send(Message::Close(None)).unwrap();
+        // the connections whose client is already gone are skipped
+        if send_message_to_client(client.tx.clone(), notice_event).is_ok() {
+          let _ = client.tx.try_send(Message::Close(None));
+        }
       }
     }
     .await;
@@ -327,17 +1226,40 @@ pub async fn initiate_relay() -> Result<(), MainError> {
       // Clone the states we want to be able to mutate
       // throughout different threads
       let client_connection_info = Arc::clone(&client_connection_info);
+      let rate_limiter = Arc::clone(&rate_limiter);
+      let pubkey_policy = Arc::clone(&pubkey_policy);
       let events = Arc::clone(&events);
-      let events_db = Arc::clone(&events_db);
+      let events_index = Arc::clone(&events_index);
+      let events_writer = events_writer.clone();
+      let pending_verifications = Arc::clone(&pending_verifications);
+      let metrics = Arc::clone(&metrics);
+      let tls_acceptor = tls_acceptor.clone();
 
       // Spawn the handler to run async
-      tokio::spawn(handle_connection(
-        stream,
-        addr,
-        client_connection_info,
-        events,
-        events_db,
-      ));
+      tokio::spawn(async move {
+        // the TLS handshake is done here so a slow client does not hold the others
+        let stream = match accept_stream(stream, tls_acceptor.as_deref()).await {
+          Ok(stream) => stream,
+          Err(err) => {
+            error!("TLS handshake with {addr} failed: {err}");
+            return;
+          }
+        };
+        handle_connection(
+          stream,
+          addr,
+          client_connection_info,
+          rate_limiter,
+          pubkey_policy,
+          events,
+          events_index,
+          events_writer,
+          pending_verifications,
+          metrics,
+          settings,
+        )
+        .await;
+      });
     }
   };
 
@@ -354,27 +1276,100 @@ mod tests {
   use std::net::{IpAddr, Ipv4Addr};
 
   use super::*;
+  use crate::{
+    client::database::ClientDatabase,
+    event::{
+      id::EventId,
+      tag::{Tag, TagKind},
+    },
+    relay::database::{wait_for_writes, EventsDB},
+  };
 
   #[cfg(test)]
   use pretty_assertions::assert_eq;
   use serde_json::json;
+  use tokio::net::TcpStream;
+  use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+  fn make_rate_limiter_sut() -> Arc<Mutex<RateLimiter>> {
+    Arc::new(Mutex::new(RateLimiter::new(
+      DEFAULT_EVENTS_PER_SEC,
+      DEFAULT_EVENTS_BURST,
+    )))
+  }
 
   fn make_clientconnectioninfo_sut(socket_addr: SocketAddr) -> ClientConnectionInfo {
-    let (tx, _rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
+    let (tx, _rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
 
     ClientConnectionInfo {
       tx,
       socket_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![],
+      disconnect: Arc::default(),
     }
   }
 
+  /// Gets the next text message sent by the relay,
+  /// skipping the pings it may send before it.
+  async fn next_text_message(ws_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> String {
+    time::timeout(Duration::from_secs(1), async {
+      loop {
+        let msg = ws_stream.next().await.unwrap().unwrap();
+        if msg.is_text() {
+          break msg.into_text().unwrap();
+        }
+      }
+    })
+    .await
+    .unwrap()
+  }
+
+  #[test]
+  fn test_client_requests_new_sums_the_limits_of_the_filters() {
+    let request = |limits: Vec<Option<u64>>| {
+      let filters = limits
+        .into_iter()
+        .map(|limit| Filter {
+          limit,
+          ..Default::default()
+        })
+        .collect();
+      ClientRequests::new(String::from("sub"), filters, IdMatching::Prefix).remaining_limit
+    };
+
+    assert_eq!(request(vec![Some(2), Some(3)]), Some(5));
+    assert_eq!(request(vec![Some(2), None]), None);
+    // only the live events are wanted, so they are not limited
+    assert_eq!(request(vec![Some(0)]), None);
+    // the limits are sent by the client, so their sum may overflow
+    assert_eq!(request(vec![Some(u64::MAX), Some(1)]), None);
+  }
+
+  /// Events as they are stored in memory.
+  fn shared(events: Vec<Event>) -> Vec<Arc<Event>> {
+    events.into_iter().map(Arc::new).collect()
+  }
+
+  #[test]
+  fn initial_events_capacity_reflects_the_loaded_events() {
+    let loaded_events = vec![Event::default(); 10];
+
+    let events = with_capacity_grace(loaded_events.clone(), 5);
+    assert_eq!(events, shared(loaded_events));
+    assert!(events.capacity() >= 15);
+
+    let events = with_capacity_grace(vec![], 0);
+    assert_eq!(events.capacity(), 0);
+  }
+
   #[test]
   fn parse_close_message() {
     let close = ClientToRelayCommClose::default();
     let close_json = close.as_json();
 
-    let result = parse_message_received_from_client(&close_json);
+    let result = parse_message_received_from_client(&Message::Text(close_json));
 
     assert_eq!(result.data.close, close);
     assert!(result.is_close);
@@ -388,7 +1383,7 @@ mod tests {
     let request = ClientToRelayCommRequest::default();
     let request_json = request.as_json();
 
-    let result = parse_message_received_from_client(&request_json);
+    let result = parse_message_received_from_client(&Message::Text(request_json));
 
     assert_eq!(result.data.request, request);
     assert!(result.is_request);
@@ -397,6 +1392,20 @@ mod tests {
     assert_eq!(result.no_op, false);
   }
 
+  #[test]
+  fn parse_connections_message() {
+    let connections = ClientToRelayCommConnections::new_connections(Event::default());
+    let connections_json = connections.as_json();
+
+    let result = parse_message_received_from_client(&Message::Text(connections_json));
+
+    assert_eq!(result.data.connections, connections);
+    assert!(result.is_connections);
+    assert_eq!(result.is_close, false);
+    assert_eq!(result.is_request, false);
+    assert_eq!(result.no_op, false);
+  }
+
   #[test]
   fn parse_event_message() {
     let event_with_correct_signature = Event::from_value(
@@ -405,7 +1414,7 @@ mod tests {
     let event = ClientToRelayCommEvent::new_event(event_with_correct_signature);
     let event_json = event.as_json();
 
-    let result = parse_message_received_from_client(&event_json);
+    let result = parse_message_received_from_client(&Message::Text(event_json));
 
     assert_eq!(result.data.event, event);
     assert!(result.is_event);
@@ -414,11 +1423,23 @@ mod tests {
     assert_eq!(result.no_op, false);
   }
 
+  #[test]
+  fn parse_malformed_event_message() {
+    let malformed_event = r#"["EVENT",{"id":"potato","kind":"text"}]"#;
+
+    let result = parse_message_received_from_client(&Message::Text(malformed_event.to_string()));
+
+    assert!(result.is_malformed_event);
+    assert_eq!(result.data.malformed_event_id, String::from("potato"));
+    assert_eq!(result.is_event, false);
+    assert_eq!(result.no_op, false);
+  }
+
   #[test]
   fn parse_noop_message() {
     let no_op = r#"{}"#;
 
-    let result = parse_message_received_from_client(no_op);
+    let result = parse_message_received_from_client(&Message::Text(no_op.to_string()));
 
     assert!(result.no_op);
     assert_eq!(result.is_request, false);
@@ -426,6 +1447,1456 @@ mod tests {
     assert_eq!(result.is_event, false);
   }
 
+  #[test]
+  fn parse_binary_messages() {
+    let request = ClientToRelayCommRequest::default();
+    let request_binary = Message::binary(request.as_json());
+
+    let result = parse_message_received_from_client(&request_binary);
+
+    assert_eq!(result.data.request, request);
+    assert!(result.is_request);
+    assert_eq!(result.no_op, false);
+
+    let invalid_utf8 = Message::Binary(vec![0xff, 0xfe, 0xfd]);
+    assert!(parse_message_received_from_client(&invalid_utf8).no_op);
+  }
+
+  #[test]
+  fn parse_control_messages_as_noop() {
+    for msg in [
+      Message::Ping(vec![1]),
+      Message::Pong(vec![1]),
+      Message::Close(None),
+    ] {
+      let result = parse_message_received_from_client(&msg);
+
+      assert!(result.no_op);
+      assert_eq!(result.is_request, false);
+      assert_eq!(result.is_close, false);
+      assert_eq!(result.is_event, false);
+    }
+  }
+
+  #[test]
+  fn test_remove_expired_events() {
+    let table_name = "test_remove_expired_events";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+
+    let not_expiring_event = Event {
+      id: String::from("1"),
+      ..Default::default()
+    };
+    let expired_event = Event {
+      id: String::from("2"),
+      tags: vec![Tag::Generic(
+        TagKind::Custom(String::from("expiration")),
+        vec![String::from("100")],
+      )],
+      ..Default::default()
+    };
+    let expiring_later_event = Event {
+      id: String::from("3"),
+      tags: vec![Tag::Generic(
+        TagKind::Custom(String::from("expiration")),
+        vec![String::from("300")],
+      )],
+      ..Default::default()
+    };
+    let mut events = shared(vec![
+      not_expiring_event.clone(),
+      expired_event.clone(),
+      expiring_later_event.clone(),
+    ]);
+    for (key, event) in events.iter().enumerate() {
+      events_db
+        .write_to_db(&(key as u64), &event.as_json())
+        .unwrap();
+    }
+    let mut events_index = EventsIndex::new(&events);
+
+    let removed = remove_expired_events(&mut events, &mut events_index, &events_writer, 200);
+
+    let expected = shared(vec![not_expiring_event, expiring_later_event]);
+    assert_eq!(removed, 1);
+    assert_eq!(events, expected);
+    assert_eq!(events_index, EventsIndex::new(&expected));
+    wait_for_writes(&events_writer);
+    assert_eq!(shared(events_db.get_all_items().unwrap()), expected);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[test]
+  fn test_ping_interval_from() {
+    let default_interval = Duration::from_secs(DEFAULT_PING_INTERVAL_SECS);
+
+    assert_eq!(
+      ping_interval_from(Some(String::from("5"))),
+      Duration::from_secs(5)
+    );
+    assert_eq!(ping_interval_from(None), default_interval);
+    assert_eq!(
+      ping_interval_from(Some(String::from("potato"))),
+      default_interval
+    );
+    // `time::interval` panics with a zero period
+    assert_eq!(
+      ping_interval_from(Some(String::from("0"))),
+      default_interval
+    );
+  }
+
+  #[test]
+  fn test_evict_oldest_events() {
+    let table_name = "test_evict_oldest_events";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let max_stored_events = 3;
+
+    let mut events: Vec<Arc<Event>> = vec![];
+    let mut events_index = EventsIndex::default();
+    // inserted out of order: the oldest ones are not necessarily the first ones
+    for (id, created_at) in [("a", 50), ("b", 10), ("c", 40), ("d", 30), ("e", 20)] {
+      let event = Event {
+        id: String::from(id),
+        created_at,
+        ..Default::default()
+      };
+      events.push(Arc::new(event.clone()));
+      events_index.insert(&event, events.len() - 1);
+      let key = events_db.next_key().unwrap();
+      events_db.write_to_db(&key, &event.as_json()).unwrap();
+
+      evict_oldest_events(
+        &mut events,
+        &mut events_index,
+        &events_writer,
+        max_stored_events,
+      );
+      assert!(events.len() <= max_stored_events);
+    }
+
+    let ids: Vec<&str> = events.iter().map(|event| event.id.as_str()).collect();
+    assert_eq!(ids, vec!["a", "c", "d"]);
+    assert_eq!(events_index, EventsIndex::new(&events));
+    wait_for_writes(&events_writer);
+    assert_eq!(shared(events_db.get_all_items().unwrap()), events);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[test]
+  fn test_evict_oldest_events_evicts_in_batches() {
+    let (events_writer, _writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let max_stored_events = 20;
+    let make_event = |created_at: Timestamp| {
+      Arc::new(Event {
+        id: created_at.to_string(),
+        created_at,
+        ..Default::default()
+      })
+    };
+
+    let mut events: Vec<Arc<Event>> = (1..=max_stored_events as Timestamp)
+      .rev()
+      .map(make_event)
+      .collect();
+    let mut events_index = EventsIndex::new(&events);
+
+    // at the limit, nothing is evicted
+    let evicted = evict_oldest_events(
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      max_stored_events,
+    );
+    assert_eq!(evicted, 0);
+
+    // above it, the oldest ones are evicted down to the low watermark
+    events.push(make_event(100));
+    events_index.insert(&events[max_stored_events], max_stored_events);
+    let evicted = evict_oldest_events(
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      max_stored_events,
+    );
+
+    assert_eq!(evicted, 3);
+    assert_eq!(events.len(), 18);
+    assert!(events.iter().all(|event| event.created_at > 3));
+    assert_eq!(events_index, EventsIndex::new(&events));
+  }
+
+  #[test]
+  fn test_evict_oldest_events_removes_stale_versions_of_replaceable_events() {
+    let table_name = "test_evict_oldest_events_removes_stale_versions_of_replaceable_events";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+
+    let stale_metadata = Event {
+      id: String::from("stale_metadata"),
+      kind: EventKind::Metadata,
+      created_at: 5,
+      ..Default::default()
+    };
+    let metadata = Event {
+      id: String::from("metadata"),
+      created_at: 10,
+      ..stale_metadata.clone()
+    };
+    let text = Event {
+      id: String::from("text"),
+      created_at: 20,
+      ..Default::default()
+    };
+    // the stale version was only replaced in memory
+    let mut events = shared(vec![metadata.clone(), text.clone()]);
+    for (key, event) in [&stale_metadata, &metadata, &text].iter().enumerate() {
+      events_db
+        .write_to_db(&(key as u64), &event.as_json())
+        .unwrap();
+    }
+    let mut events_index = EventsIndex::new(&events);
+
+    let evicted = evict_oldest_events(&mut events, &mut events_index, &events_writer, 1);
+
+    assert_eq!(evicted, 1);
+    assert_eq!(events, vec![Arc::new(text.clone())]);
+    wait_for_writes(&events_writer);
+    assert_eq!(events_db.get_all_items().unwrap(), vec![text]);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_verify_event_refuses_tampered_signature_and_releases_the_permit() {
+    let pending_verifications = Arc::new(Semaphore::new(1));
+    let valid_event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    ).unwrap();
+    let mut tampered_event = valid_event.clone();
+    tampered_event.sig = tampered_event.sig.replace("bf07", "bf08");
+
+    let permit = pending_verifications.clone().try_acquire_owned().unwrap();
+    let (event, verified) = verify_event(tampered_event.clone(), permit).await;
+
+    assert_eq!(event, tampered_event);
+    assert!(matches!(
+      verified,
+      Err(EventVerifyError::SignatureVerificationFailed)
+    ));
+    assert_eq!(pending_verifications.available_permits(), 1);
+
+    let permit = pending_verifications.clone().try_acquire_owned().unwrap();
+    let (event, verified) = verify_event(valid_event.clone(), permit).await;
+
+    assert_eq!(event, valid_event);
+    assert!(verified.is_ok());
+    assert_eq!(pending_verifications.available_permits(), 1);
+  }
+
+  #[test]
+  fn test_store_event_and_get_outbound_shares_the_stored_event_with_the_writer() {
+    let (events_writer, writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let mut events: Vec<Arc<Event>> = vec![];
+    let mut events_index = EventsIndex::default();
+    let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+    let mut clients = client_connection_info.lock().unwrap();
+
+    let event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    ).unwrap();
+
+    store_event_and_get_outbound(
+      event,
+      &mut clients,
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      false,
+    );
+
+    // the memory and the database writer hold the same event, not copies of it
+    match writes.try_recv().unwrap() {
+      WriteCmd::Insert(written) => assert!(Arc::ptr_eq(&written, &events[0])),
+      _ => panic!("expected the event to be inserted"),
+    }
+  }
+
+  #[test]
+  fn test_store_event_and_get_outbound_rejects_duplicate_events() {
+    let table_name = "test_store_event_and_get_outbound_rejects_duplicate_events";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let mut events: Vec<Arc<Event>> = vec![];
+    let mut events_index = EventsIndex::default();
+    let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+    let mut clients = client_connection_info.lock().unwrap();
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+    let mut client = make_clientconnectioninfo_sut(addr);
+    client.requests.push(ClientRequests::new(
+      String::from("all"),
+      vec![Filter::default()],
+      IdMatching::Prefix,
+    ));
+    clients.push(client);
+
+    let event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    ).unwrap();
+
+    let mut store = |event: Event| {
+      store_event_and_get_outbound(
+        event,
+        &mut clients,
+        &mut events,
+        &mut events_index,
+        &events_writer,
+        false,
+      )
+    };
+    assert_eq!(store(event.clone()).len(), 1);
+    assert_eq!(store(event.clone()).len(), 0);
+
+    assert_eq!(events, vec![Arc::new(event.clone())]);
+    assert_eq!(
+      events_index.ids,
+      events.iter().map(|event| event.id.clone()).collect()
+    );
+    wait_for_writes(&events_writer);
+    assert_eq!(events_db.get_all_items().unwrap(), vec![event]);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[test]
+  fn test_store_event_and_get_outbound_honors_prior_deletions() {
+    let table_name = "test_store_event_and_get_outbound_honors_prior_deletions";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let mut events: Vec<Arc<Event>> = vec![];
+    let mut events_index = EventsIndex::default();
+    let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+    let mut clients = client_connection_info.lock().unwrap();
+
+    let keys = crate::schnorr::generate_keys();
+    // In order to use Schnorr signatures, we have to drop the first byte of pubkey
+    let pubkey = keys.public_key.to_string()[2..].to_string();
+    let signed_event = |kind: EventKind, tags: Vec<Tag>| {
+      let mut event = Event::new_without_signature(
+        pubkey.clone(),
+        get_timestamp_in_seconds(),
+        kind,
+        tags,
+        String::from("potato"),
+      );
+      event.sign_event(keys.private_key.secret_bytes().to_vec());
+      event
+    };
+    let target = signed_event(EventKind::Text, vec![]);
+    let deletion = signed_event(
+      EventKind::Deletion,
+      vec![Tag::Event(EventId(target.id.clone()), None, None)],
+    );
+
+    // the deletion arrives before the event it deletes
+    store_event_and_get_outbound(
+      deletion.clone(),
+      &mut clients,
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      true,
+    );
+    store_event_and_get_outbound(
+      target,
+      &mut clients,
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      true,
+    );
+
+    assert_eq!(events, vec![Arc::new(deletion.clone())]);
+    wait_for_writes(&events_writer);
+    assert_eq!(events_db.get_all_items().unwrap(), vec![deletion]);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[test]
+  fn test_store_event_and_get_outbound_keeps_the_newest_parameterized_replaceable_event() {
+    let table_name =
+      "test_store_event_and_get_outbound_keeps_the_newest_parameterized_replaceable_event";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let mut events: Vec<Arc<Event>> = vec![];
+    let mut events_index = EventsIndex::default();
+    let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+    let mut clients = client_connection_info.lock().unwrap();
+
+    let keys = crate::schnorr::generate_keys();
+    let article = |identifier: &str, created_at: Timestamp| {
+      let mut event = Event::new_without_signature(
+        keys.public_key.to_string()[2..].to_string(),
+        created_at,
+        EventKind::Custom(30023),
+        vec![Tag::identifier(identifier)],
+        format!("{identifier} at {created_at}"),
+      );
+      event.sign_event(keys.private_key.secret_bytes().to_vec());
+      event
+    };
+    let now = get_timestamp_in_seconds();
+    let first_draft = article("potato-recipes", now - 10);
+    let other_article = article("tomato-recipes", now - 5);
+    let newest_draft = article("potato-recipes", now);
+
+    for event in [
+      first_draft.clone(),
+      other_article.clone(),
+      newest_draft.clone(),
+    ] {
+      store_event_and_get_outbound(
+        event,
+        &mut clients,
+        &mut events,
+        &mut events_index,
+        &events_writer,
+        false,
+      );
+    }
+    // a stale version arriving late is not stored again
+    store_event_and_get_outbound(
+      first_draft,
+      &mut clients,
+      &mut events,
+      &mut events_index,
+      &events_writer,
+      false,
+    );
+
+    let expected = vec![other_article, newest_draft];
+    assert_eq!(events, shared(expected.clone()));
+    wait_for_writes(&events_writer);
+    assert_eq!(events_db.get_all_items().unwrap(), expected);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_rejects_too_large_events() {
+    let table_name = "test_handle_connection_rejects_too_large_events";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let events = Arc::new(Mutex::new(Vec::<Arc<Event>>::new()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let events = events.clone();
+      let events_writer = events_writer.clone();
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          Arc::new(Mutex::new(vec![])),
+          make_rate_limiter_sut(),
+          Arc::default(),
+          events,
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )
+        .await;
+      });
+    }
+
+    let keys = crate::schnorr::generate_keys();
+    let mut event = Event::new_without_signature(
+      keys.public_key.to_string()[2..].to_string(),
+      get_timestamp_in_seconds(),
+      EventKind::Text,
+      vec![],
+      "a".repeat(DEFAULT_MAX_EVENT_SIZE),
+    );
+    event.sign_event(keys.private_key.secret_bytes().to_vec());
+    let event_message = ClientToRelayCommEvent {
+      event: event.clone(),
+      ..Default::default()
+    };
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    ws_stream
+      .send(Message::from(event_message.as_json()))
+      .await
+      .unwrap();
+    let answer = next_text_message(&mut ws_stream).await;
+
+    assert_eq!(
+      RelayToClientCommOk::from_json(answer).unwrap(),
+      RelayToClientCommOk::new_ok(event.id, false, String::from("invalid: too large"))
+    );
+    assert!(events.lock().unwrap().is_empty());
+    wait_for_writes(&events_writer);
+    assert!(events_db.get_all_items().unwrap().is_empty());
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_rejects_events_with_malformed_hex_fields() {
+    let events = Arc::new(Mutex::new(Vec::<Arc<Event>>::new()));
+    let (events_writer, _writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let events = events.clone();
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          Arc::new(Mutex::new(vec![])),
+          make_rate_limiter_sut(),
+          Arc::default(),
+          events,
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )
+        .await;
+      });
+    }
+
+    let keys = crate::schnorr::generate_keys();
+    let mut event = Event::new_without_signature(
+      keys.public_key.to_string()[2..].to_string(),
+      get_timestamp_in_seconds(),
+      EventKind::Text,
+      vec![],
+      String::from("potato"),
+    );
+    event.sign_event(keys.private_key.secret_bytes().to_vec());
+    let event = Event {
+      sig: event.sig.replacen(|c: char| c.is_ascii_hexdigit(), "x", 1),
+      ..event
+    };
+    let event_message = ClientToRelayCommEvent {
+      event: event.clone(),
+      ..Default::default()
+    };
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    ws_stream
+      .send(Message::from(event_message.as_json()))
+      .await
+      .unwrap();
+    let answer = next_text_message(&mut ws_stream).await;
+
+    assert_eq!(
+      RelayToClientCommOk::from_json(answer).unwrap(),
+      RelayToClientCommOk::new_ok(event.id, false, String::from("invalid: malformed sig"))
+    );
+    assert!(events.lock().unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_enforces_the_tags_of_the_kind() {
+    let events = Arc::new(Mutex::new(Vec::<Arc<Event>>::new()));
+    let (events_writer, _writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let events = events.clone();
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          Arc::new(Mutex::new(vec![])),
+          make_rate_limiter_sut(),
+          Arc::default(),
+          events,
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings {
+            enforce_kind_tags: true,
+            ..Default::default()
+          },
+        )
+        .await;
+      });
+    }
+
+    let keys = crate::schnorr::generate_keys();
+    let pubkey = keys.public_key.to_string()[2..].to_string();
+    let reaction = |tags: Vec<Tag>| {
+      let mut event = Event::new_without_signature(
+        pubkey.clone(),
+        get_timestamp_in_seconds(),
+        EventKind::Reaction,
+        tags,
+        String::from("+"),
+      );
+      event.sign_event(keys.private_key.secret_bytes().to_vec());
+      event
+    };
+    let e_tag = Tag::Event(EventId(String::from("reacted")), None, None);
+    let without_p_tag = reaction(vec![e_tag.clone()]);
+    let well_formed = reaction(vec![e_tag, Tag::PubKey(vec![pubkey.clone()], None)]);
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    for event in [&without_p_tag, &well_formed] {
+      let event_message = ClientToRelayCommEvent {
+        event: event.clone(),
+        ..Default::default()
+      };
+      ws_stream
+        .send(Message::from(event_message.as_json()))
+        .await
+        .unwrap();
+    }
+    let answer = next_text_message(&mut ws_stream).await;
+
+    assert_eq!(
+      RelayToClientCommOk::from_json(answer).unwrap(),
+      RelayToClientCommOk::new_ok(
+        without_p_tag.id,
+        false,
+        String::from(r#"invalid: kind 7 events must have a "p" tag"#)
+      )
+    );
+    time::timeout(Duration::from_secs(1), async {
+      while events.lock().unwrap().is_empty() {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+    assert_eq!(*events.lock().unwrap(), vec![Arc::new(well_formed)]);
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_sheds_events_when_overloaded() {
+    let table_name = "test_handle_connection_sheds_events_when_overloaded";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let events = Arc::new(Mutex::new(Vec::<Arc<Event>>::new()));
+    // only one event can be verified at a time
+    let pending_verifications = Arc::new(Semaphore::new(1));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let client_connection_info = Arc::new(Mutex::new(vec![]));
+      let events = events.clone();
+      let events_index = Arc::new(Mutex::new(EventsIndex::default()));
+      let pending_verifications = pending_verifications.clone();
+      tokio::spawn(async move {
+        loop {
+          let (stream, addr) = listener.accept().await.unwrap();
+          tokio::spawn(handle_connection(
+            stream,
+            addr,
+            client_connection_info.clone(),
+            make_rate_limiter_sut(),
+            Arc::default(),
+            events.clone(),
+            events_index.clone(),
+            events_writer.clone(),
+            pending_verifications.clone(),
+            Arc::default(),
+            RelaySettings::default(),
+          ));
+        }
+      });
+    }
+
+    let clients_count = 8;
+    let events_per_client = 10;
+    let keys = crate::schnorr::generate_keys();
+    let mut ws_streams = vec![];
+    for _ in 0..clients_count {
+      let (ws_stream, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+      ws_streams.push(ws_stream);
+    }
+
+    // all the clients submit their events at once
+    for (client, ws_stream) in ws_streams.iter_mut().enumerate() {
+      for index in 0..events_per_client {
+        let mut event = Event::new_without_signature(
+          keys.public_key.x_only_public_key().0.to_string(),
+          get_timestamp_in_seconds(),
+          EventKind::Text,
+          vec![],
+          format!("potato {client} {index}"),
+        );
+        event.sign_event(keys.private_key.secret_bytes().to_vec());
+        let event_message = ClientToRelayCommEvent {
+          event,
+          ..Default::default()
+        };
+        ws_stream
+          .send(Message::from(event_message.as_json()))
+          .await
+          .unwrap();
+      }
+    }
+
+    // the events not verified right away are shed (the stored ones are not answered)
+    let mut shed = 0;
+    for ws_stream in ws_streams.iter_mut() {
+      while let Ok(Some(msg)) = time::timeout(Duration::from_millis(500), ws_stream.next()).await {
+        let msg = msg.unwrap();
+        // the relay may ping meanwhile
+        if msg.is_text() {
+          assert_eq!(
+            RelayToClientCommNotice::from_json(msg.to_text().unwrap()).unwrap(),
+            RelayToClientCommNotice::new_notice(String::from(OVERLOADED_NOTICE))
+          );
+          shed += 1;
+        }
+      }
+    }
+    time::timeout(Duration::from_secs(1), async {
+      while events.lock().unwrap().len() + shed < clients_count * events_per_client {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    assert!(shed > 0);
+    assert!(!events.lock().unwrap().is_empty());
+    assert_eq!(pending_verifications.available_permits(), 1);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_rate_limits_the_events() {
+    let events = Arc::new(Mutex::new(Vec::<Arc<Event>>::new()));
+    // the writes are kept (and dropped) with `_writes`
+    let (events_writer, _writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let events = events.clone();
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          Arc::new(Mutex::new(vec![])),
+          // 2 events at once, never refilled
+          Arc::new(Mutex::new(RateLimiter::new(0.0, 2))),
+          Arc::default(),
+          events,
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )
+        .await;
+      });
+    }
+
+    let keys = crate::schnorr::generate_keys();
+    let burst: Vec<Event> = (0..3)
+      .map(|i| {
+        let mut event = Event::new_without_signature(
+          keys.public_key.to_string()[2..].to_string(),
+          get_timestamp_in_seconds(),
+          EventKind::Text,
+          vec![],
+          format!("spam {i}"),
+        );
+        event.sign_event(keys.private_key.secret_bytes().to_vec());
+        event
+      })
+      .collect();
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    for event in burst.iter() {
+      let event_message = ClientToRelayCommEvent {
+        event: event.clone(),
+        ..Default::default()
+      };
+      ws_stream
+        .send(Message::from(event_message.as_json()))
+        .await
+        .unwrap();
+    }
+    // only the refused events are answered
+    let answer = next_text_message(&mut ws_stream).await;
+
+    assert_eq!(
+      RelayToClientCommOk::from_json(answer).unwrap(),
+      RelayToClientCommOk::new_ok(
+        burst[2].id.clone(),
+        false,
+        String::from(RATE_LIMITED_MESSAGE)
+      )
+    );
+    assert_eq!(*events.lock().unwrap(), shared(burst[..2].to_vec()));
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_applies_the_pubkey_policy() {
+    use crate::{
+      relay::{
+        communication_with_client::event::RelayToClientCommEvent, pubkey_policy::PubkeyPolicyMode,
+      },
+      schnorr::AsymmetricKeys,
+    };
+
+    let blocked_keys = crate::schnorr::generate_keys();
+    let allowed_keys = crate::schnorr::generate_keys();
+    let signed_note = |keys: &AsymmetricKeys, content: &str| {
+      let mut event = Event::new_without_signature(
+        keys.public_key.to_string()[2..].to_string(),
+        get_timestamp_in_seconds(),
+        EventKind::Text,
+        vec![],
+        String::from(content),
+      );
+      event.sign_event(keys.private_key.secret_bytes().to_vec());
+      event
+    };
+    // stored before the author was blocked
+    let stored_blocked_note = signed_note(&blocked_keys, "stored");
+    let stored_allowed_note = signed_note(&allowed_keys, "stored");
+    let stored_events = shared(vec![stored_blocked_note, stored_allowed_note.clone()]);
+
+    let events = Arc::new(Mutex::new(stored_events.clone()));
+    let (events_writer, _writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let events = events.clone();
+      let pubkey_policy = PubkeyPolicy::new(
+        PubkeyPolicyMode::Deny,
+        vec![blocked_keys.public_key.to_string()],
+      );
+      let events_index = EventsIndex::new(&stored_events);
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          Arc::new(Mutex::new(vec![])),
+          make_rate_limiter_sut(),
+          Arc::new(pubkey_policy),
+          events,
+          Arc::new(Mutex::new(events_index)),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )
+        .await;
+      });
+    }
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    // the stored events of the blocked author are not sent
+    let request = ClientToRelayCommRequest::new_req(String::from("all"), vec![Filter::new()]);
+    ws_stream
+      .send(Message::from(request.as_json()))
+      .await
+      .unwrap();
+    let stored =
+      RelayToClientCommEvent::from_json(next_text_message(&mut ws_stream).await).unwrap();
+    assert_eq!(stored.event, stored_allowed_note);
+    assert!(RelayToClientCommEose::from_json(next_text_message(&mut ws_stream).await).is_ok());
+
+    // and the new ones are refused
+    let blocked_note = signed_note(&blocked_keys, "new");
+    let allowed_note = signed_note(&allowed_keys, "new");
+    for note in [&blocked_note, &allowed_note] {
+      let event_message = ClientToRelayCommEvent {
+        event: note.clone(),
+        ..Default::default()
+      };
+      ws_stream
+        .send(Message::from(event_message.as_json()))
+        .await
+        .unwrap();
+    }
+    assert_eq!(
+      RelayToClientCommOk::from_json(next_text_message(&mut ws_stream).await).unwrap(),
+      RelayToClientCommOk::new_ok(
+        blocked_note.id.clone(),
+        false,
+        String::from(BLOCKED_PUBKEY_MESSAGE)
+      )
+    );
+    let live = RelayToClientCommEvent::from_json(next_text_message(&mut ws_stream).await).unwrap();
+    assert_eq!(live.event, allowed_note);
+    assert!(!events.lock().unwrap().contains(&Arc::new(blocked_note)));
+  }
+
+  /// Keeps what is logged in memory, to be inspected by the tests.
+  #[derive(Clone, Default)]
+  struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+  impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.0.lock().unwrap().extend_from_slice(buf);
+      Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_tags_the_logs_with_the_client_address() {
+    let logs = CapturedLogs::default();
+    let subscriber = tracing_subscriber::fmt()
+      .with_writer({
+        let logs = logs.clone();
+        move || logs.clone()
+      })
+      .with_ansi(false)
+      .finish();
+    // the tasks spawned by the test run on its thread, so they log to `logs` as well
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let relay = tokio::spawn(async move {
+      let client_connection_info = Arc::new(Mutex::new(vec![]));
+      let (events_writer, _) = std::sync::mpsc::channel::<WriteCmd>();
+      let mut connections = vec![];
+      for _ in 0..2 {
+        let (stream, addr) = listener.accept().await.unwrap();
+        connections.push(tokio::spawn(handle_connection(
+          stream,
+          addr,
+          Arc::clone(&client_connection_info),
+          make_rate_limiter_sut(),
+          Arc::default(),
+          Arc::new(Mutex::new(vec![])),
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer.clone(),
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )));
+      }
+      for connection in connections {
+        connection.await.unwrap();
+      }
+    });
+
+    // two clients connected at the same time
+    let mut clients = vec![];
+    for _ in 0..2 {
+      let (ws_stream, _) = tokio_tungstenite::connect_async(url.clone()).await.unwrap();
+      let addr = match ws_stream.get_ref() {
+        tokio_tungstenite::MaybeTlsStream::Plain(stream) => stream.local_addr().unwrap(),
+        _ => unreachable!(),
+      };
+      clients.push((ws_stream, addr));
+    }
+    let mut addrs = vec![];
+    for (mut ws_stream, addr) in clients {
+      ws_stream.close(None).await.unwrap();
+      addrs.push(addr);
+    }
+    time::timeout(Duration::from_secs(1), relay)
+      .await
+      .unwrap()
+      .unwrap();
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    for addr in addrs {
+      let span = format!("connection{{addr={addr}}}");
+      let connected = format!("WebSocket connection established: {addr}");
+      let disconnected = format!("Client with address {addr} disconnected");
+
+      for message in [connected, disconnected] {
+        let line = logs.lines().find(|line| line.contains(&message)).unwrap();
+        assert!(line.contains(&span), "{line}");
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_sends_events_with_the_subscribed_hashtag() {
+    use crate::relay::communication_with_client::event::RelayToClientCommEvent;
+
+    let table_name = "test_handle_connection_sends_events_with_the_subscribed_hashtag";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let events_writer = events_writer.clone();
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          Arc::new(Mutex::new(vec![])),
+          make_rate_limiter_sut(),
+          Arc::default(),
+          Arc::new(Mutex::new(vec![])),
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )
+        .await;
+      });
+    }
+
+    let keys = crate::schnorr::generate_keys();
+    let signed_note = |tags: Vec<Tag>| {
+      let mut event = Event::new_without_signature(
+        keys.public_key.to_string()[2..].to_string(),
+        get_timestamp_in_seconds(),
+        EventKind::Text,
+        tags,
+        String::from("potato"),
+      );
+      event.sign_event(keys.private_key.secret_bytes().to_vec());
+      event
+    };
+    let other_note = signed_note(vec![Tag::hashtag("tomato")]);
+    let hashtag_note = signed_note(vec![Tag::hashtag("potato")]);
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let hashtag_subscription = ClientToRelayCommRequest::new_req(
+      String::from("potatoes"),
+      vec![Filter::new().t_tags(vec![String::from("potato")])],
+    );
+    ws_stream
+      .send(Message::from(hashtag_subscription.as_json()))
+      .await
+      .unwrap();
+    for note in [&other_note, &hashtag_note] {
+      let event_message = ClientToRelayCommEvent {
+        event: note.clone(),
+        ..Default::default()
+      };
+      ws_stream
+        .send(Message::from(event_message.as_json()))
+        .await
+        .unwrap();
+    }
+
+    // the relay sends the EOSE of the subscription first
+    let received = loop {
+      let msg = next_text_message(&mut ws_stream).await;
+      if let Ok(event) = RelayToClientCommEvent::from_json(msg) {
+        break event;
+      }
+    };
+
+    assert_eq!(received.subscription_id, String::from("potatoes"));
+    assert_eq!(received.event, hashtag_note);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_ping_client() {
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+    let ping_interval = Duration::from_millis(10);
+    let pong_timeout = Duration::from_secs(1);
+    let last_pong = Arc::new(Mutex::new(Instant::now()));
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
+    let _ = time::timeout(
+      Duration::from_millis(50),
+      ping_client(
+        tx,
+        addr,
+        ping_interval,
+        pong_timeout,
+        last_pong.clone(),
+        false,
+      ),
+    )
+    .await;
+    assert_eq!(rx.try_recv().unwrap(), Message::Ping(vec![]));
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
+    let _ = time::timeout(
+      Duration::from_millis(50),
+      ping_client(tx, addr, ping_interval, pong_timeout, last_pong, true),
+    )
+    .await;
+    assert!(rx.try_recv().is_err());
+  }
+
+  #[tokio::test]
+  async fn test_disconnect_lagging_clients() {
+    let (lagging_tx, mut lagging_rx) = tokio::sync::mpsc::channel::<Message>(1);
+    let lagging_client = ClientConnectionInfo {
+      tx: lagging_tx,
+      ..make_clientconnectioninfo_sut(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        8080,
+      ))
+    };
+    let client = make_clientconnectioninfo_sut(SocketAddr::new(
+      IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+      8080,
+    ));
+    // fills the channel of the lagging client
+    send_message_to_client(lagging_client.tx.clone(), String::from("first")).unwrap();
+
+    let lagging = broadcast_message_to_clients((
+      vec![lagging_client.tx.clone(), client.tx.clone()],
+      String::from("second"),
+    ));
+    disconnect_lagging_clients(&[lagging_client.clone(), client.clone()], &lagging);
+
+    // the message is not buffered for the lagging client, which is disconnected instead
+    assert_eq!(lagging_rx.recv().await.unwrap().to_string(), "first");
+    assert!(lagging_rx.try_recv().is_err());
+    let disconnected = time::timeout(
+      Duration::from_millis(10),
+      lagging_client.disconnect.notified(),
+    )
+    .await;
+    assert!(disconnected.is_ok());
+    let disconnected = time::timeout(Duration::from_millis(10), client.disconnect.notified()).await;
+    assert!(disconnected.is_err());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_handle_connection_closes_connections_without_pong() {
+    let table_name = "test_handle_connection_closes_connections_without_pong";
+    let events_db = Arc::new(EventsDB::new(Some(table_name.to_string())).unwrap());
+    let events_writer = events_db.spawn_writer();
+    let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let client_connection_info = client_connection_info.clone();
+      tokio::spawn(async move {
+        let (stream, addr) = listener.accept().await.unwrap();
+        handle_connection(
+          stream,
+          addr,
+          client_connection_info,
+          make_rate_limiter_sut(),
+          Arc::default(),
+          Arc::new(Mutex::new(vec![])),
+          Arc::new(Mutex::new(EventsIndex::default())),
+          events_writer,
+          Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+          Arc::default(),
+          RelaySettings::default(),
+        )
+        .await;
+      });
+    }
+
+    // the client never reads the pings, so it never answers them
+    let (_ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let wait_until = |connected: bool| {
+      let client_connection_info = client_connection_info.clone();
+      time::timeout(
+        Duration::from_secs(DEFAULT_PING_INTERVAL_SECS * 10),
+        async move {
+          while client_connection_info.lock().unwrap().is_empty() == connected {
+            time::sleep(Duration::from_secs(1)).await;
+          }
+        },
+      )
+    };
+    wait_until(true).await.unwrap();
+    let connected_at = Instant::now();
+    wait_until(false).await.unwrap();
+
+    let missed_pongs_timeout =
+      Duration::from_secs(DEFAULT_PING_INTERVAL_SECS) * DEFAULT_MISSED_PONGS;
+    assert!(connected_at.elapsed() >= missed_pongs_timeout);
+
+    std::fs::remove_file(format!("db/{table_name}.redb")).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_over_tls() {
+    use openssl::{
+      asn1::Asn1Time,
+      hash::MessageDigest,
+      pkey::PKey,
+      rsa::Rsa,
+      x509::{X509NameBuilder, X509},
+    };
+    use tokio_native_tls::native_tls;
+    use tokio_tungstenite::Connector;
+
+    use crate::relay::config::TlsConfig;
+
+    // self-signed certificate for `localhost`
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+    let mut cert = X509::builder().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert
+      .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+      .unwrap();
+    cert
+      .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+      .unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = cert.build();
+
+    let dir = env::temp_dir().join(format!("relay-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let tls = TlsConfig {
+      cert_path: dir.join("cert.pem"),
+      key_path: dir.join("key.pem"),
+    };
+    std::fs::write(&tls.cert_path, cert.to_pem().unwrap()).unwrap();
+    std::fs::write(&tls.key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    let tls_acceptor = tls.acceptor().unwrap();
+
+    let (events_writer, _events_writes) = std::sync::mpsc::channel::<WriteCmd>();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("wss://localhost:{}", listener.local_addr().unwrap().port());
+    tokio::spawn(async move {
+      let (stream, addr) = listener.accept().await.unwrap();
+      let stream = accept_stream(stream, Some(&tls_acceptor)).await.unwrap();
+      handle_connection(
+        stream,
+        addr,
+        Arc::new(Mutex::new(vec![])),
+        make_rate_limiter_sut(),
+        Arc::default(),
+        Arc::default(),
+        Arc::new(Mutex::new(EventsIndex::default())),
+        events_writer,
+        Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+        Arc::default(),
+        RelaySettings::default(),
+      )
+      .await;
+    });
+
+    // the certificate is not signed by a trusted CA
+    let connector = native_tls::TlsConnector::builder()
+      .danger_accept_invalid_certs(true)
+      .build()
+      .unwrap();
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async_tls_with_config(
+      url,
+      None,
+      false,
+      Some(Connector::NativeTls(connector)),
+    )
+    .await
+    .unwrap();
+
+    let request = ClientToRelayCommRequest::new_req(String::from("sub"), vec![Filter::new()]);
+    ws_stream
+      .send(Message::from(request.as_json()))
+      .await
+      .unwrap();
+    let answer = next_text_message(&mut ws_stream).await;
+    assert!(RelayToClientCommEose::from_json(answer).is_ok());
+
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_counts_the_connected_clients() {
+    let metrics = Arc::new(RelayMetrics::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    {
+      let metrics = Arc::clone(&metrics);
+      tokio::spawn(async move {
+        let client_connection_info = Arc::new(Mutex::new(vec![]));
+        let (events_writer, _) = std::sync::mpsc::channel::<WriteCmd>();
+        while let Ok((stream, addr)) = listener.accept().await {
+          tokio::spawn(handle_connection(
+            stream,
+            addr,
+            Arc::clone(&client_connection_info),
+            make_rate_limiter_sut(),
+            Arc::default(),
+            Arc::default(),
+            Arc::new(Mutex::new(EventsIndex::default())),
+            events_writer.clone(),
+            Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+            Arc::clone(&metrics),
+            RelaySettings::default(),
+          ));
+        }
+      });
+    }
+    let wait_for_connected_clients = |expected: usize| {
+      let metrics = Arc::clone(&metrics);
+      time::timeout(Duration::from_secs(1), async move {
+        while metrics.snapshot().connected_clients != expected {
+          time::sleep(Duration::from_millis(10)).await;
+        }
+      })
+    };
+
+    let (mut first_client, _) = tokio_tungstenite::connect_async(url.clone()).await.unwrap();
+    let (_second_client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    wait_for_connected_clients(2).await.unwrap();
+    assert_eq!(metrics.snapshot().connected_clients, 2);
+
+    first_client.close(None).await.unwrap();
+    wait_for_connected_clients(1).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_sends_more_stored_events_than_fit_in_the_channel() {
+    use crate::relay::communication_with_client::event::RelayToClientCommEvent;
+
+    let stored_events: Vec<Arc<Event>> = (0..DEFAULT_CLIENT_CHANNEL_CAPACITY + 100)
+      .map(|i| {
+        Arc::new(Event {
+          id: format!("{i:064x}"),
+          created_at: i as u64,
+          ..Default::default()
+        })
+      })
+      .collect();
+    let events_index = EventsIndex::new(&stored_events);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (events_writer, _) = std::sync::mpsc::channel::<WriteCmd>();
+      let (stream, addr) = listener.accept().await.unwrap();
+      handle_connection(
+        stream,
+        addr,
+        Arc::new(Mutex::new(vec![])),
+        make_rate_limiter_sut(),
+        Arc::default(),
+        Arc::new(Mutex::new(stored_events)),
+        Arc::new(Mutex::new(events_index)),
+        events_writer,
+        Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+        Arc::default(),
+        RelaySettings::default(),
+      )
+      .await;
+    });
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let request = ClientToRelayCommRequest::new_req(String::from("all"), vec![Filter::new()]);
+    ws_stream
+      .send(Message::from(request.as_json()))
+      .await
+      .unwrap();
+
+    // the client is not disconnected for not reading them as fast as they are sent
+    let received = time::timeout(Duration::from_secs(5), async {
+      let mut received = 0;
+      loop {
+        let msg = ws_stream.next().await.unwrap().unwrap();
+        let msg = msg.to_text().unwrap_or_default();
+        if RelayToClientCommEvent::from_json(msg).is_ok() {
+          received += 1;
+        } else if RelayToClientCommEose::from_json(msg).is_ok() {
+          break received;
+        }
+      }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(received, DEFAULT_CLIENT_CHANNEL_CAPACITY + 100);
+  }
+
+  #[tokio::test]
+  async fn test_handle_connection_matches_the_ids_exactly_when_set_to() {
+    use crate::relay::communication_with_client::event::RelayToClientCommEvent;
+
+    async fn events_received_for(
+      ws_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+      ids: Vec<String>,
+    ) -> Vec<Event> {
+      let request =
+        ClientToRelayCommRequest::new_req(String::from("ids"), vec![Filter::new().ids(ids)]);
+      ws_stream
+        .send(Message::from(request.as_json()))
+        .await
+        .unwrap();
+      let mut received = vec![];
+      loop {
+        let msg = next_text_message(ws_stream).await;
+        if let Ok(event) = RelayToClientCommEvent::from_json(&msg) {
+          received.push(event.event);
+        } else if RelayToClientCommEose::from_json(&msg).is_ok() {
+          break received;
+        }
+      }
+    }
+
+    let stored_event = Arc::new(Event {
+      id: format!("{:064x}", 1),
+      created_at: 1,
+      ..Default::default()
+    });
+    let stored_events = vec![stored_event.clone()];
+    let events_index = EventsIndex::new(&stored_events);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      let (events_writer, _) = std::sync::mpsc::channel::<WriteCmd>();
+      let (stream, addr) = listener.accept().await.unwrap();
+      handle_connection(
+        stream,
+        addr,
+        Arc::new(Mutex::new(vec![])),
+        make_rate_limiter_sut(),
+        Arc::default(),
+        Arc::new(Mutex::new(stored_events)),
+        Arc::new(Mutex::new(events_index)),
+        events_writer,
+        Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS)),
+        Arc::default(),
+        RelaySettings {
+          id_matching: IdMatching::Exact,
+          ..Default::default()
+        },
+      )
+      .await;
+    });
+
+    let (mut ws_stream, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    // a prefix of the id does not match it
+    let received =
+      events_received_for(&mut ws_stream, vec![stored_event.id[..8].to_string()]).await;
+    assert!(received.is_empty());
+
+    let received = events_received_for(&mut ws_stream, vec![stored_event.id.clone()]).await;
+    assert_eq!(received, vec![Event::clone(&stored_event)]);
+  }
+
   #[test]
   fn test_connection_cleanup() {
     let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
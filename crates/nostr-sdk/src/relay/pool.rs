@@ -1,9 +1,21 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::{collections::HashMap, sync::Arc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::Arc,
+  time::Duration,
+};
 
+use crate::client::communication_with_relay::{
+  close::ClientToRelayCommClose, request::ClientToRelayCommRequest,
+};
+use crate::event::Event;
+use crate::filter::Filter;
 use crate::relay::communication_with_client::{
-  eose::RelayToClientCommEose, event::RelayToClientCommEvent, notice::RelayToClientCommNotice,
+  closed::RelayToClientCommClosed, eose::RelayToClientCommEose, event::RelayToClientCommEvent,
+  notice::RelayToClientCommNotice,
 };
+use crate::relay::relay_url::RelayUrl;
+use futures_util::Sink;
 use futures_util::SinkExt;
 use futures_util::StreamExt;
 use log::debug;
@@ -11,19 +23,70 @@ use log::error;
 use log::info;
 use tokio::sync::MutexGuard;
 use tokio::sync::{
+  broadcast,
   mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-  Mutex,
+  oneshot, Mutex,
 };
+use tokio::task::JoinHandle;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum RelayPoolMessage {
   /// Relay received some that was forwarded from another client
   ReceivedMsg { relay_url: String, msg: Message },
+  /// The pool is shutting down, so the pool task must stop
+  Shutdown,
 }
 
 type PoolTaskSender = tokio::sync::mpsc::UnboundedSender<RelayPoolMessage>;
 
+/// How many notifications the notification channel holds
+/// before the slowest receivers start missing them.
+const NOTIFICATIONS_CHANNEL_CAPACITY: usize = 1024;
+
+/// How many messages are kept while the notifications are paused.
+/// When it is full, the oldest ones are dropped.
+const PAUSED_NOTIFICATIONS_BUFFER_CAPACITY: usize = 1024;
+
+/// How long the shutdown waits for the tasks of a relay
+/// (or of the pool) to end before aborting them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the pool notifies about the messages received from the relays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayPoolNotification {
+  Event {
+    relay_url: String,
+    subscription_id: String,
+    event: Event,
+  },
+  Notice {
+    relay_url: String,
+    message: String,
+  },
+  /// The relay sent all of its stored events of the subscription,
+  /// so the next ones are live events.
+  Eose {
+    relay_url: String,
+    subscription_id: String,
+  },
+  /// The relay ended (or refused) the subscription.
+  Closed {
+    relay_url: String,
+    subscription_id: String,
+    message: String,
+  },
+}
+
+/// State of the connection with a relay (see [`RelayPool::status`]).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RelayStatus {
+  pub connected: bool,
+  /// Messages queued to be sent to the relay, e.g.: published while it was not connected.
+  pub pending_messages: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct RelayData {
   /// Url to connect to this relay.
@@ -34,14 +97,21 @@ pub struct RelayData {
   relay_tx: UnboundedSender<Message>,
   /// Rx part of the channel to receive messages (by this client) from this relay.
   relay_rx: Arc<Mutex<UnboundedReceiver<Message>>>,
+  /// How many messages are in the channel above (its receiver is
+  /// held by the task sending them, so it cannot be asked).
+  pending_messages: Arc<AtomicUsize>,
   /// Flag to signal if the connection must be closed
   close_communication: Arc<AtomicBool>,
   /// Flag to signal if the relay is already connected
   is_connected: Arc<AtomicBool>,
+  /// Tasks reading from and writing to the connection with this relay.
+  tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+  /// Latest metadata of the client, sent on every (re)connect.
+  metadata: Arc<Mutex<Message>>,
 }
 
 impl RelayData {
-  fn new(url: String, pool_task_sender: PoolTaskSender) -> Self {
+  fn new(url: String, pool_task_sender: PoolTaskSender, metadata: Message) -> Self {
     let (relay_tx, relay_rx) = unbounded_channel();
     let close_communication = Arc::new(AtomicBool::new(false));
     let is_connected = Arc::new(AtomicBool::new(false));
@@ -51,14 +121,23 @@ impl RelayData {
       pool_task_sender,
       relay_tx,
       relay_rx: Arc::new(Mutex::new(relay_rx)),
+      pending_messages: Arc::new(AtomicUsize::new(0)),
       close_communication,
       is_connected,
+      tasks: Arc::new(Mutex::new(vec![])),
+      metadata: Arc::new(Mutex::new(metadata)),
     }
   }
 
-  async fn connect(&self, metadata: Message) {
+  /// Connects to the relay. Once connected, it sends, in this order:
+  /// the latest `metadata`, the `requests` (REQs of the subscriptions) and then
+  /// every message queued while it was not connected (see [`replay_on_connect`]).
+  ///
+  async fn connect(&self, requests: Vec<Message>) {
     debug!("❯ Connecting to {}", self.url.clone());
+    let metadata = self.metadata.lock().await.clone();
 
+    self.end_tasks().await;
     let connection = connect_async(self.url.clone()).await;
 
     // Connect
@@ -67,47 +146,81 @@ impl RelayData {
         info!("❯ Connected to {}", self.url.clone());
         self.is_connected.store(true, Ordering::Relaxed);
         let (mut ws_tx, mut ws_rx) = ws_stream.split();
-
-        // Send metadata on connection
-        ws_tx.send(metadata).await.unwrap();
-        debug!("Metadata sent to relay");
+        let mut tasks = self.tasks.lock().await;
+        // Dropped when the connection is closed by the relay, so the task writing to it
+        // ends and the messages queued from then on are sent on the next connection.
+        let (connection_closed_tx, mut connection_closed_rx) = oneshot::channel::<()>();
 
         // Whatever we receive from the relay (that was sent by other clients),
         // we'll send to the pool.
         // Check `RelayPoolTask.run` method to see where all messages
         // forwarded to the pool end up.
         let relay = self.clone();
-        tokio::spawn(async move {
+        tasks.push(tokio::spawn(async move {
           debug!("❯ Relay Message Thread Started");
+          let _connection_closed_tx = connection_closed_tx;
 
           while let Some(msg_res) = ws_rx.next().await {
-            if let Ok(msg) = msg_res {
-              relay
-                .pool_task_sender
-                .send(RelayPoolMessage::ReceivedMsg {
-                  relay_url: relay.url.clone(),
-                  msg,
-                })
-                .unwrap();
+            match msg_res {
+              Ok(msg) => {
+                if !relay.handle_message_from_relay(msg) {
+                  break;
+                }
+              }
+              Err(err) => {
+                error!("❯ Error reading from {}: {}", relay.url, err);
+                break;
+              }
             }
           }
+          // however it ended (closed by the relay, dropped or errored),
+          // the relay is no longer connected
+          relay.is_connected.store(false, Ordering::Relaxed);
 
           debug!("❯ Exited from Message Thread of {}", relay.url);
-        });
+        }));
 
         // Send messages sent to this relay, which were sent by our client.
         let relay = self.clone();
-        tokio::spawn(async move {
+        tasks.push(tokio::spawn(async move {
           let mut rx = relay.relay_rx.lock().await;
-          while let Some(msg) = rx.recv().await {
-            if relay.close_communication.load(Ordering::Relaxed) {
-              break;
+          let is_shutting_down = match replay_on_connect(
+            &mut ws_tx,
+            metadata,
+            requests,
+            &mut rx,
+            &relay.pending_messages,
+          )
+          .await
+          {
+            Ok(is_shutting_down) => is_shutting_down,
+            Err(err) => {
+              error!(
+                "Error sending the initial messages to {}: {}",
+                relay.url, err
+              );
+              false
+            }
+          };
+          debug!("Metadata and subscriptions sent to relay");
+
+          if !is_shutting_down {
+            while let Some(msg) = tokio::select! {
+              biased;
+              _ = &mut connection_closed_rx => None,
+              msg = rx.recv() => msg,
+            } {
+              relay.pending_messages.fetch_sub(1, Ordering::Relaxed);
+              if relay.close_communication.load(Ordering::Relaxed) || is_shutdown_request(&msg) {
+                break;
+              }
+              let _ = ws_tx.send(msg).await;
             }
-            let _ = ws_tx.send(msg).await;
           }
           // Closes WS connection when `relay.close_communication` is true
+          // or the relay is shut down
           let _ = ws_tx.close().await;
-        });
+        }));
       }
       Err(err) => {
         error!("Impossible to connect to {}: {}", self.url, err);
@@ -115,15 +228,230 @@ impl RelayData {
     };
   }
 
+  /// Handles a message received from the relay:
+  ///   - `Text`: forwarded to the pool;
+  ///   - `Ping`: answered with a `Pong`;
+  ///   - `Close`: the relay is marked as not connected, so it
+  ///     can be connected again (see [`RelayPool::connect`]);
+  ///   - anything else is ignored.
+  ///
+  /// Returns whether it should keep reading from the relay.
+  ///
+  fn handle_message_from_relay(&self, msg: Message) -> bool {
+    match msg {
+      Message::Text(_) => {
+        self
+          .pool_task_sender
+          .send(RelayPoolMessage::ReceivedMsg {
+            relay_url: self.url.clone(),
+            msg,
+          })
+          .unwrap();
+      }
+      Message::Ping(data) => {
+        debug!("❯ Ping from {}", self.url);
+        self.send_message(Message::Pong(data));
+      }
+      Message::Close(frame) => {
+        info!("❯ {} closed the connection: {:?}", self.url, frame);
+        self.is_connected.store(false, Ordering::Relaxed);
+        return false;
+      }
+      Message::Pong(_) => {}
+      _ => {
+        debug!("❯ Ignoring non-text message from {}", self.url);
+      }
+    }
+    true
+  }
+
+  /// Connects again to the relay after a [`RelayData::disconnect`],
+  /// sending the latest metadata and the `requests` (see [`RelayData::connect`]).
+  ///
+  async fn reconnect(&self, requests: Vec<Message>) {
+    debug!("❯ Reconnecting to {}", self.url);
+    self.end_tasks().await;
+    self.close_communication.store(false, Ordering::Relaxed);
+    self.connect(requests).await;
+  }
+
+  /// Ends the tasks of the previous connection, if any. They only notice it was
+  /// closed when there is something to send (or when the relay closes it), so, if
+  /// left running, they could e.g. mark the next connection as not connected.
+  ///
+  async fn end_tasks(&self) {
+    let tasks: Vec<JoinHandle<()>> = self.tasks.lock().await.drain(..).collect();
+    for task in tasks {
+      task.abort();
+      let _ = task.await;
+    }
+  }
+
   fn disconnect(&self) {
     debug!("❯ Disconnecting from {}", self.url);
     self.close_communication.store(true, Ordering::Relaxed);
     self.is_connected.store(false, Ordering::Relaxed);
   }
 
+  /// Closes the connection once everything already queued is sent
+  /// to the relay and waits for its tasks to end (aborting them
+  /// if they take longer than [`SHUTDOWN_TIMEOUT`]).
+  ///
+  async fn shutdown(&self) {
+    debug!("❯ Shutting down the connection with {}", self.url);
+    self.send_message(Message::Close(None));
+
+    let mut tasks = self.tasks.lock().await;
+    for task in tasks.iter_mut() {
+      if tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut *task)
+        .await
+        .is_err()
+      {
+        task.abort();
+      }
+    }
+    self.disconnect();
+  }
+
   fn send_message(&self, message: Message) {
+    // counted before it can be taken from the channel
+    self.pending_messages.fetch_add(1, Ordering::Relaxed);
     self.relay_tx.send(message).unwrap()
   }
+
+  fn status(&self) -> RelayStatus {
+    RelayStatus {
+      connected: self.is_connected.load(Ordering::Relaxed),
+      pending_messages: self.pending_messages.load(Ordering::Relaxed),
+    }
+  }
+}
+
+/// Sends what a relay needs right after the connection is established,
+/// always in the same order:
+///   1. the client `metadata`;
+///   2. the `requests` (REQs), so the subscriptions are active again;
+///   3. every message `queued` while disconnected (e.g. published events),
+///      so the client does not miss the echo of its own events.
+///
+/// Returns whether a shutdown was requested (see [`RelayData::shutdown`])
+/// while the queued messages were sent.
+///
+async fn replay_on_connect<S>(
+  ws_tx: &mut S,
+  metadata: Message,
+  requests: Vec<Message>,
+  queued: &mut UnboundedReceiver<Message>,
+  pending_messages: &AtomicUsize,
+) -> Result<bool, S::Error>
+where
+  S: Sink<Message> + Unpin,
+{
+  ws_tx.send(metadata).await?;
+  for request in requests {
+    ws_tx.send(request).await?;
+  }
+  while let Ok(msg) = queued.try_recv() {
+    pending_messages.fetch_sub(1, Ordering::Relaxed);
+    if is_shutdown_request(&msg) {
+      return Ok(true);
+    }
+    ws_tx.send(msg).await?;
+  }
+  Ok(false)
+}
+
+/// Checks if the message queued to a relay is the one sent by
+/// [`RelayData::shutdown`], after everything queued before it.
+///
+fn is_shutdown_request(msg: &Message) -> bool {
+  matches!(msg, Message::Close(_))
+}
+
+/// Checks if the relay at `url` has the event with `event_id`
+/// (see [`query_relay`]).
+///
+async fn relay_has_event(url: &str, event_id: &str, timeout: Duration) -> bool {
+  let filters = vec![Filter::new().ids(vec![event_id.to_string()])];
+  query_relay(url, filters, timeout)
+    .await
+    .events
+    .iter()
+    .any(|event| event.id == event_id)
+}
+
+/// Answer of a relay to a one-shot REQ (see [`query_relay`]).
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryResult {
+  pub url: String,
+  /// Events received, with a valid id and signature.
+  pub events: Vec<Event>,
+  /// Whether the relay sent the EOSE, i.e.: `events` are all of its events that
+  /// match, not only the ones received before the query failed or timed out.
+  pub reached_eose: bool,
+}
+
+/// Gets the events of the relay at `url` that match the `filters` by sending
+/// a one-shot REQ, on a connection of its own, and waiting for the EOSE
+/// (or the `timeout`, keeping the events received until then).
+///
+/// Only events with a valid id and signature are kept.
+///
+async fn query_relay(url: &str, filters: Vec<Filter>, timeout: Duration) -> QueryResult {
+  let mut events = vec![];
+  let mut reached_eose = false;
+
+  let query = async {
+    let Ok((ws_stream, _)) = connect_async(url).await else {
+      return;
+    };
+    let (mut ws_tx, mut ws_rx) = ws_stream.split();
+
+    let subscription_id = Uuid::new_v4().to_string();
+    let request = ClientToRelayCommRequest {
+      subscription_id: subscription_id.clone(),
+      filters,
+      ..Default::default()
+    };
+    if ws_tx.send(Message::from(request.as_json())).await.is_err() {
+      return;
+    }
+
+    while let Some(Ok(msg)) = ws_rx.next().await {
+      let Ok(text) = msg.to_text() else {
+        continue;
+      };
+
+      if let Ok(event_msg) = RelayToClientCommEvent::from_json(text) {
+        if event_msg.subscription_id == subscription_id && event_msg.event.is_fully_valid() {
+          events.push(event_msg.event);
+        }
+        continue;
+      }
+
+      if let Ok(eose_msg) = RelayToClientCommEose::from_json(text) {
+        if eose_msg.subscription_id == subscription_id {
+          reached_eose = true;
+          break;
+        }
+      }
+    }
+
+    let close = ClientToRelayCommClose::new_close(subscription_id);
+    let _ = ws_tx.send(Message::from(close.as_json())).await;
+    let _ = ws_tx.close().await;
+  };
+
+  if tokio::time::timeout(timeout, query).await.is_err() {
+    debug!("Timed out querying {url}");
+  }
+
+  QueryResult {
+    url: url.to_string(),
+    events,
+    reached_eose,
+  }
 }
 
 #[derive(Debug)]
@@ -131,6 +459,8 @@ pub struct RelayPool {
   relays: Arc<Mutex<HashMap<String, RelayData>>>,
   pool_task_sender: PoolTaskSender,
   relay_pool_task: RelayPoolTask,
+  /// Task running the `relay_pool_task`, once the notifications are started.
+  relay_pool_task_handle: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Default for RelayPool {
@@ -154,6 +484,7 @@ impl RelayPool {
       relays,
       pool_task_sender,
       relay_pool_task,
+      relay_pool_task_handle: Mutex::new(None),
     }
   }
 
@@ -175,22 +506,48 @@ impl RelayPool {
     self.relays.lock().await
   }
 
+  /// Gets the state of the connection with each relay, by url.
+  pub async fn status(&self) -> HashMap<String, RelayStatus> {
+    self
+      .relays
+      .lock()
+      .await
+      .iter()
+      .map(|(url, relay)| (url.clone(), relay.status()))
+      .collect()
+  }
+
   /// Add relay to the pool hashmap and tries to connect to it
   /// if it does not already exist.
   ///
-  pub async fn add_relay(&self, url: String, metadata: Message) {
+  pub async fn add_relay(&self, url: RelayUrl, metadata: Message) {
+    let url = url.to_string();
     let mut relays = self.relays_mut().await;
 
     if relays.get(&url).is_none() {
-      let relay = RelayData::new(url.clone(), self.pool_task_sender.clone());
+      let relay = RelayData::new(url.clone(), self.pool_task_sender.clone(), metadata);
       relays.insert(url, relay.clone());
-      relay.connect(metadata).await;
+      relay.connect(vec![]).await;
+    }
+  }
+
+  /// Keeps `metadata` as the one sent to every relay when it (re)connects,
+  /// and sends it right away to the relays already connected.
+  ///
+  pub async fn update_metadata(&self, metadata: Message) {
+    let relays = self.relays().await;
+    for relay in relays.values() {
+      *relay.metadata.lock().await = metadata.clone();
+      if relay.is_connected.load(Ordering::Relaxed) {
+        relay.send_message(metadata.clone());
+      }
     }
   }
 
   /// Removes from the pool and disconnects from the relay.
   ///
-  pub async fn remove_relay(&self, url: String) {
+  pub async fn remove_relay(&self, url: RelayUrl) {
+    let url = url.to_string();
     let mut relays = self.relays_mut().await;
     if relays.contains_key(&url) {
       relays[&url].disconnect();
@@ -198,34 +555,144 @@ impl RelayPool {
     }
   }
 
-  /// Connects to all relays in the pool that are not yet connected.
+  /// Connects to all relays in the pool that are not yet connected,
+  /// sending the latest metadata and the `requests` (REQs) before anything
+  /// queued while they were not connected.
   ///
-  pub async fn connect(&self, metadata: Message) {
+  pub async fn connect(&self, requests: Vec<Message>) {
     let relays = self.relays().await;
     for relay in relays.values() {
       if !relay.is_connected.load(Ordering::Relaxed) {
-        relay.connect(metadata.clone()).await;
+        relay.connect(requests.clone()).await;
       }
     }
   }
 
   /// Disconnects from a relay (does not remove it from the pool).
   ///
-  pub async fn disconnect_relay(&self, relay_url: String) {
+  pub async fn disconnect_relay(&self, relay_url: RelayUrl) {
     let relays = self.relays().await;
-    if let Some(relay) = relays.get(&relay_url) {
+    if let Some(relay) = relays.get(relay_url.as_str()) {
       relay.disconnect();
     };
   }
 
+  /// Connects again to a relay of the pool that is not connected, e.g.: after
+  /// [`RelayPool::disconnect_relay`], sending the latest metadata and the `requests` (REQs).
+  ///
+  pub async fn reconnect_relay(&self, relay_url: RelayUrl, requests: Vec<Message>) {
+    let relays = self.relays().await;
+    if let Some(relay) = relays.get(relay_url.as_str()) {
+      if !relay.is_connected.load(Ordering::Relaxed) {
+        relay.reconnect(requests).await;
+      }
+    };
+  }
+
+  /// Checks, for each connected relay, if it has the event with `event_id`.
+  /// Each relay gets its own one-shot REQ and has up to `timeout` to answer it.
+  ///
+  /// Returns whether the event was received (before the EOSE) by relay url.
+  ///
+  pub async fn event_presence(&self, event_id: &str, timeout: Duration) -> HashMap<String, bool> {
+    let relays = self.relays().await;
+    let connected_urls: Vec<String> = relays
+      .values()
+      .filter(|relay| relay.is_connected.load(Ordering::Relaxed))
+      .map(|relay| relay.url.clone())
+      .collect();
+
+    let checks = connected_urls
+      .iter()
+      .map(|url| relay_has_event(url, event_id, timeout));
+    let presence = futures_util::future::join_all(checks).await;
+
+    connected_urls.into_iter().zip(presence).collect()
+  }
+
+  /// Gets the events that match the `filters` from all the connected relays,
+  /// each one queried with a one-shot REQ (see [`query_relay`]).
+  /// The same event may be returned by more than one relay.
+  ///
+  pub async fn query(&self, filters: Vec<Filter>, timeout: Duration) -> Vec<QueryResult> {
+    let relays = self.relays().await;
+    let queries = relays
+      .values()
+      .filter(|relay| relay.is_connected.load(Ordering::Relaxed))
+      .map(|relay| query_relay(&relay.url, filters.clone(), timeout));
+
+    futures_util::future::join_all(queries).await
+  }
+
+  /// Same as [`RelayPool::query`], but only for the relay at `url`.
+  ///
+  pub async fn query_relay(
+    &self,
+    url: &str,
+    filters: Vec<Filter>,
+    timeout: Duration,
+  ) -> QueryResult {
+    query_relay(url, filters, timeout).await
+  }
+
   pub async fn notifications(&self) {
     let mut relay_pool_task = self.relay_pool_task.clone();
-    tokio::spawn(async move { relay_pool_task.run().await });
+    let handle = tokio::spawn(async move { relay_pool_task.run().await });
+    *self.relay_pool_task_handle.lock().await = Some(handle);
+  }
+
+  /// Tears down the whole pool: every relay is removed from it and
+  /// disconnected once the messages already queued to it are sent.
+  /// The pool task stops after handling the messages received until then.
+  ///
+  pub async fn shutdown(&self) {
+    let relays: Vec<RelayData> = self
+      .relays_mut()
+      .await
+      .drain()
+      .map(|(_, relay)| relay)
+      .collect();
+    futures_util::future::join_all(relays.iter().map(|relay| relay.shutdown())).await;
+
+    let _ = self.pool_task_sender.send(RelayPoolMessage::Shutdown);
+    if let Some(handle) = self.relay_pool_task_handle.lock().await.as_mut() {
+      if tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut *handle)
+        .await
+        .is_err()
+      {
+        handle.abort();
+      }
+    }
+  }
+
+  /// Gets a receiver of the notifications sent from now on.
+  pub fn notifications_receiver(&self) -> broadcast::Receiver<RelayPoolNotification> {
+    self.relay_pool_task.notifications_receiver()
+  }
+
+  /// This function has the same semantics as `RelayPoolTask.pause()`.
+  pub async fn pause_notifications(&self) {
+    self.relay_pool_task.pause().await;
   }
 
+  /// This function has the same semantics as `RelayPoolTask.resume()`.
+  pub async fn resume_notifications(&self) {
+    self.relay_pool_task.resume().await;
+  }
+
+  /// Sends the `message` to all relays in the pool.
+  ///
+  /// Broadcasting is hot, so the relays are not cloned and
+  /// the `message` is moved into the last one instead of cloned.
+  ///
   pub async fn broadcast_messages(&self, message: Message) {
-    let relays = self.relays().await;
-    for relay in relays.values() {
+    let relays = self.relays.lock().await;
+    let mut relays = relays.values().peekable();
+    while let Some(relay) = relays.next() {
+      if relays.peek().is_none() {
+        relay.send_message(message);
+        break;
+      }
       relay.send_message(message.clone());
     }
   }
@@ -236,6 +703,7 @@ struct AnyCommunicationFromRelay {
   eose: RelayToClientCommEose,
   event: RelayToClientCommEvent,
   notice: RelayToClientCommNotice,
+  closed: RelayToClientCommClosed,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -244,21 +712,100 @@ struct MsgResult {
   is_eose: bool,
   is_event: bool,
   is_notice: bool,
+  is_closed: bool,
   data: AnyCommunicationFromRelay,
 }
 #[derive(Debug, Clone)]
 pub struct RelayPoolTask {
   receiver: Arc<Mutex<UnboundedReceiver<RelayPoolMessage>>>,
+  /// Tx part of the channel to notify the messages received from the relays.
+  notification_sender: broadcast::Sender<RelayPoolNotification>,
+  /// Flag to signal if the notifications are paused.
+  paused: Arc<AtomicBool>,
+  /// Messages received while the notifications are paused (not even parsed yet).
+  paused_buffer: Arc<Mutex<VecDeque<RelayPoolMessage>>>,
+  paused_buffer_capacity: usize,
 }
 
 impl RelayPoolTask {
   pub fn new(receiver: UnboundedReceiver<RelayPoolMessage>) -> Self {
+    let (notification_sender, _) = broadcast::channel(NOTIFICATIONS_CHANNEL_CAPACITY);
+
     Self {
       receiver: Arc::new(Mutex::new(receiver)),
+      notification_sender,
+      paused: Arc::new(AtomicBool::new(false)),
+      paused_buffer: Arc::new(Mutex::new(VecDeque::new())),
+      paused_buffer_capacity: PAUSED_NOTIFICATIONS_BUFFER_CAPACITY,
     }
   }
 
-  /// Helper to parse the function into EOSE, NOTICE or EVENT.
+  pub fn notifications_receiver(&self) -> broadcast::Receiver<RelayPoolNotification> {
+    self.notification_sender.subscribe()
+  }
+
+  /// Stops processing the messages received from the relays, without
+  /// disconnecting from them. Until [`RelayPoolTask::resume`] is called, the
+  /// messages are only buffered (up to `paused_buffer_capacity`, dropping the oldest ones).
+  ///
+  pub async fn pause(&self) {
+    let _paused_buffer = self.paused_buffer.lock().await;
+    self.paused.store(true, Ordering::Relaxed);
+  }
+
+  /// Processes the messages buffered while paused and goes back
+  /// to processing the messages as they are received.
+  ///
+  pub async fn resume(&self) {
+    let mut paused_buffer = self.paused_buffer.lock().await;
+    self.paused.store(false, Ordering::Relaxed);
+    for msg in paused_buffer.drain(..) {
+      self.handle_message(msg);
+    }
+  }
+
+  /// Parses the message and notifies it, if it is an EVENT, an EOSE, a NOTICE or a CLOSED.
+  fn handle_message(&self, msg: RelayPoolMessage) {
+    let RelayPoolMessage::ReceivedMsg { relay_url, msg } = msg else {
+      return;
+    };
+    let Ok(text) = msg.to_text() else {
+      debug!("Non-text message from {relay_url} ignored");
+      return;
+    };
+
+    let result = self.parse_message_received_from_relay(text, relay_url.clone());
+    let notification = if result.is_event {
+      RelayPoolNotification::Event {
+        relay_url,
+        subscription_id: result.data.event.subscription_id,
+        event: result.data.event.event,
+      }
+    } else if result.is_eose {
+      RelayPoolNotification::Eose {
+        relay_url,
+        subscription_id: result.data.eose.subscription_id,
+      }
+    } else if result.is_notice {
+      RelayPoolNotification::Notice {
+        relay_url,
+        message: result.data.notice.message,
+      }
+    } else if result.is_closed {
+      RelayPoolNotification::Closed {
+        relay_url,
+        subscription_id: result.data.closed.subscription_id,
+        message: result.data.closed.message,
+      }
+    } else {
+      return;
+    };
+
+    // it only fails when there is no one listening
+    let _ = self.notification_sender.send(notification);
+  }
+
+  /// Helper to parse the function into EOSE, NOTICE, CLOSED or EVENT.
   ///
   fn parse_message_received_from_relay(&self, msg: &str, relay_url: String) -> MsgResult {
     let mut result = MsgResult::default();
@@ -274,11 +821,11 @@ impl RelayPoolTask {
     if let Ok(event_msg) = RelayToClientCommEvent::from_json(msg.to_string()) {
       debug!("EVENT from {relay_url}:\n {:?}\n", event_msg);
 
-      // validates signature
-      if !event_msg.event.check_event_signature() {
+      // validates id and signature
+      if !event_msg.event.is_fully_valid() {
         result.no_op = true;
-        error!("Received an event, but its signature is not valid!");
-        debug!("Event signature with error: {:?}", event_msg.event);
+        error!("Received an event, but its id or signature is not valid!");
+        debug!("Event with error: {:?}", event_msg.event);
         return result;
       }
 
@@ -295,6 +842,14 @@ impl RelayPoolTask {
       return result;
     }
 
+    if let Ok(closed_msg) = RelayToClientCommClosed::from_json(msg.to_string()) {
+      debug!("CLOSED from {relay_url}:\n {:?}\n", closed_msg);
+
+      result.is_closed = true;
+      result.data.closed = closed_msg;
+      return result;
+    }
+
     result.no_op = true;
     debug!("NO-OP from {relay_url}: {:?}", msg);
     result
@@ -305,11 +860,18 @@ impl RelayPoolTask {
   pub async fn run(&mut self) {
     debug!("RelayPool Thread Started");
     while let Some(msg) = self.receiver.lock().await.recv().await {
-      match msg {
-        RelayPoolMessage::ReceivedMsg { relay_url, msg } => {
-          let _ = self.parse_message_received_from_relay(msg.to_text().unwrap(), relay_url);
+      if let RelayPoolMessage::Shutdown = msg {
+        break;
+      }
+      let mut paused_buffer = self.paused_buffer.lock().await;
+      if self.paused.load(Ordering::Relaxed) {
+        if paused_buffer.len() >= self.paused_buffer_capacity {
+          paused_buffer.pop_front();
         }
+        paused_buffer.push_back(msg);
+        continue;
       }
+      self.handle_message(msg);
     }
     debug!("RelayPool Thread Ended");
   }
@@ -327,7 +889,11 @@ mod tests {
 
   fn make_relaydata_sut() -> RelayData {
     let (pool_task_sender, _pool_task_receiver) = tokio::sync::mpsc::unbounded_channel();
-    RelayData::new(String::from("potato_url"), pool_task_sender)
+    RelayData::new(
+      String::from("potato_url"),
+      pool_task_sender,
+      Message::Text(String::from("metadata")),
+    )
   }
 
   fn make_relaypooltask_sut() -> RelayPoolTask {
@@ -351,34 +917,180 @@ mod tests {
     assert!(relay_data.close_communication.load(Ordering::Relaxed));
   }
 
+  #[test]
+  fn relaydata_answers_ping_with_pong() {
+    let (pool_task_sender, mut pool_task_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let relay_data = RelayData::new(
+      String::from("potato_url"),
+      pool_task_sender,
+      Message::Text(String::from("metadata")),
+    );
+    relay_data.is_connected.store(true, Ordering::Relaxed);
+
+    assert!(relay_data.handle_message_from_relay(Message::Ping(vec![1, 2, 3])));
+    assert!(relay_data.handle_message_from_relay(Message::Pong(vec![])));
+
+    let mut relay_rx = relay_data.relay_rx.try_lock().unwrap();
+    assert_eq!(relay_rx.try_recv().unwrap(), Message::Pong(vec![1, 2, 3]));
+    assert!(relay_rx.try_recv().is_err());
+    // neither ping nor pong are forwarded to the pool
+    assert!(pool_task_receiver.try_recv().is_err());
+    assert!(relay_data.is_connected.load(Ordering::Relaxed));
+  }
+
+  #[test]
+  fn relaydata_forwards_only_text_and_stops_on_close() {
+    let (pool_task_sender, mut pool_task_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let relay_data = RelayData::new(
+      String::from("potato_url"),
+      pool_task_sender,
+      Message::Text(String::from("metadata")),
+    );
+    relay_data.is_connected.store(true, Ordering::Relaxed);
+
+    assert!(relay_data.handle_message_from_relay(Message::Binary(vec![1])));
+    assert!(relay_data.handle_message_from_relay(Message::Text(String::from("potato"))));
+
+    let RelayPoolMessage::ReceivedMsg { relay_url, msg } = pool_task_receiver.try_recv().unwrap()
+    else {
+      panic!("expected a received message");
+    };
+    assert_eq!(relay_url, String::from("potato_url"));
+    assert_eq!(msg, Message::Text(String::from("potato")));
+    assert!(pool_task_receiver.try_recv().is_err());
+
+    assert_eq!(
+      relay_data.handle_message_from_relay(Message::Close(None)),
+      false
+    );
+    assert_eq!(relay_data.is_connected.load(Ordering::Relaxed), false);
+  }
+
+  #[tokio::test]
+  async fn replay_on_connect_sends_metadata_then_requests_then_queued() {
+    let relay_data = make_relaydata_sut();
+    let metadata = Message::Text(String::from("metadata"));
+    let requests = vec![
+      Message::Text(String::from("req1")),
+      Message::Text(String::from("req2")),
+    ];
+    // published while disconnected
+    relay_data.send_message(Message::Text(String::from("event1")));
+    relay_data.send_message(Message::Text(String::from("event2")));
+
+    let sent: std::sync::Mutex<Vec<Message>> = std::sync::Mutex::new(vec![]);
+    let ws_tx = futures_util::sink::unfold((), |_, msg: Message| {
+      sent.lock().unwrap().push(msg);
+      async { Ok::<_, std::convert::Infallible>(()) }
+    });
+    futures_util::pin_mut!(ws_tx);
+    let mut rx = relay_data.relay_rx.lock().await;
+    let is_shutting_down = replay_on_connect(
+      &mut ws_tx,
+      metadata,
+      requests,
+      &mut rx,
+      &relay_data.pending_messages,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(is_shutting_down, false);
+    assert_eq!(
+      *sent.lock().unwrap(),
+      vec![
+        Message::Text(String::from("metadata")),
+        Message::Text(String::from("req1")),
+        Message::Text(String::from("req2")),
+        Message::Text(String::from("event1")),
+        Message::Text(String::from("event2")),
+      ]
+    );
+    assert!(rx.try_recv().is_err());
+    assert_eq!(relay_data.status().pending_messages, 0);
+  }
+
+  #[tokio::test]
+  async fn relaypool_status() {
+    let relay_pool = RelayPool::new();
+    assert_eq!(relay_pool.status().await, HashMap::new());
+
+    let relay_data = make_relaydata_sut();
+    relay_pool
+      .relays_mut()
+      .await
+      .insert(relay_data.url.clone(), relay_data.clone());
+    // published while not connected
+    relay_data.send_message(Message::Text(String::from("event1")));
+    relay_data.send_message(Message::Text(String::from("event2")));
+
+    assert_eq!(
+      relay_pool.status().await,
+      HashMap::from([(
+        relay_data.url.clone(),
+        RelayStatus {
+          connected: false,
+          pending_messages: 2,
+        }
+      )])
+    );
+
+    relay_data.is_connected.store(true, Ordering::Relaxed);
+    assert!(relay_pool.status().await[&relay_data.url].connected);
+  }
+
+  #[tokio::test]
+  async fn relaypool_broadcast_messages_sends_to_every_relay() {
+    let relay_pool = RelayPool::new();
+    let message = Message::Text(String::from("potato"));
+
+    // nothing to send to
+    relay_pool.broadcast_messages(message.clone()).await;
+
+    let mut relays = relay_pool.relays_mut().await;
+    for url in ["relay1", "relay2", "relay3"] {
+      relays.insert(url.to_string(), make_relaydata_sut());
+    }
+    drop(relays);
+
+    relay_pool.broadcast_messages(message.clone()).await;
+
+    for relay_data in relay_pool.relays().await.values() {
+      let mut relay_rx = relay_data.relay_rx.try_lock().unwrap();
+      assert_eq!(relay_rx.try_recv().unwrap(), message);
+      assert!(relay_rx.try_recv().is_err());
+    }
+  }
+
   #[tokio::test]
   async fn relaypool_remove_relay() {
     let relay_pool = RelayPool::new();
-    let url = String::from("key_potato");
+    let url = RelayUrl::parse("wss://potato.com").unwrap();
     let relay_data = make_relaydata_sut();
 
     assert_eq!(relay_pool.relays().await.len(), 0);
 
     let mut relays = relay_pool.relays_mut().await;
-    relays.insert(url.clone(), relay_data);
+    relays.insert(url.to_string(), relay_data);
     drop(relays);
 
     assert_eq!(relay_pool.relays().await.len(), 1);
     // if the key does not exist, should not do anything
     relay_pool
-      .remove_relay(String::from("non-existent url"))
+      .remove_relay(RelayUrl::parse("wss://non-existent.com").unwrap())
       .await;
     assert_eq!(relay_pool.relays().await.len(), 1);
 
     // act
-    relay_pool.remove_relay(url.clone()).await;
+    relay_pool.remove_relay(url).await;
     assert_eq!(relay_pool.relays().await.len(), 0);
   }
 
   #[tokio::test]
   async fn relaypool_disconnect_relay() {
     let relay_pool = RelayPool::new();
-    let url = String::from("key_potato");
+    let relay_url = RelayUrl::parse("wss://potato.com").unwrap();
+    let url = relay_url.to_string();
     let relay_data = make_relaydata_sut();
 
     assert_eq!(relay_pool.relays().await.len(), 0);
@@ -392,7 +1104,7 @@ mod tests {
 
     // if the key does not exist, should not do anything
     relay_pool
-      .disconnect_relay(String::from("non-existent url"))
+      .disconnect_relay(RelayUrl::parse("wss://non-existent.com").unwrap())
       .await;
     assert_eq!(relay_pool.relays().await.len(), 1);
     assert_eq!(relays[&url].is_connected.load(Ordering::Relaxed), false);
@@ -402,13 +1114,197 @@ mod tests {
     );
 
     // act
-    relay_pool.disconnect_relay(url.clone()).await;
+    relay_pool.disconnect_relay(relay_url).await;
     assert_eq!(relay_pool.relays().await.len(), 1);
 
     assert_eq!(relays[&url].is_connected.load(Ordering::Relaxed), false);
     assert!(relays[&url].close_communication.load(Ordering::Relaxed));
   }
 
+  #[tokio::test]
+  async fn relaypool_reconnect_relay() {
+    let (received_tx, mut received_rx) = unbounded_channel();
+    let url = spawn_mock_relay(received_tx).await;
+    let relay_url = RelayUrl::parse(&url).unwrap();
+    let relay_pool = RelayPool::new();
+    relay_pool
+      .add_relay(relay_url.clone(), Message::Text(String::from("metadata")))
+      .await;
+    let relay = relay_pool.relays().await[relay_url.as_str()].clone();
+    assert!(relay.is_connected.load(Ordering::Relaxed));
+
+    relay_pool.disconnect_relay(relay_url.clone()).await;
+    assert_eq!(relay.is_connected.load(Ordering::Relaxed), false);
+
+    // act
+    relay_pool
+      .reconnect_relay(relay_url, vec![Message::Text(String::from("REQ"))])
+      .await;
+    assert!(relay.is_connected.load(Ordering::Relaxed));
+    assert_eq!(relay.close_communication.load(Ordering::Relaxed), false);
+
+    // the previous connection is closed and the new one gets the subscriptions
+    let received = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+      .await
+      .unwrap();
+    assert_eq!(received.unwrap(), vec![String::from("metadata")]);
+    relay_pool.shutdown().await;
+    let received = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+      .await
+      .unwrap();
+    assert_eq!(
+      received.unwrap(),
+      vec![String::from("metadata"), String::from("REQ")]
+    );
+  }
+
+  /// Spawns a relay that sends, on `received`, the text messages
+  /// it got on each connection once the connection is closed.
+  ///
+  async fn spawn_mock_relay(received: UnboundedSender<Vec<String>>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let received = received.clone();
+        tokio::spawn(async move {
+          let mut ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+          let mut texts = vec![];
+          while let Some(Ok(msg)) = ws_stream.next().await {
+            if let Message::Text(text) = msg {
+              texts.push(text);
+            }
+          }
+          let _ = received.send(texts);
+        });
+      }
+    });
+
+    format!("ws://{addr}")
+  }
+
+  #[tokio::test]
+  async fn relaypool_sends_the_latest_metadata_on_reconnect() {
+    // relay that closes each connection after the first message (the metadata)
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (received_tx, mut received_rx) = unbounded_channel();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let mut ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+        while let Some(Ok(msg)) = ws_stream.next().await {
+          if let Message::Text(text) = msg {
+            received_tx.send(text).unwrap();
+            break;
+          }
+        }
+        let _ = ws_stream.close(None).await;
+      }
+    });
+    let relay_pool = RelayPool::new();
+    let relay_url = RelayUrl::parse(&url).unwrap();
+
+    relay_pool
+      .add_relay(relay_url.clone(), Message::Text(String::from("metadata")))
+      .await;
+    assert_eq!(received_rx.recv().await.unwrap(), "metadata");
+    let relay = relay_pool.relays().await[relay_url.as_str()].clone();
+    tokio::time::timeout(Duration::from_secs(5), async {
+      while relay.is_connected.load(Ordering::Relaxed) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    // the relay is not connected, so it only gets it on reconnect
+    relay_pool
+      .update_metadata(Message::Text(String::from("new metadata")))
+      .await;
+    relay_pool.connect(vec![]).await;
+
+    let received = tokio::time::timeout(Duration::from_secs(5), received_rx.recv())
+      .await
+      .unwrap();
+    assert_eq!(received.unwrap(), "new metadata");
+    assert!(received_rx.try_recv().is_err());
+  }
+
+  #[tokio::test]
+  async fn relaydata_is_not_connected_once_the_relay_drops_the_connection() {
+    // relay that drops the TCP connection (without a Close frame) after the first message
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let mut ws_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+        ws_stream.next().await;
+        drop(ws_stream);
+      }
+    });
+    let relay_pool = RelayPool::new();
+    let relay_url = RelayUrl::parse(&url).unwrap();
+
+    relay_pool
+      .add_relay(relay_url.clone(), Message::Text(String::from("metadata")))
+      .await;
+    let relay = relay_pool.relays().await[relay_url.as_str()].clone();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+      while relay.is_connected.load(Ordering::Relaxed) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    // so it is connected again
+    relay_pool.connect(vec![]).await;
+    assert!(relay.is_connected.load(Ordering::Relaxed));
+  }
+
+  #[tokio::test]
+  async fn relaypool_shutdown_removes_relays_and_ends_their_tasks() {
+    let (received_tx, mut received_rx) = unbounded_channel();
+    let relay_pool = RelayPool::new();
+    relay_pool.notifications().await;
+    for _ in 0..2 {
+      let url = spawn_mock_relay(received_tx.clone()).await;
+      relay_pool
+        .add_relay(
+          RelayUrl::parse(&url).unwrap(),
+          Message::Text(String::from("metadata")),
+        )
+        .await;
+    }
+    relay_pool
+      .broadcast_messages(Message::Text(String::from("potato")))
+      .await;
+    let relays = relay_pool.relays().await;
+    assert_eq!(relays.len(), 2);
+
+    relay_pool.shutdown().await;
+
+    assert_eq!(relay_pool.relays().await.len(), 0);
+    for relay in relays.values() {
+      assert!(relay.close_communication.load(Ordering::Relaxed));
+      let tasks = relay.tasks.lock().await;
+      assert_eq!(tasks.len(), 2);
+      assert!(tasks.iter().all(|task| task.is_finished()));
+    }
+    let relay_pool_task_handle = relay_pool.relay_pool_task_handle.lock().await;
+    assert!(relay_pool_task_handle.as_ref().unwrap().is_finished());
+
+    // what was queued before the shutdown was still sent
+    for _ in 0..2 {
+      assert_eq!(
+        received_rx.recv().await.unwrap(),
+        vec![String::from("metadata"), String::from("potato")]
+      );
+    }
+  }
+
   #[test]
   fn parse_eose_message() {
     let relay_pool_task = make_relaypooltask_sut();
@@ -441,6 +1337,26 @@ mod tests {
     assert_eq!(result.no_op, false);
   }
 
+  #[test]
+  fn parse_closed_message() {
+    let relay_pool_task = make_relaypooltask_sut();
+    let closed = RelayToClientCommClosed::new_closed(
+      String::from("potato_subs"),
+      String::from("too many subscriptions"),
+    );
+    let closed_json = closed.as_json();
+
+    let result =
+      relay_pool_task.parse_message_received_from_relay(&closed_json, String::from("potato_url"));
+
+    assert_eq!(result.data.closed, closed);
+    assert!(result.is_closed);
+    assert_eq!(result.is_event, false);
+    assert_eq!(result.is_notice, false);
+    assert_eq!(result.is_eose, false);
+    assert_eq!(result.no_op, false);
+  }
+
   #[test]
   fn parse_event_message() {
     let relay_pool_task = make_relaypooltask_sut();
@@ -461,6 +1377,108 @@ mod tests {
     assert_eq!(result.no_op, false);
   }
 
+  #[tokio::test]
+  async fn relaypooltask_notifies_the_eose_after_the_stored_events() {
+    let (pool_task_sender, pool_task_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let relay_pool_task = RelayPoolTask::new(pool_task_receiver);
+    let mut notifications = relay_pool_task.notifications_receiver();
+    let mut running_task = relay_pool_task.clone();
+    tokio::spawn(async move { running_task.run().await });
+
+    let event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    ).unwrap();
+    let event_msg = RelayToClientCommEvent::new_event(String::from("potato_subs"), event.clone());
+    let eose_msg = RelayToClientCommEose::new_eose(String::from("potato_subs"));
+    for msg in [event_msg.as_json(), eose_msg.as_json()] {
+      pool_task_sender
+        .send(RelayPoolMessage::ReceivedMsg {
+          relay_url: String::from("potato_url"),
+          msg: Message::from(msg),
+        })
+        .unwrap();
+    }
+
+    assert_eq!(
+      notifications.recv().await.unwrap(),
+      RelayPoolNotification::Event {
+        relay_url: String::from("potato_url"),
+        subscription_id: String::from("potato_subs"),
+        event,
+      }
+    );
+    assert_eq!(
+      notifications.recv().await.unwrap(),
+      RelayPoolNotification::Eose {
+        relay_url: String::from("potato_url"),
+        subscription_id: String::from("potato_subs"),
+      }
+    );
+  }
+
+  #[tokio::test]
+  async fn relaypooltask_pauses_and_resumes_notifications() {
+    let (pool_task_sender, pool_task_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut relay_pool_task = RelayPoolTask::new(pool_task_receiver);
+    relay_pool_task.paused_buffer_capacity = 2;
+    let mut notifications = relay_pool_task.notifications_receiver();
+    let mut running_task = relay_pool_task.clone();
+    tokio::spawn(async move { running_task.run().await });
+
+    let event = Event::from_value(
+      json!({"content":"potato","created_at":1684589418,"id":"00960bd35499f8c63a4f65e79d6b1a2b7f1b8c97e76652325567b78c496350ae","kind":1,"pubkey":"614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6","sig":"bf073c935f71de50ec72bdb79f75b0bf32f9049305c3b22f97c06422c6f2edc86e0d7e07d7d7222678b238b1daee071be5f6fa653c611971395ec0d1c6407caf","tags":[]}),
+    ).unwrap();
+    let send_event = |subscription_id: &str| {
+      let msg = RelayToClientCommEvent::new_event(subscription_id.to_string(), event.clone());
+      pool_task_sender
+        .send(RelayPoolMessage::ReceivedMsg {
+          relay_url: String::from("potato_url"),
+          msg: Message::from(msg.as_json()),
+        })
+        .unwrap();
+    };
+    let subscription_id_of = |notification: RelayPoolNotification| match notification {
+      RelayPoolNotification::Event {
+        subscription_id, ..
+      } => subscription_id,
+      other => panic!("not an event: {other:?}"),
+    };
+
+    relay_pool_task.pause().await;
+    send_event("first");
+    send_event("second");
+    send_event("third");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // paused, nothing is delivered
+    assert!(notifications.try_recv().is_err());
+
+    // the oldest one was dropped when the buffer got full
+    relay_pool_task.resume().await;
+    assert_eq!(
+      subscription_id_of(notifications.recv().await.unwrap()),
+      "second"
+    );
+    assert_eq!(
+      subscription_id_of(notifications.recv().await.unwrap()),
+      "third"
+    );
+
+    send_event("fourth");
+    let notification = tokio::time::timeout(Duration::from_secs(1), notifications.recv())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      notification,
+      RelayPoolNotification::Event {
+        relay_url: String::from("potato_url"),
+        subscription_id: String::from("fourth"),
+        event,
+      }
+    );
+  }
+
   #[test]
   fn parse_noop_message() {
     let relay_pool_task = make_relaypooltask_sut();
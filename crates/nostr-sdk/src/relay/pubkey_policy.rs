@@ -0,0 +1,142 @@
+use std::{collections::HashSet, env, fs};
+
+use log::warn;
+
+use crate::{event::PubKey, schnorr::normalize_pubkey};
+
+/// Whether the pubkeys of a [`PubkeyPolicy`] are the only ones allowed
+/// to publish to the relay or the ones denied.
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PubkeyPolicyMode {
+  Allow,
+  #[default]
+  Deny,
+}
+
+/// Which authors can publish events to the relay (and have them sent to the clients).
+/// By default, no one is denied.
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PubkeyPolicy {
+  pub mode: PubkeyPolicyMode,
+  /// x-only hex pubkeys
+  pub keys: HashSet<PubKey>,
+}
+
+impl PubkeyPolicy {
+  /// Creates the policy for the hex pubkeys `keys` (x-only or compressed).
+  /// The ones that are not pubkeys are skipped with a warning.
+  ///
+  pub fn new<I>(mode: PubkeyPolicyMode, keys: I) -> Self
+  where
+    I: IntoIterator<Item = String>,
+  {
+    let keys = keys
+      .into_iter()
+      .filter_map(|key| match normalize_pubkey(&key) {
+        Ok(pubkey) => Some(pubkey),
+        Err(err) => {
+          warn!("Skipping the pubkey {key} of the pubkey policy: {err}");
+          None
+        }
+      })
+      .collect();
+
+    Self { mode, keys }
+  }
+
+  /// Checks if the author `pubkey` (hex, x-only or compressed) can publish to the relay.
+  pub fn allows(&self, pubkey: &str) -> bool {
+    let listed = normalize_pubkey(pubkey).is_ok_and(|pubkey| self.keys.contains(&pubkey));
+    match self.mode {
+      PubkeyPolicyMode::Allow => listed,
+      PubkeyPolicyMode::Deny => !listed,
+    }
+  }
+}
+
+/// Gets the pubkey policy of the relay from the env vars:
+/// - `RELAY_PUBKEY_POLICY`: `allow` (only the listed pubkeys can publish)
+///   or `deny` (the listed pubkeys cannot publish). Defaults to `deny`;
+/// - `RELAY_PUBKEY_POLICY_KEYS`: the hex pubkeys, separated by commas;
+/// - `RELAY_PUBKEY_POLICY_FILE`: path of a file with more of them,
+///   separated by commas or whitespace (e.g.: one per line).
+///
+pub fn get_pubkey_policy() -> PubkeyPolicy {
+  let mode = match env::var("RELAY_PUBKEY_POLICY").as_deref() {
+    Ok("allow") => PubkeyPolicyMode::Allow,
+    _ => PubkeyPolicyMode::Deny,
+  };
+
+  let mut keys = env::var("RELAY_PUBKEY_POLICY_KEYS").unwrap_or_default();
+  if let Ok(path) = env::var("RELAY_PUBKEY_POLICY_FILE") {
+    match fs::read_to_string(&path) {
+      Ok(file_keys) => keys = format!("{keys},{file_keys}"),
+      Err(err) => warn!("Could not read the pubkey policy file {path}: {err}"),
+    }
+  }
+
+  PubkeyPolicy::new(
+    mode,
+    keys
+      .split(|c: char| c == ',' || c.is_whitespace())
+      .filter(|key| !key.is_empty())
+      .map(String::from),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  fn make_pubkey_sut() -> PubKey {
+    normalize_pubkey(&crate::schnorr::generate_keys().public_key.to_string()).unwrap()
+  }
+
+  #[test]
+  fn allow_mode_only_allows_the_listed_pubkeys() {
+    let listed = make_pubkey_sut();
+    let not_listed = make_pubkey_sut();
+    let policy = PubkeyPolicy::new(PubkeyPolicyMode::Allow, vec![listed.clone()]);
+
+    assert!(policy.allows(&listed));
+    assert!(policy.allows(&listed.to_uppercase()));
+    assert!(!policy.allows(&not_listed));
+    // with no one listed, no one is allowed
+    assert!(!PubkeyPolicy::new(PubkeyPolicyMode::Allow, vec![]).allows(&listed));
+  }
+
+  #[test]
+  fn deny_mode_denies_the_listed_pubkeys() {
+    let listed = make_pubkey_sut();
+    let not_listed = make_pubkey_sut();
+    let policy = PubkeyPolicy::new(PubkeyPolicyMode::Deny, vec![listed.clone()]);
+
+    assert!(!policy.allows(&listed));
+    assert!(policy.allows(&not_listed));
+    // nor with the compressed (prefixed) form of the pubkey
+    for prefix in ["02", "03"] {
+      assert!(!policy.allows(&format!("{prefix}{listed}")));
+    }
+    // by default, no one is denied
+    assert!(PubkeyPolicy::default().allows(&listed));
+  }
+
+  #[test]
+  fn new_normalizes_the_pubkeys_and_skips_the_invalid_ones() {
+    let keys = crate::schnorr::generate_keys();
+    let compressed = keys.public_key.to_string();
+    let x_only = keys.public_key.x_only_public_key().0.to_string();
+
+    let policy = PubkeyPolicy::new(
+      PubkeyPolicyMode::Deny,
+      vec![compressed, String::from("not a pubkey")],
+    );
+
+    assert_eq!(policy.keys, HashSet::from([x_only]));
+  }
+}
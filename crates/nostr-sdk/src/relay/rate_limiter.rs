@@ -0,0 +1,103 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+use tokio::time::Instant;
+
+/// Tokens left to a client and when they were last refilled.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+  tokens: f64,
+  last_refill: Instant,
+}
+
+/// Limits how many events each client (by its address) can send to the relay.
+///
+/// Every client has a bucket holding up to `burst` tokens, refilled at
+/// `events_per_sec` tokens per second. Each event takes one token and,
+/// when there are none left, the event is refused.
+///
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+  events_per_sec: f64,
+  burst: f64,
+  buckets: HashMap<SocketAddr, TokenBucket>,
+}
+
+impl RateLimiter {
+  pub fn new(events_per_sec: f64, burst: u32) -> Self {
+    Self {
+      events_per_sec,
+      burst: f64::from(burst),
+      buckets: HashMap::new(),
+    }
+  }
+
+  /// Takes a token from the bucket of `addr` (which starts full).
+  ///
+  /// Returns `false`, without taking anything, if the bucket is empty.
+  ///
+  pub fn try_acquire(&mut self, addr: SocketAddr, now: Instant) -> bool {
+    let burst = self.burst;
+    let bucket = self.buckets.entry(addr).or_insert(TokenBucket {
+      tokens: burst,
+      last_refill: now,
+    });
+
+    let elapsed = now.saturating_duration_since(bucket.last_refill);
+    bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.events_per_sec).min(burst);
+    bucket.last_refill = now;
+
+    if bucket.tokens < 1.0 {
+      return false;
+    }
+    bucket.tokens -= 1.0;
+    true
+  }
+
+  /// Forgets the bucket of `addr`, e.g.: when the client disconnects.
+  pub fn remove(&mut self, addr: &SocketAddr) {
+    self.buckets.remove(addr);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::{IpAddr, Ipv4Addr};
+
+  use tokio::time::Duration;
+
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn try_acquire_allows_a_burst_and_then_the_rate() {
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+    let another_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081);
+    let mut rate_limiter = RateLimiter::new(2.0, 3);
+    let now = Instant::now();
+
+    let burst: Vec<bool> = (0..4)
+      .map(|_| rate_limiter.try_acquire(addr, now))
+      .collect();
+    assert_eq!(burst, vec![true, true, true, false]);
+    // the buckets are per client
+    assert!(rate_limiter.try_acquire(another_addr, now));
+
+    // 2 events per second: one token every half a second
+    let later = now + Duration::from_millis(500);
+    assert!(rate_limiter.try_acquire(addr, later));
+    assert!(!rate_limiter.try_acquire(addr, later));
+
+    // it never holds more than `burst` tokens
+    let much_later = later + Duration::from_secs(60);
+    let burst: Vec<bool> = (0..4)
+      .map(|_| rate_limiter.try_acquire(addr, much_later))
+      .collect();
+    assert_eq!(burst, vec![true, true, true, false]);
+
+    // a client that reconnects starts with a full bucket
+    rate_limiter.remove(&addr);
+    assert!(rate_limiter.try_acquire(addr, much_later));
+  }
+}
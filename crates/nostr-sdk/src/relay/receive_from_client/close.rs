@@ -33,9 +33,9 @@ mod tests {
     sync::{Arc, Mutex},
   };
 
-  use crate::filter::Filter;
+  use crate::filter::{Filter, IdMatching};
 
-  use crate::relay::{ClientRequests, Tx};
+  use crate::relay::{ClientRequests, Tx, DEFAULT_CLIENT_CHANNEL_CAPACITY};
 
   use super::*;
 
@@ -59,7 +59,7 @@ mod tests {
 
       let mock_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
 
-      let (mock_tx, _rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
+      let (mock_tx, _rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
 
       Self {
         mock_addr,
@@ -77,10 +77,15 @@ mod tests {
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![ClientRequests {
         subscription_id: mock.mock_subscription_id.clone(),
         filters: vec![Filter::default()],
+        remaining_limit: None,
+        id_matching: IdMatching::Prefix,
       }],
+      disconnect: Arc::default(),
     });
     let another_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081);
 
@@ -97,10 +102,15 @@ mod tests {
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![ClientRequests {
         subscription_id: "another_subs_id".to_string(),
         filters: vec![Filter::default()],
+        remaining_limit: None,
+        id_matching: IdMatching::Prefix,
       }],
+      disconnect: Arc::default(),
     });
 
     on_close_message(mock.mock_subscription_id, &mut clients, mock.mock_addr);
@@ -116,10 +126,15 @@ mod tests {
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![ClientRequests {
         subscription_id: mock.mock_subscription_id.clone(),
         filters: vec![Filter::default()],
+        remaining_limit: None,
+        id_matching: IdMatching::Prefix,
       }],
+      disconnect: Arc::default(),
     });
 
     on_close_message(mock.mock_subscription_id, &mut clients, mock.mock_addr);
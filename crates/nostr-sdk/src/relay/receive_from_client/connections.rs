@@ -0,0 +1,225 @@
+use std::{env, sync::MutexGuard};
+
+use crate::{
+  client::communication_with_relay::connections::CONNECTIONS_ADMIN_KIND,
+  event::{kind::EventKind, Event, PubKey, Timestamp},
+  relay::{
+    communication_with_client::connections::{ConnectionSummary, RelayToClientCommConnections},
+    ClientConnectionInfo,
+  },
+};
+
+/// How many seconds, before or after the relay clock, the `created_at` of the
+/// admin event of a `CONNECTIONS` command can be. Beyond that it is refused,
+/// so a leaked admin event stops being usable soon after it was made.
+///
+const CONNECTIONS_ADMIN_WINDOW_SECS: u64 = 60;
+
+/// [`on_connections_message`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+  #[error("restricted: not authorized")]
+  Unauthorized,
+}
+
+/// Gets the (x-only, hex-encoded) pubkey of the relay admin
+/// from the `RELAY_ADMIN_PUBKEY` env var. If it is not set,
+/// admin commands are disabled.
+///
+pub(crate) fn get_admin_pubkey() -> Option<PubKey> {
+  env::var("RELAY_ADMIN_PUBKEY")
+    .ok()
+    .filter(|pubkey| !pubkey.is_empty())
+}
+
+/// Checks if `admin_event` is a `CONNECTIONS` admin event (see
+/// [`CONNECTIONS_ADMIN_KIND`]) validly signed by the admin and created
+/// at most [`CONNECTIONS_ADMIN_WINDOW_SECS`] away from `now`.
+///
+fn is_admin_event(admin_event: &Event, admin_pubkey: &str, now: Timestamp) -> bool {
+  admin_event.kind == EventKind::Custom(CONNECTIONS_ADMIN_KIND)
+    && admin_event.content == "CONNECTIONS"
+    && admin_event.pubkey == admin_pubkey
+    && admin_event.created_at.abs_diff(now) <= CONNECTIONS_ADMIN_WINDOW_SECS
+    && admin_event.verify().is_ok()
+}
+
+/// Answers the admin `CONNECTIONS` command with a summary of the connected clients.
+///
+/// Fails if there is no admin configured or if `admin_event` was not
+/// made by it, recently (relative to `now`).
+///
+pub fn on_connections_message(
+  admin_event: &Event,
+  admin_pubkey: Option<PubKey>,
+  clients: &MutexGuard<Vec<ClientConnectionInfo>>,
+  now: Timestamp,
+) -> Result<RelayToClientCommConnections, Error> {
+  match admin_pubkey {
+    Some(admin_pubkey) if is_admin_event(admin_event, &admin_pubkey, now) => {}
+    _ => return Err(Error::Unauthorized),
+  }
+
+  let connections = clients
+    .iter()
+    .map(|client| ConnectionSummary {
+      subscriptions: client.requests.len(),
+      pubkey: client.authenticated_pubkey.clone(),
+      user_agent: client.user_agent.clone(),
+    })
+    .collect();
+
+  Ok(RelayToClientCommConnections::new_connections(connections))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+  };
+
+  use serde_json::json;
+  use tokio_tungstenite::tungstenite::Message;
+
+  use crate::{
+    client::communication_with_relay::connections::ClientToRelayCommConnections,
+    filter::{Filter, IdMatching},
+    relay::{ClientRequests, DEFAULT_CLIENT_CHANNEL_CAPACITY},
+    schnorr::{generate_keys, normalize_pubkey, AsymmetricKeys},
+  };
+
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  struct ConnectionsSut {
+    mock_clients: Arc<Mutex<Vec<ClientConnectionInfo>>>,
+    mock_admin_keys: AsymmetricKeys,
+    mock_admin_pubkey: PubKey,
+  }
+
+  impl ConnectionsSut {
+    fn new() -> Self {
+      let (tx, _rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
+      let mock_clients = Arc::new(Mutex::new(vec![
+        ClientConnectionInfo {
+          tx: tx.clone(),
+          socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
+          user_agent: Some(String::from("potato/1.0")),
+          authenticated_pubkey: None,
+          requests: vec![
+            ClientRequests {
+              subscription_id: String::from("sub1"),
+              filters: vec![Filter::default()],
+              remaining_limit: None,
+              id_matching: IdMatching::Prefix,
+            },
+            ClientRequests {
+              subscription_id: String::from("sub2"),
+              filters: vec![Filter::default()],
+              remaining_limit: None,
+              id_matching: IdMatching::Prefix,
+            },
+          ],
+          disconnect: Arc::default(),
+        },
+        ClientConnectionInfo {
+          tx,
+          socket_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 9090),
+          user_agent: None,
+          authenticated_pubkey: Some(String::from("mock_pubkey")),
+          requests: vec![],
+          disconnect: Arc::default(),
+        },
+      ]));
+
+      let mock_admin_keys = generate_keys();
+      let mock_admin_pubkey = normalize_pubkey(&mock_admin_keys.public_key.to_string()).unwrap();
+
+      Self {
+        mock_clients,
+        mock_admin_keys,
+        mock_admin_pubkey,
+      }
+    }
+  }
+
+  #[test]
+  fn on_connections_message_returns_a_summary_of_the_clients() {
+    let sut = ConnectionsSut::new();
+    let admin_event = ClientToRelayCommConnections::signed(&sut.mock_admin_keys).admin_event;
+
+    let result = on_connections_message(
+      &admin_event,
+      Some(sut.mock_admin_pubkey.clone()),
+      &sut.mock_clients.lock().unwrap(),
+      admin_event.created_at,
+    )
+    .unwrap();
+
+    let expected = json!([
+      "CONNECTIONS",
+      {
+        "count": 2,
+        "connections": [
+          {"subscriptions": 2, "pubkey": null, "user_agent": "potato/1.0"},
+          {"subscriptions": 0, "pubkey": "mock_pubkey", "user_agent": null},
+        ],
+      }
+    ]);
+
+    assert_eq!(result.as_value(), expected);
+    // raw IPs are never exposed
+    assert!(!result.as_json().contains("127.0.0.1"));
+    assert!(!result.as_json().contains("10.0.0.2"));
+  }
+
+  #[test]
+  fn on_connections_message_requires_a_recent_admin_event() {
+    let sut = ConnectionsSut::new();
+    let clients = sut.mock_clients.lock().unwrap();
+    let not_admin_event = ClientToRelayCommConnections::signed(&generate_keys()).admin_event;
+    let admin_event = ClientToRelayCommConnections::signed(&sut.mock_admin_keys).admin_event;
+    let now = admin_event.created_at;
+    let other_kind_event = crate::event::build_event(
+      &sut.mock_admin_keys,
+      EventKind::Text,
+      String::from("CONNECTIONS"),
+      vec![],
+    );
+    let admin_pubkey = Some(sut.mock_admin_pubkey.clone());
+
+    assert_eq!(
+      on_connections_message(&not_admin_event, admin_pubkey.clone(), &clients, now),
+      Err(Error::Unauthorized)
+    );
+    assert_eq!(
+      on_connections_message(&other_kind_event, admin_pubkey.clone(), &clients, now),
+      Err(Error::Unauthorized)
+    );
+    // replayed after the window
+    assert_eq!(
+      on_connections_message(
+        &admin_event,
+        admin_pubkey.clone(),
+        &clients,
+        now + CONNECTIONS_ADMIN_WINDOW_SECS + 1
+      ),
+      Err(Error::Unauthorized)
+    );
+    assert!(on_connections_message(
+      &admin_event,
+      admin_pubkey,
+      &clients,
+      now + CONNECTIONS_ADMIN_WINDOW_SECS
+    )
+    .is_ok());
+    // no admin configured
+    assert_eq!(
+      on_connections_message(&admin_event, None, &clients, now),
+      Err(Error::Unauthorized)
+    );
+  }
+}
@@ -1,49 +1,231 @@
-use std::sync::MutexGuard;
+use std::sync::{Arc, MutexGuard};
 
 use crate::{
-  client::communication_with_relay::check_event_match_filter, event::Event,
-  relay::communication_with_client::event::RelayToClientCommEvent,
+  event::{kind::EventKind, tag::Tag, Event, Timestamp},
+  filter::{Filter, IdMatching},
+  relay::{communication_with_client::event::RelayToClientCommEvent, events_index::EventsIndex},
 };
 
 use crate::relay::{
-  ClientConnectionInfo,
-  send_to_client::OutboundInfo
+  receive_from_client::request::limit_reached_closed, send_to_client::OutboundInfo,
+  ClientConnectionInfo, Tx,
 };
 
+/// Checks if the event was created more than `max_age` seconds before `now`.
+/// Without a `max_age`, events of any age are accepted.
+///
+pub fn is_event_too_old(event: &Event, now: Timestamp, max_age: Option<u64>) -> bool {
+  match max_age {
+    Some(max_age) => event.created_at < now.saturating_sub(max_age),
+    None => false,
+  }
+}
+
+/// Checks if the event was created more than `max_drift` seconds after `now`
+/// (e.g.: by a client with a wrong clock).
+///
+pub fn is_event_too_far_in_the_future(event: &Event, now: Timestamp, max_drift: u64) -> bool {
+  event.created_at > now.saturating_add(max_drift)
+}
+
+/// Checks if the serialized event has more than `max_size` bytes.
+pub fn is_event_too_large(event: &Event, max_size: usize) -> bool {
+  event.as_json().len() > max_size
+}
+
+/// Checks if an `incoming` replaceable event should replace the `stored` one.
+/// Only a newer event replaces the stored one. If both have the same
+/// timestamp, the one with the lowest id is kept.
+///
+pub fn should_replace(stored: &Event, incoming: &Event) -> bool {
+  incoming.created_at > stored.created_at
+    || (incoming.created_at == stored.created_at && incoming.id < stored.id)
+}
+
+/// Checks if the `stored` and `incoming` events are versions of the same replaceable
+/// event: same author and replaceable kind (NIP-16) or, for parameterized replaceable
+/// kinds, same author, kind and `"d"` tag (NIP-33).
+///
+pub fn is_same_replaceable_event(stored: &Event, incoming: &Event) -> bool {
+  if stored.pubkey != incoming.pubkey || stored.kind != incoming.kind {
+    return false;
+  }
+
+  if incoming.kind.is_parameterized_replaceable() {
+    return stored.identifier() == incoming.identifier();
+  }
+  incoming.kind.is_replaceable()
+}
+
+/// Handles an incoming event of a replaceable (NIP-16) or parameterized replaceable
+/// (NIP-33) kind, removing from `events` the stored event it replaces, if any.
+///
+/// Returns `false` when a newer version of the event is already
+/// stored, meaning the incoming one is stale and must not be stored.
+///
+pub fn on_replaceable_event(
+  event: &Event,
+  events: &mut Vec<Arc<Event>>,
+  events_index: &mut EventsIndex,
+) -> bool {
+  if !event.kind.is_replaceable() && !event.kind.is_parameterized_replaceable() {
+    return true;
+  }
+
+  let stored_position = events
+    .iter()
+    .position(|stored| is_same_replaceable_event(stored, event));
+
+  match stored_position {
+    Some(position) => {
+      if !should_replace(&events[position], event) {
+        return false;
+      }
+      events.remove(position);
+      events_index.rebuild(events);
+      true
+    }
+    None => true,
+  }
+}
+
+/// Handles an incoming deletion event (NIP-09), removing from `events`
+/// the events referenced by its `"e"` tags that have the same author.
+/// Deletion events themselves cannot be deleted.
+///
+/// Returns the ids of the events removed.
+///
+pub fn on_deletion_event(
+  event: &Event,
+  events: &mut Vec<Arc<Event>>,
+  events_index: &mut EventsIndex,
+) -> Vec<String> {
+  if event.kind != EventKind::Deletion {
+    return vec![];
+  }
+
+  let targets: Vec<&str> = event
+    .tags
+    .iter()
+    .filter_map(|tag| match tag {
+      Tag::Event(event_id, _, _) => Some(event_id.0.as_str()),
+      _ => None,
+    })
+    .collect();
+
+  let mut deleted = vec![];
+  events.retain(|stored| {
+    let is_deleted = stored.kind != EventKind::Deletion
+      && stored.pubkey == event.pubkey
+      && targets.contains(&stored.id.as_str());
+    if is_deleted {
+      deleted.push(stored.id.clone());
+    }
+    !is_deleted
+  });
+
+  if !deleted.is_empty() {
+    events_index.rebuild(events);
+  }
+
+  deleted
+}
+
+/// Checks if a deletion event (NIP-09) of the same author, stored
+/// before `event` arrived, asks for `event` to be deleted.
+///
+pub fn is_deleted_by_prior_request(
+  event: &Event,
+  events: &[Arc<Event>],
+  events_index: &EventsIndex,
+) -> bool {
+  if event.kind == EventKind::Deletion {
+    return false;
+  }
+
+  let prior_deletions = Filter {
+    authors: Some(vec![event.pubkey.clone()]),
+    kinds: Some(vec![EventKind::Deletion]),
+    e: Some(vec![event.id.clone()]),
+    ..Default::default()
+  };
+
+  !events_index
+    .matching_events(events, &prior_deletions, IdMatching::Exact)
+    .is_empty()
+}
+
+/// Gets the clients whose subscriptions match the event, grouped
+/// by subscription id: the `EVENT` message only depends on it,
+/// so it is built once for each group, from the event serialized
+/// only once for all of them.
+///
+/// Each event sent counts towards the `limit` of the subscription,
+/// which is closed once it is reached: its `CLOSED` is returned
+/// after the `EVENT` messages.
+///
 pub fn on_event_message(
-  event: Event,
+  event: &Event,
   clients: &mut MutexGuard<Vec<ClientConnectionInfo>>,
 ) -> Vec<OutboundInfo> {
-  let mut outbound_client_and_message: Vec<OutboundInfo> = vec![];
+  let mut txs_by_subscription_id: Vec<(String, Vec<Tx>)> = vec![];
+  let mut closed: Vec<OutboundInfo> = vec![];
 
   // when an `event` message is received, it's because we are already connected to the client and, therefore,
   // we have its data stored in `clients`, so NO need to verify if he exists
   for client in clients.iter_mut() {
     // Check filters
-    'outer: for client_req in client.requests.iter() {
+    'outer: for client_req in client.requests.iter_mut() {
       for filter in client_req.filters.iter() {
-        if check_event_match_filter(event.clone(), filter.clone()) {
-          outbound_client_and_message.push(OutboundInfo {
-            tx: client.tx.clone(),
-            content: RelayToClientCommEvent {
-              subscription_id: client_req.subscription_id.clone(),
-              event: event.clone(),
-              ..Default::default()
-            }
-            .as_json(),
-          });
+        if filter.matches_with(event, client_req.id_matching) {
+          client_req.remaining_limit = client_req
+            .remaining_limit
+            .map(|remaining| remaining.saturating_sub(1));
+          match txs_by_subscription_id
+            .iter_mut()
+            .find(|(subscription_id, _)| *subscription_id == client_req.subscription_id)
+          {
+            Some((_, txs)) => txs.push(client.tx.clone()),
+            None => txs_by_subscription_id
+              .push((client_req.subscription_id.clone(), vec![client.tx.clone()])),
+          }
           // I can break from going through client requests
           // because I have already found that this client requests
           // this event, therefore after adding him to the
-          // `outbound_client_and_message` array, I can go
+          // `txs_by_subscription_id` array, I can go
           // to the next one.
           break 'outer;
         }
       }
     }
+    // the subscriptions that reached their limit are closed
+    client.requests.retain(|client_req| {
+      let reached = client_req.remaining_limit == Some(0);
+      if reached {
+        closed.push((
+          vec![client.tx.clone()],
+          limit_reached_closed(&client_req.subscription_id),
+        ));
+      }
+      !reached
+    });
   }
 
-  outbound_client_and_message
+  if txs_by_subscription_id.is_empty() {
+    return vec![];
+  }
+
+  let event_json = event.as_value().to_string();
+  txs_by_subscription_id
+    .into_iter()
+    .map(|(subscription_id, txs)| {
+      (
+        txs,
+        RelayToClientCommEvent::json_with_event_json(&subscription_id, &event_json),
+      )
+    })
+    .chain(closed)
+    .collect()
 }
 
 #[cfg(test)]
@@ -54,11 +236,12 @@ mod tests {
   };
 
   use crate::{
-    client::communication_with_relay::request::ClientToRelayCommRequest, event::id::EventId,
+    client::communication_with_relay::request::ClientToRelayCommRequest,
+    event::{id::EventId, kind::EventKind},
     filter::Filter,
   };
 
-  use crate::relay::{ClientRequests, Tx};
+  use crate::relay::{ClientRequests, Tx, DEFAULT_CLIENT_CHANNEL_CAPACITY};
 
   use super::*;
 
@@ -90,9 +273,16 @@ mod tests {
         kinds: None,
         e: None,
         p: None,
+        t: None,
+        a: None,
         since: None,
         until: None,
         limit: None,
+        search: None,
+        #[cfg(feature = "filter-exclusions")]
+        exclude_authors: None,
+        #[cfg(feature = "filter-exclusions")]
+        exclude_kinds: None,
       };
 
       let mock_client_request = ClientToRelayCommRequest {
@@ -104,7 +294,7 @@ mod tests {
       let mock_event = Self::mock_event(mock_filter_id);
 
       let mock_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
-      let (mock_tx, _rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
+      let (mock_tx, _rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
 
       Self {
         mock_addr,
@@ -124,12 +314,187 @@ mod tests {
     }
   }
 
+  fn mock_metadata_event(id: &str, created_at: u64) -> Event {
+    Event {
+      id: id.to_string(),
+      pubkey: String::from("614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6"),
+      kind: EventKind::Metadata,
+      created_at,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_is_event_too_old() {
+    let thirty_days = 30 * 24 * 60 * 60;
+    let now = 1684589418;
+    let old_event = mock_metadata_event("old", now - thirty_days - 1);
+    let recent_event = mock_metadata_event("recent", now - 60);
+
+    assert!(is_event_too_old(&old_event, now, Some(thirty_days)));
+    assert_eq!(
+      is_event_too_old(&recent_event, now, Some(thirty_days)),
+      false
+    );
+    assert_eq!(is_event_too_old(&old_event, now, None), false);
+  }
+
+  #[test]
+  fn test_is_event_too_far_in_the_future() {
+    let fifteen_minutes = 15 * 60;
+    let now = 1684589418;
+    let future_event = mock_metadata_event("future", now + 60 * 60);
+    let in_window_event = mock_metadata_event("in_window", now + 60);
+
+    assert!(is_event_too_far_in_the_future(
+      &future_event,
+      now,
+      fifteen_minutes
+    ));
+    assert_eq!(
+      is_event_too_far_in_the_future(&in_window_event, now, fifteen_minutes),
+      false
+    );
+  }
+
+  #[test]
+  fn test_is_event_too_large() {
+    let event = Event {
+      content: "a".repeat(100),
+      ..Default::default()
+    };
+    let size = event.as_json().len();
+
+    assert!(is_event_too_large(&event, size - 1));
+    assert_eq!(is_event_too_large(&event, size), false);
+  }
+
+  #[test]
+  fn test_should_replace() {
+    let stored = mock_metadata_event("b", 10);
+
+    assert!(should_replace(&stored, &mock_metadata_event("c", 11)));
+    assert!(should_replace(&stored, &mock_metadata_event("a", 10)));
+    assert_eq!(should_replace(&stored, &mock_metadata_event("c", 10)), false);
+    assert_eq!(should_replace(&stored, &mock_metadata_event("a", 9)), false);
+  }
+
+  #[test]
+  fn test_on_replaceable_event_does_not_replace_newer_stored_event() {
+    let newer = mock_metadata_event("newer", 20);
+    let older = mock_metadata_event("older", 10);
+    let mut events = vec![Arc::new(newer.clone())];
+    let mut events_index = EventsIndex::new(&events);
+
+    let should_store = on_replaceable_event(&older, &mut events, &mut events_index);
+
+    assert_eq!(should_store, false);
+    assert_eq!(events, vec![Arc::new(newer)]);
+    assert_eq!(events_index, EventsIndex::new(&events));
+  }
+
+  #[test]
+  fn test_on_replaceable_event_removes_older_stored_event() {
+    let text_event = EvtSut::mock_event(String::from("text"));
+    let older = mock_metadata_event("older", 10);
+    let newer = mock_metadata_event("newer", 20);
+    let mut events = vec![Arc::new(older), Arc::new(text_event.clone())];
+    let mut events_index = EventsIndex::new(&events);
+
+    let should_store = on_replaceable_event(&newer, &mut events, &mut events_index);
+
+    assert!(should_store);
+    assert_eq!(events, vec![Arc::new(text_event)]);
+    assert_eq!(events_index, EventsIndex::new(&events));
+  }
+
+  fn mock_deletion_event(id: &str, pubkey: &str, targets: &[&str]) -> Event {
+    Event {
+      id: id.to_string(),
+      pubkey: pubkey.to_string(),
+      kind: EventKind::Deletion,
+      tags: targets
+        .iter()
+        .map(|target| Tag::Event(EventId(target.to_string()), None, None))
+        .collect(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_on_deletion_event_only_removes_events_of_the_same_author() {
+    let author = String::from("author");
+    let own_event = Event {
+      id: String::from("own"),
+      pubkey: author.clone(),
+      ..Default::default()
+    };
+    let others_event = Event {
+      id: String::from("others"),
+      pubkey: String::from("someone_else"),
+      ..Default::default()
+    };
+    let prior_deletion = mock_deletion_event("prior_deletion", &author, &[]);
+    let mut events = vec![
+      Arc::new(own_event),
+      Arc::new(others_event.clone()),
+      Arc::new(prior_deletion.clone()),
+    ];
+    let mut events_index = EventsIndex::new(&events);
+
+    let deletion = mock_deletion_event("deletion", &author, &["own", "others", "prior_deletion"]);
+    let deleted = on_deletion_event(&deletion, &mut events, &mut events_index);
+
+    assert_eq!(deleted, vec![String::from("own")]);
+    assert_eq!(
+      events,
+      vec![Arc::new(others_event), Arc::new(prior_deletion)]
+    );
+    assert_eq!(events_index, EventsIndex::new(&events));
+  }
+
+  #[test]
+  fn test_is_deleted_by_prior_request() {
+    let author = make_author();
+    let deletion = mock_deletion_event("deletion", &author, &["target"]);
+    let events = vec![Arc::new(deletion)];
+    let events_index = EventsIndex::new(&events);
+
+    let target = Event {
+      id: String::from("target"),
+      pubkey: author.clone(),
+      ..Default::default()
+    };
+    let same_id_other_author = Event {
+      pubkey: String::from("someone_else"),
+      ..target.clone()
+    };
+    let not_targeted = Event {
+      id: String::from("not_targeted"),
+      ..target.clone()
+    };
+
+    assert!(is_deleted_by_prior_request(&target, &events, &events_index));
+    assert_eq!(
+      is_deleted_by_prior_request(&same_id_other_author, &events, &events_index),
+      false
+    );
+    assert_eq!(
+      is_deleted_by_prior_request(&not_targeted, &events, &events_index),
+      false
+    );
+  }
+
+  fn make_author() -> String {
+    String::from("614a695bab54e8dc98946abdb8ec019599ece6dada0c23890977d0fa128081d6")
+  }
+
   #[test]
   fn test_on_event_message_returns_empty_array_when_no_event_match() {
     let mock = EvtSut::new();
     let mut clients = mock.mock_clients.lock().unwrap();
 
-    let outbound_client_and_message = on_event_message(mock.mock_event.clone(), &mut clients);
+    let outbound_client_and_message = on_event_message(&mock.mock_event, &mut clients);
 
     assert_eq!(outbound_client_and_message.len(), 0);
   }
@@ -141,13 +506,18 @@ mod tests {
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![ClientRequests {
         subscription_id: mock.mock_client_request.subscription_id.clone(),
         filters: mock.mock_client_request.filters,
+        remaining_limit: None,
+        id_matching: IdMatching::Prefix,
       }],
+      disconnect: Arc::default(),
     });
 
-    let outbound_client_and_message = on_event_message(mock.mock_event.clone(), &mut clients);
+    let outbound_client_and_message = on_event_message(&mock.mock_event, &mut clients);
 
     assert_eq!(outbound_client_and_message.len(), 1);
   }
@@ -159,14 +529,161 @@ mod tests {
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![ClientRequests {
         subscription_id: mock.mock_client_request.subscription_id.clone(),
         filters: vec![vec![mock.mock_filter], mock.mock_client_request.filters].concat(),
+        remaining_limit: None,
+        id_matching: IdMatching::Prefix,
       }],
+      disconnect: Arc::default(),
     });
 
-    let outbound_client_and_message = on_event_message(mock.mock_event.clone(), &mut clients);
+    let outbound_client_and_message = on_event_message(&mock.mock_event, &mut clients);
 
     assert_eq!(outbound_client_and_message.len(), 1);
   }
+
+  #[test]
+  fn test_on_event_message_serializes_the_content_once_for_each_subscription() {
+    let mock = EvtSut::new();
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut subscriber = |subscription_id: &str| {
+      let (tx, rx) = tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
+      clients.push(ClientConnectionInfo {
+        tx,
+        socket_addr: mock.mock_addr,
+        user_agent: None,
+        authenticated_pubkey: None,
+        requests: vec![ClientRequests {
+          subscription_id: subscription_id.to_string(),
+          filters: vec![mock.mock_filter.clone()],
+          remaining_limit: None,
+          id_matching: IdMatching::Prefix,
+        }],
+        disconnect: Arc::default(),
+      });
+      rx
+    };
+    let mut receivers = [subscriber("feed"), subscriber("feed"), subscriber("other")];
+
+    let outbound_client_and_message = on_event_message(&mock.mock_event, &mut clients);
+
+    // one serialized content for each subscription id
+    assert_eq!(outbound_client_and_message.len(), 2);
+    let (feed_txs, feed_content) = &outbound_client_and_message[0];
+    assert_eq!(feed_txs.len(), 2);
+    assert_eq!(
+      *feed_content,
+      RelayToClientCommEvent {
+        subscription_id: String::from("feed"),
+        event: mock.mock_event.clone(),
+        ..Default::default()
+      }
+      .as_json()
+    );
+
+    for outbound in outbound_client_and_message.clone() {
+      crate::relay::send_to_client::broadcast_message_to_clients(outbound);
+    }
+    let received: Vec<String> = receivers
+      .iter_mut()
+      .map(|rx| rx.try_recv().unwrap().to_string())
+      .collect();
+    assert_eq!(received[0], *feed_content);
+    assert_eq!(received[1], *feed_content);
+    assert_eq!(received[2], outbound_client_and_message[1].1);
+  }
+
+  #[test]
+  fn test_on_event_message_stops_sending_once_the_limit_is_reached() {
+    let mock = EvtSut::new();
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let filter = Filter {
+      kinds: Some(vec![EventKind::Metadata]),
+      limit: Some(2),
+      ..Default::default()
+    };
+    let stored_events = std::sync::Mutex::new(vec![Arc::new(mock_metadata_event("stored", 1))]);
+    let stored_events = stored_events.lock().unwrap();
+    let events_index = std::sync::Mutex::new(EventsIndex::new(&stored_events));
+    let events_index = events_index.lock().unwrap();
+
+    let sent_on_request = crate::relay::receive_from_client::request::on_request_message(
+      String::from("limited"),
+      vec![filter],
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx.clone(),
+      &stored_events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+    assert_eq!(sent_on_request.len(), 1);
+    assert_eq!(clients[0].requests[0].remaining_limit, Some(1));
+
+    let delivered: Vec<Vec<String>> = (0..4)
+      .map(|i| {
+        let event = mock_metadata_event(&format!("live{i}"), 2 + i);
+        on_event_message(&event, &mut clients)
+          .into_iter()
+          .map(|(_, content)| content)
+          .collect()
+      })
+      .collect();
+
+    // the stored event and the first live one reach the limit of 2,
+    // so the subscription is closed after the live one
+    assert_eq!(delivered[0].len(), 2);
+    assert_eq!(delivered[0][1], limit_reached_closed("limited"));
+    assert_eq!(delivered[1..], vec![Vec::<String>::new(); 3]);
+    assert_eq!(clients[0].requests, vec![]);
+  }
+
+  #[test]
+  fn test_on_event_message_after_the_stored_events_reached_the_limit() {
+    let mock = EvtSut::new();
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let filter = Filter {
+      kinds: Some(vec![EventKind::Metadata]),
+      limit: Some(2),
+      ..Default::default()
+    };
+    let stored_events = std::sync::Mutex::new(
+      (0..3)
+        .map(|i| Arc::new(mock_metadata_event(&format!("stored{i}"), i)))
+        .collect::<Vec<_>>(),
+    );
+    let stored_events = stored_events.lock().unwrap();
+    let events_index = std::sync::Mutex::new(EventsIndex::new(&stored_events));
+    let events_index = events_index.lock().unwrap();
+
+    let sent_on_request = crate::relay::receive_from_client::request::on_request_message(
+      String::from("limited"),
+      vec![filter],
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx.clone(),
+      &stored_events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+
+    // the stored events already reach the limit, so the subscription is closed
+    assert_eq!(sent_on_request.len(), 2);
+    assert_eq!(
+      crate::relay::receive_from_client::request::is_subscription_open(
+        &clients,
+        mock.mock_addr,
+        "limited"
+      ),
+      false
+    );
+
+    let live_event = mock_metadata_event("live", 10);
+    assert_eq!(on_event_message(&live_event, &mut clients).len(), 0);
+  }
 }
@@ -1,3 +1,4 @@
 pub mod request;
 pub mod event;
-pub mod close;
\ No newline at end of file
+pub mod close;
+pub mod connections;
\ No newline at end of file
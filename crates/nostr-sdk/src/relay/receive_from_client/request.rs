@@ -1,27 +1,146 @@
-use std::{net::SocketAddr, sync::MutexGuard, vec};
+use std::{
+  cmp::Reverse,
+  env,
+  net::SocketAddr,
+  sync::{Arc, MutexGuard},
+  vec,
+};
+
+use log::error;
 
 use crate::{
-  client::communication_with_relay::check_event_match_filter, event::Event, filter::Filter,
-  relay::communication_with_client::event::RelayToClientCommEvent,
+  event::Event,
+  filter::{Filter, IdMatching},
+  relay::{
+    communication_with_client::{
+      closed::RelayToClientCommClosed, event::RelayToClientCommEvent,
+      notice::RelayToClientCommNotice,
+    },
+    events_index::EventsIndex,
+  },
+};
+
+use crate::relay::{
+  get_timestamp_in_seconds, send_to_client::send_message_to_client, ClientConnectionInfo,
+  ClientRequests, Tx,
 };
 
-use crate::relay::{ClientConnectionInfo, ClientRequests, Tx};
+/// Default maximum number of subscriptions a client can have open at the same time.
+const DEFAULT_MAX_SUBSCRIPTIONS_PER_CLIENT: usize = 20;
+
+/// Maximum number of characters of a `subscription_id`.
+const MAX_SUBSCRIPTION_ID_LENGTH: usize = 64;
+
+/// [`on_request_message`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+  #[error("too many subscriptions")]
+  TooManySubscriptions,
+  #[error("invalid: subscription id must have between 1 and 64 characters")]
+  InvalidSubscriptionId,
+}
+
+/// Message of the `NOTICE` sent when a filter has `since` after `until`.
+const INVALID_RANGE_NOTICE: &str = "invalid: filter has since after until";
+
+/// Message of the `CLOSED` sent when a subscription reaches its `limit`.
+const LIMIT_REACHED_MESSAGE: &str = "closed: limit reached";
+
+/// Gets the `CLOSED` sent when the subscription `subscription_id` reaches its `limit`.
+pub fn limit_reached_closed(subscription_id: &str) -> String {
+  RelayToClientCommClosed::new_closed(
+    subscription_id.to_string(),
+    String::from(LIMIT_REACHED_MESSAGE),
+  )
+  .as_json()
+}
+
+/// Checks if the client at `addr` has the subscription `subscription_id` open.
+pub fn is_subscription_open(
+  clients: &MutexGuard<Vec<ClientConnectionInfo>>,
+  addr: SocketAddr,
+  subscription_id: &str,
+) -> bool {
+  clients
+    .iter()
+    .filter(|client| client.socket_addr == addr)
+    .any(|client| {
+      client
+        .requests
+        .iter()
+        .any(|req| req.subscription_id == subscription_id)
+    })
+}
+
+/// Gets the maximum number of subscriptions per client
+/// from the `MAX_SUBSCRIPTIONS_PER_CLIENT` env var.
+///
+fn get_max_subscriptions_per_client() -> usize {
+  env::var("MAX_SUBSCRIPTIONS_PER_CLIENT")
+    .ok()
+    .and_then(|max| max.parse::<usize>().ok())
+    .unwrap_or(DEFAULT_MAX_SUBSCRIPTIONS_PER_CLIENT)
+}
+
+/// Checks if the `NOTICE` about filters with a reversed range (`since` after `until`)
+/// is disabled by setting the `RELAY_DISABLE_INVALID_FILTER_NOTICE` env var to `true`.
+///
+fn is_invalid_filter_notice_disabled() -> bool {
+  env::var("RELAY_DISABLE_INVALID_FILTER_NOTICE").is_ok_and(|disable| disable == "true")
+}
 
 /// Updates an already connected client -
 /// overwriting the filters if they have the same
 /// `subscription_id` or adding the new ones to the array -
 /// or create a new one with this request.
 ///
-/// Returns the saved events that match the requested filters.
+/// Returns the saved events that match the requested filters, newest first
+/// (by `created_at`). The `limit` of a filter keeps only its newest events.
+/// If the client already has the maximum number of subscriptions,
+/// a `CLOSED` is sent to it and the request is not added.
+///
+/// The stored events sent count towards the `limit` of the subscription,
+/// so the live events are only sent until it is reached. If they already
+/// reach it, the subscription is closed (see [`is_subscription_open`]) and
+/// the caller sends the [`limit_reached_closed`] after the stored events.
+///
+/// Filters with `since` after `until` never match any event. The client
+/// is warned about them with a `NOTICE`, unless it is disabled.
+///
+/// Requests with an empty `subscription_id`, or one longer than
+/// [`MAX_SUBSCRIPTION_ID_LENGTH`] characters, are refused with a `CLOSED`.
+///
+/// The `ids` of the filters are matched as set by `id_matching`.
 ///
+#[allow(clippy::too_many_arguments)]
 pub fn on_request_message(
   subscription_id: String,
   filters: Vec<Filter>,
   clients: &mut MutexGuard<Vec<ClientConnectionInfo>>,
   addr: SocketAddr,
   tx: Tx,
-  events: &MutexGuard<Vec<Event>>,
-) -> Vec<RelayToClientCommEvent> {
+  events: &MutexGuard<Vec<Arc<Event>>>,
+  events_index: &MutexGuard<EventsIndex>,
+  id_matching: IdMatching,
+) -> Result<Vec<RelayToClientCommEvent>, Error> {
+  if subscription_id.is_empty() || subscription_id.chars().count() > MAX_SUBSCRIPTION_ID_LENGTH {
+    let closed = RelayToClientCommClosed::new_closed(
+      subscription_id,
+      Error::InvalidSubscriptionId.to_string(),
+    );
+    if let Err(err) = send_message_to_client(tx, closed.as_json()) {
+      error!("Error sending the invalid subscription id CLOSED to {addr}: {err}");
+    }
+    return Err(Error::InvalidSubscriptionId);
+  }
+
+  if filters.iter().any(|filter| !filter.is_valid()) && !is_invalid_filter_notice_disabled() {
+    let notice = RelayToClientCommNotice::new_notice(String::from(INVALID_RANGE_NOTICE));
+    if let Err(err) = send_message_to_client(tx.clone(), notice.as_json()) {
+      error!("Error sending the invalid filter NOTICE to {addr}: {err}");
+    }
+  }
+
   // we need to do this because on the first time a client connects, it will send a `REQUEST` message
   // and we won't have it in our `clients` array yet.
   match clients.iter_mut().find(|client| client.socket_addr == addr) {
@@ -32,63 +151,99 @@ pub fn on_request_message(
         .iter_mut()
         .position(|req| req.subscription_id == subscription_id)
       {
-        Some(index) => client.requests[index].filters = filters.clone(), // overwrites filters
-        None => client.requests.push(ClientRequests {
+        // overwrites filters, restarting the count towards their limit
+        Some(index) => {
+          client.requests[index] =
+            ClientRequests::new(subscription_id.clone(), filters.clone(), id_matching)
+        }
+        None => {
+          if client.requests.len() >= get_max_subscriptions_per_client() {
+            let closed = RelayToClientCommClosed::new_closed(
+              subscription_id,
+              Error::TooManySubscriptions.to_string(),
+            );
+            if let Err(err) = send_message_to_client(client.tx.clone(), closed.as_json()) {
+              error!("Error sending the too many subscriptions CLOSED to {addr}: {err}");
+            }
+            return Err(Error::TooManySubscriptions);
+          }
+
           // adds new one to the array of requests of this connected client
-          subscription_id: subscription_id.clone(),
-          filters: filters.clone(),
-        }),
+          client.requests.push(ClientRequests::new(
+            subscription_id.clone(),
+            filters.clone(),
+            id_matching,
+          ))
+        }
       };
     }
     None => clients.push(ClientConnectionInfo {
       // creates a new client connection
       tx,
       socket_addr: addr,
-      requests: vec![ClientRequests {
-        subscription_id: subscription_id.clone(),
-        filters: filters.clone(),
-      }],
+      user_agent: None,
+      authenticated_pubkey: None,
+      requests: vec![ClientRequests::new(
+        subscription_id.clone(),
+        filters.clone(),
+        id_matching,
+      )],
+      disconnect: Arc::default(),
     }),
   };
 
   // Check all events from the database that match the requested filter
   let mut events_to_send_to_client_that_match_the_requested_filter: Vec<RelayToClientCommEvent> =
     vec![];
+  let now = get_timestamp_in_seconds();
 
-  for filter in filters.iter() {
+  for filter in filters.iter().filter(|filter| filter.is_valid()) {
     let mut events_added_for_this_filter: Vec<RelayToClientCommEvent> = vec![];
-    for event in events.iter() {
-      if check_event_match_filter(event.clone(), filter.clone()) {
-        events_added_for_this_filter.push(RelayToClientCommEvent {
-          subscription_id: subscription_id.clone(),
-          event: event.clone(),
-          ..Default::default()
-        });
+    for event in events_index.matching_events(events, filter, id_matching) {
+      // NIP-40: expired events must not be sent to clients
+      if event.is_expired(now) {
+        continue;
       }
+      events_added_for_this_filter.push(RelayToClientCommEvent {
+        subscription_id: subscription_id.clone(),
+        event: Event::clone(event),
+        ..Default::default()
+      });
     }
 
     // Put the newest events first
-    events_added_for_this_filter
-      .sort_by(|event1, event2| event2.event.created_at.cmp(&event1.event.created_at));
-
-    let events_added_length = events_added_for_this_filter.len();
-    if events_added_length != 0 {
-      // Check limit of the filter as the REQ message will only be called on the first time something is required.
-      if let Some(limit) = filter.limit {
-        let limit: usize = if (limit as usize) < events_added_length {
-          limit as usize
-        } else {
-          events_added_length - 1
-        };
-        // Get up to the limit defined by the filter
-        let slice = &events_added_for_this_filter.clone()[..limit];
-        events_added_for_this_filter = slice.to_vec();
-      }
+    events_added_for_this_filter.sort_by_key(|event| Reverse(event.event.created_at));
+
+    // Check limit of the filter as the REQ message will only be called on the first time something is required.
+    if let Some(limit) = filter.limit {
+      // Get up to the limit defined by the filter (the newest ones)
+      events_added_for_this_filter.truncate(limit as usize);
     }
     events_to_send_to_client_that_match_the_requested_filter.extend(events_added_for_this_filter);
   }
 
+  // the events of all filters are sent newest first too
   events_to_send_to_client_that_match_the_requested_filter
+    .sort_by_key(|event| Reverse(event.event.created_at));
+
+  // the stored events count towards the limit of the subscription
+  if let Some(client) = clients.iter_mut().find(|client| client.socket_addr == addr) {
+    let sent = events_to_send_to_client_that_match_the_requested_filter.len() as u64;
+    for request in client
+      .requests
+      .iter_mut()
+      .filter(|req| req.subscription_id == subscription_id)
+    {
+      request.remaining_limit = request
+        .remaining_limit
+        .map(|remaining| remaining.saturating_sub(sent));
+    }
+    client
+      .requests
+      .retain(|req| req.subscription_id != subscription_id || req.remaining_limit != Some(0));
+  }
+
+  Ok(events_to_send_to_client_that_match_the_requested_filter)
 }
 
 #[cfg(test)]
@@ -100,21 +255,30 @@ mod tests {
   };
 
   use crate::{
-    event::{id::EventId, Timestamp},
+    event::{
+      id::EventId,
+      kind::EventKind,
+      tag::{Tag, TagKind},
+      Timestamp,
+    },
     filter::Filter,
+    relay::DEFAULT_CLIENT_CHANNEL_CAPACITY,
   };
 
   use super::*;
 
   #[cfg(test)]
   use pretty_assertions::assert_eq;
+  use tokio::sync::mpsc::Receiver;
   use tokio_tungstenite::tungstenite::Message;
 
   struct ReqSut {
     mock_clients: Arc<Mutex<Vec<ClientConnectionInfo>>>,
     mock_addr: SocketAddr,
     mock_tx: Tx,
-    mock_events: Arc<Mutex<Vec<Event>>>,
+    mock_rx: Receiver<Message>,
+    mock_events: Arc<Mutex<Vec<Arc<Event>>>>,
+    mock_events_index: Arc<Mutex<EventsIndex>>,
     mock_event: Event,
     mock_relay_to_client_event: RelayToClientCommEvent,
     mock_filters: Vec<Filter>,
@@ -134,18 +298,27 @@ mod tests {
         kinds: None,
         e: None,
         p: None,
+        t: None,
+        a: None,
         since: None,
         until: None,
         limit: filter_limit,
+        search: None,
+        #[cfg(feature = "filter-exclusions")]
+        exclude_authors: None,
+        #[cfg(feature = "filter-exclusions")]
+        exclude_kinds: None,
       };
 
       let mock_subscription_id = String::from("potato");
 
       let mock_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
-      let (mock_tx, _rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
+      let (mock_tx, mock_rx) =
+        tokio::sync::mpsc::channel::<Message>(DEFAULT_CLIENT_CHANNEL_CAPACITY);
 
-      let empty_events: Vec<Event> = vec![];
+      let empty_events: Vec<Arc<Event>> = vec![];
       let mock_events = Arc::new(Mutex::new(empty_events));
+      let mock_events_index = Arc::new(Mutex::new(EventsIndex::default()));
 
       let mock_event = Self::mock_event(mock_filter_id);
 
@@ -161,7 +334,9 @@ mod tests {
         mock_addr,
         mock_clients,
         mock_events,
+        mock_events_index,
         mock_tx,
+        mock_rx,
         mock_event,
         mock_relay_to_client_event,
         mock_filters,
@@ -182,6 +357,7 @@ mod tests {
     let mock = ReqSut::new(None);
     let mut clients = mock.mock_clients.lock().unwrap();
     let events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
 
     let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
       mock.mock_subscription_id,
@@ -190,7 +366,10 @@ mod tests {
       mock.mock_addr,
       mock.mock_tx,
       &events,
-    );
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
 
     assert_eq!(
       events_to_send_to_client_that_match_the_requested_filter,
@@ -206,10 +385,14 @@ mod tests {
     let mock = ReqSut::new(None);
     let mut clients = mock.mock_clients.lock().unwrap();
     let events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
+      user_agent: None,
+      authenticated_pubkey: None,
       requests: vec![],
+      disconnect: Arc::default(),
     });
 
     let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
@@ -219,7 +402,10 @@ mod tests {
       mock.mock_addr,
       mock.mock_tx,
       &events,
-    );
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
 
     assert_eq!(
       events_to_send_to_client_that_match_the_requested_filter,
@@ -231,10 +417,11 @@ mod tests {
     assert_eq!(clients[0].requests.len(), 1);
     assert_eq!(
       clients[0].requests[0],
-      ClientRequests {
-        subscription_id: mock.mock_subscription_id,
-        filters: mock.mock_filters
-      }
+      ClientRequests::new(
+        mock.mock_subscription_id,
+        mock.mock_filters,
+        IdMatching::Prefix
+      )
     );
   }
 
@@ -243,13 +430,18 @@ mod tests {
     let mock = ReqSut::new(None);
     let mut clients = mock.mock_clients.lock().unwrap();
     let events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
     clients.push(ClientConnectionInfo {
       tx: mock.mock_tx.clone(),
       socket_addr: mock.mock_addr,
-      requests: vec![ClientRequests {
-        subscription_id: mock.mock_subscription_id.clone(),
-        filters: vec![Filter::default()],
-      }],
+      user_agent: None,
+      authenticated_pubkey: None,
+      requests: vec![ClientRequests::new(
+        mock.mock_subscription_id.clone(),
+        vec![Filter::default()],
+        IdMatching::Prefix,
+      )],
+      disconnect: Arc::default(),
     });
 
     let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
@@ -259,7 +451,10 @@ mod tests {
       mock.mock_addr,
       mock.mock_tx,
       &events,
-    );
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
 
     assert_eq!(
       events_to_send_to_client_that_match_the_requested_filter,
@@ -271,10 +466,11 @@ mod tests {
     assert_eq!(clients[0].requests.len(), 1);
     assert_eq!(
       clients[0].requests[0],
-      ClientRequests {
-        subscription_id: mock.mock_subscription_id,
-        filters: mock.mock_filters
-      }
+      ClientRequests::new(
+        mock.mock_subscription_id,
+        mock.mock_filters,
+        IdMatching::Prefix
+      )
     );
   }
 
@@ -283,7 +479,9 @@ mod tests {
     let mock = ReqSut::new(None);
     let mut clients = mock.mock_clients.lock().unwrap();
     let mut events = mock.mock_events.lock().unwrap();
-    events.push(mock.mock_event.clone());
+    events.push(Arc::new(mock.mock_event.clone()));
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
 
     let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
       mock.mock_subscription_id,
@@ -292,7 +490,10 @@ mod tests {
       mock.mock_addr,
       mock.mock_tx,
       &events,
-    );
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
 
     assert_eq!(
       events_to_send_to_client_that_match_the_requested_filter.len(),
@@ -311,10 +512,12 @@ mod tests {
     let mock = ReqSut::new(Some(3));
     let mut clients = mock.mock_clients.lock().unwrap();
     let mut events = mock.mock_events.lock().unwrap();
-    events.push(mock.mock_event.clone());
-    events.push(mock.mock_event.clone());
-    events.push(mock.mock_event.clone());
-    events.push(mock.mock_event.clone());
+    events.push(Arc::new(mock.mock_event.clone()));
+    events.push(Arc::new(mock.mock_event.clone()));
+    events.push(Arc::new(mock.mock_event.clone()));
+    events.push(Arc::new(mock.mock_event.clone()));
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
 
     let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
       mock.mock_subscription_id,
@@ -323,7 +526,10 @@ mod tests {
       mock.mock_addr,
       mock.mock_tx,
       &events,
-    );
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
 
     assert_eq!(
       events_to_send_to_client_that_match_the_requested_filter.len(),
@@ -340,4 +546,359 @@ mod tests {
     assert_eq!(clients.len(), 1);
     assert_eq!(clients[0].socket_addr, mock.mock_addr);
   }
+
+  #[test]
+  fn test_on_req_msg_keeps_open_the_subscriptions_of_live_events_only() {
+    let mock = ReqSut::new(Some(0));
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut events = mock.mock_events.lock().unwrap();
+    events.push(Arc::new(mock.mock_event.clone()));
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
+
+    let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
+      mock.mock_subscription_id.clone(),
+      mock.mock_filters,
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx,
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+
+    assert_eq!(
+      events_to_send_to_client_that_match_the_requested_filter,
+      vec![]
+    );
+    assert!(is_subscription_open(
+      &clients,
+      mock.mock_addr,
+      &mock.mock_subscription_id
+    ));
+  }
+
+  #[test]
+  fn test_on_req_msg_sends_the_newest_events_up_to_the_limit_newest_first() {
+    let mock = ReqSut::new(Some(3));
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut events = mock.mock_events.lock().unwrap();
+    for created_at in [30, 10, 50, 20, 40] {
+      let mut event = mock.mock_event.clone();
+      event.created_at = created_at;
+      events.push(Arc::new(event));
+    }
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
+
+    let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
+      mock.mock_subscription_id,
+      mock.mock_filters,
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx,
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+
+    let sent_created_at: Vec<Timestamp> = events_to_send_to_client_that_match_the_requested_filter
+      .iter()
+      .map(|relay_to_client_event| relay_to_client_event.event.created_at)
+      .collect();
+    assert_eq!(sent_created_at, vec![50, 40, 30]);
+  }
+
+  #[test]
+  fn test_on_req_msg_sends_the_events_newest_first_without_a_limit() {
+    let mock = ReqSut::new(None);
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut events = mock.mock_events.lock().unwrap();
+    for created_at in [30, 10, 50, 20, 40] {
+      let mut event = mock.mock_event.clone();
+      event.created_at = created_at;
+      events.push(Arc::new(event));
+    }
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
+    // events matching different filters are sent newest first too
+    let filters = vec![
+      Filter {
+        until: Some(20),
+        ..mock.mock_filters[0].clone()
+      },
+      Filter {
+        since: Some(30),
+        ..mock.mock_filters[0].clone()
+      },
+    ];
+
+    let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
+      mock.mock_subscription_id,
+      filters,
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx,
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+
+    let sent_created_at: Vec<Timestamp> = events_to_send_to_client_that_match_the_requested_filter
+      .iter()
+      .map(|relay_to_client_event| relay_to_client_event.event.created_at)
+      .collect();
+    assert_eq!(sent_created_at, vec![50, 40, 30, 20, 10]);
+  }
+
+  #[test]
+  fn test_on_req_msg_sends_all_events_when_limit_is_bigger_than_stored() {
+    let mock = ReqSut::new(Some(10));
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut events = mock.mock_events.lock().unwrap();
+    events.push(Arc::new(mock.mock_event.clone()));
+    events.push(Arc::new(mock.mock_event.clone()));
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
+
+    let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
+      mock.mock_subscription_id,
+      mock.mock_filters,
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx,
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+
+    assert_eq!(
+      events_to_send_to_client_that_match_the_requested_filter.len(),
+      2
+    );
+  }
+
+  #[test]
+  fn test_on_req_msg_does_not_return_expired_events() {
+    let mock = ReqSut::new(None);
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut events = mock.mock_events.lock().unwrap();
+    let mut expired_event = mock.mock_event.clone();
+    expired_event.tags = vec![Tag::Generic(
+      TagKind::Custom(String::from("expiration")),
+      vec![String::from("1")],
+    )];
+    events.push(Arc::new(expired_event));
+    events.push(Arc::new(mock.mock_event.clone()));
+    let mut events_index = mock.mock_events_index.lock().unwrap();
+    events_index.rebuild(&events);
+
+    let events_to_send_to_client_that_match_the_requested_filter = on_request_message(
+      mock.mock_subscription_id,
+      mock.mock_filters,
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx,
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    )
+    .unwrap();
+
+    assert_eq!(
+      events_to_send_to_client_that_match_the_requested_filter,
+      vec![mock.mock_relay_to_client_event]
+    );
+  }
+
+  #[test]
+  fn test_on_req_msg_refuses_subscriptions_over_the_limit() {
+    let mut mock = ReqSut::new(None);
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
+
+    for i in 0..DEFAULT_MAX_SUBSCRIPTIONS_PER_CLIENT {
+      let result = on_request_message(
+        format!("subscription_{i}"),
+        mock.mock_filters.clone(),
+        &mut clients,
+        mock.mock_addr,
+        mock.mock_tx.clone(),
+        &events,
+        &events_index,
+        IdMatching::Prefix,
+      );
+      assert!(result.is_ok());
+    }
+
+    let result = on_request_message(
+      String::from("one_too_many"),
+      mock.mock_filters.clone(),
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx.clone(),
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    );
+
+    assert_eq!(result, Err(Error::TooManySubscriptions));
+    assert_eq!(
+      clients[0].requests.len(),
+      DEFAULT_MAX_SUBSCRIPTIONS_PER_CLIENT
+    );
+    let closed = mock.mock_rx.try_recv().unwrap();
+    assert_eq!(
+      closed.to_string(),
+      RelayToClientCommClosed::new_closed(
+        String::from("one_too_many"),
+        String::from("too many subscriptions")
+      )
+      .as_json()
+    );
+  }
+
+  #[test]
+  fn test_on_req_msg_with_reversed_range_returns_empty_and_sends_a_notice() {
+    let mut mock = ReqSut::new(None);
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let mut events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
+    events.push(Arc::new(Event {
+      created_at: 150,
+      ..mock.mock_event.clone()
+    }));
+    let reversed_range_filter = Filter {
+      ids: None,
+      since: Some(200),
+      until: Some(100),
+      ..mock.mock_filters[0].clone()
+    };
+
+    let result = on_request_message(
+      mock.mock_subscription_id.clone(),
+      vec![reversed_range_filter],
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx.clone(),
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    );
+
+    assert_eq!(result, Ok(vec![]));
+    let notice = mock.mock_rx.try_recv().unwrap();
+    assert_eq!(
+      notice.to_string(),
+      RelayToClientCommNotice::new_notice(String::from(INVALID_RANGE_NOTICE)).as_json()
+    );
+
+    // valid ranges are not noticed
+    let result = on_request_message(
+      mock.mock_subscription_id.clone(),
+      vec![Filter {
+        ids: None,
+        since: Some(100),
+        until: Some(200),
+        ..mock.mock_filters[0].clone()
+      }],
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx.clone(),
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    );
+
+    assert_eq!(result.unwrap().len(), 1);
+    assert!(mock.mock_rx.try_recv().is_err());
+  }
+
+  #[test]
+  fn test_on_req_msg_refuses_empty_and_too_long_subscription_ids() {
+    let mut mock = ReqSut::new(None);
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
+
+    for subscription_id in [String::new(), "a".repeat(MAX_SUBSCRIPTION_ID_LENGTH + 1)] {
+      let result = on_request_message(
+        subscription_id.clone(),
+        mock.mock_filters.clone(),
+        &mut clients,
+        mock.mock_addr,
+        mock.mock_tx.clone(),
+        &events,
+        &events_index,
+        IdMatching::Prefix,
+      );
+
+      assert_eq!(result, Err(Error::InvalidSubscriptionId));
+      let closed = mock.mock_rx.try_recv().unwrap();
+      assert_eq!(
+        closed.to_string(),
+        RelayToClientCommClosed::new_closed(
+          subscription_id,
+          Error::InvalidSubscriptionId.to_string()
+        )
+        .as_json()
+      );
+    }
+    assert!(clients.is_empty());
+
+    let result = on_request_message(
+      "a".repeat(MAX_SUBSCRIPTION_ID_LENGTH),
+      mock.mock_filters.clone(),
+      &mut clients,
+      mock.mock_addr,
+      mock.mock_tx.clone(),
+      &events,
+      &events_index,
+      IdMatching::Prefix,
+    );
+
+    assert!(result.is_ok());
+    assert_eq!(clients[0].requests.len(), 1);
+  }
+
+  #[test]
+  fn test_on_req_msg_reusing_a_subscription_id_overwrites_its_filters() {
+    let mock = ReqSut::new(None);
+    let mut clients = mock.mock_clients.lock().unwrap();
+    let events = mock.mock_events.lock().unwrap();
+    let events_index = mock.mock_events_index.lock().unwrap();
+    let new_filters = vec![Filter {
+      kinds: Some(vec![EventKind::Metadata]),
+      ..Default::default()
+    }];
+
+    for filters in [mock.mock_filters.clone(), new_filters.clone()] {
+      on_request_message(
+        mock.mock_subscription_id.clone(),
+        filters,
+        &mut clients,
+        mock.mock_addr,
+        mock.mock_tx.clone(),
+        &events,
+        &events_index,
+        IdMatching::Prefix,
+      )
+      .unwrap();
+    }
+
+    assert_eq!(clients.len(), 1);
+    assert_eq!(
+      clients[0].requests,
+      vec![ClientRequests::new(
+        mock.mock_subscription_id,
+        new_filters,
+        IdMatching::Prefix
+      )]
+    );
+  }
 }
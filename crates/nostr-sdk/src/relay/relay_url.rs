@@ -0,0 +1,78 @@
+//! Urls of the relays the pool connects to.
+
+use std::{fmt, str::FromStr};
+
+use url::Url;
+
+/// [`RelayUrl`] error
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum RelayUrlError {
+  #[error("Invalid relay URL: {0}")]
+  Parse(#[from] url::ParseError),
+  #[error("Unsupported relay URL scheme `{0}`: expected `ws` or `wss`")]
+  UnsupportedScheme(String),
+}
+
+/// Url of a relay, validated to be a websocket (`ws://` or `wss://`) url.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RelayUrl(Url);
+
+impl RelayUrl {
+  pub fn parse(url: &str) -> Result<Self, RelayUrlError> {
+    let url = Url::parse(url)?;
+    match url.scheme() {
+      "ws" | "wss" => Ok(Self(url)),
+      scheme => Err(RelayUrlError::UnsupportedScheme(scheme.to_string())),
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    self.0.as_str()
+  }
+}
+
+impl FromStr for RelayUrl {
+  type Err = RelayUrlError;
+
+  fn from_str(url: &str) -> Result<Self, Self::Err> {
+    Self::parse(url)
+  }
+}
+
+impl fmt::Display for RelayUrl {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn accepts_websocket_urls() {
+    let url = RelayUrl::parse("wss://relay.damus.io").unwrap();
+    assert_eq!(url.as_str(), "wss://relay.damus.io/");
+
+    let url: RelayUrl = "ws://127.0.0.1:8080".parse().unwrap();
+    assert_eq!(url.to_string(), String::from("ws://127.0.0.1:8080/"));
+  }
+
+  #[test]
+  fn rejects_other_urls() {
+    assert_eq!(
+      RelayUrl::parse("http://relay.damus.io"),
+      Err(RelayUrlError::UnsupportedScheme(String::from("http")))
+    );
+    assert_eq!(
+      RelayUrl::parse("relay.damus.io"),
+      Err(RelayUrlError::Parse(
+        url::ParseError::RelativeUrlWithoutBase
+      ))
+    );
+  }
+}
@@ -1,23 +1,41 @@
-use log::debug;
+use log::{debug, error};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio_tungstenite::tungstenite::Message;
 
 use crate::relay::Tx;
 
-#[derive(Debug, Clone)]
-pub struct OutboundInfo {
-  pub tx: Tx,
-  pub content: String,
-}
+/// The clients (`Tx`s) that must receive the same serialized `content`.
+/// The content is serialized only once for all of them, but each client
+/// gets its own copy, as the text of a `Message` is an owned `String`.
+///
+pub type OutboundInfo = (Vec<Tx>, String);
 
-pub fn send_message_to_client(tx: Tx, content: String) {
+/// Sends `content` to the client. Fails if the client can no longer
+/// receive messages, i.e.: the receiving end (`rx`) was dropped,
+/// or if it is not keeping up with them, i.e.: its channel is full.
+///
+pub fn send_message_to_client(tx: Tx, content: String) -> Result<(), TrySendError<Message>> {
   debug!("{content}");
-  tx.send(Message::Text(content)).unwrap();
+  tx.try_send(Message::Text(content))
 }
 
-pub fn broadcast_message_to_clients(outbound_client_and_message: Vec<OutboundInfo>) {
-  for recp in outbound_client_and_message {
-    send_message_to_client(recp.tx.clone(), recp.content.clone());
+/// Sends `content` to all the clients. The ones that can no longer
+/// receive messages are skipped, so they do not affect the others.
+///
+/// Returns the clients that are not keeping up with the messages (their channel
+/// is full), which are meant to be disconnected instead of buffering more for them.
+///
+pub fn broadcast_message_to_clients((txs, content): OutboundInfo) -> Vec<Tx> {
+  debug!("{content}");
+  let mut lagging = vec![];
+  for tx in txs {
+    match tx.try_send(Message::Text(content.clone())) {
+      Ok(()) => {}
+      Err(TrySendError::Full(_)) => lagging.push(tx),
+      Err(err) => error!("Error broadcasting message: {err}"),
+    }
   }
+  lagging
 }
 
 #[cfg(test)]
@@ -26,42 +44,96 @@ mod tests {
 
   #[cfg(test)]
   use pretty_assertions::assert_eq;
-  use tokio::sync::mpsc::UnboundedReceiver;
+  use tokio::sync::mpsc::Receiver;
 
   struct Sut {
-    outbound_info: OutboundInfo,
-    rx: UnboundedReceiver<Message>,
+    tx: Tx,
+    rx: Receiver<Message>,
   }
 
-  fn make_sut(content: &str) -> Sut {
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
+  fn make_sut() -> Sut {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Message>(1);
 
-    Sut {
-      outbound_info: OutboundInfo { tx, content: content.to_string() },
-      rx,
-    }
+    Sut { tx, rx }
   }
 
   #[tokio::test]
   async fn test_send_message_to_client() {
-    let mut sut = make_sut("first_content");
+    let mut sut = make_sut();
+    let content = String::from("first_content");
 
-    send_message_to_client(sut.outbound_info.tx, sut.outbound_info.content.clone());
+    send_message_to_client(sut.tx, content.clone()).unwrap();
 
     let received = sut.rx.recv().await.unwrap();
-    assert_eq!(received.to_string(), sut.outbound_info.content);
+    assert_eq!(received.to_string(), content);
+  }
+
+  #[test]
+  fn test_send_message_to_client_fails_when_the_receiver_was_dropped() {
+    let sut = make_sut();
+    drop(sut.rx);
+
+    let result = send_message_to_client(sut.tx, String::from("first_content"));
+
+    assert_eq!(
+      result,
+      Err(TrySendError::Closed(Message::Text(String::from(
+        "first_content"
+      ))))
+    );
+  }
+
+  #[test]
+  fn test_send_message_to_client_fails_when_the_channel_is_full() {
+    let sut = make_sut();
+    send_message_to_client(sut.tx.clone(), String::from("first_content")).unwrap();
+
+    let result = send_message_to_client(sut.tx, String::from("second_content"));
+
+    assert_eq!(
+      result,
+      Err(TrySendError::Full(Message::Text(String::from(
+        "second_content"
+      ))))
+    );
   }
 
   #[tokio::test]
   async fn test_broadcast_message_to_clients() {
-    let mut sut1 = make_sut("first_content");
-    let mut sut2 = make_sut("second_content");
+    let mut sut1 = make_sut();
+    let mut sut2 = make_sut();
+    let content = String::from("shared_content");
 
-    broadcast_message_to_clients(vec![sut1.outbound_info.clone(), sut2.outbound_info.clone()]);
+    let lagging =
+      broadcast_message_to_clients((vec![sut1.tx.clone(), sut2.tx.clone()], content.clone()));
 
     let received1 = sut1.rx.recv().await.unwrap();
     let received2 = sut2.rx.recv().await.unwrap();
-    assert_eq!(received1.to_string(), sut1.outbound_info.content);
-    assert_eq!(received2.to_string(), sut2.outbound_info.content);
+    assert_eq!(received1.to_string(), content);
+    assert_eq!(received2.to_string(), content);
+    assert!(lagging.is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_broadcast_message_to_clients_returns_the_lagging_ones() {
+    let mut lagging_sut = make_sut();
+    let mut sut = make_sut();
+    let content = String::from("shared_content");
+    send_message_to_client(lagging_sut.tx.clone(), String::from("first_content")).unwrap();
+
+    let lagging = broadcast_message_to_clients((
+      vec![lagging_sut.tx.clone(), sut.tx.clone()],
+      content.clone(),
+    ));
+
+    assert_eq!(lagging.len(), 1);
+    assert!(lagging[0].same_channel(&lagging_sut.tx));
+    // the message is not buffered for the lagging client
+    assert_eq!(
+      lagging_sut.rx.recv().await.unwrap().to_string(),
+      "first_content"
+    );
+    assert!(lagging_sut.rx.try_recv().is_err());
+    assert_eq!(sut.rx.recv().await.unwrap().to_string(), content);
   }
 }
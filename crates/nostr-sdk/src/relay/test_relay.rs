@@ -0,0 +1,152 @@
+use std::{
+  io::Result as IoResult,
+  sync::{Arc, Mutex},
+};
+
+use tokio::{net::TcpListener, sync::Semaphore, task::JoinHandle};
+
+use crate::{
+  event::Event,
+  relay::{
+    event_store::{spawn_writer, MemoryEventStore},
+    events_index::EventsIndex,
+    handle_connection,
+    rate_limiter::RateLimiter,
+    ClientConnectionInfo, RelaySettings, DEFAULT_EVENTS_BURST, DEFAULT_EVENTS_PER_SEC,
+    DEFAULT_MAX_PENDING_VERIFICATIONS,
+  },
+};
+
+/// A relay listening on an ephemeral port of `127.0.0.1` that keeps
+/// the events only in memory, to test clients against, e.g.:
+/// `client.add_relay(test_relay.url()).await`.
+///
+/// It stops accepting connections once dropped.
+///
+pub struct TestRelay {
+  url: String,
+  events: Arc<Mutex<Vec<Arc<Event>>>>,
+  accept_task: JoinHandle<()>,
+}
+
+impl TestRelay {
+  /// Starts the relay. Each connection is handled like in the real relay.
+  ///
+  pub async fn start() -> IoResult<Self> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("ws://{}/", listener.local_addr()?);
+
+    let client_connection_info = Arc::new(Mutex::new(Vec::<ClientConnectionInfo>::new()));
+    let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(
+      DEFAULT_EVENTS_PER_SEC,
+      DEFAULT_EVENTS_BURST,
+    )));
+    let events = Arc::new(Mutex::new(Vec::<Arc<Event>>::new()));
+    let events_index = Arc::new(Mutex::new(EventsIndex::default()));
+    let events_writer = spawn_writer(Box::<MemoryEventStore>::default());
+    let pending_verifications = Arc::new(Semaphore::new(DEFAULT_MAX_PENDING_VERIFICATIONS));
+
+    let accept_task = {
+      let events = Arc::clone(&events);
+      tokio::spawn(async move {
+        while let Ok((stream, addr)) = listener.accept().await {
+          tokio::spawn(handle_connection(
+            stream,
+            addr,
+            Arc::clone(&client_connection_info),
+            Arc::clone(&rate_limiter),
+            Arc::default(),
+            Arc::clone(&events),
+            Arc::clone(&events_index),
+            events_writer.clone(),
+            Arc::clone(&pending_verifications),
+            Arc::default(),
+            RelaySettings::default(),
+          ));
+        }
+      })
+    };
+
+    Ok(Self {
+      url,
+      events,
+      accept_task,
+    })
+  }
+
+  /// Url (`ws://127.0.0.1:<port>/`) to connect to the relay.
+  pub fn url(&self) -> String {
+    self.url.clone()
+  }
+
+  /// Events stored by the relay.
+  pub fn events(&self) -> Vec<Event> {
+    self
+      .events
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|event| Event::clone(event))
+      .collect()
+  }
+}
+
+impl Drop for TestRelay {
+  fn drop(&mut self) {
+    self.accept_task.abort();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use tokio::time;
+
+  use super::*;
+  use crate::{client::Client, filter::Filter, relay::pool::RelayPoolNotification};
+
+  #[cfg(test)]
+  use pretty_assertions::assert_eq;
+
+  #[tokio::test]
+  async fn publishes_and_retrieves_an_event() {
+    let test_relay = TestRelay::start().await.unwrap();
+    let mut client = Client::new(
+      Some("publishes_and_retrieves_an_event".to_string()),
+      Some("publishes_and_retrieves_an_event".to_string()),
+      None,
+    );
+    client.add_relay(test_relay.url()).await.unwrap();
+    let mut notifications = client.notifications_receiver();
+    client.get_notifications().await;
+
+    let note = client.create_text_note_event(String::from("potato"));
+    client.broadcast_messages(note.as_json()).await;
+    time::timeout(Duration::from_secs(5), async {
+      while !test_relay.events().contains(&note.event) {
+        time::sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .unwrap();
+
+    client
+      .subscribe(vec![Filter::new().ids(vec![note.event.id.clone()])])
+      .await;
+    let received = time::timeout(Duration::from_secs(5), async {
+      loop {
+        if let Ok(RelayPoolNotification::Event { event, .. }) = notifications.recv().await {
+          break event;
+        }
+      }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(received, note.event);
+
+    client.disconnect_all().await;
+    std::fs::remove_file("db/publishes_and_retrieves_an_event.redb").unwrap();
+  }
+}
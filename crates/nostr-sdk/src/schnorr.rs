@@ -223,6 +223,7 @@ pub fn verify_schnorr<C: Verification>(
 ) -> Result<bool, SchnorrError> {
   let hash_from_hex = sha256::Hash::from_hex(&msg)?;
   let msg = Message::from_slice(hash_from_hex.as_ref())?;
+  // the events only have x-only pubkeys (NIP-01), so one key has a single form
   let x_only_pubkey = XOnlyPublicKey::from_str(&pubkey)?;
 
   match secp.verify_schnorr(&sig, &msg, &x_only_pubkey) {
@@ -234,6 +235,22 @@ pub fn verify_schnorr<C: Verification>(
   }
 }
 
+/// Gets the x-only (32 bytes) hex of a public key, the one used by the events,
+/// from either its x-only hex or its compressed (33 bytes, `02`/`03` prefixed) hex.
+/// Meant for keys given by the user (e.g.: config), not for the pubkey of events.
+///
+pub fn normalize_pubkey(hex: &str) -> Result<String, SchnorrError> {
+  Ok(parse_x_only_pubkey(hex)?.to_string())
+}
+
+fn parse_x_only_pubkey(hex: &str) -> Result<XOnlyPublicKey, SchnorrError> {
+  // compressed public keys have the `02`/`03` prefix
+  if hex.len() == 66 {
+    return Ok(PublicKey::from_str(hex)?.x_only_public_key().0);
+  }
+  Ok(XOnlyPublicKey::from_str(hex)?)
+}
+
 ///
 /// Generates random keypairs (private and public keys) that
 /// can be used for both Schnorr and ECDSA signatures.
@@ -317,6 +334,26 @@ mod tests {
     assert!(verify_schnorr(&sut.secp, sut.msg, signature_schnorr, pubkey.0.to_string()).is_ok());
   }
 
+  #[test]
+  fn normalize_pubkey_accepts_x_only_and_compressed_keys() {
+    let keys = generate_keys();
+    let compressed = keys.public_key.to_hex();
+    let x_only = &compressed[2..];
+
+    assert_eq!(normalize_pubkey(&compressed).unwrap(), x_only);
+    assert_eq!(normalize_pubkey(x_only).unwrap(), x_only);
+    assert!(normalize_pubkey(&compressed[4..]).is_err());
+  }
+
+  #[test]
+  fn test_should_not_verify_schnorr_with_a_compressed_pubkey() {
+    let sut: Sut = make_sut();
+    let signature_schnorr = sign_schnorr(&sut.secp, sut.msg.clone(), sut.seckey.to_vec()).unwrap();
+    let seckey = SecretKey::from_slice(&sut.seckey).unwrap();
+    let pubkey = PublicKey::from_secret_key(&sut.secp, &seckey);
+    assert!(verify_schnorr(&sut.secp, sut.msg, signature_schnorr, pubkey.to_string()).is_err());
+  }
+
   #[test]
   fn should_get_converted_pubkey_without_errors() {
     let keys = generate_keys();